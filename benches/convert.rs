@@ -0,0 +1,43 @@
+//! Benchmarks of whole-frame YUYV to RGB conversion.
+//!
+//! Compares the SIMD path picked at runtime with the scalar reference.
+//! Run with `cargo bench --bench convert`.
+
+// criterion_group! generates an undocumented public function
+#![allow(missing_docs)]
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use pi_cam_capture::convert::{simd_backend, yuyv_to_rgb_into, yuyv_to_rgb_scalar_into};
+use pi_cam_capture::{Colorimetry, Format, FourCC, RgbLayout, YuvMatrix, YuvRange};
+
+/// A 720p YUYV frame with varied content.
+fn frame(format: &Format) -> Vec<u8> {
+    (0..format.size)
+        .map(|index| (index.wrapping_mul(2_654_435_761) >> 24).to_le_bytes()[0])
+        .collect()
+}
+
+fn bench_yuyv_to_rgb(c: &mut Criterion) {
+    let format = Format::new(1280, 720, FourCC::YUYV);
+    let data = frame(&format);
+    let colorimetry = Colorimetry::new(YuvMatrix::Bt709, YuvRange::Limited);
+    let simd = simd_backend().unwrap_or("scalar fallback");
+
+    let mut group = c.benchmark_group("yuyv_to_rgb_720p");
+    group.throughput(Throughput::Elements(u64::from(
+        format.width * format.height,
+    )));
+    for layout in [RgbLayout::Rgb24, RgbLayout::Rgba, RgbLayout::Bgr24] {
+        let mut out = Vec::new();
+        group.bench_function(BenchmarkId::new(simd, format!("{layout:?}")), |b| {
+            b.iter(|| yuyv_to_rgb_into(&data, &format, colorimetry, layout, &mut out));
+        });
+        group.bench_function(BenchmarkId::new("scalar", format!("{layout:?}")), |b| {
+            b.iter(|| yuyv_to_rgb_scalar_into(&data, &format, colorimetry, layout, &mut out));
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_yuyv_to_rgb);
+criterion_main!(benches);
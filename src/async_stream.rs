@@ -0,0 +1,150 @@
+//! Async capture for the tokio runtime.
+//!
+//! [`AsyncFrameStream`] adapts any [`AsyncCapture`] stream into a
+//! [`futures_core::Stream`] of frames. V4L2 streams register their file
+//! descriptor with the tokio reactor, so waiting for a frame does not tie up a
+//! thread.
+
+use std::os::fd::{AsRawFd, RawFd};
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use futures_core::Stream;
+
+use crate::traits::{CaptureStream, Frame, Result};
+
+/// A capture stream that can signal frame readiness to an async runtime.
+pub trait AsyncCapture: CaptureStream {
+    /// Poll until a frame can be captured without blocking.
+    ///
+    /// Readiness may be spurious; callers confirm it with a non-blocking
+    /// capture and poll again if no frame was available.
+    fn poll_frame_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>>;
+
+    /// Wrap this stream in an [`AsyncFrameStream`].
+    fn into_async(self) -> AsyncFrameStream<Self>
+    where
+        Self: Sized,
+    {
+        AsyncFrameStream::new(self)
+    }
+}
+
+/// Async stream of owned frames.
+///
+/// The stream never ends on its own; errors are yielded as items and the
+/// caller decides whether to keep polling. Wrap `next` calls in
+/// `tokio::time::timeout` to detect a stalled sensor.
+#[derive(Debug)]
+pub struct AsyncFrameStream<S> {
+    stream: S,
+}
+
+impl<S> AsyncFrameStream<S> {
+    /// Wrap a capture stream.
+    pub const fn new(stream: S) -> Self {
+        Self { stream }
+    }
+
+    /// Get a reference to the underlying capture stream.
+    pub const fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    /// Get a mutable reference to the underlying capture stream.
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    /// Unwrap the underlying capture stream.
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+impl<S: AsyncCapture + Unpin> Stream for AsyncFrameStream<S> {
+    type Item = Result<Frame>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let stream = &mut self.get_mut().stream;
+        loop {
+            if let Err(err) = ready!(stream.poll_frame_ready(cx)) {
+                return Poll::Ready(Some(Err(err)));
+            }
+            match stream.try_next_frame() {
+                Ok(Some(frame)) => return Poll::Ready(Some(Ok(frame))),
+                // Stale readiness: poll again so the waker is registered
+                Ok(None) => {}
+                Err(err) => return Poll::Ready(Some(Err(err))),
+            }
+        }
+    }
+}
+
+/// Borrowed stream file descriptor registered with the reactor.
+///
+/// The owner must drop the registration before closing the descriptor.
+#[derive(Debug)]
+pub(crate) struct StreamFd(pub(crate) RawFd);
+
+impl AsRawFd for StreamFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{FramePacing, MockDevice};
+    use crate::traits::{CameraDevice, Fraction};
+    use std::future::poll_fn;
+    use std::time::Duration;
+    use tokio::time::{timeout, Instant};
+
+    /// Await the next item of a stream.
+    async fn next<S: Stream + Unpin>(stream: &mut S) -> Option<S::Item> {
+        poll_fn(|cx| Pin::new(&mut *stream).poll_next(cx)).await
+    }
+
+    #[tokio::test]
+    async fn test_async_mock_frames_are_paced() {
+        let mut device = MockDevice::new()
+            .with_frame_interval(Fraction::from_fps(100))
+            .with_pacing(FramePacing::Realtime);
+        let mut frames = device
+            .create_stream(4)
+            .expect("create_stream should succeed")
+            .into_async();
+
+        let start = Instant::now();
+        for expected in 0..5 {
+            let frame = next(&mut frames)
+                .await
+                .expect("stream should not end")
+                .expect("frame should arrive");
+            assert_eq!(frame.metadata.sequence, expected);
+        }
+        assert!(
+            start.elapsed() >= Duration::from_millis(40),
+            "five frames at 100 fps took only {:?}",
+            start.elapsed()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_async_mock_stall_stays_pending() {
+        let mut device = MockDevice::new()
+            .with_frame_interval(Fraction::from_fps(100))
+            .with_pacing(FramePacing::Realtime)
+            .with_stall_after(1);
+        let mut frames = device
+            .create_stream(4)
+            .expect("create_stream should succeed")
+            .into_async();
+
+        assert!(next(&mut frames).await.is_some());
+        let stalled = timeout(Duration::from_millis(50), next(&mut frames)).await;
+        assert!(stalled.is_err(), "stalled stream should not yield frames");
+    }
+}
@@ -0,0 +1,662 @@
+//! Software auto exposure and auto white balance.
+//!
+//! Without the libcamera IPA nothing adapts a bare sensor to the light in the
+//! scene. [`AutoControl`] closes that loop in software: it measures the
+//! frames it is given and moves the exposure, analogue gain and red and blue
+//! balance controls a step toward the targets of its [`AutoConfig`].
+//!
+//! Exposure is raised before gain, which adds noise, and lowered after it.
+//! White balance follows the grey world assumption: the unclipped pixels of a
+//! scene average out to neutral grey. Both assume the image brightens in
+//! proportion to the control values, as it does for most sensors. Each step
+//! applies only part of the correction, so the loop does not overshoot while
+//! the sensor catches up with new settings.
+//!
+//! A V4L2 device accepts control writes from a second handle opened on the
+//! same node while the first one streams, so a capture loop can feed every
+//! frame to [`AutoControl::update`] and [`apply`](AutoControl::apply) the
+//! result through the other handle. [`AutoControl::run`] instead alternates
+//! short captures and control writes on a single handle.
+
+use std::io;
+use std::time::Duration;
+
+use crate::controls::{cid, ControlValue};
+use crate::stats::{FrameStats, StatsConfig};
+use crate::traits::{CameraDevice, CameraError, CaptureStream, Format, Frame, Result};
+
+/// Statistics gathered per step: a single region, with pixels counting as
+/// clipped from channel value 250 and as black up to 5.
+const STATS_CONFIG: StatsConfig = StatsConfig::new(1, 1).with_levels(5, 250);
+
+/// Value of `EXPOSURE_AUTO` selecting manual exposure (`V4L2_EXPOSURE_MANUAL`).
+const EXPOSURE_MANUAL: i64 = 1;
+
+/// Buffers requested for the captures of [`AutoControl::run`].
+const RUN_BUFFERS: u32 = 2;
+
+/// Longest wait for a frame in [`AutoControl::run`].
+const FRAME_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Targets and tuning of the control loop.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AutoConfig {
+    /// Whether to drive exposure and gain.
+    pub exposure: bool,
+    /// Whether to drive the red and blue balance.
+    pub white_balance: bool,
+    /// Mean luma to aim for, 0-255.
+    pub target_luma: f32,
+    /// Distance from the target luma accepted as settled.
+    pub luma_tolerance: f32,
+    /// Relative difference of the red and blue means from the green one
+    /// accepted as balanced.
+    pub balance_tolerance: f32,
+    /// Share of each correction applied per step, above `0.0` and up to
+    /// `1.0`: `1.0` jumps straight to the estimate, smaller values approach
+    /// it gradually.
+    pub damping: f32,
+    /// Longest exposure used before raising the gain; `None` allows the
+    /// control maximum.
+    pub max_exposure: Option<i64>,
+    /// Consecutive settled steps before the loop reports convergence.
+    pub stable_steps: u32,
+    /// Frames discarded at the start of each capture of [`AutoControl::run`].
+    pub settle_frames: u32,
+}
+
+impl Default for AutoConfig {
+    fn default() -> Self {
+        Self {
+            exposure: true,
+            white_balance: true,
+            // 18% grey through the sRGB curve
+            target_luma: 118.0,
+            luma_tolerance: 4.0,
+            balance_tolerance: 0.02,
+            damping: 0.5,
+            max_exposure: None,
+            stable_steps: 2,
+            settle_frames: 2,
+        }
+    }
+}
+
+impl AutoConfig {
+    /// Set the mean luma to aim for.
+    #[must_use]
+    pub const fn with_target_luma(mut self, target_luma: f32) -> Self {
+        self.target_luma = target_luma;
+        self
+    }
+
+    /// Set the share of each correction applied per step.
+    #[must_use]
+    pub const fn with_damping(mut self, damping: f32) -> Self {
+        self.damping = damping;
+        self
+    }
+
+    /// Set the longest exposure used before raising the gain.
+    #[must_use]
+    pub const fn with_max_exposure(mut self, max_exposure: i64) -> Self {
+        self.max_exposure = Some(max_exposure);
+        self
+    }
+}
+
+/// Progress of the exposure or white balance loop after a step.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoopState {
+    /// The loop is disabled.
+    Off,
+    /// Moving toward the target.
+    Adjusting,
+    /// The controls are at the end of their range with the target out of
+    /// reach, e.g. in a scene too dark for the longest exposure.
+    Limited,
+    /// Within tolerance of the target.
+    Settled,
+}
+
+impl LoopState {
+    /// Whether the loop has nothing left to do: settled or disabled.
+    #[must_use]
+    pub const fn is_settled(self) -> bool {
+        matches!(self, Self::Off | Self::Settled)
+    }
+}
+
+/// Measurements and control writes of one step of the loop.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AutoStep {
+    /// Mean luma of the frame, 0-255.
+    pub luma: f32,
+    /// Mean red, green and blue of the pixels neither clipped nor black,
+    /// 0-255, or `None` if there are none.
+    pub rgb: Option<[f32; 3]>,
+    /// Control writes moving toward the targets.
+    pub controls: Vec<(u32, ControlValue)>,
+    /// State of auto exposure.
+    pub exposure: LoopState,
+    /// State of auto white balance.
+    pub white_balance: LoopState,
+    /// Whether both loops have stayed settled for the configured number of
+    /// steps.
+    pub converged: bool,
+}
+
+/// Steps taken by [`AutoControl::run`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AutoReport {
+    /// Every step, in order.
+    pub steps: Vec<AutoStep>,
+}
+
+impl AutoReport {
+    /// Whether the loop converged.
+    #[must_use]
+    pub fn converged(&self) -> bool {
+        self.steps.last().is_some_and(|step| step.converged)
+    }
+
+    /// The last step taken.
+    #[must_use]
+    pub fn last(&self) -> Option<&AutoStep> {
+        self.steps.last()
+    }
+}
+
+/// A control driven by the loop, with its range and last known value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Driven {
+    id: u32,
+    minimum: i64,
+    maximum: i64,
+    value: i64,
+}
+
+impl Driven {
+    /// Round `value` to an integer within the range of the control.
+    #[allow(clippy::cast_possible_truncation)]
+    fn clamp(&self, value: f32) -> i64 {
+        (value.round() as i64).clamp(self.minimum, self.maximum)
+    }
+
+    /// The last known value as a float.
+    #[allow(clippy::cast_precision_loss)]
+    const fn level(&self) -> f32 {
+        self.value as f32
+    }
+}
+
+/// Software auto exposure and white balance loop.
+#[derive(Debug, Clone)]
+pub struct AutoControl {
+    config: AutoConfig,
+    exposure: Option<Driven>,
+    gain: Option<Driven>,
+    red: Option<Driven>,
+    blue: Option<Driven>,
+    /// Consecutive settled steps so far.
+    stable: u32,
+}
+
+impl AutoControl {
+    /// Set up the loop for `device`, reading the ranges and values of the
+    /// controls it drives.
+    ///
+    /// The driver's own automatic exposure, gain and white balance are
+    /// switched off for the loops that are enabled. Gain is taken from
+    /// `ANALOGUE_GAIN`, or `GAIN` if the device has no analogue gain, and is
+    /// optional.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Io` with `InvalidInput` if the damping is not a finite
+    /// positive number; `ControlNotFound` if the device lacks a writable
+    /// `EXPOSURE` control with auto exposure enabled, or `RED_BALANCE` and
+    /// `BLUE_BALANCE` with auto white balance enabled; `ControlError` if a
+    /// driven control does not hold an integer; or the errors of reading and
+    /// writing the controls.
+    pub fn new<D: CameraDevice>(device: &mut D, config: AutoConfig) -> Result<Self> {
+        if !config.damping.is_finite() || config.damping <= 0.0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid damping {}", config.damping),
+            )
+            .into());
+        }
+        let infos = device.controls()?;
+        let find = |id| -> Result<Option<Driven>> {
+            let Some(info) = infos
+                .iter()
+                .find(|info| info.id == id && info.is_writable())
+            else {
+                return Ok(None);
+            };
+            let value = device.control(id)?;
+            let value = value.as_integer().ok_or_else(|| {
+                CameraError::ControlError(format!(
+                    "Control \"{}\" holds {value:?}, not an integer",
+                    info.name
+                ))
+            })?;
+            Ok(Some(Driven {
+                id,
+                minimum: info.minimum,
+                maximum: info.maximum,
+                value,
+            }))
+        };
+        let require = |id| find(id)?.ok_or(CameraError::ControlNotFound(id)).map(Some);
+
+        let (exposure, gain) = if config.exposure {
+            let gain = match find(cid::ANALOGUE_GAIN)? {
+                Some(gain) => Some(gain),
+                None => find(cid::GAIN)?,
+            };
+            (require(cid::EXPOSURE)?, gain)
+        } else {
+            (None, None)
+        };
+        let (red, blue) = if config.white_balance {
+            (require(cid::RED_BALANCE)?, require(cid::BLUE_BALANCE)?)
+        } else {
+            (None, None)
+        };
+
+        let mut manual = Vec::new();
+        if config.exposure {
+            manual.push((cid::EXPOSURE_AUTO, ControlValue::Integer(EXPOSURE_MANUAL)));
+            manual.push((cid::AUTOGAIN, ControlValue::Boolean(false)));
+        }
+        if config.white_balance {
+            manual.push((cid::AUTO_WHITE_BALANCE, ControlValue::Boolean(false)));
+        }
+        manual.retain(|(id, _)| {
+            infos
+                .iter()
+                .any(|info| info.id == *id && info.is_writable())
+        });
+        if !manual.is_empty() {
+            device.set_controls(&manual)?;
+        }
+
+        Ok(Self {
+            config,
+            exposure,
+            gain,
+            red,
+            blue,
+            stable: 0,
+        })
+    }
+
+    /// Configuration of the loop.
+    #[must_use]
+    pub const fn config(&self) -> &AutoConfig {
+        &self.config
+    }
+
+    /// Last known value of a control the loop drives.
+    #[must_use]
+    pub fn control_value(&self, id: u32) -> Option<i64> {
+        [self.exposure, self.gain, self.red, self.blue]
+            .into_iter()
+            .flatten()
+            .find(|control| control.id == id)
+            .map(|control| control.value)
+    }
+
+    /// Measure a frame and work out the next control values.
+    ///
+    /// The frame should have been captured with the values of the last
+    /// [`apply`](Self::apply)d step; pass every step to `apply`, even when
+    /// it has no writes.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of [`FrameStats::compute`] for frames it cannot
+    /// convert.
+    pub fn update(&mut self, data: &[u8], format: &Format) -> Result<AutoStep> {
+        let stats = FrameStats::compute(data, format, &STATS_CONFIG)?;
+        let luma = stats.mean_rgb_luma();
+        let neutral = stats.neutral_rgb;
+
+        let mut controls = Vec::new();
+        let exposure = self.expose(luma, &mut controls);
+        let white_balance = self.balance(neutral, &mut controls);
+        if exposure.is_settled() && white_balance.is_settled() {
+            self.stable = self.stable.saturating_add(1);
+        } else {
+            self.stable = 0;
+        }
+
+        Ok(AutoStep {
+            luma,
+            rgb: neutral,
+            controls,
+            exposure,
+            white_balance,
+            converged: self.stable >= self.config.stable_steps.max(1),
+        })
+    }
+
+    /// Write the controls of `step` and read back the values the driver
+    /// granted.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of writing and reading the controls.
+    pub fn apply<D: CameraDevice>(&mut self, device: &mut D, step: &AutoStep) -> Result<()> {
+        if step.controls.is_empty() {
+            return Ok(());
+        }
+        device.set_controls(&step.controls)?;
+        for control in [
+            &mut self.exposure,
+            &mut self.gain,
+            &mut self.red,
+            &mut self.blue,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            if let Some(value) = device.control(control.id)?.as_integer() {
+                control.value = value;
+            }
+        }
+        Ok(())
+    }
+
+    /// Run the loop on `device` until it converges, for at most `max_steps`
+    /// steps.
+    ///
+    /// Each step opens a short capture stream, discards the configured
+    /// settle frames, measures the next frame and applies the result.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of capturing, measuring and writing controls.
+    pub fn run<D: CameraDevice>(&mut self, device: &mut D, max_steps: u32) -> Result<AutoReport> {
+        let mut report = AutoReport::default();
+        for _ in 0..max_steps {
+            let format = device.format()?;
+            let frame = self.capture(device)?;
+            let step = self.update(&frame.data, &format)?;
+            self.apply(device, &step)?;
+            let converged = step.converged;
+            report.steps.push(step);
+            if converged {
+                break;
+            }
+        }
+        Ok(report)
+    }
+
+    /// Capture a frame on a new stream after the settle frames.
+    fn capture<D: CameraDevice>(&self, device: &mut D) -> Result<Frame> {
+        let mut stream = device.create_stream(RUN_BUFFERS)?;
+        for _ in 0..self.config.settle_frames {
+            stream.next_frame_ref_timeout(FRAME_TIMEOUT)?;
+        }
+        stream.next_frame_timeout(FRAME_TIMEOUT)
+    }
+
+    /// Work out exposure and gain for a frame of mean `luma`.
+    #[allow(clippy::cast_precision_loss)]
+    fn expose(&self, luma: f32, controls: &mut Vec<(u32, ControlValue)>) -> LoopState {
+        let Some(exposure) = self.exposure else {
+            return LoopState::Off;
+        };
+        if (luma - self.config.target_luma).abs() <= self.config.luma_tolerance {
+            return LoopState::Settled;
+        }
+
+        let ratio = self.config.target_luma / luma.max(1.0);
+        // Controls resting at zero, like UVC gains, would pin the product at
+        // zero; count them as 1 to match `lowest_gain`
+        let wanted = ratio.powf(self.config.damping.clamp(0.0, 1.0))
+            * exposure.level().max(1.0)
+            * self.gain.map_or(1.0, |gain| gain.level().max(1.0));
+        let longest = self
+            .config
+            .max_exposure
+            .map_or(exposure.maximum, |longest| {
+                longest.clamp(exposure.minimum, exposure.maximum)
+            });
+        let lowest_gain = self.gain.map_or(1.0, |gain| gain.minimum.max(1) as f32);
+        let new_exposure = exposure.clamp(wanted / lowest_gain).min(longest);
+        push_change(controls, exposure, new_exposure);
+
+        // Brightening ends at the longest exposure and highest gain
+        let brighten = ratio > 1.0;
+        let mut at_limit = new_exposure == if brighten { longest } else { exposure.minimum };
+        if let Some(gain) = self.gain {
+            let new_gain = gain.clamp(wanted / new_exposure.max(1) as f32);
+            push_change(controls, gain, new_gain);
+            at_limit &= new_gain == if brighten { gain.maximum } else { gain.minimum };
+        }
+        if at_limit {
+            LoopState::Limited
+        } else {
+            LoopState::Adjusting
+        }
+    }
+
+    /// Work out the red and blue balance for the neutral channel means.
+    fn balance(
+        &self,
+        neutral: Option<[f32; 3]>,
+        controls: &mut Vec<(u32, ControlValue)>,
+    ) -> LoopState {
+        let (Some(red), Some(blue)) = (self.red, self.blue) else {
+            return LoopState::Off;
+        };
+        let Some([red_mean, green_mean, blue_mean]) =
+            neutral.filter(|means| means.iter().all(|&mean| mean > 0.0))
+        else {
+            // Nothing to measure in a clipped or black frame
+            return LoopState::Adjusting;
+        };
+        let tolerance = self.config.balance_tolerance;
+        if (red_mean / green_mean - 1.0).abs() <= tolerance
+            && (blue_mean / green_mean - 1.0).abs() <= tolerance
+        {
+            return LoopState::Settled;
+        }
+
+        let writes = controls.len();
+        let damping = self.config.damping.clamp(0.0, 1.0);
+        for (control, mean) in [(red, red_mean), (blue, blue_mean)] {
+            let correction = (green_mean / mean).powf(damping);
+            push_change(
+                controls,
+                control,
+                control.clamp(control.level().max(1.0) * correction),
+            );
+        }
+        if controls.len() == writes {
+            LoopState::Limited
+        } else {
+            LoopState::Adjusting
+        }
+    }
+}
+
+/// Queue a write of `value` to `control` if it differs from the current one.
+fn push_change(controls: &mut Vec<(u32, ControlValue)>, control: Driven, value: i64) {
+    if value != control.value {
+        controls.push((control.id, ControlValue::Integer(value)));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::controls::{ControlInfo, ControlType};
+    use crate::mock::{MockDevice, TestPattern};
+    use crate::traits::FourCC;
+
+    /// A small mock camera looking at a flat scene.
+    fn scene(pattern: TestPattern) -> MockDevice {
+        MockDevice::new()
+            .with_format(Format::new(64, 48, FourCC::YUYV))
+            .with_pattern(pattern)
+    }
+
+    fn exposure_only() -> AutoConfig {
+        AutoConfig {
+            white_balance: false,
+            ..AutoConfig::default()
+        }
+    }
+
+    fn value(device: &MockDevice, id: u32) -> i64 {
+        device
+            .control(id)
+            .expect("control should exist")
+            .as_integer()
+            .expect("control should be an integer")
+    }
+
+    #[test]
+    fn test_exposure_brightens_dark_scene() {
+        let mut device = scene(TestPattern::Solid(50, 128, 128));
+        let mut auto = AutoControl::new(&mut device, exposure_only()).expect("loop should set up");
+        let report = auto.run(&mut device, 30).expect("loop should run");
+
+        assert!(report.converged(), "{report:?}");
+        let last = report.last().expect("loop should take steps");
+        assert!((last.luma - 118.0).abs() <= 4.0);
+        assert!(
+            report.steps.len() > 2,
+            "damping should spread the correction"
+        );
+        assert!(value(&device, cid::EXPOSURE) > 25_000);
+        assert_eq!(value(&device, cid::ANALOGUE_GAIN), 112);
+        assert_eq!(
+            auto.control_value(cid::EXPOSURE),
+            Some(value(&device, cid::EXPOSURE))
+        );
+    }
+
+    #[test]
+    fn test_exposure_darkens_bright_scene() {
+        let mut device = scene(TestPattern::Solid(220, 128, 128));
+        let config = exposure_only().with_target_luma(100.0).with_damping(1.0);
+        let mut auto = AutoControl::new(&mut device, config).expect("loop should set up");
+        let report = auto.run(&mut device, 30).expect("loop should run");
+
+        assert!(report.converged(), "{report:?}");
+        assert!(value(&device, cid::EXPOSURE) < 10_000);
+    }
+
+    #[test]
+    fn test_gain_takes_over_at_max_exposure() {
+        let mut device = scene(TestPattern::Solid(50, 128, 128));
+        let config = exposure_only().with_max_exposure(15_000);
+        let mut auto = AutoControl::new(&mut device, config).expect("loop should set up");
+        let report = auto.run(&mut device, 30).expect("loop should run");
+
+        assert!(report.converged(), "{report:?}");
+        assert_eq!(value(&device, cid::EXPOSURE), 15_000);
+        assert!(value(&device, cid::ANALOGUE_GAIN) > 112);
+    }
+
+    #[test]
+    fn test_exposure_with_gain_starting_at_zero() {
+        // UVC webcams expose a plain GAIN control whose range starts at 0
+        let mut device = scene(TestPattern::Solid(50, 128, 128)).with_controls(vec![
+            ControlInfo::integer(cid::EXPOSURE, "Exposure", 1, 66_666, 1, 10_000),
+            ControlInfo::integer(cid::GAIN, "Gain", 0, 255, 1, 0),
+        ]);
+        let mut auto = AutoControl::new(&mut device, exposure_only()).expect("loop should set up");
+        let report = auto.run(&mut device, 30).expect("loop should run");
+
+        assert!(report.converged(), "{report:?}");
+        assert!(value(&device, cid::EXPOSURE) > 10_000);
+    }
+
+    #[test]
+    fn test_exposure_limited_on_black_scene() {
+        let mut device = scene(TestPattern::Solid(16, 128, 128));
+        let mut auto = AutoControl::new(&mut device, exposure_only()).expect("loop should set up");
+        let report = auto.run(&mut device, 40).expect("loop should run");
+
+        assert!(!report.converged());
+        assert_eq!(report.steps.len(), 40);
+        assert_eq!(
+            report.last().map(|step| step.exposure),
+            Some(LoopState::Limited)
+        );
+        assert_eq!(value(&device, cid::EXPOSURE), 66_666);
+        assert_eq!(value(&device, cid::ANALOGUE_GAIN), 960);
+    }
+
+    #[test]
+    fn test_white_balance_neutralizes_cast() {
+        // A bluish grey
+        let mut device = scene(TestPattern::Solid(110, 145, 115));
+        let mut auto =
+            AutoControl::new(&mut device, AutoConfig::default()).expect("loop should set up");
+        assert_eq!(
+            device
+                .control(cid::AUTO_WHITE_BALANCE)
+                .expect("control should exist"),
+            ControlValue::Boolean(false)
+        );
+        let report = auto.run(&mut device, 40).expect("loop should run");
+
+        assert!(report.converged(), "{report:?}");
+        let [red, green, blue] = report
+            .last()
+            .and_then(|step| step.rgb)
+            .expect("frame should have neutral pixels");
+        assert!((red / green - 1.0).abs() <= 0.02 && (blue / green - 1.0).abs() <= 0.02);
+        assert!(value(&device, cid::RED_BALANCE) > 1024);
+        assert!(value(&device, cid::BLUE_BALANCE) < 1024);
+    }
+
+    #[test]
+    fn test_missing_controls() {
+        let mut device = MockDevice::new().with_controls(vec![ControlInfo::integer(
+            cid::EXPOSURE,
+            "Exposure",
+            1,
+            1000,
+            1,
+            100,
+        )]);
+        assert!(AutoControl::new(&mut device, exposure_only()).is_ok());
+        assert!(matches!(
+            AutoControl::new(&mut device, AutoConfig::default()),
+            Err(CameraError::ControlNotFound(cid::RED_BALANCE))
+        ));
+
+        let mut device = MockDevice::new().with_controls(vec![ControlInfo {
+            control_type: ControlType::String,
+            ..ControlInfo::integer(cid::EXPOSURE, "Exposure", 0, 64, 1, 0)
+        }]);
+        assert!(matches!(
+            AutoControl::new(&mut device, exposure_only()),
+            Err(CameraError::ControlError(_))
+        ));
+    }
+
+    #[test]
+    fn test_rejects_invalid_damping() {
+        for damping in [0.0, -0.5, f32::NAN] {
+            let mut device = scene(TestPattern::Solid(50, 128, 128));
+            let config = exposure_only().with_damping(damping);
+            assert!(
+                matches!(
+                    AutoControl::new(&mut device, config),
+                    Err(CameraError::Io(ref err)) if err.kind() == io::ErrorKind::InvalidInput
+                ),
+                "{damping}"
+            );
+        }
+    }
+}
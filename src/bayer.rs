@@ -0,0 +1,780 @@
+//! Raw Bayer formats and software demosaicing.
+//!
+//! Sensors such as the IMX708 on the Camera Module 3 deliver raw Bayer data
+//! when no ISP sits between them and the host: one color sample per pixel,
+//! arranged in a 2x2 color filter array (CFA). Samples are 8, 10, 12 or 16
+//! bits deep and either stored one per byte or little-endian 16-bit word
+//! ("unpacked"), or in the MIPI CSI-2 packing used by the receiver, where
+//! each group of pixels stores its high bits first and shares a byte of low
+//! bits.
+//!
+//! [`RawImage::unpack`] reads such frames into plain samples and
+//! [`RawImage::demosaic`] interpolates the two missing colors of every
+//! pixel, either bilinearly or with edge-aware interpolation that follows
+//! the direction of edges and keeps color fringes down.
+
+use crate::convert::too_short;
+use crate::traits::{CameraError, Format, FourCC, Result};
+
+/// Color of a filter in the color filter array.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CfaColor {
+    /// Red filter.
+    Red,
+    /// Green filter.
+    Green,
+    /// Blue filter.
+    Blue,
+}
+
+impl CfaColor {
+    /// Index of the color in an RGB triplet.
+    #[must_use]
+    pub const fn index(self) -> usize {
+        match self {
+            Self::Red => 0,
+            Self::Green => 1,
+            Self::Blue => 2,
+        }
+    }
+}
+
+/// Order of the filters in the 2x2 CFA tile, top row first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CfaPattern {
+    /// Blue, green / green, red.
+    Bggr,
+    /// Green, blue / red, green.
+    Gbrg,
+    /// Green, red / blue, green.
+    Grbg,
+    /// Red, green / green, blue.
+    Rggb,
+}
+
+impl CfaPattern {
+    /// Color of the filter over pixel (`x`, `y`).
+    #[must_use]
+    pub const fn color_at(self, x: u32, y: u32) -> CfaColor {
+        self.color(x % 2 == 1, y % 2 == 1)
+    }
+
+    /// Color of the filter at the given column and row parity.
+    const fn color(self, odd_column: bool, odd_row: bool) -> CfaColor {
+        let (top_left, top_right, bottom_left, bottom_right) = match self {
+            Self::Bggr => (
+                CfaColor::Blue,
+                CfaColor::Green,
+                CfaColor::Green,
+                CfaColor::Red,
+            ),
+            Self::Gbrg => (
+                CfaColor::Green,
+                CfaColor::Blue,
+                CfaColor::Red,
+                CfaColor::Green,
+            ),
+            Self::Grbg => (
+                CfaColor::Green,
+                CfaColor::Red,
+                CfaColor::Blue,
+                CfaColor::Green,
+            ),
+            Self::Rggb => (
+                CfaColor::Red,
+                CfaColor::Green,
+                CfaColor::Green,
+                CfaColor::Blue,
+            ),
+        };
+        match (odd_column, odd_row) {
+            (false, false) => top_left,
+            (true, false) => top_right,
+            (false, true) => bottom_left,
+            (true, true) => bottom_right,
+        }
+    }
+}
+
+/// How the samples of a Bayer format are stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BayerPacking {
+    /// One byte per 8-bit sample, otherwise one little-endian 16-bit word
+    /// with the sample in its low bits.
+    Unpacked,
+    /// MIPI CSI-2 packing: 4 pixels in 5 bytes for 10-bit samples, 2 pixels
+    /// in 3 bytes for 12-bit samples.
+    Csi2,
+}
+
+/// Sample layout of a raw Bayer pixel format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BayerFormat {
+    /// Order of the color filters.
+    pub pattern: CfaPattern,
+    /// Bits per sample.
+    pub bit_depth: u32,
+    /// Storage of the samples.
+    pub packing: BayerPacking,
+}
+
+/// The Bayer pixel formats known to this crate.
+const BAYER_FORMATS: [(FourCC, BayerFormat); 24] = [
+    (FourCC::SBGGR8, BayerFormat::unpacked(CfaPattern::Bggr, 8)),
+    (FourCC::SGBRG8, BayerFormat::unpacked(CfaPattern::Gbrg, 8)),
+    (FourCC::SGRBG8, BayerFormat::unpacked(CfaPattern::Grbg, 8)),
+    (FourCC::SRGGB8, BayerFormat::unpacked(CfaPattern::Rggb, 8)),
+    (FourCC::SBGGR10, BayerFormat::unpacked(CfaPattern::Bggr, 10)),
+    (FourCC::SGBRG10, BayerFormat::unpacked(CfaPattern::Gbrg, 10)),
+    (FourCC::SGRBG10, BayerFormat::unpacked(CfaPattern::Grbg, 10)),
+    (FourCC::SRGGB10, BayerFormat::unpacked(CfaPattern::Rggb, 10)),
+    (FourCC::SBGGR10P, BayerFormat::csi2(CfaPattern::Bggr, 10)),
+    (FourCC::SGBRG10P, BayerFormat::csi2(CfaPattern::Gbrg, 10)),
+    (FourCC::SGRBG10P, BayerFormat::csi2(CfaPattern::Grbg, 10)),
+    (FourCC::SRGGB10P, BayerFormat::csi2(CfaPattern::Rggb, 10)),
+    (FourCC::SBGGR12, BayerFormat::unpacked(CfaPattern::Bggr, 12)),
+    (FourCC::SGBRG12, BayerFormat::unpacked(CfaPattern::Gbrg, 12)),
+    (FourCC::SGRBG12, BayerFormat::unpacked(CfaPattern::Grbg, 12)),
+    (FourCC::SRGGB12, BayerFormat::unpacked(CfaPattern::Rggb, 12)),
+    (FourCC::SBGGR12P, BayerFormat::csi2(CfaPattern::Bggr, 12)),
+    (FourCC::SGBRG12P, BayerFormat::csi2(CfaPattern::Gbrg, 12)),
+    (FourCC::SGRBG12P, BayerFormat::csi2(CfaPattern::Grbg, 12)),
+    (FourCC::SRGGB12P, BayerFormat::csi2(CfaPattern::Rggb, 12)),
+    (FourCC::SBGGR16, BayerFormat::unpacked(CfaPattern::Bggr, 16)),
+    (FourCC::SGBRG16, BayerFormat::unpacked(CfaPattern::Gbrg, 16)),
+    (FourCC::SGRBG16, BayerFormat::unpacked(CfaPattern::Grbg, 16)),
+    (FourCC::SRGGB16, BayerFormat::unpacked(CfaPattern::Rggb, 16)),
+];
+
+impl BayerFormat {
+    /// Describe an unpacked format.
+    const fn unpacked(pattern: CfaPattern, bit_depth: u32) -> Self {
+        Self {
+            pattern,
+            bit_depth,
+            packing: BayerPacking::Unpacked,
+        }
+    }
+
+    /// Describe a CSI-2 packed format.
+    const fn csi2(pattern: CfaPattern, bit_depth: u32) -> Self {
+        Self {
+            pattern,
+            bit_depth,
+            packing: BayerPacking::Csi2,
+        }
+    }
+
+    /// Look up the Bayer layout of a pixel format.
+    ///
+    /// Returns `None` for formats that are not raw Bayer.
+    #[must_use]
+    pub const fn from_fourcc(fourcc: FourCC) -> Option<Self> {
+        let code = u32::from_le_bytes(fourcc.0);
+        let mut rest: &[(FourCC, Self)] = &BAYER_FORMATS;
+        while let [(candidate, format), tail @ ..] = rest {
+            if u32::from_le_bytes(candidate.0) == code {
+                return Some(*format);
+            }
+            rest = tail;
+        }
+        None
+    }
+
+    /// Average bits per pixel in memory.
+    #[must_use]
+    pub const fn bits_per_pixel(self) -> u32 {
+        match self.packing {
+            BayerPacking::Unpacked if self.bit_depth <= 8 => 8,
+            BayerPacking::Unpacked => 16,
+            BayerPacking::Csi2 => self.bit_depth,
+        }
+    }
+
+    /// Largest sample value.
+    ///
+    /// Bit depths outside `1..=16` saturate: 0 for a zero depth and
+    /// `u16::MAX` above 16.
+    #[must_use]
+    pub const fn max_value(self) -> u16 {
+        match u16::MAX.checked_shr(16u32.saturating_sub(self.bit_depth)) {
+            Some(max) => max,
+            None => 0,
+        }
+    }
+
+    /// Read sample `x` of a line.
+    fn read(self, line: &[u8], x: usize) -> Option<u16> {
+        let sample = match (self.packing, self.bit_depth) {
+            (BayerPacking::Unpacked, 8) => u16::from(*line.get(x)?),
+            (BayerPacking::Unpacked, _) => {
+                u16::from_le_bytes(line.get(x * 2..x * 2 + 2)?.try_into().ok()?)
+            }
+            (BayerPacking::Csi2, 10) => {
+                let group = x / 4 * 5;
+                let high = *line.get(group + x % 4)?;
+                let low = *line.get(group + 4)?;
+                u16::from(high) << 2 | u16::from(low >> (x % 4 * 2)) & 0x3
+            }
+            (BayerPacking::Csi2, 12) => {
+                let group = x / 2 * 3;
+                let high = *line.get(group + x % 2)?;
+                let low = *line.get(group + 2)?;
+                u16::from(high) << 4 | u16::from(low >> (x % 2 * 4)) & 0xf
+            }
+            (BayerPacking::Csi2, _) => return None,
+        };
+        Some(sample & self.max_value())
+    }
+
+    /// Write sample `x` of a line, returning whether it fit.
+    fn write(self, line: &mut [u8], x: usize, sample: u16) -> bool {
+        let [low_byte, high_byte] = sample.to_le_bytes();
+        match (self.packing, self.bit_depth) {
+            (BayerPacking::Unpacked, 8) => line.get_mut(x).map(|byte| *byte = low_byte),
+            (BayerPacking::Unpacked, _) => line
+                .get_mut(x * 2..x * 2 + 2)
+                .map(|bytes| bytes.copy_from_slice(&[low_byte, high_byte])),
+            (BayerPacking::Csi2, 10) => {
+                let group = x / 4 * 5;
+                let shift = x % 4 * 2;
+                write_csi2(line, group + x % 4, group + 4, sample, 2, shift)
+            }
+            (BayerPacking::Csi2, 12) => {
+                let group = x / 2 * 3;
+                let shift = x % 2 * 4;
+                write_csi2(line, group + x % 2, group + 2, sample, 4, shift)
+            }
+            (BayerPacking::Csi2, _) => None,
+        }
+        .is_some()
+    }
+}
+
+/// Store the high bits of a CSI-2 sample and merge its `low_bits` low bits
+/// into the shared byte at `shift`.
+fn write_csi2(
+    line: &mut [u8],
+    high_at: usize,
+    low_at: usize,
+    sample: u16,
+    low_bits: u32,
+    shift: usize,
+) -> Option<()> {
+    let mask = (1 << low_bits) - 1;
+    let [high, _] = (sample >> low_bits).to_le_bytes();
+    let [low, _] = (sample & mask).to_le_bytes();
+    let [mask, _] = mask.to_le_bytes();
+    *line.get_mut(high_at)? = high;
+    let shared = line.get_mut(low_at)?;
+    *shared = *shared & !(mask << shift) | low << shift;
+    Some(())
+}
+
+/// Demosaicing algorithm.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Demosaic {
+    /// Average the nearest samples of each missing color.
+    ///
+    /// Fast, but edges get soft and pick up color fringes.
+    #[default]
+    Bilinear,
+    /// Interpolate green along edges rather than across them
+    /// (Hamilton-Adams), then red and blue from their difference to green.
+    EdgeAware,
+}
+
+/// Offsets of the eight neighbors of a pixel.
+const NEIGHBORS: [(isize, isize); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+/// A raw Bayer image, one sample per pixel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawImage {
+    /// Width in pixels.
+    pub width: u32,
+    /// Height in pixels.
+    pub height: u32,
+    /// Sample layout, of which the CFA pattern and bit depth apply here.
+    pub format: BayerFormat,
+    /// Samples in row-major order, `width * height` of them.
+    pub samples: Vec<u16>,
+}
+
+impl RawImage {
+    /// Unpack a raw Bayer frame laid out as `format`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `FormatNotSupported` if `format` is not a Bayer format, or
+    /// `StreamError` if `data` is shorter than the format describes.
+    pub fn unpack(data: &[u8], format: &Format) -> Result<Self> {
+        let bayer = bayer_format(format)?;
+        let width = format.width as usize;
+        let samples: Option<Vec<u16>> = (0..format.height as usize)
+            .flat_map(|y| {
+                let line = data.get(y * format.stride as usize..).unwrap_or_default();
+                (0..width).map(move |x| bayer.read(line, x))
+            })
+            .collect();
+        Ok(Self {
+            width: format.width,
+            height: format.height,
+            format: bayer,
+            samples: samples.ok_or_else(|| too_short(data, format))?,
+        })
+    }
+
+    /// Pack the image into a frame buffer laid out as `format`.
+    ///
+    /// The buffer is `format.size` bytes long; line padding is left zero.
+    ///
+    /// # Errors
+    ///
+    /// Returns `FormatNotSupported` if `format` is not a Bayer format, or
+    /// `StreamError` if its dimensions differ from the image.
+    pub fn pack(&self, format: &Format) -> Result<Vec<u8>> {
+        let bayer = bayer_format(format)?;
+        if (format.width, format.height) != (self.width, self.height) {
+            return Err(CameraError::StreamError(format!(
+                "Cannot pack a {}x{} image as {}x{} {}",
+                self.width, self.height, format.width, format.height, format.fourcc
+            )));
+        }
+        let mut data = vec![0; format.size as usize];
+        let lines = data.chunks_mut(format.stride.max(1) as usize);
+        let rows = self.samples.chunks(self.width.max(1) as usize);
+        let packed = lines.zip(rows).all(|(line, row)| {
+            row.iter()
+                .enumerate()
+                .all(|(x, &sample)| bayer.write(line, x, sample))
+        });
+        if packed {
+            Ok(data)
+        } else {
+            Err(CameraError::StreamError(format!(
+                "Frame size {} too small for {}x{} {}",
+                format.size, format.width, format.height, format.fourcc
+            )))
+        }
+    }
+
+    /// Interpolate the full RGB color of every pixel.
+    ///
+    /// Returns RGB triplets at the bit depth of the samples.
+    #[must_use]
+    pub fn demosaic(&self, method: Demosaic) -> Vec<u16> {
+        match method {
+            Demosaic::Bilinear => self.bilinear(),
+            Demosaic::EdgeAware => self.edge_aware(),
+        }
+    }
+
+    /// Demosaic to tightly packed 8-bit RGB, keeping the high bits.
+    #[must_use]
+    pub fn to_rgb24(&self, method: Demosaic) -> Vec<u8> {
+        let shift = self.format.bit_depth.saturating_sub(8);
+        self.demosaic(method)
+            .iter()
+            .map(|&value| to_u8(value, shift))
+            .collect()
+    }
+
+    /// Sample at (`x`, `y`) moved by the given offsets, mirrored at the
+    /// borders so that it keeps its CFA color.
+    fn sample_near(&self, x: usize, y: usize, dx: isize, dy: isize) -> i32 {
+        let width = self.width as usize;
+        let x = mirror(x, dx, width);
+        let y = mirror(y, dy, self.height as usize);
+        self.samples
+            .get(y * width + x)
+            .map_or(0, |&sample| i32::from(sample))
+    }
+
+    /// Bilinear demosaic of the whole image.
+    fn bilinear(&self) -> Vec<u16> {
+        let width = self.width as usize;
+        let height = self.height as usize;
+        let sample = |x: usize, y: usize| self.samples.get(y * width + x).copied();
+        (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .flat_map(|(x, y)| {
+                bilinear_at(self.format.pattern, width, height, x, y, sample).unwrap_or_default()
+            })
+            .collect()
+    }
+
+    /// Edge-aware demosaic of the whole image.
+    fn edge_aware(&self) -> Vec<u16> {
+        let width = self.width as usize;
+        let height = self.height as usize;
+        let green: Vec<i32> = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| self.green_at(x, y))
+            .collect();
+        (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .flat_map(|(x, y)| self.edge_aware_at(&green, x, y))
+            .collect()
+    }
+
+    /// Green at (`x`, `y`), interpolated along the smoother direction with
+    /// a correction from the curvature of the pixel's own color.
+    fn green_at(&self, x: usize, y: usize) -> i32 {
+        let center = self.sample_near(x, y, 0, 0);
+        if self.color(x, y) == CfaColor::Green {
+            return center;
+        }
+        let along = |dx: isize, dy: isize| {
+            let near = (
+                self.sample_near(x, y, -dx, -dy),
+                self.sample_near(x, y, dx, dy),
+            );
+            let far = (
+                self.sample_near(x, y, -2 * dx, -2 * dy),
+                self.sample_near(x, y, 2 * dx, 2 * dy),
+            );
+            let curvature = 2 * center - far.0 - far.1;
+            let gradient = (near.0 - near.1).abs() + curvature.abs();
+            (gradient, (near.0 + near.1) / 2 + curvature / 4)
+        };
+        let (horizontal_gradient, horizontal) = along(1, 0);
+        let (vertical_gradient, vertical) = along(0, 1);
+        let green = match horizontal_gradient.cmp(&vertical_gradient) {
+            std::cmp::Ordering::Less => horizontal,
+            std::cmp::Ordering::Greater => vertical,
+            std::cmp::Ordering::Equal => (horizontal + vertical) / 2,
+        };
+        green.clamp(0, i32::from(self.format.max_value()))
+    }
+
+    /// RGB at (`x`, `y`), with red and blue interpolated from their
+    /// difference to the interpolated `green` plane.
+    fn edge_aware_at(&self, green: &[i32], x: usize, y: usize) -> [u16; 3] {
+        let own_green = green_value(green, self.width as usize, x, y);
+        let native = self.color(x, y);
+        [CfaColor::Red, CfaColor::Green, CfaColor::Blue].map(|color| {
+            let value = if color == CfaColor::Green {
+                own_green
+            } else if color == native {
+                self.sample_near(x, y, 0, 0)
+            } else {
+                own_green + self.mean_difference(green, x, y, color)
+            };
+            clamp_sample(value, self.format.max_value())
+        })
+    }
+
+    /// Mean difference between the samples of `color` around (`x`, `y`)
+    /// and the green interpolated at the same positions.
+    fn mean_difference(&self, green: &[i32], x: usize, y: usize, color: CfaColor) -> i32 {
+        let width = self.width as usize;
+        let height = self.height as usize;
+        let (sum, count) = NEIGHBORS
+            .iter()
+            .map(|&(dx, dy)| (dx, dy, mirror(x, dx, width), mirror(y, dy, height)))
+            .filter(|&(_, _, nx, ny)| self.color(nx, ny) == color)
+            .fold((0, 0), |(sum, count), (dx, dy, nx, ny)| {
+                let difference = self.sample_near(x, y, dx, dy) - green_value(green, width, nx, ny);
+                (sum + difference, count + 1)
+            });
+        sum.checked_div(count).unwrap_or(0)
+    }
+
+    /// CFA color at (`x`, `y`).
+    const fn color(&self, x: usize, y: usize) -> CfaColor {
+        self.format.pattern.color(x % 2 == 1, y % 2 == 1)
+    }
+}
+
+/// Demosaic a raw Bayer frame to tightly packed 8-bit RGB.
+///
+/// # Errors
+///
+/// Returns the errors of [`RawImage::unpack`].
+pub fn to_rgb24(data: &[u8], format: &Format, method: Demosaic) -> Result<Vec<u8>> {
+    Ok(RawImage::unpack(data, format)?.to_rgb24(method))
+}
+
+/// Bilinearly demosaiced RGB of the pixel at (`x`, `y`) of a raw Bayer frame.
+///
+/// Returns `None` if `format` is not Bayer or the pixel lies outside `data`.
+pub(crate) fn pixel(data: &[u8], x: u32, y: u32, format: &Format) -> Option<(u8, u8, u8)> {
+    let bayer = BayerFormat::from_fourcc(format.fourcc)?;
+    let stride = format.stride as usize;
+    let sample = |x: usize, y: usize| bayer.read(data.get(y * stride..)?, x);
+    let [red, green, blue] = bilinear_at(
+        bayer.pattern,
+        format.width as usize,
+        format.height as usize,
+        x as usize,
+        y as usize,
+        sample,
+    )?;
+    let shift = bayer.bit_depth.saturating_sub(8);
+    Some((to_u8(red, shift), to_u8(green, shift), to_u8(blue, shift)))
+}
+
+/// Bilinear RGB at (`x`, `y`): the pixel's own sample, and the mean of the
+/// neighboring samples of each other color.
+fn bilinear_at<F>(
+    pattern: CfaPattern,
+    width: usize,
+    height: usize,
+    x: usize,
+    y: usize,
+    sample: F,
+) -> Option<[u16; 3]>
+where
+    F: Fn(usize, usize) -> Option<u16>,
+{
+    let mut sums = [0u32; 3];
+    let mut counts = [0u32; 3];
+    for (dx, dy) in NEIGHBORS {
+        let (nx, ny) = (mirror(x, dx, width), mirror(y, dy, height));
+        let color = pattern.color(nx % 2 == 1, ny % 2 == 1).index();
+        let value = sample(nx, ny)?;
+        if let (Some(sum), Some(count)) = (sums.get_mut(color), counts.get_mut(color)) {
+            *sum += u32::from(value);
+            *count += 1;
+        }
+    }
+
+    let own = sample(x, y)?;
+    let native = pattern.color(x % 2 == 1, y % 2 == 1).index();
+    let mut pixel = [0; 3];
+    for (index, ((slot, sum), count)) in pixel.iter_mut().zip(sums).zip(counts).enumerate() {
+        *slot = if index == native {
+            own
+        } else {
+            u16::try_from(sum.checked_div(count).unwrap_or(0)).unwrap_or(u16::MAX)
+        };
+    }
+    Some(pixel)
+}
+
+/// Reflect `pos + offset` back into `0..len` at the borders.
+///
+/// Reflection keeps the parity of the position, and with it the CFA color.
+fn mirror(pos: usize, offset: isize, len: usize) -> usize {
+    let last = len.saturating_sub(1);
+    let reflected = match pos.checked_add_signed(offset) {
+        None => offset.unsigned_abs() - pos,
+        Some(target) if target > last => (2 * last).saturating_sub(target),
+        Some(target) => target,
+    };
+    reflected.min(last)
+}
+
+/// Value of a `width`-pixel wide plane at (`x`, `y`).
+fn green_value(plane: &[i32], width: usize, x: usize, y: usize) -> i32 {
+    plane.get(y * width + x).copied().unwrap_or(0)
+}
+
+/// Clamp an interpolated value to the sample range.
+fn clamp_sample(value: i32, max: u16) -> u16 {
+    u16::try_from(value.clamp(0, i32::from(max))).unwrap_or(max)
+}
+
+/// Reduce a sample to 8 bits by dropping `shift` low bits.
+fn to_u8(value: u16, shift: u32) -> u8 {
+    u8::try_from(value.checked_shr(shift).unwrap_or(0)).unwrap_or(u8::MAX)
+}
+
+/// Look up the Bayer layout of `format`.
+fn bayer_format(format: &Format) -> Result<BayerFormat> {
+    BayerFormat::from_fourcc(format.fourcc).ok_or_else(|| CameraError::FormatNotSupported {
+        format: format.clone(),
+        reason: "is not a raw Bayer format".to_owned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A raw image of `pattern` whose samples come from `color(x, y)`.
+    fn raw_image<F>(format: &Format, color: F) -> RawImage
+    where
+        F: Fn(u32, u32) -> [u16; 3],
+    {
+        let bayer = BayerFormat::from_fourcc(format.fourcc).expect("format should be Bayer");
+        let samples = (0..format.height)
+            .flat_map(|y| (0..format.width).map(move |x| (x, y)))
+            .map(|(x, y)| color(x, y)[bayer.pattern.color_at(x, y).index()])
+            .collect();
+        RawImage {
+            width: format.width,
+            height: format.height,
+            format: bayer,
+            samples,
+        }
+    }
+
+    #[test]
+    fn test_cfa_patterns() {
+        assert_eq!(CfaPattern::Rggb.color_at(0, 0), CfaColor::Red);
+        assert_eq!(CfaPattern::Rggb.color_at(3, 3), CfaColor::Blue);
+        assert_eq!(CfaPattern::Bggr.color_at(1, 1), CfaColor::Red);
+        assert_eq!(CfaPattern::Gbrg.color_at(1, 0), CfaColor::Blue);
+        assert_eq!(CfaPattern::Grbg.color_at(0, 1), CfaColor::Blue);
+        assert_eq!(CfaPattern::Grbg.color_at(1, 1), CfaColor::Green);
+    }
+
+    #[test]
+    fn test_formats() {
+        let format = BayerFormat::from_fourcc(FourCC::SRGGB10P).expect("SRGGB10P should be Bayer");
+        assert_eq!(format.pattern, CfaPattern::Rggb);
+        assert_eq!(format.bits_per_pixel(), 10);
+        assert_eq!(format.max_value(), 1023);
+        let zero = BayerFormat {
+            bit_depth: 0,
+            ..format
+        };
+        assert_eq!(zero.max_value(), 0);
+        let wide = BayerFormat {
+            bit_depth: 20,
+            ..format
+        };
+        assert_eq!(wide.max_value(), u16::MAX);
+        let raw = RawImage {
+            width: 2,
+            height: 2,
+            format: BayerFormat {
+                bit_depth: 32,
+                ..format
+            },
+            samples: vec![u16::MAX; 4],
+        };
+        assert_eq!(raw.to_rgb24(Demosaic::Bilinear), vec![0; 12]);
+        assert_eq!(
+            BayerFormat::from_fourcc(FourCC::SGRBG12).map(BayerFormat::bits_per_pixel),
+            Some(16)
+        );
+        assert!(BayerFormat::from_fourcc(FourCC::YUYV).is_none());
+        for (fourcc, _) in BAYER_FORMATS {
+            assert!(fourcc.info().is_some(), "{fourcc} missing from the table");
+        }
+    }
+
+    #[test]
+    fn test_unpack_csi2() {
+        // High bits first, then the low bits of all four pixels in one byte
+        let format = Format::new(4, 1, FourCC::SRGGB10P);
+        let data = [0xff, 0x00, 0x55, 0xaa, 0b10_01_00_11];
+        let raw = RawImage::unpack(&data, &format).expect("frame should unpack");
+        assert_eq!(raw.samples, [0x3ff, 0x000, 0x155, 0x2aa]);
+        assert_eq!(raw.pack(&format).expect("image should pack"), data);
+
+        let format = Format::new(2, 1, FourCC::SBGGR12P);
+        let data = [0xab, 0x12, 0x3c];
+        let raw = RawImage::unpack(&data, &format).expect("frame should unpack");
+        assert_eq!(raw.samples, [0xabc, 0x123]);
+        assert_eq!(raw.pack(&format).expect("image should pack"), data);
+    }
+
+    #[test]
+    fn test_pack_round_trip() {
+        for (fourcc, bayer) in BAYER_FORMATS {
+            let format = Format::new(8, 4, fourcc);
+            let max = u32::from(bayer.max_value());
+            let raw = raw_image(&format, |x, y| {
+                let value = u16::try_from((x * 37 + y * 101) * 977 % (max + 1))
+                    .expect("sample should fit u16");
+                [value; 3]
+            });
+            let data = raw.pack(&format).expect("image should pack");
+            assert_eq!(data.len(), format.size as usize, "{fourcc}");
+            assert_eq!(
+                RawImage::unpack(&data, &format).expect("frame should unpack"),
+                raw,
+                "{fourcc}"
+            );
+        }
+
+        let format = Format::new(8, 4, FourCC::SRGGB10P);
+        let result = RawImage::unpack(&[0; 30], &format);
+        assert!(matches!(result, Err(CameraError::StreamError(_))));
+        let result = RawImage::unpack(&[0; 64], &Format::new(8, 4, FourCC::YUYV));
+        assert!(matches!(
+            result,
+            Err(CameraError::FormatNotSupported { .. })
+        ));
+    }
+
+    #[test]
+    fn test_demosaic_flat_color() {
+        for fourcc in [
+            FourCC::SBGGR8,
+            FourCC::SGBRG10,
+            FourCC::SGRBG12P,
+            FourCC::SRGGB16,
+        ] {
+            let format = Format::new(6, 4, fourcc);
+            let raw = raw_image(&format, |_, _| [40, 90, 20]);
+            for method in [Demosaic::Bilinear, Demosaic::EdgeAware] {
+                let rgb = raw.demosaic(method);
+                assert_eq!(rgb.len(), 6 * 4 * 3);
+                assert!(
+                    rgb.chunks_exact(3).all(|pixel| pixel == [40, 90, 20]),
+                    "{fourcc} {method:?}: {rgb:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_pixel_matches_demosaic() {
+        let format = Format::new(10, 6, FourCC::SGRBG10P);
+        let raw = raw_image(&format, |x, y| {
+            let [x, y] =
+                [x, y].map(|value| u16::try_from(value).expect("coordinate should fit u16"));
+            [x * 90, y * 150, 500]
+        });
+        let data = raw.pack(&format).expect("image should pack");
+        let rgb = raw.to_rgb24(Demosaic::Bilinear);
+        let positions = (0..6).flat_map(|y| (0..10).map(move |x| (x, y)));
+        for ((x, y), expected) in positions.zip(rgb.chunks_exact(3)) {
+            let actual = pixel(&data, x, y, &format).expect("pixel should be in bounds");
+            assert_eq!(<[u8; 3]>::from(actual), expected, "pixel ({x}, {y})");
+        }
+    }
+
+    #[test]
+    fn test_edge_aware_reduces_fringes() {
+        // A sharp vertical edge between dark and light grey
+        let format = Format::new(16, 8, FourCC::SRGGB8);
+        let raw = raw_image(&format, |x, _| [if x < 7 { 30 } else { 220 }; 3]);
+        let fringes = |method| -> u32 {
+            raw.demosaic(method)
+                .chunks_exact(3)
+                .map(|pixel| {
+                    u32::from(pixel[0].abs_diff(pixel[1])) + u32::from(pixel[2].abs_diff(pixel[1]))
+                })
+                .sum()
+        };
+        let bilinear = fringes(Demosaic::Bilinear);
+        let edge_aware = fringes(Demosaic::EdgeAware);
+        assert!(
+            edge_aware * 2 < bilinear,
+            "edge-aware {edge_aware}, bilinear {bilinear}"
+        );
+    }
+
+    #[test]
+    fn test_mirror() {
+        assert_eq!(mirror(0, -1, 8), 1);
+        assert_eq!(mirror(1, -2, 8), 1);
+        assert_eq!(mirror(7, 1, 8), 6);
+        assert_eq!(mirror(6, 2, 8), 6);
+        assert_eq!(mirror(0, 1, 1), 0);
+    }
+}
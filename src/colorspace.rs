@@ -0,0 +1,401 @@
+//! Colorimetry metadata of a V4L2 format.
+//!
+//! Mirrors the `colorspace`, `xfer_func`, `ycbcr_enc`, `quantization` and
+//! `field` members of `struct v4l2_pix_format`. Drivers may leave any of them
+//! at `Default`, meaning "whatever the colorspace implies"; the rules V4L2
+//! uses to resolve those defaults are applied by [`Format::colorimetry`] to
+//! pick the YUV to RGB conversion for a format.
+//!
+//! Variants carry their V4L2 values as discriminants, which is also how
+//! recordings store them.
+//!
+//! [`Format::colorimetry`]: crate::traits::Format::colorimetry
+
+use crate::convert::{YuvMatrix, YuvRange};
+
+/// Color primaries and default encodings of an image (`V4L2_COLORSPACE_*`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Colorspace {
+    /// Chosen by the driver.
+    #[default]
+    Default = 0,
+    /// SMPTE 170M, used by SDTV.
+    Smpte170m = 1,
+    /// SMPTE 240M, an obsolete HDTV standard.
+    Smpte240m = 2,
+    /// Rec. 709, used by HDTV.
+    Rec709 = 3,
+    /// NTSC 1953.
+    Ntsc = 5,
+    /// EBU Tech. 3213, used by PAL/SECAM.
+    Ebu = 6,
+    /// JPEG: sRGB primaries with BT.601 encoding and full range.
+    Jpeg = 7,
+    /// sRGB.
+    Srgb = 8,
+    /// opRGB (Adobe RGB).
+    Oprgb = 9,
+    /// Rec. 2020, used by UHDTV.
+    Rec2020 = 10,
+    /// Raw sensor data without defined primaries.
+    Raw = 11,
+    /// DCI-P3, used by digital cinema.
+    DciP3 = 12,
+}
+
+/// Transfer function of an image (`V4L2_XFER_FUNC_*`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum TransferFunction {
+    /// Implied by the colorspace.
+    #[default]
+    Default = 0,
+    /// Rec. 709.
+    Rec709 = 1,
+    /// sRGB.
+    Srgb = 2,
+    /// opRGB.
+    Oprgb = 3,
+    /// SMPTE 240M.
+    Smpte240m = 4,
+    /// Linear, no transfer function applied.
+    None = 5,
+    /// DCI-P3.
+    DciP3 = 6,
+    /// SMPTE ST 2084 (PQ).
+    Smpte2084 = 7,
+}
+
+/// YUV encoding of an image (`V4L2_YCBCR_ENC_*`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum YcbcrEncoding {
+    /// Implied by the colorspace.
+    #[default]
+    Default = 0,
+    /// ITU-R BT.601.
+    Bt601 = 1,
+    /// Rec. 709.
+    Bt709 = 2,
+    /// xvYCC with BT.601 coefficients.
+    Xv601 = 3,
+    /// xvYCC with Rec. 709 coefficients.
+    Xv709 = 4,
+    /// BT.2020 non-constant luminance.
+    Bt2020 = 6,
+    /// BT.2020 constant luminance.
+    Bt2020ConstLum = 7,
+    /// SMPTE 240M.
+    Smpte240m = 8,
+}
+
+/// Quantization range of an image (`V4L2_QUANTIZATION_*`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum Quantization {
+    /// Implied by the colorspace and pixel format.
+    #[default]
+    Default = 0,
+    /// Full range, 0-255 for 8-bit samples.
+    FullRange = 1,
+    /// Limited range, 16-235 for luma and 16-240 for chroma.
+    LimitedRange = 2,
+}
+
+/// Field order of an image (`V4L2_FIELD_*`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum FieldOrder {
+    /// Chosen by the driver.
+    #[default]
+    Any = 0,
+    /// Progressive, not interlaced.
+    None = 1,
+    /// Top field only.
+    Top = 2,
+    /// Bottom field only.
+    Bottom = 3,
+    /// Both fields interlaced line by line.
+    Interlaced = 4,
+    /// Top field stored first, then the bottom field.
+    SequentialTopBottom = 5,
+    /// Bottom field stored first, then the top field.
+    SequentialBottomTop = 6,
+    /// Alternating fields, one per buffer.
+    Alternate = 7,
+    /// Both fields interlaced, top field transmitted first.
+    InterlacedTopBottom = 8,
+    /// Both fields interlaced, bottom field transmitted first.
+    InterlacedBottomTop = 9,
+}
+
+impl Colorspace {
+    /// V4L2 value of this colorspace, as stored in recordings.
+    pub(crate) const fn code(self) -> u8 {
+        self as u8
+    }
+
+    /// The colorspace with V4L2 value `code`, if known.
+    pub(crate) const fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(Self::Default),
+            1 => Some(Self::Smpte170m),
+            2 => Some(Self::Smpte240m),
+            3 => Some(Self::Rec709),
+            5 => Some(Self::Ntsc),
+            6 => Some(Self::Ebu),
+            7 => Some(Self::Jpeg),
+            8 => Some(Self::Srgb),
+            9 => Some(Self::Oprgb),
+            10 => Some(Self::Rec2020),
+            11 => Some(Self::Raw),
+            12 => Some(Self::DciP3),
+            _ => None,
+        }
+    }
+}
+
+impl TransferFunction {
+    /// V4L2 value of this transfer function, as stored in recordings.
+    pub(crate) const fn code(self) -> u8 {
+        self as u8
+    }
+
+    /// The transfer function with V4L2 value `code`, if known.
+    pub(crate) const fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(Self::Default),
+            1 => Some(Self::Rec709),
+            2 => Some(Self::Srgb),
+            3 => Some(Self::Oprgb),
+            4 => Some(Self::Smpte240m),
+            5 => Some(Self::None),
+            6 => Some(Self::DciP3),
+            7 => Some(Self::Smpte2084),
+            _ => None,
+        }
+    }
+}
+
+impl YcbcrEncoding {
+    /// V4L2 value of this encoding, as stored in recordings.
+    pub(crate) const fn code(self) -> u8 {
+        self as u8
+    }
+
+    /// The encoding with V4L2 value `code`, if known.
+    pub(crate) const fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(Self::Default),
+            1 => Some(Self::Bt601),
+            2 => Some(Self::Bt709),
+            3 => Some(Self::Xv601),
+            4 => Some(Self::Xv709),
+            6 => Some(Self::Bt2020),
+            7 => Some(Self::Bt2020ConstLum),
+            8 => Some(Self::Smpte240m),
+            _ => None,
+        }
+    }
+
+    /// Encoding implied by `colorspace` (`V4L2_MAP_YCBCR_ENC_DEFAULT`).
+    #[must_use]
+    pub const fn for_colorspace(colorspace: Colorspace) -> Self {
+        match colorspace {
+            Colorspace::Rec709 | Colorspace::DciP3 => Self::Bt709,
+            Colorspace::Rec2020 => Self::Bt2020,
+            Colorspace::Smpte240m => Self::Smpte240m,
+            _ => Self::Bt601,
+        }
+    }
+
+    /// Resolve `Default` to the encoding implied by `colorspace`.
+    #[must_use]
+    pub const fn resolve(self, colorspace: Colorspace) -> Self {
+        match self {
+            Self::Default => Self::for_colorspace(colorspace),
+            encoding => encoding,
+        }
+    }
+
+    /// Conversion matrix for this encoding.
+    ///
+    /// SMPTE 240M coefficients are within 0.001 of Rec. 709 and share its
+    /// matrix; `Default` is treated as BT.601.
+    #[must_use]
+    pub const fn matrix(self) -> YuvMatrix {
+        match self {
+            Self::Default | Self::Bt601 | Self::Xv601 => YuvMatrix::Bt601,
+            Self::Bt709 | Self::Xv709 | Self::Smpte240m => YuvMatrix::Bt709,
+            Self::Bt2020 | Self::Bt2020ConstLum => YuvMatrix::Bt2020,
+        }
+    }
+}
+
+impl Quantization {
+    /// V4L2 value of this quantization, as stored in recordings.
+    pub(crate) const fn code(self) -> u8 {
+        self as u8
+    }
+
+    /// The quantization with V4L2 value `code`, if known.
+    pub(crate) const fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(Self::Default),
+            1 => Some(Self::FullRange),
+            2 => Some(Self::LimitedRange),
+            _ => None,
+        }
+    }
+
+    /// Resolve `Default` to the range implied by the colorspace and whether
+    /// the pixel format is RGB (`V4L2_MAP_QUANTIZATION_DEFAULT`).
+    #[must_use]
+    pub const fn resolve(self, colorspace: Colorspace, is_rgb: bool) -> Self {
+        match self {
+            Self::Default if is_rgb || matches!(colorspace, Colorspace::Jpeg) => Self::FullRange,
+            Self::Default => Self::LimitedRange,
+            quantization => quantization,
+        }
+    }
+
+    /// Conversion range for this quantization; `Default` is treated as
+    /// limited.
+    #[must_use]
+    pub const fn range(self) -> YuvRange {
+        match self {
+            Self::FullRange => YuvRange::Full,
+            Self::Default | Self::LimitedRange => YuvRange::Limited,
+        }
+    }
+}
+
+impl FieldOrder {
+    /// V4L2 value of this field order, as stored in recordings.
+    pub(crate) const fn code(self) -> u8 {
+        self as u8
+    }
+
+    /// The field order with V4L2 value `code`, if known.
+    pub(crate) const fn from_code(code: u8) -> Option<Self> {
+        match code {
+            0 => Some(Self::Any),
+            1 => Some(Self::None),
+            2 => Some(Self::Top),
+            3 => Some(Self::Bottom),
+            4 => Some(Self::Interlaced),
+            5 => Some(Self::SequentialTopBottom),
+            6 => Some(Self::SequentialBottomTop),
+            7 => Some(Self::Alternate),
+            8 => Some(Self::InterlacedTopBottom),
+            9 => Some(Self::InterlacedBottomTop),
+            _ => None,
+        }
+    }
+}
+
+impl From<v4l::format::Colorspace> for Colorspace {
+    fn from(colorspace: v4l::format::Colorspace) -> Self {
+        use v4l::format::Colorspace as V4l;
+        match colorspace {
+            V4l::Default => Self::Default,
+            V4l::SMPTE170M => Self::Smpte170m,
+            V4l::SMPTE240M => Self::Smpte240m,
+            V4l::Rec709 => Self::Rec709,
+            V4l::NTSC => Self::Ntsc,
+            V4l::EBUTech3212 => Self::Ebu,
+            V4l::JPEG => Self::Jpeg,
+            V4l::SRGB => Self::Srgb,
+            V4l::OPRGB => Self::Oprgb,
+            V4l::Rec2020 => Self::Rec2020,
+            V4l::RAW => Self::Raw,
+            V4l::DCIP3 => Self::DciP3,
+        }
+    }
+}
+
+impl From<v4l::format::TransferFunction> for TransferFunction {
+    fn from(xfer: v4l::format::TransferFunction) -> Self {
+        use v4l::format::TransferFunction as V4l;
+        match xfer {
+            V4l::Default => Self::Default,
+            V4l::Rec709 => Self::Rec709,
+            V4l::SRGB => Self::Srgb,
+            V4l::OPRGB => Self::Oprgb,
+            V4l::SMPTE240M => Self::Smpte240m,
+            V4l::None => Self::None,
+            V4l::DCIP3 => Self::DciP3,
+            V4l::SMPTE2084 => Self::Smpte2084,
+        }
+    }
+}
+
+impl From<v4l::format::Quantization> for Quantization {
+    fn from(quantization: v4l::format::Quantization) -> Self {
+        use v4l::format::Quantization as V4l;
+        match quantization {
+            V4l::Default => Self::Default,
+            V4l::FullRange => Self::FullRange,
+            V4l::LimitedRange => Self::LimitedRange,
+        }
+    }
+}
+
+impl From<v4l::format::FieldOrder> for FieldOrder {
+    fn from(field: v4l::format::FieldOrder) -> Self {
+        use v4l::format::FieldOrder as V4l;
+        match field {
+            V4l::Any => Self::Any,
+            V4l::Progressive => Self::None,
+            V4l::Top => Self::Top,
+            V4l::Bottom => Self::Bottom,
+            V4l::Interlaced => Self::Interlaced,
+            V4l::SequentialTB => Self::SequentialTopBottom,
+            V4l::SequentialBT => Self::SequentialBottomTop,
+            V4l::Alternate => Self::Alternate,
+            V4l::InterlacedTB => Self::InterlacedTopBottom,
+            V4l::InterlacedBT => Self::InterlacedBottomTop,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ycbcr_encoding_defaults() {
+        let cases = [
+            (Colorspace::Default, YuvMatrix::Bt601),
+            (Colorspace::Srgb, YuvMatrix::Bt601),
+            (Colorspace::Jpeg, YuvMatrix::Bt601),
+            (Colorspace::Rec709, YuvMatrix::Bt709),
+            (Colorspace::DciP3, YuvMatrix::Bt709),
+            (Colorspace::Rec2020, YuvMatrix::Bt2020),
+        ];
+        for (colorspace, matrix) in cases {
+            assert_eq!(
+                YcbcrEncoding::Default.resolve(colorspace).matrix(),
+                matrix,
+                "{colorspace:?}"
+            );
+        }
+        assert_eq!(
+            YcbcrEncoding::Bt709.resolve(Colorspace::Srgb),
+            YcbcrEncoding::Bt709
+        );
+    }
+
+    #[test]
+    fn test_quantization_defaults() {
+        let yuv = |colorspace| Quantization::Default.resolve(colorspace, false);
+        assert_eq!(yuv(Colorspace::Srgb), Quantization::LimitedRange);
+        assert_eq!(yuv(Colorspace::Rec709), Quantization::LimitedRange);
+        assert_eq!(yuv(Colorspace::Jpeg), Quantization::FullRange);
+        assert_eq!(
+            Quantization::Default.resolve(Colorspace::Srgb, true),
+            Quantization::FullRange
+        );
+        assert_eq!(
+            Quantization::LimitedRange.resolve(Colorspace::Jpeg, false),
+            Quantization::LimitedRange
+        );
+        assert_eq!(Quantization::FullRange.range(), YuvRange::Full);
+    }
+}
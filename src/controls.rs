@@ -0,0 +1,349 @@
+//! Camera controls (exposure, gain, white balance, etc.).
+//!
+//! Controls are identified by their V4L2 control ID. Well-known IDs are
+//! available in the [`cid`] module; drivers may expose additional private
+//! controls, which can be discovered with `CameraDevice::controls`.
+
+use std::fmt;
+use std::ops::BitOr;
+
+/// Well-known V4L2 control IDs.
+pub mod cid {
+    /// Picture brightness (user class).
+    pub const BRIGHTNESS: u32 = 0x0098_0900;
+    /// Picture contrast (user class).
+    pub const CONTRAST: u32 = 0x0098_0901;
+    /// Color saturation (user class).
+    pub const SATURATION: u32 = 0x0098_0902;
+    /// Hue (user class).
+    pub const HUE: u32 = 0x0098_0903;
+    /// Automatic white balance on/off (user class).
+    pub const AUTO_WHITE_BALANCE: u32 = 0x0098_090c;
+    /// Red chroma balance (user class).
+    pub const RED_BALANCE: u32 = 0x0098_090e;
+    /// Blue chroma balance (user class).
+    pub const BLUE_BALANCE: u32 = 0x0098_090f;
+    /// Gamma adjustment (user class).
+    pub const GAMMA: u32 = 0x0098_0910;
+    /// Exposure time in driver-specific units (user class).
+    pub const EXPOSURE: u32 = 0x0098_0911;
+    /// Automatic gain on/off (user class).
+    pub const AUTOGAIN: u32 = 0x0098_0912;
+    /// Gain (user class).
+    pub const GAIN: u32 = 0x0098_0913;
+    /// Power line frequency filter menu (user class).
+    pub const POWER_LINE_FREQUENCY: u32 = 0x0098_0918;
+    /// White balance color temperature in Kelvin (user class).
+    pub const WHITE_BALANCE_TEMPERATURE: u32 = 0x0098_091a;
+    /// Sharpness (user class).
+    pub const SHARPNESS: u32 = 0x0098_091b;
+    /// Auto exposure mode menu (camera class).
+    pub const EXPOSURE_AUTO: u32 = 0x009a_0901;
+    /// Exposure time in 100 µs units (camera class).
+    pub const EXPOSURE_ABSOLUTE: u32 = 0x009a_0902;
+    /// Vertical blanking in lines (image source class).
+    pub const VBLANK: u32 = 0x009e_0901;
+    /// Horizontal blanking in pixels (image source class).
+    pub const HBLANK: u32 = 0x009e_0902;
+    /// Sensor analogue gain (image source class).
+    pub const ANALOGUE_GAIN: u32 = 0x009e_0903;
+    /// Test pattern menu (image processing class).
+    pub const TEST_PATTERN: u32 = 0x009f_0903;
+    /// Digital gain (image processing class).
+    pub const DIGITAL_GAIN: u32 = 0x009f_0905;
+
+    /// Mask selecting the control class from a control ID.
+    pub const CLASS_MASK: u32 = 0x0fff_0000;
+
+    /// Control class of a control ID.
+    #[must_use]
+    pub const fn class(id: u32) -> u32 {
+        id & CLASS_MASK
+    }
+}
+
+/// Control data type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ControlType {
+    /// 32-bit signed integer.
+    Integer,
+    /// On/off switch.
+    Boolean,
+    /// Menu with named entries.
+    Menu,
+    /// Action triggered on write.
+    Button,
+    /// 64-bit signed integer.
+    Integer64,
+    /// Control class marker (not a settable control).
+    CtrlClass,
+    /// Character string.
+    String,
+    /// Bit mask.
+    Bitmask,
+    /// Menu with integer entries.
+    IntegerMenu,
+    /// Array of unsigned 8-bit values.
+    U8,
+    /// Array of unsigned 16-bit values.
+    U16,
+    /// Array of unsigned 32-bit values.
+    U32,
+    /// Width/height area.
+    Area,
+}
+
+impl From<v4l::control::Type> for ControlType {
+    fn from(typ: v4l::control::Type) -> Self {
+        use v4l::control::Type;
+        match typ {
+            Type::Integer => Self::Integer,
+            Type::Boolean => Self::Boolean,
+            Type::Menu => Self::Menu,
+            Type::Button => Self::Button,
+            Type::Integer64 => Self::Integer64,
+            Type::CtrlClass => Self::CtrlClass,
+            Type::String => Self::String,
+            Type::Bitmask => Self::Bitmask,
+            Type::IntegerMenu => Self::IntegerMenu,
+            Type::U8 => Self::U8,
+            Type::U16 => Self::U16,
+            Type::U32 => Self::U32,
+            Type::Area => Self::Area,
+        }
+    }
+}
+
+/// Control flags as reported by the driver.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ControlFlags(pub u32);
+
+impl ControlFlags {
+    /// No flags set.
+    pub const NONE: Self = Self(0);
+    /// Control is permanently disabled.
+    pub const DISABLED: Self = Self(0x0001);
+    /// Control is temporarily unchangeable (e.g., during streaming).
+    pub const GRABBED: Self = Self(0x0002);
+    /// Control can only be read.
+    pub const READ_ONLY: Self = Self(0x0004);
+    /// Changing this control may affect other controls.
+    pub const UPDATE: Self = Self(0x0008);
+    /// Control is inactive because of the value of another control.
+    pub const INACTIVE: Self = Self(0x0010);
+    /// Control is best represented as a slider.
+    pub const SLIDER: Self = Self(0x0020);
+    /// Control can only be written.
+    pub const WRITE_ONLY: Self = Self(0x0040);
+    /// Control value changes on its own (e.g., under auto mode).
+    pub const VOLATILE: Self = Self(0x0080);
+
+    /// Check whether all flags in `other` are set.
+    #[must_use]
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for ControlFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+/// Label of a menu control entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MenuLabel {
+    /// Named entry of a `Menu` control.
+    Name(String),
+    /// Integer entry of an `IntegerMenu` control.
+    Value(i64),
+}
+
+impl fmt::Display for MenuLabel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Name(name) => write!(f, "{name}"),
+            Self::Value(value) => write!(f, "{value}"),
+        }
+    }
+}
+
+/// A single entry of a menu control.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MenuItem {
+    /// Menu index, used as the control value.
+    pub index: u32,
+    /// Entry label.
+    pub label: MenuLabel,
+}
+
+/// Description of a device control.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ControlInfo {
+    /// Control ID.
+    pub id: u32,
+    /// Human-readable control name.
+    pub name: String,
+    /// Control data type.
+    pub control_type: ControlType,
+    /// Minimum value, inclusive.
+    pub minimum: i64,
+    /// Maximum value, inclusive.
+    pub maximum: i64,
+    /// Value step.
+    pub step: u64,
+    /// Default value.
+    pub default: i64,
+    /// Control flags.
+    pub flags: ControlFlags,
+    /// Menu entries (empty unless the control is a menu).
+    pub menu_items: Vec<MenuItem>,
+}
+
+impl ControlInfo {
+    /// Create an integer control description with no flags.
+    #[must_use]
+    pub fn integer(
+        id: u32,
+        name: &str,
+        minimum: i64,
+        maximum: i64,
+        step: u64,
+        default: i64,
+    ) -> Self {
+        Self {
+            id,
+            name: name.to_owned(),
+            control_type: ControlType::Integer,
+            minimum,
+            maximum,
+            step,
+            default,
+            flags: ControlFlags::NONE,
+            menu_items: Vec::new(),
+        }
+    }
+
+    /// Create a boolean control description with no flags.
+    #[must_use]
+    pub fn boolean(id: u32, name: &str, default: bool) -> Self {
+        Self {
+            control_type: ControlType::Boolean,
+            ..Self::integer(id, name, 0, 1, 1, i64::from(default))
+        }
+    }
+
+    /// Create a menu control description with entries indexed from 0.
+    #[must_use]
+    pub fn menu(id: u32, name: &str, items: &[&str], default: i64) -> Self {
+        let menu_items: Vec<MenuItem> = (0u32..)
+            .zip(items)
+            .map(|(index, item)| MenuItem {
+                index,
+                label: MenuLabel::Name((*item).to_owned()),
+            })
+            .collect();
+        let maximum = i64::try_from(items.len()).unwrap_or(i64::MAX) - 1;
+
+        Self {
+            control_type: ControlType::Menu,
+            menu_items,
+            ..Self::integer(id, name, 0, maximum.max(0), 1, default)
+        }
+    }
+
+    /// Set the flags of this control description.
+    #[must_use]
+    pub const fn with_flags(mut self, flags: ControlFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
+    /// Check whether the control value can be written.
+    #[must_use]
+    pub const fn is_writable(&self) -> bool {
+        !self.flags.contains(ControlFlags::READ_ONLY)
+            && !self.flags.contains(ControlFlags::DISABLED)
+            && !matches!(self.control_type, ControlType::CtrlClass)
+    }
+}
+
+/// Value of a device control.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlValue {
+    /// Integer, menu index or bitmask value.
+    Integer(i64),
+    /// Boolean value.
+    Boolean(bool),
+    /// String value.
+    String(String),
+}
+
+impl ControlValue {
+    /// Integer representation of the value, or `None` for strings.
+    #[must_use]
+    pub const fn as_integer(&self) -> Option<i64> {
+        match *self {
+            Self::Integer(value) => Some(value),
+            Self::Boolean(value) => Some(value as i64),
+            Self::String(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for ControlValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Integer(value) => write!(f, "{value}"),
+            Self::Boolean(value) => write!(f, "{value}"),
+            Self::String(value) => write!(f, "\"{value}\""),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_control_class() {
+        assert_eq!(cid::class(cid::BRIGHTNESS), 0x0098_0000);
+        assert_eq!(cid::class(cid::EXPOSURE_ABSOLUTE), 0x009a_0000);
+        assert_ne!(cid::class(cid::GAIN), cid::class(cid::ANALOGUE_GAIN));
+    }
+
+    #[test]
+    fn test_control_flags() {
+        let flags = ControlFlags::READ_ONLY | ControlFlags::VOLATILE;
+        assert!(flags.contains(ControlFlags::READ_ONLY));
+        assert!(flags.contains(ControlFlags::VOLATILE));
+        assert!(!flags.contains(ControlFlags::SLIDER));
+        assert!(flags.contains(ControlFlags::NONE));
+    }
+
+    #[test]
+    fn test_menu_control() {
+        let info = ControlInfo::menu(
+            cid::POWER_LINE_FREQUENCY,
+            "Power Line",
+            &["Off", "50 Hz", "60 Hz"],
+            1,
+        );
+        assert_eq!(info.control_type, ControlType::Menu);
+        assert_eq!(info.maximum, 2);
+        assert_eq!(
+            info.menu_items[2].label,
+            MenuLabel::Name("60 Hz".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_is_writable() {
+        let info = ControlInfo::integer(cid::GAIN, "Gain", 0, 255, 1, 0);
+        assert!(info.is_writable());
+        assert!(!info.with_flags(ControlFlags::READ_ONLY).is_writable());
+    }
+}
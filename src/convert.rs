@@ -0,0 +1,840 @@
+//! Conversion of frames to RGB and greyscale images.
+//!
+//! Whole packed YUV 4:2:2 frames (YUYV and its reorderings) are converted in
+//! bulk to RGB24, RGBA or BGR24 with [`yuyv_to_rgb`], using the matrix and
+//! quantization range chosen by a [`Colorimetry`], with SIMD where the CPU
+//! supports it. Other packed RGB and
+//! greyscale formats are decoded pixel by pixel the same way as
+//! [`Frame::pixel`](crate::traits::Frame::pixel). Semi-planar (NV12 and
+//! friends) and planar (YU12 and friends) YUV formats are decoded from their
+//! separate luma and chroma planes. Raw Bayer formats are demosaiced
+//! bilinearly; [`bayer`](crate::bayer) offers edge-aware demosaicing too.
+
+use crate::bayer::{self, Demosaic};
+use crate::pixel_format::{Layout, PixelFormatInfo};
+use crate::simd::Kernel;
+use crate::traits::{pixel_in, unpack_yuv422, CameraError, Format, FourCC, Result};
+
+/// Fractional bits of the fixed-point conversion coefficients.
+pub(crate) const FIXED_SHIFT: i32 = 16;
+
+/// YUV to RGB conversion matrix.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum YuvMatrix {
+    /// ITU-R BT.601, used by SD video and most webcams.
+    #[default]
+    Bt601,
+    /// ITU-R BT.709, used by HD video.
+    Bt709,
+    /// ITU-R BT.2020, used by UHD video.
+    Bt2020,
+}
+
+impl YuvMatrix {
+    /// Red and blue luma weights `(Kr, Kb)`.
+    const fn weights(self) -> (f32, f32) {
+        match self {
+            Self::Bt601 => (0.299, 0.114),
+            Self::Bt709 => (0.2126, 0.0722),
+            Self::Bt2020 => (0.2627, 0.0593),
+        }
+    }
+}
+
+/// Quantization range of YUV samples.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum YuvRange {
+    /// Studio swing: luma spans 16-235 and chroma 16-240.
+    Limited,
+    /// Full swing: luma and chroma span 0-255.
+    #[default]
+    Full,
+}
+
+/// Colorimetry settings used to convert YUV samples to RGB.
+///
+/// The default is BT.601 with full range, which [`Frame::pixel_at`] applies
+/// since it has no format to go by. Conversions given a [`Format`] use
+/// [`Format::colorimetry`] instead, which resolves to limited range for YUV
+/// unless the format says otherwise.
+///
+/// [`Frame::pixel_at`]: crate::traits::Frame::pixel_at
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Colorimetry {
+    /// Conversion matrix.
+    pub matrix: YuvMatrix,
+    /// Quantization range.
+    pub range: YuvRange,
+}
+
+impl Colorimetry {
+    /// Create colorimetry settings.
+    #[must_use]
+    pub const fn new(matrix: YuvMatrix, range: YuvRange) -> Self {
+        Self { matrix, range }
+    }
+
+    /// Convert one YUV sample to RGB.
+    #[must_use]
+    #[allow(clippy::many_single_char_names)]
+    pub fn yuv_to_rgb(self, y: u8, u: u8, v: u8) -> (u8, u8, u8) {
+        Coefficients::new(self).rgb(y, u, v).into()
+    }
+
+    /// Convert one RGB pixel to YUV, the inverse of [`Self::yuv_to_rgb`].
+    #[must_use]
+    #[allow(
+        clippy::many_single_char_names,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    pub fn rgb_to_yuv(self, r: u8, g: u8, b: u8) -> (u8, u8, u8) {
+        let (kr, kb) = self.matrix.weights();
+        let [r, g, b] = [r, g, b].map(|value| f32::from(value) / 255.0);
+        let luma = kr.mul_add(r, kb.mul_add(b, (1.0 - kr - kb) * g));
+        let cb = (b - luma) / (2.0 * (1.0 - kb));
+        let cr = (r - luma) / (2.0 * (1.0 - kr));
+        let (y_offset, y_scale, c_scale) = match self.range {
+            YuvRange::Full => (0.0, 255.0, 255.0),
+            YuvRange::Limited => (16.0, 219.0, 224.0),
+        };
+        let quantize = |value: f32| value.round().clamp(0.0, 255.0) as u8;
+        (
+            quantize(luma.mul_add(y_scale, y_offset)),
+            quantize(cb.mul_add(c_scale, 128.0)),
+            quantize(cr.mul_add(c_scale, 128.0)),
+        )
+    }
+}
+
+/// Fixed-point YUV to RGB coefficients for one [`Colorimetry`].
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Coefficients {
+    pub(crate) y_offset: i32,
+    pub(crate) y_gain: i32,
+    pub(crate) r_v: i32,
+    pub(crate) g_u: i32,
+    pub(crate) g_v: i32,
+    pub(crate) b_u: i32,
+}
+
+impl Coefficients {
+    pub(crate) fn new(colorimetry: Colorimetry) -> Self {
+        let (kr, kb) = colorimetry.matrix.weights();
+        let kg = 1.0 - kr - kb;
+        let (y_offset, y_scale, c_scale) = match colorimetry.range {
+            YuvRange::Full => (0, 1.0, 1.0),
+            YuvRange::Limited => (16, 255.0 / 219.0, 255.0 / 224.0),
+        };
+        #[allow(clippy::cast_possible_truncation)]
+        let fixed = |value: f32| (value * 65536.0).round() as i32;
+        Self {
+            y_offset,
+            y_gain: fixed(y_scale),
+            r_v: fixed(2.0 * (1.0 - kr) * c_scale),
+            g_u: fixed(2.0 * kb * (1.0 - kb) / kg * c_scale),
+            g_v: fixed(2.0 * kr * (1.0 - kr) / kg * c_scale),
+            b_u: fixed(2.0 * (1.0 - kb) * c_scale),
+        }
+    }
+
+    /// Convert one YUV sample to `[r, g, b]`, rounding to nearest.
+    #[allow(clippy::many_single_char_names)]
+    pub(crate) fn rgb(&self, y: u8, u: u8, v: u8) -> [u8; 3] {
+        let luma = (i32::from(y) - self.y_offset) * self.y_gain + (1 << (FIXED_SHIFT - 1));
+        let u = i32::from(u) - 128;
+        let v = i32::from(v) - 128;
+        [
+            saturate(luma + self.r_v * v),
+            saturate(luma - self.g_u * u - self.g_v * v),
+            saturate(luma + self.b_u * u),
+        ]
+    }
+}
+
+/// Scale a fixed-point value back to 8 bits, saturating.
+fn saturate(value: i32) -> u8 {
+    u8::try_from((value >> FIXED_SHIFT).clamp(0, 255)).unwrap_or(u8::MAX)
+}
+
+/// Byte layout of converted RGB pixels.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RgbLayout {
+    /// Three bytes per pixel: red, green, blue.
+    #[default]
+    Rgb24,
+    /// Four bytes per pixel: red, green, blue, then an opaque alpha.
+    Rgba,
+    /// Three bytes per pixel: blue, green, red.
+    Bgr24,
+}
+
+impl RgbLayout {
+    /// Bytes per converted pixel.
+    #[must_use]
+    pub const fn bytes_per_pixel(self) -> usize {
+        match self {
+            Self::Rgb24 | Self::Bgr24 => 3,
+            Self::Rgba => 4,
+        }
+    }
+
+    /// Append the first `count` pixels of planar R, G and B buffers.
+    fn extend_planar(self, out: &mut Vec<u8>, [red, green, blue]: &[Vec<u8>; 3], count: usize) {
+        let start = out.len();
+        out.resize(start + count * self.bytes_per_pixel(), u8::MAX);
+        let pixels = red.iter().zip(green).zip(blue);
+        let dst = out.get_mut(start..).unwrap_or_default();
+        match self {
+            Self::Rgb24 => {
+                for (dst, ((&r, &g), &b)) in dst.chunks_exact_mut(3).zip(pixels) {
+                    dst.copy_from_slice(&[r, g, b]);
+                }
+            }
+            Self::Rgba => {
+                for (dst, ((&r, &g), &b)) in dst.chunks_exact_mut(4).zip(pixels) {
+                    dst.copy_from_slice(&[r, g, b, u8::MAX]);
+                }
+            }
+            Self::Bgr24 => {
+                for (dst, ((&r, &g), &b)) in dst.chunks_exact_mut(3).zip(pixels) {
+                    dst.copy_from_slice(&[b, g, r]);
+                }
+            }
+        }
+    }
+
+    /// Append one pixel in this layout.
+    fn push(self, out: &mut Vec<u8>, [r, g, b]: [u8; 3]) {
+        match self {
+            Self::Rgb24 => out.extend_from_slice(&[r, g, b]),
+            Self::Rgba => out.extend_from_slice(&[r, g, b, u8::MAX]),
+            Self::Bgr24 => out.extend_from_slice(&[b, g, r]),
+        }
+    }
+}
+
+/// Convert a whole packed YUV 4:2:2 frame to RGB.
+///
+/// Accepts YUYV, YVYU, UYVY and VYUY; line padding is skipped. Both pixels of
+/// a pair share its chroma.
+///
+/// # Errors
+///
+/// Returns `FormatNotSupported` if `format` is not packed YUV 4:2:2, or
+/// `StreamError` if `data` is shorter than the format describes.
+pub fn yuyv_to_rgb(
+    data: &[u8],
+    format: &Format,
+    colorimetry: Colorimetry,
+    layout: RgbLayout,
+) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    yuyv_to_rgb_into(data, format, colorimetry, layout, &mut out)?;
+    Ok(out)
+}
+
+/// Convert a whole packed YUV 4:2:2 frame to RGB into `out`.
+///
+/// `out` is cleared first, so its allocation can be reused from frame to
+/// frame. The conversion uses the widest SIMD kernel the CPU supports (AVX2
+/// or SSE4.1 on x86-64, NEON on 64-bit ARM), falling back to the scalar path;
+/// both produce identical output. See [`yuyv_to_rgb`].
+///
+/// # Errors
+///
+/// Same as [`yuyv_to_rgb`].
+pub fn yuyv_to_rgb_into(
+    data: &[u8],
+    format: &Format,
+    colorimetry: Colorimetry,
+    layout: RgbLayout,
+    out: &mut Vec<u8>,
+) -> Result<()> {
+    convert_yuv422(data, format, colorimetry, layout, out, Kernel::detect())
+}
+
+/// Convert a whole packed YUV 4:2:2 frame to RGB into `out` without SIMD.
+///
+/// This is the reference the SIMD kernels are checked against, exposed for
+/// verification and benchmarking. See [`yuyv_to_rgb_into`].
+///
+/// # Errors
+///
+/// Same as [`yuyv_to_rgb`].
+pub fn yuyv_to_rgb_scalar_into(
+    data: &[u8],
+    format: &Format,
+    colorimetry: Colorimetry,
+    layout: RgbLayout,
+    out: &mut Vec<u8>,
+) -> Result<()> {
+    convert_yuv422(data, format, colorimetry, layout, out, None)
+}
+
+/// Name of the SIMD instruction set used by [`yuyv_to_rgb`] on this CPU, or
+/// `None` if it runs the scalar path.
+#[must_use]
+pub fn simd_backend() -> Option<&'static str> {
+    Kernel::detect().map(|kernel| kernel.name())
+}
+
+/// Convert a packed YUV 4:2:2 frame, running `kernel` over each line before
+/// finishing it with the scalar path.
+fn convert_yuv422(
+    data: &[u8],
+    format: &Format,
+    colorimetry: Colorimetry,
+    layout: RgbLayout,
+    out: &mut Vec<u8>,
+    kernel: Option<Kernel>,
+) -> Result<()> {
+    let info = checked_info(data, format)?;
+    if info.layout != Layout::Packed || info.chroma_h_subsampling != 2 {
+        return Err(CameraError::FormatNotSupported {
+            format: format.clone(),
+            reason: "not packed YUV 4:2:2".to_owned(),
+        });
+    }
+
+    let width = format.width as usize;
+    let height = format.height as usize;
+    let row_len = width * layout.bytes_per_pixel();
+    let coefficients = Coefficients::new(colorimetry);
+    let mut planes = [(); 3].map(|()| vec![0; if kernel.is_some() { width } else { 0 }]);
+    out.clear();
+    out.reserve(row_len * height);
+    for line in data.chunks(format.stride.max(1) as usize).take(height) {
+        let row_start = out.len();
+        let groups = line.get(..width.div_ceil(2) * 4).unwrap_or(line);
+        let converted = kernel.map_or(0, |kernel| {
+            kernel.convert(&coefficients, format.fourcc, groups, &mut planes)
+        });
+        layout.extend_planar(out, &planes, converted);
+
+        for group in groups
+            .get(converted * 2..)
+            .unwrap_or_default()
+            .chunks_exact(4)
+        {
+            let (y0, u, y1, v) =
+                unpack_yuv422(format.fourcc, group).ok_or_else(|| too_short(data, format))?;
+            layout.push(out, coefficients.rgb(y0, u, v));
+            layout.push(out, coefficients.rgb(y1, u, v));
+        }
+        // An odd width leaves the second pixel of the last pair unused
+        out.truncate(row_start + row_len);
+    }
+    if out.len() == row_len * height {
+        Ok(())
+    } else {
+        Err(too_short(data, format))
+    }
+}
+
+/// Convert a frame to tightly packed 8-bit RGB, three bytes per pixel.
+///
+/// YUV formats are converted with `colorimetry`.
+///
+/// # Errors
+///
+/// Returns `FormatNotSupported` for unknown formats, and for compressed ones
+/// unless the `mjpeg` feature is enabled; `StreamError` if `data` is shorter
+/// than the format describes; or `Io` with `InvalidData` if a JPEG frame
+/// cannot be decoded.
+pub fn to_rgb24(data: &[u8], format: &Format, colorimetry: Colorimetry) -> Result<Vec<u8>> {
+    #[cfg(feature = "mjpeg")]
+    if is_jpeg(format) {
+        return crate::mjpeg::decode_frame(data, format, FourCC::RGB3);
+    }
+    let info = checked_info(data, format)?;
+    let width = format.width as usize;
+    let height = format.height as usize;
+    let mut rgb = Vec::with_capacity(width * height * 3);
+
+    let decoded = match info.layout {
+        Layout::Packed if info.chroma_h_subsampling == 2 => {
+            return yuyv_to_rgb(data, format, colorimetry, RgbLayout::Rgb24);
+        }
+        Layout::Packed => (0..format.height).all(|y| {
+            (0..format.width).all(|x| push_rgb(&mut rgb, pixel_in(data, x, y, format, colorimetry)))
+        }),
+        Layout::SemiPlanar | Layout::Planar => {
+            let planes = YuvPlanes::new(data, format, info, colorimetry);
+            (0..height).all(|y| (0..width).all(|x| push_rgb(&mut rgb, planes.rgb_at(x, y))))
+        }
+        Layout::Bayer => return bayer::to_rgb24(data, format, Demosaic::Bilinear),
+        Layout::Compressed => false,
+    };
+    if decoded {
+        Ok(rgb)
+    } else {
+        Err(too_short(data, format))
+    }
+}
+
+/// Extract the luma of a frame as tightly packed 8-bit greyscale.
+///
+/// YUV formats yield their luma samples unchanged; RGB formats are weighted
+/// with the BT.601 luma coefficients.
+///
+/// # Errors
+///
+/// Returns the errors of [`to_rgb24`].
+pub fn to_luma(data: &[u8], format: &Format) -> Result<Vec<u8>> {
+    #[cfg(feature = "mjpeg")]
+    if is_jpeg(format) {
+        return crate::mjpeg::decode_frame(data, format, FourCC::GREY);
+    }
+    let info = checked_info(data, format)?;
+    let width = format.width as usize;
+    let stride = format.stride as usize;
+    let rows = data.chunks(stride.max(1)).take(format.height as usize);
+
+    let luma: Option<Vec<u8>> = match (info.layout, info.chroma_h_subsampling) {
+        (Layout::SemiPlanar | Layout::Planar, _) => rows
+            .map(|row| row.get(..width))
+            .collect::<Option<Vec<_>>>()
+            .map(|rows| rows.concat()),
+        (Layout::Packed, 2) => {
+            // Luma sits at the odd bytes of UYVY and VYUY, the even ones otherwise
+            let offset = usize::from(matches!(format.fourcc, FourCC::UYVY | FourCC::VYUY));
+            rows.map(|row| {
+                let samples = row.get(..width * 2)?;
+                Some(samples.iter().skip(offset).step_by(2).copied())
+            })
+            .collect::<Option<Vec<_>>>()
+            .map(|rows| rows.into_iter().flatten().collect())
+        }
+        (Layout::Packed, _) if format.fourcc == FourCC::GREY => rows
+            .map(|row| row.get(..width))
+            .collect::<Option<Vec<_>>>()
+            .map(|rows| rows.concat()),
+        (Layout::Packed | Layout::Bayer, _) => to_rgb24(data, format, format.colorimetry())
+            .ok()
+            .map(|rgb| rgb.chunks_exact(3).map(rgb_luma).collect()),
+        (Layout::Compressed, _) => None,
+    };
+    luma.filter(|luma| luma.len() == width * format.height as usize)
+        .ok_or_else(|| too_short(data, format))
+}
+
+/// Luma of a frame that [`to_rgb24`] already converted to `rgb`.
+///
+/// Packed RGB and Bayer frames take their luma from `rgb` rather than being
+/// converted a second time; other formats read it from `data`.
+pub(crate) fn to_luma_with_rgb(data: &[u8], format: &Format, rgb: &[u8]) -> Result<Vec<u8>> {
+    let from_rgb = format.fourcc.info().is_some_and(|info| match info.layout {
+        Layout::Packed => info.chroma_h_subsampling != 2 && format.fourcc != FourCC::GREY,
+        Layout::Bayer => true,
+        _ => false,
+    });
+    if from_rgb {
+        Ok(rgb.chunks_exact(3).map(rgb_luma).collect())
+    } else {
+        to_luma(data, format)
+    }
+}
+
+/// RGB value of the pixel at (`x`, `y`) of a semi-planar or planar YUV frame.
+///
+/// Returns `None` if the pixel lies beyond the end of `data`.
+pub(crate) fn planar_pixel(
+    data: &[u8],
+    x: u32,
+    y: u32,
+    format: &Format,
+    info: &PixelFormatInfo,
+    colorimetry: Colorimetry,
+) -> Option<(u8, u8, u8)> {
+    YuvPlanes::new(data, format, info, colorimetry).rgb_at(x as usize, y as usize)
+}
+
+/// Append a decoded pixel, returning whether there was one.
+fn push_rgb(rgb: &mut Vec<u8>, pixel: Option<(u8, u8, u8)>) -> bool {
+    pixel.is_some_and(|pixel| {
+        rgb.extend_from_slice(&<[u8; 3]>::from(pixel));
+        true
+    })
+}
+
+/// BT.601 luma of an RGB pixel.
+fn rgb_luma(pixel: &[u8]) -> u8 {
+    let &[r, g, b] = pixel else {
+        return 0;
+    };
+    let luma = 77 * u32::from(r) + 150 * u32::from(g) + 29 * u32::from(b);
+    u8::try_from((luma + 128) >> 8).unwrap_or(u8::MAX)
+}
+
+/// Average two samples, rounding half up.
+pub(crate) fn average(first: u8, second: u8) -> u8 {
+    u8::try_from((u16::from(first) + u16::from(second)).div_ceil(2)).unwrap_or(u8::MAX)
+}
+
+/// Whether `format` is compressed, which only JPEG formats are.
+#[cfg(feature = "mjpeg")]
+pub(crate) fn is_jpeg(format: &Format) -> bool {
+    format
+        .fourcc
+        .info()
+        .is_some_and(PixelFormatInfo::is_compressed)
+}
+
+/// Look up the layout of `format`, checking it can be converted from `data`.
+fn checked_info(data: &[u8], format: &Format) -> Result<&'static PixelFormatInfo> {
+    let info = format
+        .fourcc
+        .info()
+        .filter(|info| !info.is_compressed())
+        .ok_or_else(|| CameraError::FormatNotSupported {
+            format: format.clone(),
+            reason: "cannot be converted to an image".to_owned(),
+        })?;
+    if data.len() < info.frame_size(format.width, format.height, format.stride) as usize {
+        return Err(too_short(data, format));
+    }
+    Ok(info)
+}
+
+/// Error for a frame holding less data than its format describes.
+pub(crate) fn too_short(data: &[u8], format: &Format) -> CameraError {
+    CameraError::StreamError(format!(
+        "Frame holds {} bytes, too few for {}x{} {}",
+        data.len(),
+        format.width,
+        format.height,
+        format.fourcc
+    ))
+}
+
+/// The luma and chroma planes of a semi-planar or planar YUV frame.
+struct YuvPlanes<'a> {
+    luma: &'a [u8],
+    /// Interleaved `CbCr` plane, or the first of the two chroma planes.
+    first: &'a [u8],
+    /// Second chroma plane of planar formats (empty for semi-planar ones).
+    second: &'a [u8],
+    stride: usize,
+    chroma_stride: usize,
+    h_sub: usize,
+    v_sub: usize,
+    semi_planar: bool,
+    /// Whether Cr comes before Cb (NV21, NV61, YV12).
+    swapped: bool,
+    coefficients: Coefficients,
+}
+
+impl<'a> YuvPlanes<'a> {
+    fn new(
+        data: &'a [u8],
+        format: &Format,
+        info: &PixelFormatInfo,
+        colorimetry: Colorimetry,
+    ) -> Self {
+        let stride = format.stride as usize;
+        let chroma_stride = info.plane_stride(1, format.stride) as usize;
+        let chroma_size = chroma_stride * info.plane_height(1, format.height) as usize;
+        let (luma, chroma) = data.split_at((stride * format.height as usize).min(data.len()));
+        let (first, second) = chroma.split_at(chroma_size.min(chroma.len()));
+        Self {
+            luma,
+            first,
+            second,
+            stride,
+            chroma_stride,
+            h_sub: info.chroma_h_subsampling as usize,
+            v_sub: info.chroma_v_subsampling as usize,
+            semi_planar: info.layout == Layout::SemiPlanar,
+            swapped: matches!(format.fourcc, FourCC::NV21 | FourCC::NV61 | FourCC::YV12),
+            coefficients: Coefficients::new(colorimetry),
+        }
+    }
+
+    /// RGB value of the pixel at (`x`, `y`).
+    fn rgb_at(&self, x: usize, y: usize) -> Option<(u8, u8, u8)> {
+        let luma = *self.luma.get(y * self.stride + x)?;
+        let line = y / self.v_sub * self.chroma_stride;
+        let (first, second) = if self.semi_planar {
+            let offset = line + x / self.h_sub * 2;
+            (*self.first.get(offset)?, *self.first.get(offset + 1)?)
+        } else {
+            let offset = line + x / self.h_sub;
+            (*self.first.get(offset)?, *self.second.get(offset)?)
+        };
+        let (u, v) = if self.swapped {
+            (second, first)
+        } else {
+            (first, second)
+        };
+        Some(self.coefficients.rgb(luma, u, v).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{generate_test_frame, TestPattern};
+    use crate::traits::{Frame, FrameMetadata};
+    use std::time::Duration;
+
+    #[test]
+    fn test_yuyv_to_rgb() {
+        let format = Format::new(640, 480, FourCC::YUYV);
+        let frame = generate_test_frame(&format, TestPattern::ColorBars);
+        let rgb =
+            to_rgb24(&frame, &format, Colorimetry::default()).expect("conversion should succeed");
+        assert_eq!(rgb.len(), 640 * 480 * 3);
+        // The first bar is white, the last one black
+        assert!(rgb[..3].iter().all(|&value| value > 230));
+        assert!(rgb[rgb.len() - 3..].iter().all(|&value| value < 25));
+
+        let luma = to_luma(&frame, &format).expect("luma should succeed");
+        assert_eq!(luma.len(), 640 * 480);
+        assert_eq!(luma[0], frame[0]);
+        assert_eq!(luma[1], frame[2]);
+    }
+
+    #[test]
+    fn test_nv12_to_rgb() {
+        // 4x2 frame: a grey luma plane and one reddish CbCr pair per 2x2 block
+        let mut format = Format::new(4, 2, FourCC::NV12);
+        format.stride = 4;
+        format.size = 12;
+        let data = [128, 128, 128, 128, 128, 128, 128, 128, 128, 200, 128, 128];
+        let rgb =
+            to_rgb24(&data, &format, Colorimetry::default()).expect("conversion should succeed");
+        assert_eq!(&rgb[..3], &[229, 77, 128]);
+        assert_eq!(&rgb[6..9], &[128, 128, 128]);
+        // The second row shares the chroma of the first
+        assert_eq!(&rgb[12..15], &[229, 77, 128]);
+
+        format.fourcc = FourCC::NV21;
+        let rgb =
+            to_rgb24(&data, &format, Colorimetry::default()).expect("conversion should succeed");
+        assert_eq!(&rgb[..3], &[128, 103, 255]);
+        assert_eq!(
+            to_luma(&data, &format).expect("luma should succeed"),
+            [128; 8]
+        );
+    }
+
+    #[test]
+    fn test_rgb_and_grey_luma() {
+        let format = Format::new(2, 1, FourCC::RGB3);
+        assert_eq!(
+            to_luma(&[255, 255, 255, 255, 0, 0], &format).expect("luma should succeed"),
+            [255, 77]
+        );
+
+        let mut format = Format::new(2, 2, FourCC::GREY);
+        format.stride = 4;
+        format.size = 8;
+        let data = [1, 2, 0, 0, 3, 4, 0, 0];
+        assert_eq!(
+            to_luma(&data, &format).expect("luma should succeed"),
+            [1, 2, 3, 4]
+        );
+        assert_eq!(
+            to_rgb24(&data, &format, Colorimetry::default()).expect("conversion should succeed"),
+            [1, 1, 1, 2, 2, 2, 3, 3, 3, 4, 4, 4]
+        );
+    }
+
+    #[test]
+    fn test_rgb_to_yuv_round_trip() {
+        let colorimetries = [
+            Colorimetry::default(),
+            Colorimetry::new(YuvMatrix::Bt601, YuvRange::Limited),
+            Colorimetry::new(YuvMatrix::Bt709, YuvRange::Limited),
+            Colorimetry::new(YuvMatrix::Bt2020, YuvRange::Full),
+        ];
+        let colors = [
+            (255, 255, 255),
+            (0, 0, 0),
+            (255, 0, 0),
+            (20, 200, 90),
+            (7, 90, 250),
+        ];
+        for colorimetry in colorimetries {
+            assert_eq!(colorimetry.rgb_to_yuv(255, 255, 255).1, 128);
+            for (r, g, b) in colors {
+                let (y, u, v) = colorimetry.rgb_to_yuv(r, g, b);
+                let (r2, g2, b2) = colorimetry.yuv_to_rgb(y, u, v);
+                assert!(
+                    r.abs_diff(r2) <= 2 && g.abs_diff(g2) <= 2 && b.abs_diff(b2) <= 2,
+                    "{colorimetry:?} ({r}, {g}, {b}) came back as ({r2}, {g2}, {b2})"
+                );
+            }
+        }
+        let limited = Colorimetry::new(YuvMatrix::Bt601, YuvRange::Limited);
+        assert_eq!(limited.rgb_to_yuv(255, 255, 255), (235, 128, 128));
+        assert_eq!(limited.rgb_to_yuv(0, 0, 0), (16, 128, 128));
+    }
+
+    #[test]
+    fn test_rejects_unconvertible_frames() {
+        let format = Format::new(640, 480, FourCC::MJPG);
+        #[cfg(not(feature = "mjpeg"))]
+        assert!(matches!(
+            to_rgb24(&[0xff, 0xd8], &format, Colorimetry::default()),
+            Err(CameraError::FormatNotSupported { .. })
+        ));
+        #[cfg(feature = "mjpeg")]
+        assert!(matches!(
+            to_rgb24(&[0xff, 0xd8], &format, Colorimetry::default()),
+            Err(CameraError::Io(_))
+        ));
+
+        let format = Format::new(640, 480, FourCC::YUYV);
+        assert!(matches!(
+            to_rgb24(&[0; 100], &format, Colorimetry::default()),
+            Err(CameraError::StreamError(_))
+        ));
+        assert!(matches!(
+            to_luma(&[0; 100], &format),
+            Err(CameraError::StreamError(_))
+        ));
+
+        // A zero stride cannot describe the frame
+        let format = Format {
+            stride: 0,
+            ..Format::new(4, 2, FourCC::YUYV)
+        };
+        assert!(matches!(
+            to_rgb24(&[0; 16], &format, Colorimetry::default()),
+            Err(CameraError::StreamError(_))
+        ));
+    }
+
+    #[test]
+    fn test_colorimetry_matrices_and_ranges() {
+        let full = Colorimetry::default();
+        let limited = Colorimetry::new(YuvMatrix::Bt601, YuvRange::Limited);
+        assert_eq!(full.yuv_to_rgb(235, 128, 128), (235, 235, 235));
+        assert_eq!(limited.yuv_to_rgb(235, 128, 128), (255, 255, 255));
+        assert_eq!(limited.yuv_to_rgb(16, 128, 128), (0, 0, 0));
+        assert_eq!(limited.yuv_to_rgb(126, 128, 128), (128, 128, 128));
+
+        // Pure red in each matrix, full range
+        for (matrix, y, v) in [
+            (YuvMatrix::Bt601, 76, 255),
+            (YuvMatrix::Bt709, 54, 255),
+            (YuvMatrix::Bt2020, 67, 255),
+        ] {
+            let kb = matrix.weights().1;
+            #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+            let u = (128.0 - f32::from(y) / (2.0 * (1.0 - kb))).round() as u8;
+            let (r, g, b) = Colorimetry::new(matrix, YuvRange::Full).yuv_to_rgb(y, u, v);
+            assert!(r > 250, "{matrix:?} red {r}");
+            assert!(g < 5, "{matrix:?} green {g}");
+            assert!(b < 5, "{matrix:?} blue {b}");
+        }
+    }
+
+    #[test]
+    fn test_yuyv_layouts() {
+        let format = Format::new(2, 1, FourCC::YUYV);
+        let data = [76, 85, 150, 255];
+        let colorimetry = Colorimetry::default();
+        let rgb = yuyv_to_rgb(&data, &format, colorimetry, RgbLayout::Rgb24)
+            .expect("conversion should succeed");
+        let bgr = yuyv_to_rgb(&data, &format, colorimetry, RgbLayout::Bgr24)
+            .expect("conversion should succeed");
+        let rgba = yuyv_to_rgb(&data, &format, colorimetry, RgbLayout::Rgba)
+            .expect("conversion should succeed");
+        assert_eq!(rgb.len(), 6);
+        assert_eq!(bgr, [rgb[2], rgb[1], rgb[0], rgb[5], rgb[4], rgb[3]]);
+        assert_eq!(
+            rgba,
+            [rgb[0], rgb[1], rgb[2], 255, rgb[3], rgb[4], rgb[5], 255]
+        );
+    }
+
+    #[test]
+    fn test_bulk_matches_pixel_access() {
+        let mut format = Format::new(63, 4, FourCC::UYVY);
+        format.stride = 160;
+        format.size = 640;
+        let data: Vec<u8> = (0..640u32)
+            .map(|i| (i * 37 % 256).to_le_bytes()[0])
+            .collect();
+        let frame = Frame {
+            data,
+            metadata: FrameMetadata {
+                sequence: 0,
+                timestamp: Duration::ZERO,
+                bytes_used: 640,
+            },
+        };
+
+        let colorimetry = Colorimetry::new(YuvMatrix::Bt709, YuvRange::Limited);
+        let mut rgb = vec![1, 2, 3];
+        yuyv_to_rgb_into(
+            &frame.data,
+            &format,
+            colorimetry,
+            RgbLayout::Rgb24,
+            &mut rgb,
+        )
+        .expect("conversion should succeed");
+        assert_eq!(rgb.len(), 63 * 4 * 3);
+        for (index, pixel) in rgb.chunks_exact(3).enumerate() {
+            let index = u32::try_from(index).expect("index should fit u32");
+            let (x, y) = (index % 63, index / 63);
+            let expected = frame.pixel_with(x, y, &format, colorimetry);
+            assert_eq!(Some((pixel[0], pixel[1], pixel[2])), expected, "({x}, {y})");
+        }
+
+        assert!(matches!(
+            yuyv_to_rgb(
+                &frame.data,
+                &Format::new(4, 4, FourCC::RGB3),
+                colorimetry,
+                RgbLayout::Rgb24
+            ),
+            Err(CameraError::FormatNotSupported { .. })
+        ));
+    }
+
+    #[test]
+    fn test_pixel_at_honours_colorimetry() {
+        let frame = Frame {
+            data: vec![235, 128, 16, 128],
+            metadata: FrameMetadata {
+                sequence: 0,
+                timestamp: Duration::ZERO,
+                bytes_used: 4,
+            },
+        };
+        let limited = Colorimetry::new(YuvMatrix::Bt709, YuvRange::Limited);
+        assert_eq!(frame.pixel_at(0, 0, 2), Some((235, 235, 235)));
+        assert_eq!(frame.pixel_at_with(0, 0, 2, limited), Some((255, 255, 255)));
+        assert_eq!(frame.pixel_at_with(1, 0, 2, limited), Some((0, 0, 0)));
+
+        // Without a format, pixel_at stays full range where pixel does not
+        let format = Format::new(2, 1, FourCC::YUYV);
+        assert_eq!(frame.pixel(0, 0, &format), Some((255, 255, 255)));
+    }
+
+    #[test]
+    fn test_simd_path_matches_scalar() {
+        let mut format = Format::new(37, 5, FourCC::YVYU);
+        format.stride = 80;
+        format.size = 400;
+        let data: Vec<u8> = (0..400u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 11).to_le_bytes()[0])
+            .collect();
+        let colorimetry = Colorimetry::new(YuvMatrix::Bt2020, YuvRange::Limited);
+
+        for layout in [RgbLayout::Rgb24, RgbLayout::Rgba, RgbLayout::Bgr24] {
+            let mut fast = Vec::new();
+            let mut scalar = Vec::new();
+            yuyv_to_rgb_into(&data, &format, colorimetry, layout, &mut fast)
+                .expect("conversion should succeed");
+            yuyv_to_rgb_scalar_into(&data, &format, colorimetry, layout, &mut scalar)
+                .expect("conversion should succeed");
+            assert_eq!(fast, scalar, "{layout:?} via {:?}", simd_backend());
+        }
+    }
+}
@@ -1,17 +1,42 @@
 //! V4L2 device implementation using the v4l crate.
 
-use v4l::buffer::Type;
-use v4l::io::mmap::Stream;
-use v4l::io::traits::CaptureStream as V4lCaptureStream;
+use v4l::control::{Control, MenuItem as V4lMenuItem, Value};
+use v4l::format::description::Flags as FormatFlags;
+use v4l::frameinterval::FrameIntervalEnum;
+use v4l::framesize::FrameSizeEnum;
+use v4l::parameters::Capabilities as ParamCapabilities;
+use v4l::video::capture::Parameters;
 use v4l::video::Capture;
 use v4l::Device;
 
+#[cfg(feature = "tokio")]
+use tokio::io::unix::AsyncFd;
+#[cfg(feature = "tokio")]
+use tokio::io::Interest;
+
+#[cfg(feature = "tokio")]
+use crate::async_stream::{AsyncCapture, StreamFd};
+use crate::colorspace::{Colorspace, YcbcrEncoding};
+use crate::controls::{cid, ControlFlags, ControlInfo, ControlValue, MenuItem, MenuLabel};
+use crate::discovery;
+use crate::mmap::MappedBuffers;
 use crate::traits::{
-    CameraDevice, CameraError, CaptureStream, DeviceCapabilities, Format, FourCC, Frame,
-    FrameMetadata, Result,
+    CameraDevice, CameraError, CaptureStream, DeviceCapabilities, Format, FormatDescription,
+    FourCC, Fraction, FrameInterval, FrameMetadata, FrameRef, FrameSize, Result,
 };
+use std::io;
+use std::marker::PhantomData;
+use std::path::Path;
+#[cfg(feature = "tokio")]
+use std::task::{ready, Context, Poll};
 use std::time::Duration;
 
+/// poll(2) event flag signalling that a buffer can be dequeued.
+const POLLIN: i16 = 0x0001;
+
+/// Minimum number of mmap buffers requested for a stream.
+const MIN_BUFFERS: u32 = 2;
+
 /// V4L2 device implementation wrapping the v4l crate.
 pub struct V4L2Device {
     device: Device,
@@ -20,21 +45,40 @@ pub struct V4L2Device {
 
 impl V4L2Device {
     /// Open a V4L2 device by index (e.g., 0 for /dev/video0).
+    ///
+    /// Indices follow kernel probe order and may change between boots; prefer
+    /// [`open_path`](Self::open_path), [`open_by_card`](Self::open_by_card) or
+    /// [`open_by_bus_info`](Self::open_by_bus_info) on multi-camera systems.
     pub fn open(index: u32) -> Result<Self> {
         let device = Device::new(index as usize)
             .map_err(|err| CameraError::DeviceOpenFailed(err.to_string()))?;
 
-        let caps = device
-            .query_caps()
-            .map_err(|err| CameraError::DeviceOpenFailed(err.to_string()))?;
+        Self::from_device(device)
+    }
 
-        let capabilities = DeviceCapabilities {
-            driver: caps.driver,
-            card: caps.card,
-            bus_info: caps.bus,
-            can_capture: caps.capabilities.contains(v4l::capability::Flags::VIDEO_CAPTURE),
-            can_stream: caps.capabilities.contains(v4l::capability::Flags::STREAMING),
-        };
+    /// Open a V4L2 device by node path (e.g., `/dev/video0`).
+    pub fn open_path<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let device = Device::with_path(path)
+            .map_err(|err| CameraError::DeviceOpenFailed(format!("{}: {err}", path.display())))?;
+
+        Self::from_device(device)
+    }
+
+    /// Open the first capture-capable device with the given card name.
+    pub fn open_by_card(card: &str) -> Result<Self> {
+        let info = discovery::find_by_card(card)?;
+        Self::open_path(info.path)
+    }
+
+    /// Open the first capture-capable device with the given bus information.
+    pub fn open_by_bus_info(bus_info: &str) -> Result<Self> {
+        let info = discovery::find_by_bus_info(bus_info)?;
+        Self::open_path(info.path)
+    }
+
+    fn from_device(device: Device) -> Result<Self> {
+        let capabilities = discovery::query_capabilities(&device)?;
 
         Ok(Self {
             device,
@@ -56,13 +100,7 @@ impl CameraDevice for V4L2Device {
             .format()
             .map_err(|err| CameraError::StreamError(err.to_string()))?;
 
-        Ok(Format {
-            width: fmt.width,
-            height: fmt.height,
-            fourcc: FourCC::from(fmt.fourcc),
-            stride: fmt.stride,
-            size: fmt.size,
-        })
+        Ok(convert_format(&fmt))
     }
 
     fn set_format(&mut self, format: &Format) -> Result<Format> {
@@ -80,48 +118,327 @@ impl CameraDevice for V4L2Device {
             .set_format(&fmt)
             .map_err(|err| CameraError::StreamError(err.to_string()))?;
 
-        Ok(Format {
-            width: fmt.width,
-            height: fmt.height,
-            fourcc: FourCC::from(fmt.fourcc),
-            stride: fmt.stride,
-            size: fmt.size,
-        })
+        Ok(convert_format(&fmt))
+    }
+
+    fn frame_interval(&self) -> Result<Fraction> {
+        let params = self
+            .device
+            .params()
+            .map_err(|err| CameraError::StreamError(err.to_string()))?;
+
+        Ok(Fraction::from(params.interval))
+    }
+
+    fn set_frame_interval(&mut self, interval: Fraction) -> Result<Fraction> {
+        let params = self
+            .device
+            .params()
+            .map_err(|err| CameraError::StreamError(err.to_string()))?;
+
+        if !params
+            .capabilities
+            .contains(ParamCapabilities::TIME_PER_FRAME)
+        {
+            return Err(CameraError::StreamError(
+                "Driver does not support frame interval selection".to_owned(),
+            ));
+        }
+
+        let params = self
+            .device
+            .set_params(&Parameters::new(interval.into()))
+            .map_err(|err| CameraError::StreamError(err.to_string()))?;
+
+        Ok(Fraction::from(params.interval))
+    }
+
+    fn supported_formats(&self) -> Result<Vec<FormatDescription>> {
+        let formats = self
+            .device
+            .enum_formats()
+            .map_err(|err| CameraError::StreamError(err.to_string()))?;
+
+        Ok(formats
+            .into_iter()
+            .map(|desc| FormatDescription {
+                fourcc: FourCC::from(desc.fourcc),
+                description: desc.description,
+                compressed: desc.flags.contains(FormatFlags::COMPRESSED),
+            })
+            .collect())
+    }
+
+    fn frame_sizes(&self, fourcc: FourCC) -> Result<Vec<FrameSize>> {
+        let sizes = self
+            .device
+            .enum_framesizes(fourcc.into())
+            .map_err(|err| CameraError::StreamError(err.to_string()))?;
+
+        Ok(sizes
+            .into_iter()
+            .map(|size| match size.size {
+                FrameSizeEnum::Discrete(discrete) => FrameSize::Discrete {
+                    width: discrete.width,
+                    height: discrete.height,
+                },
+                FrameSizeEnum::Stepwise(stepwise) => FrameSize::Stepwise {
+                    min_width: stepwise.min_width,
+                    max_width: stepwise.max_width,
+                    step_width: stepwise.step_width,
+                    min_height: stepwise.min_height,
+                    max_height: stepwise.max_height,
+                    step_height: stepwise.step_height,
+                },
+            })
+            .collect())
+    }
+
+    fn frame_intervals(
+        &self,
+        fourcc: FourCC,
+        width: u32,
+        height: u32,
+    ) -> Result<Vec<FrameInterval>> {
+        let intervals = self
+            .device
+            .enum_frameintervals(fourcc.into(), width, height)
+            .map_err(|err| CameraError::StreamError(err.to_string()))?;
+
+        Ok(intervals
+            .into_iter()
+            .map(|interval| match interval.interval {
+                FrameIntervalEnum::Discrete(fraction) => {
+                    FrameInterval::Discrete(Fraction::from(fraction))
+                }
+                FrameIntervalEnum::Stepwise(stepwise) => FrameInterval::Stepwise {
+                    min: Fraction::from(stepwise.min),
+                    max: Fraction::from(stepwise.max),
+                    step: Fraction::from(stepwise.step),
+                },
+            })
+            .collect())
+    }
+
+    fn controls(&self) -> Result<Vec<ControlInfo>> {
+        let descriptions = self
+            .device
+            .query_controls()
+            .map_err(|err| CameraError::ControlError(err.to_string()))?;
+
+        Ok(descriptions
+            .into_iter()
+            .map(|desc| ControlInfo {
+                id: desc.id,
+                name: desc.name,
+                control_type: desc.typ.into(),
+                minimum: desc.minimum,
+                maximum: desc.maximum,
+                step: desc.step,
+                default: desc.default,
+                flags: ControlFlags(desc.flags.bits()),
+                menu_items: desc
+                    .items
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|(index, item)| MenuItem {
+                        index,
+                        label: match item {
+                            V4lMenuItem::Name(name) => MenuLabel::Name(name),
+                            V4lMenuItem::Value(value) => MenuLabel::Value(value),
+                        },
+                    })
+                    .collect(),
+            })
+            .collect())
+    }
+
+    fn control(&self, id: u32) -> Result<ControlValue> {
+        let control = self
+            .device
+            .control(id)
+            .map_err(|err| CameraError::ControlError(format!("{id:#010x}: {err}")))?;
+
+        match control.value {
+            Value::Integer(value) => Ok(ControlValue::Integer(value)),
+            Value::Boolean(value) => Ok(ControlValue::Boolean(value)),
+            Value::String(value) => Ok(ControlValue::String(value)),
+            other => Err(CameraError::ControlError(format!(
+                "{id:#010x}: unsupported control value {other:?}"
+            ))),
+        }
+    }
+
+    fn set_controls(&mut self, controls: &[(u32, ControlValue)]) -> Result<()> {
+        // VIDIOC_S_EXT_CTRLS via the v4l crate requires a single control class
+        // per call, so controls are grouped by class in first-seen order.
+        let mut groups: Vec<(u32, Vec<Control>)> = Vec::new();
+        for (id, value) in controls {
+            let control = Control {
+                id: *id,
+                value: match value {
+                    ControlValue::Integer(value) => Value::Integer(*value),
+                    ControlValue::Boolean(value) => Value::Boolean(*value),
+                    ControlValue::String(value) => Value::String(value.clone()),
+                },
+            };
+            let class = cid::class(*id);
+            match groups.iter_mut().find(|(group, _)| *group == class) {
+                Some((_, group)) => group.push(control),
+                None => groups.push((class, vec![control])),
+            }
+        }
+
+        for (class, group) in groups {
+            self.device
+                .set_controls(group)
+                .map_err(|err| CameraError::ControlError(format!("class {class:#010x}: {err}")))?;
+        }
+
+        Ok(())
     }
 
     fn create_stream(&mut self, buffer_count: u32) -> Result<Self::Stream<'_>> {
-        let stream = Stream::with_buffers(&self.device, Type::VideoCapture, buffer_count)
+        // One buffer must stay queued while the application holds another
+        let buffers = MappedBuffers::new(&self.device, buffer_count.max(MIN_BUFFERS))
             .map_err(|err| CameraError::StreamError(err.to_string()))?;
 
-        Ok(V4L2Stream { stream })
+        Ok(V4L2Stream {
+            #[cfg(feature = "tokio")]
+            reactor: None,
+            buffers,
+            started: false,
+            _device: PhantomData,
+        })
+    }
+}
+
+/// Convert a format reported by the driver.
+///
+/// The v4l crate does not expose `ycbcr_enc`, so the encoding is the one the
+/// colorspace implies, which is what drivers report unless told otherwise.
+fn convert_format(fmt: &v4l::Format) -> Format {
+    let colorspace = Colorspace::from(fmt.colorspace);
+    Format {
+        width: fmt.width,
+        height: fmt.height,
+        fourcc: FourCC::from(fmt.fourcc),
+        stride: fmt.stride,
+        size: fmt.size,
+        colorspace,
+        xfer: fmt.transfer.into(),
+        ycbcr_enc: YcbcrEncoding::for_colorspace(colorspace),
+        quantization: fmt.quantization.into(),
+        field: fmt.field_order.into(),
     }
 }
 
 /// V4L2 capture stream wrapping mmap-based streaming.
+///
+/// Borrowed frames point straight into the driver's mmap buffers, and each
+/// buffer is queued again as soon as its frame is dropped.
 pub struct V4L2Stream<'a> {
-    stream: Stream<'a>,
+    /// Reactor registration of the stream fd, created on first async poll.
+    /// Declared before `buffers` so it is deregistered before streaming stops.
+    #[cfg(feature = "tokio")]
+    reactor: Option<AsyncFd<StreamFd>>,
+    buffers: MappedBuffers,
+    started: bool,
+    _device: PhantomData<&'a mut Device>,
 }
 
 impl CaptureStream for V4L2Stream<'_> {
-    fn next_frame(&mut self) -> Result<Frame> {
-        let (buf, meta) = self
-            .stream
-            .next()
-            .map_err(|err| CameraError::StreamError(err.to_string()))?;
+    fn next_frame_ref(&mut self) -> Result<FrameRef<'_>> {
+        self.capture(None)
+    }
+
+    fn next_frame_ref_timeout(&mut self, timeout: Duration) -> Result<FrameRef<'_>> {
+        self.capture(Some(timeout))
+    }
+}
+
+impl V4L2Stream<'_> {
+    /// Queue the buffers and turn streaming on.
+    fn start(&mut self) -> Result<()> {
+        self.buffers.start()?;
+        self.started = true;
+        Ok(())
+    }
+
+    /// Dequeue the next buffer, waiting at most `timeout` if one is given.
+    fn capture(&mut self, timeout: Option<Duration>) -> Result<FrameRef<'_>> {
+        if !self.started {
+            self.start()?;
+        }
+        let millis = timeout.map_or(-1, poll_millis);
+        if self.buffers.handle().poll(POLLIN, millis)? == 0 {
+            return Err(CameraError::Timeout);
+        }
+
+        let dequeued = match self.buffers.dequeue() {
+            Ok(dequeued) => dequeued,
+            // Only reachable if the buffer reported by poll vanished
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
+                return Err(CameraError::Timeout)
+            }
+            Err(err) => return Err(CameraError::StreamError(err.to_string())),
+        };
 
         // Safe conversions: V4L2 timestamps are always non-negative in practice
         #[allow(clippy::cast_sign_loss)]
-        let secs = meta.timestamp.sec.max(0) as u64;
+        let secs = dequeued.timestamp.sec.max(0) as u64;
         #[allow(clippy::cast_sign_loss, clippy::cast_possible_truncation)]
-        let nanos = (meta.timestamp.usec.max(0) as u32).saturating_mul(1000);
+        let nanos = (dequeued.timestamp.usec.max(0) as u32).saturating_mul(1000);
 
-        Ok(Frame {
-            data: buf.to_vec(),
-            metadata: FrameMetadata {
-                sequence: meta.sequence,
+        let Some(buf) = self.buffers.buffer(dequeued.index) else {
+            // Hand the buffer straight back rather than losing it
+            let _ = self.buffers.queue(dequeued.index);
+            return Err(CameraError::StreamError(format!(
+                "driver returned unknown buffer {}",
+                dequeued.index
+            )));
+        };
+        // Compressed frames fill only part of the buffer
+        let data = buf.get(..dequeued.bytes_used as usize).unwrap_or(buf);
+        Ok(FrameRef::in_buffer(
+            data,
+            FrameMetadata {
+                sequence: dequeued.sequence,
                 timestamp: Duration::new(secs, nanos),
-                bytes_used: meta.bytesused,
+                bytes_used: dequeued.bytes_used,
             },
-        })
+            &self.buffers,
+            dequeued.index,
+        ))
     }
 }
+
+#[cfg(feature = "tokio")]
+impl AsyncCapture for V4L2Stream<'_> {
+    fn poll_frame_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        if !self.started {
+            self.start()?;
+        }
+        let reactor = match &mut self.reactor {
+            Some(reactor) => reactor,
+            slot @ None => {
+                let fd = StreamFd(self.buffers.handle().fd());
+                slot.insert(AsyncFd::with_interest(fd, Interest::READABLE)?)
+            }
+        };
+
+        loop {
+            let mut guard = ready!(reactor.poll_read_ready(cx))?;
+            // Reactor readiness is edge-triggered and may be stale
+            if self.buffers.handle().poll(POLLIN, 0)? > 0 {
+                return Poll::Ready(Ok(()));
+            }
+            guard.clear_ready();
+        }
+    }
+}
+
+/// Convert a timeout to poll(2) milliseconds, rounding partial milliseconds up.
+fn poll_millis(timeout: Duration) -> i32 {
+    i32::try_from(timeout.as_micros().div_ceil(1000)).unwrap_or(i32::MAX)
+}
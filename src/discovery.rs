@@ -0,0 +1,152 @@
+//! V4L2 device discovery.
+//!
+//! Device indices are assigned by the kernel in probe order and are not stable
+//! across reboots on systems with several cameras. This module enumerates the
+//! video nodes present under `/dev` so callers can select a device by path,
+//! card name or bus information instead of by index.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use v4l::capability::Flags;
+use v4l::Device;
+
+use crate::traits::{CameraError, DeviceCapabilities, Result};
+
+/// Directory scanned for video device nodes.
+const DEV_DIR: &str = "/dev";
+
+/// Prefix of V4L2 video device node names (e.g., `video0`).
+const VIDEO_NODE_PREFIX: &str = "video";
+
+/// A discovered V4L2 video node.
+#[derive(Debug, Clone)]
+pub struct DeviceInfo {
+    /// Device node path (e.g., `/dev/video0`).
+    pub path: PathBuf,
+    /// Capabilities reported by the driver for this node.
+    pub capabilities: DeviceCapabilities,
+}
+
+impl DeviceInfo {
+    /// Query the driver for the node at `path`.
+    pub fn probe<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let device = Device::with_path(path)
+            .map_err(|err| CameraError::DeviceOpenFailed(format!("{}: {err}", path.display())))?;
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            capabilities: query_capabilities(&device)?,
+        })
+    }
+}
+
+/// List every V4L2 video node on the system.
+///
+/// Nodes are returned in ascending index order. Nodes that cannot be opened
+/// or queried (e.g., due to missing permissions) are skipped.
+///
+/// # Errors
+///
+/// Returns `Io` if the device directory cannot be read.
+pub fn list_devices() -> Result<Vec<DeviceInfo>> {
+    let mut nodes: Vec<(u32, PathBuf)> = fs::read_dir(DEV_DIR)?
+        .filter_map(std::result::Result::ok)
+        .filter_map(|entry| {
+            let index = node_index(&entry.file_name().to_string_lossy())?;
+            Some((index, entry.path()))
+        })
+        .collect();
+    nodes.sort_by_key(|(index, _)| *index);
+
+    Ok(nodes
+        .into_iter()
+        .filter_map(|(_, path)| DeviceInfo::probe(path).ok())
+        .collect())
+}
+
+/// Find the first capture-capable node whose card name equals `card`.
+///
+/// # Errors
+///
+/// Returns `NoMatchingDevice` if no capture node reports the given card name,
+/// or `Io` if the device directory cannot be read.
+pub fn find_by_card(card: &str) -> Result<DeviceInfo> {
+    find_capture_device(|caps| caps.card == card)?
+        .ok_or_else(|| CameraError::NoMatchingDevice(format!("card \"{card}\"")))
+}
+
+/// Find the first capture-capable node whose bus information equals `bus_info`.
+///
+/// # Errors
+///
+/// Returns `NoMatchingDevice` if no capture node reports the given bus information,
+/// or `Io` if the device directory cannot be read.
+pub fn find_by_bus_info(bus_info: &str) -> Result<DeviceInfo> {
+    find_capture_device(|caps| caps.bus_info == bus_info)?
+        .ok_or_else(|| CameraError::NoMatchingDevice(format!("bus info \"{bus_info}\"")))
+}
+
+/// Find the first capture-capable node satisfying `predicate`.
+///
+/// Drivers such as rp1-cfe expose several nodes (image, embedded data,
+/// statistics) under the same card name, so only nodes that can capture
+/// video are considered.
+fn find_capture_device<F>(predicate: F) -> Result<Option<DeviceInfo>>
+where
+    F: Fn(&DeviceCapabilities) -> bool,
+{
+    Ok(list_devices()?
+        .into_iter()
+        .find(|info| info.capabilities.can_capture && predicate(&info.capabilities)))
+}
+
+/// Query and convert the driver capabilities of an open device.
+pub(crate) fn query_capabilities(device: &Device) -> Result<DeviceCapabilities> {
+    let caps = device
+        .query_caps()
+        .map_err(|err| CameraError::DeviceOpenFailed(err.to_string()))?;
+
+    Ok(DeviceCapabilities {
+        driver: caps.driver,
+        card: caps.card,
+        bus_info: caps.bus,
+        can_capture: caps.capabilities.contains(Flags::VIDEO_CAPTURE),
+        can_stream: caps.capabilities.contains(Flags::STREAMING),
+    })
+}
+
+/// Parse the index out of a video node name (`video12` -> `12`).
+fn node_index(name: &str) -> Option<u32> {
+    let digits = name.strip_prefix(VIDEO_NODE_PREFIX)?;
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_node_index_valid() {
+        assert_eq!(node_index("video0"), Some(0));
+        assert_eq!(node_index("video12"), Some(12));
+    }
+
+    #[test]
+    fn test_node_index_rejects_other_nodes() {
+        assert_eq!(node_index("video"), None);
+        assert_eq!(node_index("video-dec0"), None);
+        assert_eq!(node_index("media0"), None);
+        assert_eq!(node_index("v4l-subdev0"), None);
+    }
+
+    #[test]
+    fn test_probe_missing_node_fails() {
+        let result = DeviceInfo::probe("/nonexistent/video0");
+        assert!(matches!(result, Err(CameraError::DeviceOpenFailed(_))));
+    }
+}
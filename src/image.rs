@@ -0,0 +1,225 @@
+//! Still image export.
+//!
+//! Frames are converted through [`convert`](crate::convert), so every format
+//! it handles (packed YUV 4:2:2, RGB, greyscale, and semi-planar or planar
+//! YUV such as NV12) can be saved. YUV is converted with the colorimetry of
+//! the format. PPM and PGM are written without any dependency; PNG requires
+//! the `png` feature.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::convert::{to_luma, to_rgb24};
+#[cfg(feature = "png")]
+use crate::traits::FourCC;
+use crate::traits::{Format, FrameRef, Result};
+
+/// Image file format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageFormat {
+    /// Binary portable pixmap (`P6`), 8-bit RGB.
+    Ppm,
+    /// Binary portable graymap (`P5`), 8-bit luma.
+    Pgm,
+    /// PNG, greyscale for greyscale frames and RGB otherwise.
+    #[cfg(feature = "png")]
+    Png,
+}
+
+impl ImageFormat {
+    /// Pick the image format matching the extension of `path`.
+    ///
+    /// Returns `None` for unknown extensions, and for `.png` without the
+    /// `png` feature.
+    #[must_use]
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "ppm" => Some(Self::Ppm),
+            "pgm" => Some(Self::Pgm),
+            #[cfg(feature = "png")]
+            "png" => Some(Self::Png),
+            _ => None,
+        }
+    }
+}
+
+/// Save a frame to `path`, choosing the image format from the extension.
+///
+/// # Errors
+///
+/// Returns `Io` if the extension is not a supported image format or the
+/// file cannot be written, or the errors of [`write_image`].
+pub fn save_image<P: AsRef<Path>>(path: P, frame: &FrameRef<'_>, format: &Format) -> Result<()> {
+    let path = path.as_ref();
+    let image = ImageFormat::from_path(path).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Unsupported image extension: {}", path.display()),
+        )
+    })?;
+    // Encode first, so a frame that cannot be converted leaves no file behind
+    let mut bytes = Vec::new();
+    write_image(&mut bytes, image, frame, format)?;
+    fs::write(path, bytes)?;
+    Ok(())
+}
+
+/// Write a frame as an `image` file.
+///
+/// # Errors
+///
+/// Returns `FormatNotSupported` if the frame format cannot be converted,
+/// `StreamError` if the frame holds less data than its format describes, or
+/// `Io` if writing fails.
+pub fn write_image<W: Write>(
+    writer: W,
+    image: ImageFormat,
+    frame: &FrameRef<'_>,
+    format: &Format,
+) -> Result<()> {
+    match image {
+        ImageFormat::Ppm => {
+            let pixels = to_rgb24(frame.data, format, format.colorimetry())?;
+            write_pnm(writer, "P6", format, &pixels)
+        }
+        ImageFormat::Pgm => write_pnm(writer, "P5", format, &to_luma(frame.data, format)?),
+        #[cfg(feature = "png")]
+        ImageFormat::Png => write_png(writer, frame, format),
+    }
+}
+
+/// Write a binary PPM or PGM image.
+fn write_pnm<W: Write>(mut writer: W, magic: &str, format: &Format, pixels: &[u8]) -> Result<()> {
+    write!(writer, "{magic}\n{} {}\n255\n", format.width, format.height)?;
+    writer.write_all(pixels)?;
+    Ok(())
+}
+
+/// Write a PNG image.
+#[cfg(feature = "png")]
+fn write_png<W: Write>(writer: W, frame: &FrameRef<'_>, format: &Format) -> Result<()> {
+    let (color, pixels) = if matches!(format.fourcc, FourCC::GREY | FourCC::Y16) {
+        (png::ColorType::Grayscale, to_luma(frame.data, format)?)
+    } else {
+        (
+            png::ColorType::Rgb,
+            to_rgb24(frame.data, format, format.colorimetry())?,
+        )
+    };
+
+    let mut encoder = png::Encoder::new(writer, format.width, format.height);
+    encoder.set_color(color);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(png_error)?;
+    writer.write_image_data(&pixels).map_err(png_error)?;
+    writer.finish().map_err(png_error)?;
+    Ok(())
+}
+
+/// Map a PNG encoder error to an I/O error.
+#[cfg(feature = "png")]
+fn png_error(err: png::EncodingError) -> io::Error {
+    match err {
+        png::EncodingError::IoError(err) => err,
+        err => io::Error::new(io::ErrorKind::InvalidData, err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{generate_test_frame, TestPattern};
+    use crate::traits::{CameraError, FourCC, Frame, FrameMetadata};
+    use std::time::Duration;
+
+    fn frame(data: Vec<u8>) -> Frame {
+        Frame {
+            metadata: FrameMetadata {
+                sequence: 0,
+                timestamp: Duration::ZERO,
+                bytes_used: u32::try_from(data.len()).expect("frame fits u32"),
+            },
+            data,
+        }
+    }
+
+    #[test]
+    fn test_ppm_and_pgm() {
+        let format = Format::new(2, 1, FourCC::RGB3);
+        let frame = frame(vec![255, 0, 0, 0, 0, 255]);
+
+        let mut ppm = Vec::new();
+        write_image(&mut ppm, ImageFormat::Ppm, &frame.as_frame_ref(), &format)
+            .expect("PPM should write");
+        assert_eq!(ppm, b"P6\n2 1\n255\n\xff\x00\x00\x00\x00\xff");
+
+        let mut pgm = Vec::new();
+        write_image(&mut pgm, ImageFormat::Pgm, &frame.as_frame_ref(), &format)
+            .expect("PGM should write");
+        assert_eq!(pgm, b"P5\n2 1\n255\n\x4d\x1d");
+    }
+
+    #[test]
+    fn test_yuyv_ppm_size() {
+        let format = Format::new(640, 480, FourCC::YUYV);
+        let frame = frame(generate_test_frame(&format, TestPattern::ColorBars));
+        let mut ppm = Vec::new();
+        write_image(&mut ppm, ImageFormat::Ppm, &frame.as_frame_ref(), &format)
+            .expect("PPM should write");
+        assert_eq!(ppm.len(), 15 + 640 * 480 * 3);
+        assert!(ppm.starts_with(b"P6\n640 480\n255\n"));
+    }
+
+    #[test]
+    fn test_image_format_from_path() {
+        assert_eq!(ImageFormat::from_path("shot.ppm"), Some(ImageFormat::Ppm));
+        assert_eq!(
+            ImageFormat::from_path("/tmp/SHOT.PGM"),
+            Some(ImageFormat::Pgm)
+        );
+        assert_eq!(ImageFormat::from_path("shot.bmp"), None);
+        assert_eq!(ImageFormat::from_path("shot"), None);
+
+        let format = Format::new(640, 480, FourCC::YUYV);
+        let frame = frame(Vec::new());
+        assert!(matches!(
+            save_image("shot.bmp", &frame.as_frame_ref(), &format),
+            Err(CameraError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_failed_save_leaves_no_file() {
+        let path = std::env::temp_dir().join(format!("pi-cam-short-{}.ppm", std::process::id()));
+        let format = Format::new(640, 480, FourCC::YUYV);
+        let frame = frame(vec![0; 100]);
+        assert!(matches!(
+            save_image(&path, &frame.as_frame_ref(), &format),
+            Err(CameraError::StreamError(_))
+        ));
+        assert!(!path.exists(), "{} should not be created", path.display());
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn test_png() {
+        let format = Format::new(4, 2, FourCC::GREY);
+        let frame = frame((0..8).collect());
+        let mut png = Vec::new();
+        write_image(&mut png, ImageFormat::Png, &frame.as_frame_ref(), &format)
+            .expect("PNG should write");
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+
+        let decoder = png::Decoder::new(png.as_slice());
+        let mut reader = decoder.read_info().expect("PNG should decode");
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader
+            .next_frame(&mut pixels)
+            .expect("PNG frame should decode");
+        assert_eq!((info.width, info.height), (4, 2));
+        assert_eq!(info.color_type, png::ColorType::Grayscale);
+        assert_eq!(pixels, (0..8).collect::<Vec<u8>>());
+    }
+}
@@ -0,0 +1,639 @@
+//! Software image signal processing.
+//!
+//! Turns raw Bayer or already demosaiced linear RGB frames into viewable
+//! images on the CPU, the job the hardware ISP and libcamera do on a
+//! Raspberry Pi. [`Isp`] runs the classic steps in order:
+//!
+//! 1. Black level subtraction, rescaling the remaining range to full scale.
+//! 2. White balance gains per color channel.
+//! 3. Demosaicing, for raw input (see [`bayer`](crate::bayer)).
+//! 4. A 3x3 color correction matrix from camera RGB to sRGB.
+//! 5. A gamma or tone curve.
+//! 6. Output as RGB3 or YUYV.
+//!
+//! The parameters come from [`IspParams`], which can be loaded from a text
+//! tuning file.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::bayer::{Demosaic, RawImage};
+use crate::convert::{average, to_rgb24};
+use crate::pixel_format::Layout;
+use crate::traits::{CameraError, Format, FourCC, Result};
+
+/// First line of a tuning file.
+const TUNING_SIGNATURE: &str = "pi-cam-capture tuning 1";
+
+/// Entries of the tone curve lookup table.
+const TONE_LUT_SIZE: usize = 4096;
+
+/// Tone curve applied to linear values in `0.0..=1.0`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum ToneCurve {
+    /// No curve; output is linear.
+    Linear,
+    /// Power law with the given display gamma, e.g. `2.2`.
+    Gamma(f32),
+    /// The sRGB transfer function.
+    #[default]
+    Srgb,
+    /// Piecewise linear curve through `(input, output)` points with
+    /// increasing inputs; inputs outside the points clamp to the ends.
+    Points(Vec<(f32, f32)>),
+}
+
+impl ToneCurve {
+    /// Map a linear value in `0.0..=1.0` through the curve.
+    #[must_use]
+    pub fn apply(&self, value: f32) -> f32 {
+        let value = value.clamp(0.0, 1.0);
+        match self {
+            Self::Linear => value,
+            Self::Gamma(gamma) => value.powf(gamma.recip()),
+            Self::Srgb if value <= 0.003_130_8 => value * 12.92,
+            Self::Srgb => 1.055f32.mul_add(value.powf(1.0 / 2.4), -0.055),
+            Self::Points(points) => interpolate(points, value),
+        }
+    }
+}
+
+/// Evaluate a piecewise linear curve at `value`.
+fn interpolate(points: &[(f32, f32)], value: f32) -> f32 {
+    let Some(&(first_x, first_y)) = points.first() else {
+        return value;
+    };
+    if value <= first_x {
+        return first_y;
+    }
+    points
+        .windows(2)
+        .find_map(|pair| match *pair {
+            [(x0, y0), (x1, y1)] if value <= x1 => {
+                let t = (value - x0) / (x1 - x0);
+                Some((y1 - y0).mul_add(t, y0))
+            }
+            _ => None,
+        })
+        .or_else(|| points.last().map(|&(_, y)| y))
+        .unwrap_or(value)
+}
+
+/// Parameters of the software ISP.
+///
+/// A tuning file holds them as text: a signature line, then one parameter
+/// per line, any of which may be left out to keep its default. Blank lines
+/// and lines starting with `#` are ignored.
+///
+/// ```text
+/// pi-cam-capture tuning 1
+/// # Black level on a 16-bit scale (64 at 10 bits)
+/// black_level 4096
+/// # Red, green and blue gains
+/// white_balance 1.9 1 1.6
+/// # Color correction matrix, row by row
+/// ccm 1.7 -0.5 -0.2 -0.3 1.6 -0.3 0 -0.6 1.6
+/// # linear, srgb, a display gamma such as 2.2, or tone_curve points
+/// gamma srgb
+/// tone_curve 0:0 0.25:0.5 1:1
+/// demosaic edge-aware
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct IspParams {
+    /// Sensor black level on a 16-bit scale, as libcamera tuning files give
+    /// it; a 10-bit sensor with a pedestal of 64 has 4096.
+    pub black_level: u16,
+    /// Red, green and blue white balance gains.
+    pub white_balance: [f32; 3],
+    /// Color correction matrix from camera RGB to linear sRGB, row by row.
+    pub ccm: [[f32; 3]; 3],
+    /// Tone curve applied after color correction.
+    pub tone_curve: ToneCurve,
+    /// Demosaicing algorithm for raw input.
+    pub demosaic: Demosaic,
+}
+
+impl Default for IspParams {
+    /// No black level, unity gains, the identity matrix, the sRGB curve and
+    /// bilinear demosaicing.
+    fn default() -> Self {
+        Self {
+            black_level: 0,
+            white_balance: [1.0; 3],
+            ccm: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+            tone_curve: ToneCurve::Srgb,
+            demosaic: Demosaic::Bilinear,
+        }
+    }
+}
+
+impl IspParams {
+    /// Parse the text form of a tuning file.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Io` with `InvalidData` naming the first malformed line.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+        if lines.next().map(|(_, line)| line) != Some(TUNING_SIGNATURE) {
+            return Err(CameraError::invalid_data(
+                "Missing tuning file signature".to_owned(),
+            ));
+        }
+
+        let mut params = Self::default();
+        for (number, line) in lines {
+            let malformed =
+                || CameraError::invalid_data(format!("Malformed tuning line {number}: {line}"));
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                ["black_level", level] => {
+                    params.black_level = level.parse().map_err(|_| malformed())?;
+                }
+                ["white_balance", gains @ ..] => {
+                    params.white_balance = parse_floats(gains)
+                        .filter(|gains| gains.iter().all(|&gain| gain > 0.0))
+                        .ok_or_else(malformed)?;
+                }
+                ["ccm", values @ ..] => {
+                    params.ccm = parse_matrix(values).ok_or_else(malformed)?;
+                }
+                ["gamma", "linear"] => params.tone_curve = ToneCurve::Linear,
+                ["gamma", "srgb"] => params.tone_curve = ToneCurve::Srgb,
+                ["gamma", gamma] => {
+                    let [gamma] = parse_floats(&[gamma])
+                        .filter(|&[gamma]| gamma > 0.0)
+                        .ok_or_else(malformed)?;
+                    params.tone_curve = ToneCurve::Gamma(gamma);
+                }
+                ["tone_curve", points @ ..] => {
+                    params.tone_curve =
+                        ToneCurve::Points(parse_points(points).ok_or_else(malformed)?);
+                }
+                ["demosaic", "bilinear"] => params.demosaic = Demosaic::Bilinear,
+                ["demosaic", "edge-aware"] => params.demosaic = Demosaic::EdgeAware,
+                _ => return Err(malformed()),
+            }
+        }
+        Ok(params)
+    }
+
+    /// Load a tuning file.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Io` if the file cannot be read, or the errors of
+    /// [`IspParams::parse`].
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+}
+
+impl fmt::Display for IspParams {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [red, green, blue] = self.white_balance;
+        writeln!(f, "{TUNING_SIGNATURE}")?;
+        writeln!(f, "black_level {}", self.black_level)?;
+        writeln!(f, "white_balance {red} {green} {blue}")?;
+        write!(f, "ccm")?;
+        for value in self.ccm.iter().flatten() {
+            write!(f, " {value}")?;
+        }
+        writeln!(f)?;
+        match &self.tone_curve {
+            ToneCurve::Linear => writeln!(f, "gamma linear")?,
+            ToneCurve::Gamma(gamma) => writeln!(f, "gamma {gamma}")?,
+            ToneCurve::Srgb => writeln!(f, "gamma srgb")?,
+            ToneCurve::Points(points) => {
+                write!(f, "tone_curve")?;
+                for (input, output) in points {
+                    write!(f, " {input}:{output}")?;
+                }
+                writeln!(f)?;
+            }
+        }
+        let demosaic = match self.demosaic {
+            Demosaic::Bilinear => "bilinear",
+            Demosaic::EdgeAware => "edge-aware",
+        };
+        writeln!(f, "demosaic {demosaic}")
+    }
+}
+
+/// Parse a fixed number of finite decimal fields.
+fn parse_floats<const N: usize>(fields: &[&str]) -> Option<[f32; N]> {
+    let mut values = [0.0; N];
+    if fields.len() != N {
+        return None;
+    }
+    for (value, field) in values.iter_mut().zip(fields) {
+        *value = field.parse().ok().filter(|value: &f32| value.is_finite())?;
+    }
+    Some(values)
+}
+
+/// Parse a 3x3 matrix given row by row.
+fn parse_matrix(fields: &[&str]) -> Option<[[f32; 3]; 3]> {
+    let values: [f32; 9] = parse_floats(fields)?;
+    let mut matrix = [[0.0; 3]; 3];
+    for (row, chunk) in matrix.iter_mut().zip(values.chunks_exact(3)) {
+        row.copy_from_slice(chunk);
+    }
+    Some(matrix)
+}
+
+/// Parse `input:output` tone curve points in `0..=1` with increasing inputs.
+fn parse_points(fields: &[&str]) -> Option<Vec<(f32, f32)>> {
+    let points = fields
+        .iter()
+        .map(|field| {
+            let (input, output) = field.split_once(':')?;
+            let [input, output] = parse_floats(&<[&str; 2]>::from((input, output)))?;
+            let in_range = |value: f32| (0.0..=1.0).contains(&value);
+            (in_range(input) && in_range(output)).then_some((input, output))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    let increasing = points.windows(2).all(|pair| match *pair {
+        [(x0, _), (x1, _)] => x0 < x1,
+        _ => true,
+    });
+    (points.len() >= 2 && increasing).then_some(points)
+}
+
+/// Software ISP configured with a set of [`IspParams`].
+#[derive(Debug, Clone)]
+pub struct Isp {
+    params: IspParams,
+    /// Tone curve sampled at evenly spaced inputs, scaled to 8 bits.
+    tone_lut: Vec<u8>,
+}
+
+impl Isp {
+    /// Create an ISP, precomputing its tone curve.
+    #[must_use]
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    pub fn new(params: IspParams) -> Self {
+        let last = (TONE_LUT_SIZE - 1) as f32;
+        let tone_lut = (0..TONE_LUT_SIZE)
+            .map(|index| {
+                let output = params.tone_curve.apply(index as f32 / last);
+                (output * 255.0).round().clamp(0.0, 255.0) as u8
+            })
+            .collect();
+        Self { params, tone_lut }
+    }
+
+    /// Parameters of the ISP.
+    #[must_use]
+    pub const fn params(&self) -> &IspParams {
+        &self.params
+    }
+
+    /// Process a raw Bayer image to tightly packed 8-bit RGB.
+    ///
+    /// Black level and white balance are applied to the raw samples, before
+    /// demosaicing.
+    ///
+    /// # Errors
+    ///
+    /// Returns `StreamError` if the bit depth of `raw` is not `1..=16`.
+    pub fn process_raw(&self, raw: &RawImage) -> Result<Vec<u8>> {
+        if !(1..=16).contains(&raw.format.bit_depth) {
+            return Err(CameraError::StreamError(format!(
+                "Unsupported Bayer bit depth {}",
+                raw.format.bit_depth
+            )));
+        }
+        let max = f32::from(raw.format.max_value());
+        let black = self.params.black_level >> (16 - raw.format.bit_depth);
+        let mut balanced = raw.clone();
+        let positions = (0..raw.height).flat_map(|y| (0..raw.width).map(move |x| (x, y)));
+        for ((x, y), sample) in positions.zip(&mut balanced.samples) {
+            let channel = raw.format.pattern.color_at(x, y).index();
+            let level = self.level(*sample, black, raw.format.max_value(), channel);
+            *sample = quantize(level * max, raw.format.max_value());
+        }
+
+        Ok(balanced
+            .demosaic(self.params.demosaic)
+            .chunks_exact(3)
+            .flat_map(|pixel| {
+                let mut rgb = [0.0; 3];
+                for (value, &sample) in rgb.iter_mut().zip(pixel) {
+                    *value = f32::from(sample) / max;
+                }
+                self.finish(rgb)
+            })
+            .collect())
+    }
+
+    /// Process demosaiced linear 8-bit RGB, three bytes per pixel.
+    #[must_use]
+    pub fn process_rgb(&self, rgb: &[u8]) -> Vec<u8> {
+        let black = self.params.black_level >> 8;
+        rgb.chunks_exact(3)
+            .flat_map(|pixel| {
+                let mut linear = [0.0; 3];
+                for (channel, (value, &sample)) in linear.iter_mut().zip(pixel).enumerate() {
+                    *value = self.level(u16::from(sample), black, 255, channel);
+                }
+                self.finish(linear)
+            })
+            .collect()
+    }
+
+    /// Process a frame to `output`, either `RGB3` or `YUYV`.
+    ///
+    /// Raw Bayer frames run through the whole pipeline; `RGB3` frames are
+    /// taken as demosaiced linear RGB. YUYV output uses the default
+    /// colorimetry of its format, BT.601 with limited range, and averages
+    /// the chroma of each pixel pair.
+    ///
+    /// # Errors
+    ///
+    /// Returns `FormatNotSupported` for other input or output formats, or
+    /// `StreamError` if `data` is shorter than `format` describes.
+    pub fn process(
+        &self,
+        data: &[u8],
+        format: &Format,
+        output: FourCC,
+    ) -> Result<(Format, Vec<u8>)> {
+        let unsupported = |format: Format, reason: &str| CameraError::FormatNotSupported {
+            format,
+            reason: reason.to_owned(),
+        };
+        let output = Format::new(format.width, format.height, output);
+        if !matches!(output.fourcc, FourCC::RGB3 | FourCC::YUYV) {
+            return Err(unsupported(output, "is not an ISP output format"));
+        }
+
+        let rgb = match format.fourcc.info().map(|info| info.layout) {
+            Some(Layout::Bayer) => self.process_raw(&RawImage::unpack(data, format)?)?,
+            _ if format.fourcc == FourCC::RGB3 => {
+                self.process_rgb(&to_rgb24(data, format, format.colorimetry())?)
+            }
+            _ => return Err(unsupported(format.clone(), "is not an ISP input format")),
+        };
+        let data = if output.fourcc == FourCC::YUYV {
+            rgb_to_yuyv(&rgb, &output)
+        } else {
+            rgb
+        };
+        Ok((output, data))
+    }
+
+    /// Subtract the black level from a sample of `channel` and apply its
+    /// white balance gain, normalizing to `0.0..=1.0`.
+    fn level(&self, sample: u16, black: u16, max: u16, channel: usize) -> f32 {
+        let range = f32::from(max.saturating_sub(black).max(1));
+        let gain = self
+            .params
+            .white_balance
+            .get(channel)
+            .copied()
+            .unwrap_or(1.0);
+        (f32::from(sample.saturating_sub(black)) / range * gain).min(1.0)
+    }
+
+    /// Apply the color correction matrix and tone curve to a linear pixel.
+    #[allow(clippy::cast_precision_loss)]
+    fn finish(&self, [red, green, blue]: [f32; 3]) -> [u8; 3] {
+        let last = (TONE_LUT_SIZE - 1) as f32;
+        self.params.ccm.map(|[from_red, from_green, from_blue]| {
+            let value = from_red.mul_add(red, from_green.mul_add(green, from_blue * blue));
+            let index = usize::from(quantize(value.clamp(0.0, 1.0) * last, u16::MAX));
+            self.tone_lut.get(index).copied().unwrap_or(u8::MAX)
+        })
+    }
+}
+
+/// Round a non-negative value to the nearest integer up to `max`.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn quantize(value: f32, max: u16) -> u16 {
+    value.round().clamp(0.0, f32::from(max)) as u16
+}
+
+/// Pack tightly packed RGB as YUYV laid out as `format`.
+fn rgb_to_yuyv(rgb: &[u8], format: &Format) -> Vec<u8> {
+    let colorimetry = format.colorimetry();
+    let mut data = vec![0; format.size as usize];
+    let rows = rgb.chunks_exact((format.width as usize * 3).max(1));
+    for (line, row) in data.chunks_mut(format.stride.max(1) as usize).zip(rows) {
+        for (group, pair) in line.chunks_mut(4).zip(row.chunks(6)) {
+            let yuv: Vec<(u8, u8, u8)> = pair
+                .chunks_exact(3)
+                .map(|pixel| match *pixel {
+                    [r, g, b] => colorimetry.rgb_to_yuv(r, g, b),
+                    _ => (0, 128, 128),
+                })
+                .collect();
+            let (y0, u0, v0) = yuv.first().copied().unwrap_or((0, 128, 128));
+            let (y1, u1, v1) = yuv.get(1).copied().unwrap_or((y0, u0, v0));
+            let bytes = [y0, average(u0, u1), y1, average(v0, v1)];
+            for (dst, src) in group.iter_mut().zip(bytes) {
+                *dst = src;
+            }
+        }
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bayer::BayerFormat;
+    use crate::mock::{generate_test_frame, TestPattern};
+    use crate::traits::{Frame, FrameMetadata};
+    use crate::validation::validate_color_bars;
+    use std::time::Duration;
+
+    /// Parameters that leave linear values untouched.
+    fn neutral() -> IspParams {
+        IspParams {
+            tone_curve: ToneCurve::Linear,
+            ..IspParams::default()
+        }
+    }
+
+    /// A flat raw image of `color`, given at 10 bits.
+    fn flat_raw(color: [u16; 3]) -> RawImage {
+        let format = BayerFormat::from_fourcc(FourCC::SRGGB10).expect("SRGGB10 should be Bayer");
+        let samples = (0..8u32)
+            .flat_map(|y| (0..8u32).map(move |x| (x, y)))
+            .map(|(x, y)| color[format.pattern.color_at(x, y).index()])
+            .collect();
+        RawImage {
+            width: 8,
+            height: 8,
+            format,
+            samples,
+        }
+    }
+
+    #[test]
+    fn test_tone_curves() {
+        assert!((ToneCurve::Srgb.apply(0.5) - 0.735_357).abs() < 1e-4);
+        assert!((ToneCurve::Gamma(2.0).apply(0.25) - 0.5).abs() < 1e-6);
+        assert!((ToneCurve::Linear.apply(1.5) - 1.0).abs() < f32::EPSILON);
+        let points = ToneCurve::Points(vec![(0.0, 0.1), (0.5, 0.3), (1.0, 1.0)]);
+        assert!((points.apply(0.25) - 0.2).abs() < 1e-6);
+        assert!((points.apply(0.75) - 0.65).abs() < 1e-6);
+        assert!((points.apply(0.0) - 0.1).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_tuning_round_trip() {
+        let params = IspParams {
+            black_level: 4096,
+            white_balance: [1.9, 1.0, 1.6],
+            ccm: [[1.7, -0.5, -0.2], [-0.3, 1.6, -0.3], [0.0, -0.6, 1.6]],
+            tone_curve: ToneCurve::Points(vec![(0.0, 0.0), (0.25, 0.5), (1.0, 1.0)]),
+            demosaic: Demosaic::EdgeAware,
+        };
+        let text = params.to_string();
+        assert!(text.contains("white_balance 1.9 1 1.6\n"));
+        assert_eq!(
+            IspParams::parse(&text).expect("tuning should parse"),
+            params
+        );
+
+        for tone_curve in [ToneCurve::Linear, ToneCurve::Srgb, ToneCurve::Gamma(2.2)] {
+            let params = IspParams {
+                tone_curve,
+                ..IspParams::default()
+            };
+            assert_eq!(
+                IspParams::parse(&params.to_string()).expect("tuning should parse"),
+                params
+            );
+        }
+    }
+
+    #[test]
+    fn test_tuning_parse_errors() {
+        let valid = "pi-cam-capture tuning 1\n# comment\n\ngamma 2.2\n";
+        let params = IspParams::parse(valid).expect("tuning should parse");
+        assert_eq!(params.tone_curve, ToneCurve::Gamma(2.2));
+        assert_eq!(params.demosaic, Demosaic::Bilinear);
+
+        for text in [
+            "gamma 2.2\n",
+            "pi-cam-capture tuning 1\nblack_level -1\n",
+            "pi-cam-capture tuning 1\nwhite_balance 1 1\n",
+            "pi-cam-capture tuning 1\nwhite_balance 1 0 1\n",
+            "pi-cam-capture tuning 1\nccm 1 0 0 0 1 0 0 0 NaN\n",
+            "pi-cam-capture tuning 1\ngamma 0\n",
+            "pi-cam-capture tuning 1\ntone_curve 0:0 0:1\n",
+            "pi-cam-capture tuning 1\ntone_curve 0:0 1:2\n",
+            "pi-cam-capture tuning 1\ndemosaic nearest\n",
+            "pi-cam-capture tuning 1\nsaturation 1\n",
+        ] {
+            assert!(
+                IspParams::parse(text).is_err(),
+                "{text:?} should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn test_black_level_and_white_balance() {
+        // Pedestal of 64 at 10 bits; balances to grey at an eighth of the range
+        let raw = flat_raw([64 + 240, 64 + 120, 64 + 160]);
+        let isp = Isp::new(IspParams {
+            black_level: 64 << 6,
+            white_balance: [0.5, 1.0, 0.75],
+            ..neutral()
+        });
+        let rgb = isp.process_raw(&raw).expect("10-bit image should process");
+        assert!(
+            rgb.chunks_exact(3).all(|pixel| pixel == [32, 32, 32]),
+            "{:?}",
+            &rgb[..6]
+        );
+
+        let mut raw = raw;
+        raw.format.bit_depth = 0;
+        assert!(matches!(
+            isp.process_raw(&raw),
+            Err(CameraError::StreamError(_))
+        ));
+
+        let isp = Isp::new(IspParams {
+            black_level: 16 << 8,
+            white_balance: [2.0, 1.0, 1.0],
+            ..neutral()
+        });
+        assert_eq!(isp.process_rgb(&[136, 135, 255]), [255, 127, 255]);
+        assert_eq!(isp.process_rgb(&[16, 0, 10]), [0, 0, 0]);
+    }
+
+    #[test]
+    fn test_color_correction_and_gamma() {
+        let isp = Isp::new(IspParams {
+            ccm: [[0.0, 0.0, 1.0], [0.0, 1.0, 0.0], [1.5, 0.0, -0.5]],
+            ..neutral()
+        });
+        // Swaps red and blue, and mixes blue out of red with clipping
+        assert_eq!(isp.process_rgb(&[200, 100, 40]), [40, 100, 255]);
+        assert_eq!(isp.process_rgb(&[40, 100, 200]), [200, 100, 0]);
+
+        let isp = Isp::new(IspParams {
+            tone_curve: ToneCurve::Gamma(2.0),
+            ..neutral()
+        });
+        assert_eq!(isp.process_rgb(&[64, 0, 255]), [128, 0, 255]);
+    }
+
+    #[test]
+    fn test_process_mock_bayer() {
+        let isp = Isp::new(neutral());
+        for fourcc in [FourCC::SRGGB10P, FourCC::SBGGR12, FourCC::SGRBG8] {
+            let raw_format = Format::new(640, 480, fourcc);
+            let data = generate_test_frame(&raw_format, TestPattern::ColorBars);
+            for output in [FourCC::RGB3, FourCC::YUYV] {
+                let (format, image) = isp
+                    .process(&data, &raw_format, output)
+                    .expect("frame should process");
+                assert_eq!(format.fourcc, output);
+                assert_eq!(image.len(), format.size as usize);
+                let frame = Frame {
+                    data: image,
+                    metadata: FrameMetadata {
+                        sequence: 0,
+                        timestamp: Duration::ZERO,
+                        bytes_used: format.size,
+                    },
+                };
+                let result = validate_color_bars(&frame, &format);
+                assert!(result.is_ok(), "{fourcc} to {output}: {result:?}");
+            }
+        }
+
+        let yuyv = Format::new(640, 480, FourCC::YUYV);
+        let data = generate_test_frame(&yuyv, TestPattern::ColorBars);
+        assert!(matches!(
+            isp.process(&data, &yuyv, FourCC::RGB3),
+            Err(CameraError::FormatNotSupported { .. })
+        ));
+        let raw_format = Format::new(64, 16, FourCC::SRGGB10P);
+        assert!(matches!(
+            isp.process(&[0; 100], &raw_format, FourCC::NV12),
+            Err(CameraError::FormatNotSupported { .. })
+        ));
+        assert!(matches!(
+            isp.process(&[0; 100], &raw_format, FourCC::RGB3),
+            Err(CameraError::StreamError(_))
+        ));
+    }
+}
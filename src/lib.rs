@@ -3,15 +3,47 @@
 //! This library provides trait-based abstractions over V4L2 camera operations,
 //! enabling both production use with real hardware and testing with mock devices.
 
+#[cfg(feature = "tokio")]
+pub mod async_stream;
+pub mod auto;
+pub mod bayer;
+pub mod colorspace;
+pub mod controls;
+pub mod convert;
 pub mod device;
+pub mod discovery;
+pub mod image;
+pub mod isp;
+pub mod mjpeg;
+mod mmap;
+pub mod negotiation;
+pub mod pixel_format;
+pub mod recording;
+pub mod replay;
+mod simd;
+pub mod stats;
 pub mod traits;
 pub mod validation;
+pub mod worker;
+pub mod y4m;
 
-#[cfg(test)]
+#[cfg(any(test, feature = "mock"))]
 pub mod mock;
 
+#[cfg(feature = "tokio")]
+pub use async_stream::{AsyncCapture, AsyncFrameStream};
+pub use colorspace::{Colorspace, FieldOrder, Quantization, TransferFunction, YcbcrEncoding};
+pub use controls::{ControlInfo, ControlValue};
+pub use convert::{Colorimetry, RgbLayout, YuvMatrix, YuvRange};
 pub use device::V4L2Device;
+pub use discovery::{list_devices, DeviceInfo};
+pub use negotiation::{negotiate, Adjustment, FormatRequest, NegotiatedFormat};
+pub use pixel_format::{Layout, PixelFormatInfo};
+pub use recording::{Recorder, RecordingHeader, RecordingReader};
+pub use replay::{ReplayDevice, ReplayTiming, Sidecar};
 pub use traits::{
-    CameraDevice, CaptureStream, DeviceCapabilities, Format, FourCC, Frame, FrameMetadata,
+    CameraDevice, CaptureStream, DeviceCapabilities, Format, FormatDescription, FourCC, Fraction,
+    Frame, FrameInterval, FrameMetadata, FrameRef, FrameSize,
 };
 pub use validation::{validate_color_bars, validate_frame_sequence, validate_gradient};
+pub use worker::{CaptureWorker, OverflowPolicy, WorkerConfig, WorkerStats};
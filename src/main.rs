@@ -1,32 +1,159 @@
 //! Pi-cam-capture binary for testing camera capture.
 
-use pi_cam_capture::{CameraDevice, CaptureStream, Format, FourCC, V4L2Device};
+use pi_cam_capture::image::save_image;
+use pi_cam_capture::traits::{CameraError, Result};
+use pi_cam_capture::{
+    list_devices, negotiate, CameraDevice, CaptureStream, FormatRequest, FourCC, Recorder,
+    RecordingHeader, V4L2Device,
+};
+
+/// Command line usage.
+const USAGE: &str = "usage: pi-cam-capture [DEVICE]\n       \
+                     pi-cam-capture record FILE FRAMES [DEVICE]\n       \
+                     pi-cam-capture snapshot FILE [WARMUP [DEVICE]]";
+
+/// Pixel formats the binary captures, in order of preference.
+const CAPTURE_FOURCCS: &[FourCC] = &[
+    FourCC::YUYV,
+    FourCC::NV12,
+    FourCC::YU12,
+    FourCC::RGB3,
+    FourCC::GREY,
+    FourCC::MJPG,
+];
+
+/// Pixel formats the image export converts, in order of preference.
+const SNAPSHOT_FOURCCS: &[FourCC] = &[
+    FourCC::YUYV,
+    FourCC::NV12,
+    FourCC::YU12,
+    FourCC::RGB3,
+    FourCC::GREY,
+    #[cfg(feature = "mjpeg")]
+    FourCC::MJPG,
+];
+
+/// Frames discarded before a snapshot while exposure settles, by default.
+const WARMUP_FRAMES: u32 = 10;
+
+/// Action selected on the command line.
+enum Command {
+    /// Print the metadata of every captured frame.
+    Stream { selector: Option<String> },
+    /// Record a number of frames to a file.
+    Record {
+        path: String,
+        frames: u32,
+        selector: Option<String>,
+    },
+    /// Save one frame as an image after discarding warm-up frames.
+    Snapshot {
+        path: String,
+        warmup: u32,
+        selector: Option<String>,
+    },
+}
+
+impl Command {
+    /// Parse the arguments following the program name.
+    fn parse(args: &[String]) -> Option<Self> {
+        match args {
+            [command, path, frames, selector @ ..]
+                if command == "record" && selector.len() <= 1 =>
+            {
+                Some(Self::Record {
+                    path: path.clone(),
+                    frames: frames.parse().ok()?,
+                    selector: selector.first().cloned(),
+                })
+            }
+            [command, path, rest @ ..] if command == "snapshot" && rest.len() <= 2 => {
+                Some(Self::Snapshot {
+                    path: path.clone(),
+                    warmup: match rest.first() {
+                        Some(warmup) => warmup.parse().ok()?,
+                        None => WARMUP_FRAMES,
+                    },
+                    selector: rest.get(1).cloned(),
+                })
+            }
+            [command, ..] if command == "record" || command == "snapshot" => None,
+            [] | [_] => Some(Self::Stream {
+                selector: args.first().cloned(),
+            }),
+            _ => None,
+        }
+    }
+}
 
 fn main() {
-    if let Err(err) = run() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let Some(command) = Command::parse(&args) else {
+        eprintln!("{USAGE}");
+        std::process::exit(2);
+    };
+    if let Err(err) = run(command) {
         eprintln!("Error: {err}");
         std::process::exit(1);
     }
 }
 
-fn run() -> pi_cam_capture::traits::Result<()> {
-    let mut device = V4L2Device::open(0)?;
+fn run(command: Command) -> Result<()> {
+    match command {
+        Command::Stream { selector } => {
+            let mut device = setup(selector.as_deref(), CAPTURE_FOURCCS)?;
+            stream(&mut device)
+        }
+        Command::Record {
+            path,
+            frames,
+            selector,
+        } => {
+            let mut device = setup(selector.as_deref(), CAPTURE_FOURCCS)?;
+            record(&mut device, &path, frames)
+        }
+        Command::Snapshot {
+            path,
+            warmup,
+            selector,
+        } => {
+            let mut device = setup(selector.as_deref(), SNAPSHOT_FOURCCS)?;
+            snapshot(&mut device, &path, warmup)
+        }
+    }
+}
+
+/// Open the device and negotiate one of `fourccs`, printing what was
+/// negotiated.
+fn setup(selector: Option<&str>, fourccs: &[FourCC]) -> Result<V4L2Device> {
+    let mut device = open_device(selector)?;
 
     println!("Device: {}", device.capabilities().card);
     println!("Driver: {}", device.capabilities().driver);
 
-    let format = Format::new(1280, 720, FourCC::YUYV);
-    let actual_format = device.set_format(&format)?;
+    let request = FormatRequest::new(1280, 720).with_fourccs(fourccs);
+    let negotiated = negotiate(&mut device, &request)?;
+    let actual_format = negotiated.format;
 
     println!(
-        "Format: {}x{} {:?}",
+        "Format: {}x{} {}",
         actual_format.width, actual_format.height, actual_format.fourcc
     );
+    for adjustment in &negotiated.adjustments {
+        println!("Adjusted: {adjustment:?}");
+    }
+
+    let interval = device.frame_interval()?;
+    println!("Frame rate: {:.2} fps ({interval} s/frame)", interval.fps());
+    Ok(device)
+}
 
+/// Print the metadata of every captured frame.
+fn stream(device: &mut V4L2Device) -> Result<()> {
     let mut stream = device.create_stream(4)?;
 
     loop {
-        let frame = stream.next_frame()?;
+        let frame = stream.next_frame_ref()?;
         println!(
             "Frame {}: {} bytes, timestamp: {:?}",
             frame.metadata.sequence,
@@ -35,3 +162,58 @@ fn run() -> pi_cam_capture::traits::Result<()> {
         );
     }
 }
+
+/// Record `frames` frames to `path`.
+fn record(device: &mut V4L2Device, path: &str, frames: u32) -> Result<()> {
+    let header = RecordingHeader {
+        format: device.format()?,
+        interval: device.frame_interval()?,
+        capabilities: device.capabilities().clone(),
+    };
+    let mut recorder = Recorder::create(path, &header)?;
+    let mut stream = device.create_stream(4)?;
+
+    for _ in 0..frames {
+        let frame = stream.next_frame_ref()?;
+        recorder.write_frame(&frame)?;
+    }
+    recorder.finish()?;
+    println!("Recorded {frames} frames to {path}");
+    Ok(())
+}
+
+/// Save the frame following `warmup` discarded frames to `path`.
+fn snapshot(device: &mut V4L2Device, path: &str, warmup: u32) -> Result<()> {
+    let format = device.format()?;
+    let mut stream = device.create_stream(4)?;
+
+    for _ in 0..warmup {
+        stream.next_frame_ref()?;
+    }
+    let frame = stream.next_frame_ref()?;
+    save_image(path, &frame, &format)?;
+    println!("Saved frame {} to {path}", frame.metadata.sequence);
+    Ok(())
+}
+
+/// Open the device named by `selector`: a node path (`/dev/video0`), a card
+/// name or a bus info string. Without a selector, the first capture-capable
+/// device is used.
+fn open_device(selector: Option<&str>) -> Result<V4L2Device> {
+    match selector {
+        Some(path) if path.starts_with('/') => V4L2Device::open_path(path),
+        Some(name) => V4L2Device::open_by_card(name).or_else(|err| match err {
+            CameraError::NoMatchingDevice(_) => V4L2Device::open_by_bus_info(name),
+            err => Err(err),
+        }),
+        None => {
+            let info = list_devices()?
+                .into_iter()
+                .find(|info| info.capabilities.can_capture)
+                .ok_or_else(|| {
+                    CameraError::NoMatchingDevice("capture-capable device".to_owned())
+                })?;
+            V4L2Device::open_path(info.path)
+        }
+    }
+}
@@ -0,0 +1,759 @@
+//! Motion JPEG frame handling.
+//!
+//! UVC cameras deliver MJPEG as a stream of baseline JPEG images, one per
+//! buffer, often padded with zeros after the end-of-image marker. Many models
+//! also leave out the Huffman tables (the DHT segment) and rely on the
+//! defaults of the MJPEG specification, which standalone JPEG readers do not
+//! know about.
+//!
+//! [`validate`] walks the marker segments of a frame and catches frames cut
+//! short in transfer, [`insert_dht`] turns a frame into a self-contained
+//! JPEG file, and, with the `mjpeg` feature, [`decode`] converts it to RGB or
+//! YUV. The same feature lets [`convert`](crate::convert), the pixel
+//! accessors and the validators read MJPEG frames.
+
+use std::borrow::Cow;
+
+#[cfg(feature = "mjpeg")]
+use crate::colorspace::Colorspace;
+#[cfg(feature = "mjpeg")]
+use crate::convert::average;
+use crate::traits::{CameraError, Result};
+#[cfg(feature = "mjpeg")]
+use crate::traits::{Format, FourCC};
+
+/// Start of image marker.
+const SOI: u8 = 0xd8;
+/// End of image marker.
+const EOI: u8 = 0xd9;
+/// Start of scan marker.
+const SOS: u8 = 0xda;
+/// Define Huffman table marker.
+const DHT: u8 = 0xc4;
+
+/// Code counts per length of the DC luminance table (JPEG Annex K, K.3).
+const DC_LUMA_COUNTS: [u8; 16] = [0, 1, 5, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0];
+/// Code counts per length of the DC chrominance table (K.4).
+const DC_CHROMA_COUNTS: [u8; 16] = [0, 3, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0];
+/// Symbols of both DC tables.
+const DC_SYMBOLS: [u8; 12] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
+/// Code counts per length of the AC luminance table (K.5).
+const AC_LUMA_COUNTS: [u8; 16] = [0, 2, 1, 3, 3, 2, 4, 3, 5, 5, 4, 4, 0, 0, 1, 0x7d];
+/// Symbols of the AC luminance table.
+const AC_LUMA_SYMBOLS: [u8; 162] = [
+    0x01, 0x02, 0x03, 0x00, 0x04, 0x11, 0x05, 0x12, 0x21, 0x31, 0x41, 0x06, 0x13, 0x51, 0x61, 0x07,
+    0x22, 0x71, 0x14, 0x32, 0x81, 0x91, 0xa1, 0x08, 0x23, 0x42, 0xb1, 0xc1, 0x15, 0x52, 0xd1, 0xf0,
+    0x24, 0x33, 0x62, 0x72, 0x82, 0x09, 0x0a, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x25, 0x26, 0x27, 0x28,
+    0x29, 0x2a, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49,
+    0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69,
+    0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7a, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89,
+    0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa7,
+    0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4, 0xb5, 0xb6, 0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3, 0xc4, 0xc5,
+    0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xd2, 0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda, 0xe1, 0xe2,
+    0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8, 0xe9, 0xea, 0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8,
+    0xf9, 0xfa,
+];
+/// Code counts per length of the AC chrominance table (K.6).
+const AC_CHROMA_COUNTS: [u8; 16] = [0, 2, 1, 2, 4, 4, 3, 4, 7, 5, 4, 4, 0, 1, 2, 0x77];
+/// Symbols of the AC chrominance table.
+const AC_CHROMA_SYMBOLS: [u8; 162] = [
+    0x00, 0x01, 0x02, 0x03, 0x11, 0x04, 0x05, 0x21, 0x31, 0x06, 0x12, 0x41, 0x51, 0x07, 0x61, 0x71,
+    0x13, 0x22, 0x32, 0x81, 0x08, 0x14, 0x42, 0x91, 0xa1, 0xb1, 0xc1, 0x09, 0x23, 0x33, 0x52, 0xf0,
+    0x15, 0x62, 0x72, 0xd1, 0x0a, 0x16, 0x24, 0x34, 0xe1, 0x25, 0xf1, 0x17, 0x18, 0x19, 0x1a, 0x26,
+    0x27, 0x28, 0x29, 0x2a, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3a, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48,
+    0x49, 0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5a, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68,
+    0x69, 0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7a, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87,
+    0x88, 0x89, 0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9a, 0xa2, 0xa3, 0xa4, 0xa5,
+    0xa6, 0xa7, 0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4, 0xb5, 0xb6, 0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3,
+    0xc4, 0xc5, 0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xd2, 0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda,
+    0xe2, 0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8, 0xe9, 0xea, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8,
+    0xf9, 0xfa,
+];
+
+/// The default Huffman tables as (class and destination, counts, symbols).
+const DEFAULT_TABLES: [(u8, &[u8; 16], &[u8]); 4] = [
+    (0x00, &DC_LUMA_COUNTS, &DC_SYMBOLS),
+    (0x10, &AC_LUMA_COUNTS, &AC_LUMA_SYMBOLS),
+    (0x01, &DC_CHROMA_COUNTS, &DC_SYMBOLS),
+    (0x11, &AC_CHROMA_COUNTS, &AC_CHROMA_SYMBOLS),
+];
+
+/// Define quantization table marker.
+#[cfg(any(test, feature = "mock"))]
+const DQT: u8 = 0xdb;
+/// Baseline start of frame marker.
+#[cfg(any(test, feature = "mock"))]
+const SOF0: u8 = 0xc0;
+
+/// Luminance quantization table (JPEG Annex K, K.1), in row-major order.
+#[cfg(any(test, feature = "mock"))]
+#[rustfmt::skip]
+const LUMA_QUANT: [u8; 64] = [
+    16, 11, 10, 16, 24, 40, 51, 61,
+    12, 12, 14, 19, 26, 58, 60, 55,
+    14, 13, 16, 24, 40, 57, 69, 56,
+    14, 17, 22, 29, 51, 87, 80, 62,
+    18, 22, 37, 56, 68, 109, 103, 77,
+    24, 35, 55, 64, 81, 104, 113, 92,
+    49, 64, 78, 87, 103, 121, 120, 101,
+    72, 92, 95, 98, 112, 100, 103, 99,
+];
+/// Chrominance quantization table (K.2), in row-major order.
+#[cfg(any(test, feature = "mock"))]
+#[rustfmt::skip]
+const CHROMA_QUANT: [u8; 64] = [
+    17, 18, 24, 47, 99, 99, 99, 99,
+    18, 21, 26, 66, 99, 99, 99, 99,
+    24, 26, 56, 99, 99, 99, 99, 99,
+    47, 66, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+];
+/// Row-major position of each coefficient in zigzag order.
+#[cfg(any(test, feature = "mock"))]
+#[rustfmt::skip]
+const ZIGZAG: [u8; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5,
+    12, 19, 26, 33, 40, 48, 41, 34, 27, 20, 13, 6, 7, 14, 21, 28,
+    35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51,
+    58, 59, 52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+/// Scale of the quantization tables in percent, libjpeg's quality 90.
+#[cfg(any(test, feature = "mock"))]
+const QUANT_SCALE: u16 = 20;
+
+/// Where the parts of a JPEG image sit in a buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Segments {
+    /// Offset just past the EOI marker; anything after it is padding.
+    end: usize,
+    /// Offset of the first SOS marker.
+    first_scan: usize,
+    /// Whether a DHT segment precedes the first scan.
+    has_dht: bool,
+}
+
+/// Check the marker structure of a JPEG frame and strip trailing padding.
+///
+/// Returns the image from its SOI marker up to and including its EOI
+/// marker.
+///
+/// # Errors
+///
+/// Returns `Io` with `InvalidData` if the frame does not start with SOI, a
+/// segment is malformed, or the frame ends before its EOI marker.
+pub fn validate(data: &[u8]) -> Result<&[u8]> {
+    let segments = parse(data)?;
+    Ok(data.get(..segments.end).unwrap_or(data))
+}
+
+/// Make a JPEG frame self-contained by adding the default Huffman tables if
+/// it has none.
+///
+/// Frames that carry their own tables are returned as is, without trailing
+/// padding; others get the tables of JPEG Annex K.3 inserted before their
+/// first scan, as the MJPEG specification prescribes.
+///
+/// # Errors
+///
+/// Returns the errors of [`validate`].
+pub fn insert_dht(data: &[u8]) -> Result<Cow<'_, [u8]>> {
+    let segments = parse(data)?;
+    let (head, tail) = data
+        .get(..segments.end)
+        .unwrap_or(data)
+        .split_at(segments.first_scan);
+    if segments.has_dht {
+        return Ok(Cow::Borrowed(data.get(..segments.end).unwrap_or(data)));
+    }
+
+    let mut image = Vec::with_capacity(segments.end + 420);
+    image.extend_from_slice(head);
+    image.extend_from_slice(&default_dht());
+    image.extend_from_slice(tail);
+    Ok(Cow::Owned(image))
+}
+
+/// Decode a JPEG frame to `fourcc`, one of `RGB3`, `YUYV` or `GREY`.
+///
+/// Missing Huffman tables are filled in with [`insert_dht`]. YUV output keeps
+/// the samples of the JPEG (chroma pairs are averaged for YUYV) and is
+/// described as full range BT.601 by the returned format, which also gives
+/// the dimensions of the image.
+///
+/// # Errors
+///
+/// Returns `FormatNotSupported` for other output formats, and `Io` with
+/// `InvalidData` if the frame is malformed, truncated, or cannot be decoded.
+#[cfg(feature = "mjpeg")]
+pub fn decode(data: &[u8], fourcc: FourCC) -> Result<(Format, Vec<u8>)> {
+    use jpeg_decoder::{ColorTransform, Decoder, PixelFormat};
+
+    if !matches!(fourcc, FourCC::RGB3 | FourCC::YUYV | FourCC::GREY) {
+        return Err(CameraError::FormatNotSupported {
+            format: Format::new(0, 0, fourcc),
+            reason: "is not a JPEG decoding target".to_owned(),
+        });
+    }
+    let image = insert_dht(data)?;
+    let mut decoder = Decoder::new(image.as_ref());
+    if fourcc != FourCC::RGB3 {
+        // Keep the YCbCr samples rather than converting them to RGB
+        decoder.set_color_transform(ColorTransform::None);
+    }
+    let pixels = decoder
+        .decode()
+        .map_err(|err| CameraError::invalid_data(format!("JPEG decoding failed: {err}")))?;
+    let info = decoder
+        .info()
+        .ok_or_else(|| CameraError::invalid_data("JPEG has no frame header".to_owned()))?;
+
+    let width = usize::from(info.width);
+    let data = match (info.pixel_format, fourcc) {
+        (PixelFormat::RGB24, FourCC::RGB3) | (PixelFormat::L8, FourCC::GREY) => pixels,
+        (PixelFormat::RGB24, FourCC::GREY) => pixels.iter().step_by(3).copied().collect(),
+        (PixelFormat::RGB24, _) => ycbcr_to_yuyv(&pixels, width),
+        (PixelFormat::L8, FourCC::RGB3) => pixels.iter().flat_map(|&luma| [luma; 3]).collect(),
+        (PixelFormat::L8, _) => {
+            let ycbcr: Vec<u8> = pixels.iter().flat_map(|&luma| [luma, 128, 128]).collect();
+            ycbcr_to_yuyv(&ycbcr, width)
+        }
+        (pixel_format, _) => {
+            return Err(CameraError::invalid_data(format!(
+                "Unsupported JPEG pixel format {pixel_format:?}"
+            )))
+        }
+    };
+    let format = Format {
+        colorspace: Colorspace::Jpeg,
+        ..Format::new(u32::from(info.width), u32::from(info.height), fourcc)
+    };
+    Ok((format, data))
+}
+
+/// Decode a JPEG frame to `fourcc`, checking it has the size of `format`.
+#[cfg(feature = "mjpeg")]
+pub(crate) fn decode_frame(data: &[u8], format: &Format, fourcc: FourCC) -> Result<Vec<u8>> {
+    let (decoded, pixels) = decode(data, fourcc)?;
+    if (decoded.width, decoded.height) != (format.width, format.height) {
+        return Err(CameraError::invalid_data(format!(
+            "JPEG is {}x{}, expected {}x{}",
+            decoded.width, decoded.height, format.width, format.height
+        )));
+    }
+    Ok(pixels)
+}
+
+/// Pack rows of 4:4:4 `YCbCr` samples as YUYV, averaging chroma pairs.
+///
+/// A trailing odd pixel repeats its luma.
+#[cfg(feature = "mjpeg")]
+fn ycbcr_to_yuyv(pixels: &[u8], width: usize) -> Vec<u8> {
+    let mut yuyv = Vec::with_capacity(pixels.len() / 3 * 2 + 2);
+    for row in pixels.chunks_exact(width.max(1) * 3) {
+        for pair in row.chunks(6) {
+            let (y0, u, v, y1) = match *pair {
+                [y0, u0, v0, y1, u1, v1] => (y0, average(u0, u1), average(v0, v1), y1),
+                [y0, u0, v0, ..] => (y0, u0, v0, y0),
+                _ => continue,
+            };
+            yuyv.extend_from_slice(&[y0, u, y1, v]);
+        }
+    }
+    yuyv
+}
+
+/// The DHT segment holding the default Huffman tables.
+fn default_dht() -> Vec<u8> {
+    let body: Vec<u8> = DEFAULT_TABLES
+        .iter()
+        .flat_map(|&(class, counts, symbols)| {
+            std::iter::once(class)
+                .chain(counts.iter().copied())
+                .chain(symbols.iter().copied())
+        })
+        .collect();
+    let length = u16::try_from(body.len() + 2).unwrap_or(u16::MAX);
+    let mut segment = vec![0xff, DHT];
+    segment.extend_from_slice(&length.to_be_bytes());
+    segment.extend_from_slice(&body);
+    segment
+}
+
+/// Encode tightly packed 8-bit RGB as a baseline JPEG.
+///
+/// Components are not subsampled and the default Huffman tables are written
+/// out, so any JPEG reader can decode the image. Returns `None` if the image
+/// is empty, more than 65535 pixels wide or tall, or `rgb` is too short.
+#[cfg(any(test, feature = "mock"))]
+pub(crate) fn encode_rgb(rgb: &[u8], width: u32, height: u32) -> Option<Vec<u8>> {
+    let header_width = u16::try_from(width).ok().filter(|&width| width > 0)?;
+    let header_height = u16::try_from(height).ok().filter(|&height| height > 0)?;
+    let (width, height) = (usize::from(header_width), usize::from(header_height));
+    let pixels = rgb.get(..width * height * 3)?;
+
+    // JFIF YCbCr: full range BT.601
+    let mut planes = [(); 3].map(|()| Vec::with_capacity(width * height));
+    for pixel in pixels.chunks_exact(3) {
+        let [red, green, blue] = [0, 1, 2].map(|at| f32::from(pixel.get(at).copied().unwrap_or(0)));
+        let [luma, cb, cr] = &mut planes;
+        luma.push(0.114f32.mul_add(blue, 0.299f32.mul_add(red, 0.587 * green)));
+        cb.push(0.5f32.mul_add(blue, (-0.168_736f32).mul_add(red, -0.331_264 * green)) + 128.0);
+        cr.push((-0.081_312f32).mul_add(blue, 0.5f32.mul_add(red, -0.418_688 * green)) + 128.0);
+    }
+
+    let quant = [scaled_quant(&LUMA_QUANT), scaled_quant(&CHROMA_QUANT)];
+    let [dc_luma, ac_luma, dc_chroma, ac_chroma] =
+        DEFAULT_TABLES.map(|(_, counts, symbols)| huffman_codes(counts, symbols));
+    let tables = [
+        (&quant[0], &dc_luma, &ac_luma),
+        (&quant[1], &dc_chroma, &ac_chroma),
+        (&quant[1], &dc_chroma, &ac_chroma),
+    ];
+
+    let basis = dct_basis();
+    let mut writer = BitWriter::default();
+    let mut predictions = [0; 3];
+    for block_y in (0..height).step_by(8) {
+        for block_x in (0..width).step_by(8) {
+            for ((plane, prediction), (quant, dc, ac)) in
+                planes.iter().zip(&mut predictions).zip(tables)
+            {
+                let samples = block_samples(plane, width, height, block_x, block_y);
+                let coefficients = quantize(&fdct(&samples, &basis), quant);
+                *prediction = writer.put_block(&coefficients, *prediction, dc, ac);
+            }
+        }
+    }
+    writer.flush();
+
+    let mut jpeg = vec![0xff, SOI];
+    for (id, table) in (0u8..).zip(&quant) {
+        let mut body = vec![id];
+        body.extend(
+            ZIGZAG
+                .iter()
+                .map(|&at| table.get(usize::from(at)).copied().unwrap_or(1)),
+        );
+        push_segment(&mut jpeg, DQT, &body);
+    }
+    let mut frame = vec![8];
+    frame.extend_from_slice(&header_height.to_be_bytes());
+    frame.extend_from_slice(&header_width.to_be_bytes());
+    frame.extend_from_slice(&[3, 1, 0x11, 0, 2, 0x11, 1, 3, 0x11, 1]);
+    push_segment(&mut jpeg, SOF0, &frame);
+    jpeg.extend_from_slice(&default_dht());
+    push_segment(&mut jpeg, SOS, &[3, 1, 0x00, 2, 0x11, 3, 0x11, 0, 63, 0]);
+    jpeg.extend_from_slice(&writer.bytes);
+    jpeg.extend_from_slice(&[0xff, EOI]);
+    Some(jpeg)
+}
+
+/// Append a marker segment with its length.
+#[cfg(any(test, feature = "mock"))]
+fn push_segment(jpeg: &mut Vec<u8>, marker: u8, body: &[u8]) {
+    let length = u16::try_from(body.len() + 2).unwrap_or(u16::MAX);
+    jpeg.extend_from_slice(&[0xff, marker]);
+    jpeg.extend_from_slice(&length.to_be_bytes());
+    jpeg.extend_from_slice(body);
+}
+
+/// Scale a quantization table by [`QUANT_SCALE`].
+#[cfg(any(test, feature = "mock"))]
+fn scaled_quant(table: &[u8; 64]) -> [u8; 64] {
+    table.map(|step| {
+        let scaled = (u16::from(step) * QUANT_SCALE + 50) / 100;
+        u8::try_from(scaled.max(1)).unwrap_or(u8::MAX)
+    })
+}
+
+/// Code and length of each symbol of a Huffman table (JPEG Annex C).
+#[cfg(any(test, feature = "mock"))]
+fn huffman_codes(counts: &[u8; 16], symbols: &[u8]) -> [(u32, u32); 256] {
+    let mut codes = [(0, 0); 256];
+    let mut code = 0;
+    let mut symbols = symbols.iter();
+    for (length, &count) in (1..).zip(counts) {
+        for &symbol in symbols.by_ref().take(usize::from(count)) {
+            if let Some(slot) = codes.get_mut(usize::from(symbol)) {
+                *slot = (code, length);
+            }
+            code += 1;
+        }
+        code <<= 1;
+    }
+    codes
+}
+
+/// DCT basis functions: row `freq` holds the weights of the eight samples.
+#[cfg(any(test, feature = "mock"))]
+#[allow(clippy::cast_precision_loss)]
+fn dct_basis() -> [[f32; 8]; 8] {
+    std::array::from_fn(|freq| {
+        let scale = if freq == 0 {
+            std::f32::consts::FRAC_1_SQRT_2 / 2.0
+        } else {
+            0.5
+        };
+        std::array::from_fn(|at| {
+            let angle = (2 * at + 1) as f32 * freq as f32 * std::f32::consts::PI / 16.0;
+            scale * angle.cos()
+        })
+    })
+}
+
+/// Level-shifted 8x8 block of `plane` at (`left`, `top`), repeating the
+/// last row and column past the image edge.
+#[cfg(any(test, feature = "mock"))]
+fn block_samples(plane: &[f32], width: usize, height: usize, left: usize, top: usize) -> [f32; 64] {
+    let mut block = [0.0; 64];
+    for (y, row) in (top..).zip(block.chunks_exact_mut(8)) {
+        let line = y.min(height - 1) * width;
+        for (x, sample) in (left..).zip(row) {
+            *sample = plane.get(line + x.min(width - 1)).copied().unwrap_or(128.0) - 128.0;
+        }
+    }
+    block
+}
+
+/// Forward DCT of a row-major block, as rows then columns.
+#[cfg(any(test, feature = "mock"))]
+fn fdct(block: &[f32; 64], basis: &[[f32; 8]; 8]) -> [f32; 64] {
+    let dot = |weights: &[f32; 8], samples: &mut dyn Iterator<Item = f32>| -> f32 {
+        weights
+            .iter()
+            .zip(samples)
+            .map(|(weight, sample)| weight * sample)
+            .sum()
+    };
+    let mut rows = [0.0; 64];
+    for (out, samples) in rows.chunks_exact_mut(8).zip(block.chunks_exact(8)) {
+        for (value, weights) in out.iter_mut().zip(basis) {
+            *value = dot(weights, &mut samples.iter().copied());
+        }
+    }
+    let mut coefficients = [0.0; 64];
+    for (out, weights) in coefficients.chunks_exact_mut(8).zip(basis) {
+        for (column, value) in out.iter_mut().enumerate() {
+            *value = dot(weights, &mut rows.iter().copied().skip(column).step_by(8));
+        }
+    }
+    coefficients
+}
+
+/// Quantize DCT coefficients, returning them in zigzag order.
+#[cfg(any(test, feature = "mock"))]
+#[allow(clippy::cast_possible_truncation)]
+fn quantize(coefficients: &[f32; 64], quant: &[u8; 64]) -> [i32; 64] {
+    ZIGZAG.map(|at| {
+        let at = usize::from(at);
+        let step = f32::from(quant.get(at).copied().unwrap_or(1));
+        (coefficients.get(at).copied().unwrap_or(0.0) / step).round() as i32
+    })
+}
+
+/// Entropy-coded scan data under construction.
+#[cfg(any(test, feature = "mock"))]
+#[derive(Debug, Default)]
+struct BitWriter {
+    bytes: Vec<u8>,
+    /// Pending bits, the oldest highest, fewer than 8 between calls.
+    pending: u32,
+    count: u32,
+}
+
+#[cfg(any(test, feature = "mock"))]
+impl BitWriter {
+    /// Append the low `length` bits of `bits`, stuffing a zero after 0xff.
+    fn put(&mut self, bits: u32, length: u32) {
+        self.pending = self.pending << length | bits & ((1 << length) - 1);
+        self.count += length;
+        while self.count >= 8 {
+            self.count -= 8;
+            let byte = u8::try_from(self.pending >> self.count & 0xff).unwrap_or(0xff);
+            self.bytes.push(byte);
+            if byte == 0xff {
+                self.bytes.push(0);
+            }
+        }
+        self.pending &= (1 << self.count) - 1;
+    }
+
+    /// Append `value` as a Huffman-coded `symbol` combined with its size,
+    /// followed by its magnitude bits.
+    #[allow(clippy::cast_sign_loss)]
+    fn put_value(&mut self, codes: &[(u32, u32); 256], symbol: u8, value: i32) {
+        let size = 32 - value.unsigned_abs().leading_zeros();
+        let (code, length) = codes
+            .get(usize::from(symbol) | size as usize)
+            .copied()
+            .unwrap_or_default();
+        self.put(code, length);
+        // Negative values are stored as value - 1 in `size` bits
+        let magnitude = if value < 0 { value - 1 } else { value };
+        self.put(magnitude as u32, size);
+    }
+
+    /// Encode a quantized block, returning its DC value for the next one.
+    fn put_block(
+        &mut self,
+        coefficients: &[i32; 64],
+        prediction: i32,
+        dc: &[(u32, u32); 256],
+        ac: &[(u32, u32); 256],
+    ) -> i32 {
+        let (&dc_value, ac_values) = coefficients.split_first().unwrap_or((&0, &[]));
+        self.put_value(dc, 0, dc_value - prediction);
+        let mut run = 0;
+        for &value in ac_values {
+            if value == 0 {
+                run += 1;
+                continue;
+            }
+            while run > 15 {
+                // ZRL: sixteen zeros
+                self.put_value(ac, 0xf0, 0);
+                run -= 16;
+            }
+            self.put_value(ac, run << 4, value);
+            run = 0;
+        }
+        if run > 0 {
+            // EOB: the rest of the block is zero
+            self.put_value(ac, 0x00, 0);
+        }
+        dc_value
+    }
+
+    /// Pad the last byte with one bits.
+    fn flush(&mut self) {
+        if self.count > 0 {
+            self.put(0x7f, 8 - self.count);
+        }
+    }
+}
+
+/// Walk the marker segments of a JPEG image.
+fn parse(data: &[u8]) -> Result<Segments> {
+    if !data.starts_with(&[0xff, SOI]) {
+        return Err(CameraError::invalid_data(
+            "JPEG has no SOI marker".to_owned(),
+        ));
+    }
+
+    let mut pos = 2;
+    let mut first_scan = None;
+    let mut has_dht = false;
+    loop {
+        let (marker, next) = read_marker(data, pos)?;
+        match marker {
+            EOI => {
+                let first_scan = first_scan.ok_or_else(|| {
+                    CameraError::invalid_data("JPEG ends before its first scan".to_owned())
+                })?;
+                return Ok(Segments {
+                    end: next,
+                    first_scan,
+                    has_dht,
+                });
+            }
+            // Restart and TEM markers stand alone
+            0x01 | 0xd0..=0xd7 => pos = next,
+            SOI | 0x00 => {
+                return Err(CameraError::invalid_data(format!(
+                    "Unexpected JPEG marker {marker:#04x} at offset {}",
+                    next - 2
+                )))
+            }
+            _ => {
+                let length = data
+                    .get(next..next + 2)
+                    .and_then(|bytes| bytes.try_into().ok())
+                    .map(|bytes| usize::from(u16::from_be_bytes(bytes)))
+                    .ok_or_else(|| truncated("segment length"))?;
+                if length < 2 {
+                    return Err(CameraError::invalid_data(format!(
+                        "JPEG segment at offset {} has length {length}",
+                        next - 2
+                    )));
+                }
+                let end = next + length;
+                if end > data.len() {
+                    return Err(truncated("segment"));
+                }
+                has_dht |= marker == DHT && first_scan.is_none();
+                pos = if marker == SOS {
+                    first_scan.get_or_insert(next - 2);
+                    scan_end(data, end)?
+                } else {
+                    end
+                };
+            }
+        }
+    }
+}
+
+/// Read the marker at `pos`, skipping fill bytes, and return its code and
+/// the offset following it.
+fn read_marker(data: &[u8], pos: usize) -> Result<(u8, usize)> {
+    let rest = data.get(pos..).unwrap_or_default();
+    match rest.first() {
+        None => return Err(truncated("marker")),
+        Some(&0xff) => {}
+        Some(_) => {
+            return Err(CameraError::invalid_data(format!(
+                "Expected a JPEG marker at offset {pos}"
+            )))
+        }
+    }
+    let code = rest
+        .iter()
+        .position(|&byte| byte != 0xff)
+        .ok_or_else(|| truncated("marker"))?;
+    Ok((rest.get(code).copied().unwrap_or_default(), pos + code + 1))
+}
+
+/// Offset of the first marker ending the entropy-coded data at `start`.
+///
+/// Stuffed zero bytes, fill bytes and restart markers belong to the scan.
+fn scan_end(data: &[u8], start: usize) -> Result<usize> {
+    data.get(start..)
+        .unwrap_or_default()
+        .windows(2)
+        .position(
+            |pair| matches!(*pair, [0xff, code] if !matches!(code, 0x00 | 0xd0..=0xd7 | 0xff)),
+        )
+        .map(|offset| start + offset)
+        .ok_or_else(|| truncated("scan"))
+}
+
+/// Error for a JPEG cut short inside `what`.
+fn truncated(what: &str) -> CameraError {
+    CameraError::invalid_data(format!("Truncated JPEG: frame ends inside a {what}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{generate_test_frame, TestPattern};
+    use crate::traits::{Format, FourCC};
+
+    /// Encode color bars as a baseline JPEG with the standard tables.
+    fn color_bars_jpeg(width: u16, height: u16) -> Vec<u8> {
+        let format = Format::new(u32::from(width), u32::from(height), FourCC::RGB3);
+        let rgb = generate_test_frame(&format, TestPattern::ColorBars);
+        let mut jpeg = Vec::new();
+        jpeg_encoder::Encoder::new(&mut jpeg, 95)
+            .encode(&rgb, width, height, jpeg_encoder::ColorType::Rgb)
+            .expect("encoding should succeed");
+        jpeg
+    }
+
+    /// Drop the DHT segments, as many UVC cameras do.
+    fn strip_dht(jpeg: &[u8]) -> Vec<u8> {
+        let mut stripped = jpeg[..2].to_vec();
+        let mut pos = 2;
+        while jpeg[pos + 1] != SOS {
+            let length = usize::from(u16::from_be_bytes([jpeg[pos + 2], jpeg[pos + 3]]));
+            if jpeg[pos + 1] != DHT {
+                stripped.extend_from_slice(&jpeg[pos..pos + 2 + length]);
+            }
+            pos += 2 + length;
+        }
+        stripped.extend_from_slice(&jpeg[pos..]);
+        stripped
+    }
+
+    #[test]
+    fn test_validate_strips_padding() {
+        let jpeg = color_bars_jpeg(64, 16);
+        let mut padded = jpeg.clone();
+        padded.extend_from_slice(&[0; 100]);
+        assert_eq!(validate(&padded).expect("JPEG should validate"), jpeg);
+    }
+
+    #[test]
+    fn test_validate_rejects_broken_frames() {
+        let jpeg = color_bars_jpeg(64, 16);
+        for (data, what) in [
+            (&jpeg[..jpeg.len() - 2], "missing EOI"),
+            (&jpeg[..jpeg.len() / 2], "cut mid-scan"),
+            (&jpeg[..10], "cut mid-header"),
+            (&jpeg[2..], "missing SOI"),
+            (&[0xff, SOI, 0xff, EOI][..], "no scan"),
+            (&[][..], "empty"),
+        ] {
+            assert!(validate(data).is_err(), "{what} should be rejected");
+        }
+    }
+
+    #[test]
+    fn test_insert_dht() {
+        let jpeg = color_bars_jpeg(64, 16);
+        assert!(matches!(insert_dht(&jpeg), Ok(Cow::Borrowed(image)) if image == jpeg));
+
+        let stripped = strip_dht(&jpeg);
+        assert!(stripped.len() < jpeg.len());
+        let parsed = parse(&stripped).expect("stripped JPEG should parse");
+        assert!(!parsed.has_dht);
+
+        let fixed = insert_dht(&stripped).expect("DHT should insert");
+        assert_eq!(fixed.len(), stripped.len() + 420);
+        let parsed = parse(&fixed).expect("fixed JPEG should parse");
+        assert!(parsed.has_dht);
+        assert_eq!(fixed[parsed.first_scan - 420..][..2], [0xff, DHT]);
+    }
+
+    #[cfg(feature = "mjpeg")]
+    #[test]
+    fn test_decode() {
+        let jpeg = color_bars_jpeg(64, 16);
+        let (format, rgb) = decode(&jpeg, FourCC::RGB3).expect("JPEG should decode");
+        assert_eq!((format.width, format.height), (64, 16));
+        assert_eq!(rgb.len(), 64 * 16 * 3);
+
+        // The default tables are the ones the encoder used
+        let (_, stripped) = decode(&strip_dht(&jpeg), FourCC::RGB3).expect("JPEG should decode");
+        assert_eq!(stripped, rgb);
+
+        let (format, yuyv) = decode(&jpeg, FourCC::YUYV).expect("JPEG should decode");
+        assert_eq!(yuyv.len(), 64 * 16 * 2);
+        assert_eq!(format.colorimetry().range, crate::convert::YuvRange::Full);
+        let (_, grey) = decode(&jpeg, FourCC::GREY).expect("JPEG should decode");
+        assert_eq!(grey.len(), 64 * 16);
+        assert!(grey.iter().zip(yuyv.iter().step_by(2)).all(|(a, b)| a == b));
+
+        assert!(matches!(
+            decode(&jpeg, FourCC::NV12),
+            Err(CameraError::FormatNotSupported { .. })
+        ));
+    }
+
+    #[test]
+    fn test_encode_rgb_rejects_bad_input() {
+        assert!(encode_rgb(&[], 0, 8).is_none());
+        assert!(encode_rgb(&[0; 8 * 8 * 3], 8, 9).is_none());
+        assert!(encode_rgb(&[0; 3], 70_000, 1).is_none());
+    }
+
+    #[cfg(feature = "mjpeg")]
+    #[test]
+    fn test_encode_rgb_round_trip() {
+        // An odd size exercises the edge blocks and a lone trailing bit
+        let format = Format::new(37, 11, FourCC::RGB3);
+        let rgb = generate_test_frame(&format, TestPattern::Gradient);
+        let jpeg = encode_rgb(&rgb, 37, 11).expect("encoding should succeed");
+        assert_eq!(validate(&jpeg).expect("JPEG should validate"), jpeg);
+
+        let (decoded_format, decoded) = decode(&jpeg, FourCC::RGB3).expect("JPEG should decode");
+        assert_eq!((decoded_format.width, decoded_format.height), (37, 11));
+        let worst = rgb
+            .iter()
+            .zip(&decoded)
+            .map(|(expected, actual)| expected.abs_diff(*actual))
+            .max();
+        assert!(worst.is_some_and(|diff| diff <= 8), "worst error {worst:?}");
+    }
+}
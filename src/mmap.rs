@@ -0,0 +1,213 @@
+//! Memory-mapped V4L2 capture buffers.
+//!
+//! The v4l crate requeues a buffer only when it dequeues the next one, so a
+//! borrowed frame could not hand its buffer back to the driver when dropped.
+//! [`MappedBuffers`] issues the buffer ioctls itself, which lets each buffer
+//! be queued again on its own.
+//!
+//! Besides the SIMD kernels, this is the only module allowed to use `unsafe`:
+//! the ioctls and the mappings need it. Buffers are mapped read-only.
+#![allow(unsafe_code)]
+
+use std::os::raw::{c_int, c_void};
+use std::sync::Arc;
+use std::{io, mem, ptr, slice};
+
+use v4l::buffer::Type;
+use v4l::device::Handle;
+use v4l::memory::Memory;
+use v4l::v4l2;
+use v4l::v4l2::vidioc;
+use v4l::v4l_sys::{v4l2_buffer, v4l2_requestbuffers};
+use v4l::{Device, Timestamp};
+
+use crate::traits::BufferQueue;
+
+/// mmap(2) protection flag for readable pages.
+const PROT_READ: c_int = 0x1;
+
+/// mmap(2) flag sharing the mapping with the driver.
+const MAP_SHARED: c_int = 0x01;
+
+/// A buffer the driver has filled and handed back.
+#[derive(Debug, Clone, Copy)]
+pub struct Dequeued {
+    /// Index of the buffer.
+    pub index: usize,
+    /// Bytes of the buffer holding frame data.
+    pub bytes_used: u32,
+    /// Frame sequence number assigned by the driver.
+    pub sequence: u32,
+    /// Capture timestamp.
+    pub timestamp: Timestamp,
+}
+
+/// One mapped buffer.
+struct Mapping {
+    start: *mut c_void,
+    length: usize,
+}
+
+/// The mmap buffers of a capture stream.
+///
+/// Buffers are freed, and streaming turned off, when this is dropped.
+pub struct MappedBuffers {
+    handle: Arc<Handle>,
+    mappings: Vec<Mapping>,
+    streaming: bool,
+}
+
+// SAFETY: the mappings are plain memory owned by this value until it is
+// dropped; they are only read, and the ioctls may be issued from any thread.
+#[allow(clippy::non_send_fields_in_send_ty)]
+unsafe impl Send for MappedBuffers {}
+// SAFETY: as above; no method mutates the mappings through a shared reference.
+unsafe impl Sync for MappedBuffers {}
+
+impl MappedBuffers {
+    /// Request `count` buffers from the driver and map them.
+    ///
+    /// The driver may grant more or fewer buffers than requested.
+    pub fn new(device: &Device, count: u32) -> io::Result<Self> {
+        let mut request = v4l2_requestbuffers {
+            count,
+            type_: Type::VideoCapture as u32,
+            memory: Memory::Mmap as u32,
+            // SAFETY: all-zero is a valid value of this plain C struct
+            ..unsafe { mem::zeroed() }
+        };
+        let mut buffers = Self {
+            handle: device.handle(),
+            mappings: Vec::new(),
+            streaming: false,
+        };
+        buffers.ioctl(vidioc::VIDIOC_REQBUFS, &mut request)?;
+
+        for index in 0..request.count {
+            let mut desc = buffer_desc(index);
+            buffers.ioctl(vidioc::VIDIOC_QUERYBUF, &mut desc)?;
+            // SAFETY: QUERYBUF of an mmap buffer fills in the offset member
+            let offset = unsafe { desc.m.offset };
+            // off_t is only 32 bits wide on 32-bit targets
+            #[allow(clippy::unnecessary_fallible_conversions)]
+            let offset = offset
+                .try_into()
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "mmap offset overflow"))?;
+            let length = desc.length as usize;
+            // SAFETY: maps the region the driver just described for this fd;
+            // the mapping is released in `drop`
+            let start = unsafe {
+                v4l2::mmap(
+                    ptr::null_mut(),
+                    length,
+                    PROT_READ,
+                    MAP_SHARED,
+                    buffers.handle.fd(),
+                    offset,
+                )
+            }?;
+            buffers.mappings.push(Mapping { start, length });
+        }
+        Ok(buffers)
+    }
+
+    /// Number of buffers.
+    pub fn len(&self) -> usize {
+        self.mappings.len()
+    }
+
+    /// Device handle the buffers belong to.
+    pub fn handle(&self) -> &Handle {
+        &self.handle
+    }
+
+    /// Queue every buffer and turn streaming on.
+    pub fn start(&mut self) -> io::Result<()> {
+        for index in 0..self.len() {
+            self.queue(index)?;
+        }
+        let mut buf_type = Type::VideoCapture as u32;
+        self.ioctl(vidioc::VIDIOC_STREAMON, &mut buf_type)?;
+        self.streaming = true;
+        Ok(())
+    }
+
+    /// Hand buffer `index` to the driver to be filled.
+    pub fn queue(&self, index: usize) -> io::Result<()> {
+        let index = u32::try_from(index)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "buffer index overflow"))?;
+        self.ioctl(vidioc::VIDIOC_QBUF, &mut buffer_desc(index))
+    }
+
+    /// Take a filled buffer from the driver.
+    ///
+    /// Fails with `WouldBlock` if none is ready, as the device is opened
+    /// non-blocking.
+    pub fn dequeue(&self) -> io::Result<Dequeued> {
+        let mut desc = buffer_desc(0);
+        self.ioctl(vidioc::VIDIOC_DQBUF, &mut desc)?;
+        Ok(Dequeued {
+            index: desc.index as usize,
+            bytes_used: desc.bytesused,
+            sequence: desc.sequence,
+            timestamp: desc.timestamp.into(),
+        })
+    }
+
+    /// Contents of buffer `index`.
+    ///
+    /// Only meaningful for a dequeued buffer: the driver writes to queued ones.
+    pub fn buffer(&self, index: usize) -> Option<&[u8]> {
+        let mapping = self.mappings.get(index)?;
+        // SAFETY: the mapping stays valid and readable until `self` is dropped
+        Some(unsafe { slice::from_raw_parts(mapping.start.cast::<u8>(), mapping.length) })
+    }
+
+    /// Issue a buffer ioctl on the device.
+    fn ioctl<T>(&self, request: vidioc::_IOC_TYPE, arg: &mut T) -> io::Result<()> {
+        // SAFETY: every caller passes the argument type `request` expects
+        unsafe { v4l2::ioctl(self.handle.fd(), request, (arg as *mut T).cast()) }
+    }
+}
+
+impl BufferQueue for MappedBuffers {
+    fn requeue(&self, index: usize) {
+        // A failed QBUF leaves the buffer with us; the stream carries on with
+        // the others and the next dequeue reports a dead device
+        let _ = self.queue(index);
+    }
+}
+
+impl Drop for MappedBuffers {
+    fn drop(&mut self) {
+        // Errors are ignored: an unplugged device has already lost its buffers
+        if self.streaming {
+            let mut buf_type = Type::VideoCapture as u32;
+            let _ = self.ioctl(vidioc::VIDIOC_STREAMOFF, &mut buf_type);
+        }
+        for mapping in &self.mappings {
+            // SAFETY: the mapping was created in `new` and no slice of it
+            // outlives `self`
+            let _ = unsafe { v4l2::munmap(mapping.start, mapping.length) };
+        }
+        let mut request = v4l2_requestbuffers {
+            count: 0,
+            type_: Type::VideoCapture as u32,
+            memory: Memory::Mmap as u32,
+            // SAFETY: all-zero is a valid value of this plain C struct
+            ..unsafe { mem::zeroed() }
+        };
+        let _ = self.ioctl(vidioc::VIDIOC_REQBUFS, &mut request);
+    }
+}
+
+/// Describe mmap capture buffer `index` for the buffer ioctls.
+const fn buffer_desc(index: u32) -> v4l2_buffer {
+    v4l2_buffer {
+        index,
+        type_: Type::VideoCapture as u32,
+        memory: Memory::Mmap as u32,
+        // SAFETY: all-zero is a valid value of this plain C struct
+        ..unsafe { mem::zeroed() }
+    }
+}
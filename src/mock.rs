@@ -1,15 +1,301 @@
 //! Mock device implementation for testing without hardware.
+//!
+//! Available to downstream crates with the `mock` feature. Everything a test
+//! may want to vary is configurable through the [`MockDevice`] builders:
+//! capabilities, advertised formats, controls, frame interval and pacing, and
+//! the rendered [`TestPattern`]. Recorded frames, such as those read by
+//! [`Y4mReader`](crate::y4m::Y4mReader), can be played back in place of the
+//! pattern with [`MockDevice::with_clip`]. A [`FaultPlan`] scripts failures
+//! for testing error handling and recovery.
+//!
+//! The rendered pattern responds to the sensor controls like a scene seen
+//! through a real sensor: exposure and analogue gain brighten or darken it,
+//! and the red and blue balance controls scale their channels, each in
+//! proportion to the control value relative to its default. At default
+//! settings the pattern is rendered unchanged.
 
+use std::collections::VecDeque;
+#[cfg(feature = "tokio")]
+use std::future::Future;
+use std::io;
+use std::ops::Range;
+#[cfg(feature = "tokio")]
+use std::pin::Pin;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+#[cfg(feature = "tokio")]
+use std::task::{Context, Poll};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "tokio")]
+use tokio::time::{sleep_until, Sleep};
+
+#[cfg(feature = "tokio")]
+use crate::async_stream::AsyncCapture;
+use crate::bayer::{BayerFormat, RawImage};
+use crate::controls::{cid, ControlInfo, ControlType, ControlValue};
+use crate::convert::{Colorimetry, YuvMatrix, YuvRange};
+use crate::negotiation::nearest_in_size;
+use crate::pixel_format::{Layout, PixelFormatInfo};
 use crate::traits::{
-    CameraDevice, CaptureStream, DeviceCapabilities, Format, FourCC, Frame, FrameMetadata, Result,
+    BufferQueue, CameraDevice, CameraError, CaptureStream, DeviceCapabilities, Format,
+    FormatDescription, FourCC, Fraction, Frame, FrameInterval, FrameMetadata, FrameRef, FrameSize,
+    Result,
 };
-use std::time::Duration;
+
+/// A pixel format advertised by the mock device.
+///
+/// Every frame size in `frame_sizes` supports every interval in `frame_intervals`.
+#[derive(Debug, Clone)]
+pub struct MockFormat {
+    /// Format description returned by `supported_formats`.
+    pub description: FormatDescription,
+    /// Frame sizes returned by `frame_sizes`.
+    pub frame_sizes: Vec<FrameSize>,
+    /// Frame intervals returned by `frame_intervals`.
+    pub frame_intervals: Vec<FrameInterval>,
+}
+
+impl MockFormat {
+    /// Create a format entry with discrete sizes and intervals.
+    #[must_use]
+    pub fn discrete(
+        fourcc: FourCC,
+        description: &str,
+        compressed: bool,
+        sizes: &[(u32, u32)],
+        intervals: &[Fraction],
+    ) -> Self {
+        Self {
+            description: FormatDescription {
+                fourcc,
+                description: description.to_owned(),
+                compressed,
+            },
+            frame_sizes: sizes
+                .iter()
+                .map(|&(width, height)| FrameSize::Discrete { width, height })
+                .collect(),
+            frame_intervals: intervals
+                .iter()
+                .copied()
+                .map(FrameInterval::Discrete)
+                .collect(),
+        }
+    }
+}
+
+/// Default format table: YUYV and MJPG at common resolutions, 30 and 15 fps.
+#[must_use]
+pub fn default_formats() -> Vec<MockFormat> {
+    let sizes = [(640, 480), (1280, 720), (1920, 1080)];
+    let intervals = [Fraction::new(1, 30), Fraction::new(1, 15)];
+    vec![
+        MockFormat::discrete(FourCC::YUYV, "YUYV 4:2:2", false, &sizes, &intervals),
+        MockFormat::discrete(FourCC::MJPG, "Motion-JPEG", true, &sizes, &intervals),
+    ]
+}
+
+/// Default control table: a typical sensor with picture, exposure, gain and
+/// white balance controls.
+#[must_use]
+pub fn default_controls() -> Vec<ControlInfo> {
+    vec![
+        ControlInfo::integer(cid::BRIGHTNESS, "Brightness", 0, 255, 1, 128),
+        ControlInfo::integer(cid::CONTRAST, "Contrast", 0, 255, 1, 128),
+        ControlInfo::integer(cid::SATURATION, "Saturation", 0, 255, 1, 128),
+        ControlInfo::boolean(cid::AUTO_WHITE_BALANCE, "White Balance, Automatic", true),
+        ControlInfo::integer(cid::RED_BALANCE, "Red Balance", 0, 4095, 1, 1024),
+        ControlInfo::integer(cid::BLUE_BALANCE, "Blue Balance", 0, 4095, 1, 1024),
+        ControlInfo::integer(cid::EXPOSURE, "Exposure", 1, 66_666, 1, 10_000),
+        ControlInfo::integer(cid::ANALOGUE_GAIN, "Analogue Gain", 112, 960, 1, 112),
+        ControlInfo::menu(
+            cid::POWER_LINE_FREQUENCY,
+            "Power Line Frequency",
+            &["Disabled", "50 Hz", "60 Hz"],
+            1,
+        ),
+    ]
+}
+
+/// Colorimetry of the YUV pattern colors, used to render RGB formats.
+///
+/// Matches the conversion [`Format::colorimetry`] picks for a format with
+/// default colorimetry, so every format decodes to the same colors.
+const PATTERN_COLORIMETRY: Colorimetry = Colorimetry::new(YuvMatrix::Bt601, YuvRange::Limited);
+
+/// Linux `errno` reported by V4L2 once a device has been unplugged.
+const ENODEV: i32 = 19;
+
+/// Fault injected into a single frame of a mock stream.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrameFault {
+    /// The capture attempt fails with `Timeout`; the frame arrives on the
+    /// next attempt.
+    Timeout,
+    /// The capture attempt fails with `StreamError` carrying this message; the
+    /// frame arrives on the next attempt.
+    StreamError(String),
+    /// The frame is lost, leaving a gap in the sequence numbers.
+    Drop,
+    /// The frame is cut short to this many bytes used.
+    Truncate(u32),
+    /// The bytes in this range of the frame are inverted.
+    Corrupt(Range<usize>),
+    /// The device disappears: this capture and every later device operation
+    /// fails with `ENODEV`, as V4L2 reports for a disconnected camera.
+    Unplug,
+}
+
+/// Scripted faults for a [`MockDevice`].
+///
+/// Operation faults fail every call until the plan is changed; frame faults
+/// fire once, when the stream reaches the given frame. Frames are numbered by
+/// the device frame counter, which is also the sequence number of an
+/// undisturbed frame.
+#[derive(Debug, Default)]
+pub struct FaultPlan {
+    open: Option<CameraError>,
+    set_format: Option<CameraError>,
+    create_stream: Option<CameraError>,
+    frames: Vec<(u32, FrameFault)>,
+}
+
+impl Clone for FaultPlan {
+    fn clone(&self) -> Self {
+        Self {
+            open: self.open.as_ref().map(duplicate_error),
+            set_format: self.set_format.as_ref().map(duplicate_error),
+            create_stream: self.create_stream.as_ref().map(duplicate_error),
+            frames: self.frames.clone(),
+        }
+    }
+}
+
+impl FaultPlan {
+    /// Create an empty plan.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fail [`MockDevice::open`] with `error`.
+    #[must_use]
+    pub fn fail_open(mut self, error: CameraError) -> Self {
+        self.open = Some(error);
+        self
+    }
+
+    /// Fail `set_format` with `error`.
+    #[must_use]
+    pub fn fail_set_format(mut self, error: CameraError) -> Self {
+        self.set_format = Some(error);
+        self
+    }
+
+    /// Fail `create_stream` with `error`.
+    #[must_use]
+    pub fn fail_create_stream(mut self, error: CameraError) -> Self {
+        self.create_stream = Some(error);
+        self
+    }
+
+    /// Inject `fault` when the stream reaches `frame`.
+    ///
+    /// Several faults may target the same frame; they apply in the order
+    /// they were added.
+    #[must_use]
+    pub fn on_frame(mut self, frame: u32, fault: FrameFault) -> Self {
+        self.frames.push((frame, fault));
+        self
+    }
+
+    /// Drop every frame in `frames`.
+    #[must_use]
+    pub fn drop_frames<I: IntoIterator<Item = u32>>(mut self, frames: I) -> Self {
+        self.frames
+            .extend(frames.into_iter().map(|frame| (frame, FrameFault::Drop)));
+        self
+    }
+
+    /// Remove all operation faults, so the device opens and configures again.
+    pub fn clear_operation_faults(&mut self) {
+        self.open = None;
+        self.set_format = None;
+        self.create_stream = None;
+    }
+
+    /// Check whether no faults are left to inject.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.open.is_none()
+            && self.set_format.is_none()
+            && self.create_stream.is_none()
+            && self.frames.is_empty()
+    }
+
+    /// Remove and return the first fault for `frame` matching `filter`.
+    fn take_frame_fault<F: Fn(&FrameFault) -> bool>(
+        &mut self,
+        frame: u32,
+        filter: F,
+    ) -> Option<FrameFault> {
+        let position = self
+            .frames
+            .iter()
+            .position(|(target, fault)| *target == frame && filter(fault))?;
+        Some(self.frames.remove(position).1)
+    }
+}
+
+/// Copy an injected error, rebuilding I/O errors from their kind and message.
+fn duplicate_error(error: &CameraError) -> CameraError {
+    match error {
+        CameraError::DeviceNotFound(index) => CameraError::DeviceNotFound(*index),
+        CameraError::NoMatchingDevice(criteria) => CameraError::NoMatchingDevice(criteria.clone()),
+        CameraError::DeviceOpenFailed(reason) => CameraError::DeviceOpenFailed(reason.clone()),
+        CameraError::FormatNotSupported { format, reason } => CameraError::FormatNotSupported {
+            format: format.clone(),
+            reason: reason.clone(),
+        },
+        CameraError::StreamError(reason) => CameraError::StreamError(reason.clone()),
+        CameraError::ControlNotFound(id) => CameraError::ControlNotFound(*id),
+        CameraError::ControlError(reason) => CameraError::ControlError(reason.clone()),
+        CameraError::Timeout => CameraError::Timeout,
+        CameraError::Io(err) => CameraError::Io(err.raw_os_error().map_or_else(
+            || io::Error::new(err.kind(), err.to_string()),
+            io::Error::from_raw_os_error,
+        )),
+    }
+}
+
+/// How the mock spaces frames in time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FramePacing {
+    /// Frames are available as soon as they are requested.
+    #[default]
+    Immediate,
+    /// Frames become available at the frame interval, measured from the first
+    /// capture, like a real sensor.
+    Realtime,
+}
 
 /// Mock device for testing without hardware.
+#[derive(Debug, Clone)]
 pub struct MockDevice {
     capabilities: DeviceCapabilities,
     format: Format,
+    formats: Vec<MockFormat>,
+    interval: Fraction,
+    controls: Vec<(ControlInfo, ControlValue)>,
+    pattern: TestPattern,
+    pacing: FramePacing,
+    faults: FaultPlan,
+    unplugged: bool,
     frame_count: u32,
+    stall_after: Option<u32>,
+    /// Recorded frames played back instead of the pattern, with their format.
+    clip: Option<(Format, Vec<Vec<u8>>)>,
 }
 
 impl Default for MockDevice {
@@ -31,13 +317,26 @@ impl MockDevice {
                 can_stream: true,
             },
             format: Format::new(640, 480, FourCC::YUYV),
+            formats: default_formats(),
+            interval: Fraction::from_fps(30),
+            controls: Vec::new(),
+            pattern: TestPattern::ColorBars,
+            pacing: FramePacing::Immediate,
+            faults: FaultPlan::new(),
+            unplugged: false,
             frame_count: 0,
+            stall_after: None,
+            clip: None,
         }
+        .with_controls(default_controls())
     }
 
     /// Set the format for this mock device.
+    ///
+    /// Unlike `set_format`, the format is taken as is, even if the format
+    /// table does not list it.
     #[must_use]
-    pub fn with_format(mut self, format: Format) -> Self {
+    pub const fn with_format(mut self, format: Format) -> Self {
         self.format = format;
         self
     }
@@ -48,6 +347,297 @@ impl MockDevice {
         self.capabilities = capabilities;
         self
     }
+
+    /// Set the format table advertised by this mock device.
+    #[must_use]
+    pub fn with_formats(mut self, formats: Vec<MockFormat>) -> Self {
+        self.formats = formats;
+        self
+    }
+
+    /// Set the test pattern rendered by new streams.
+    #[must_use]
+    pub const fn with_pattern(mut self, pattern: TestPattern) -> Self {
+        self.pattern = pattern;
+        self
+    }
+
+    /// Set how frames are spaced in time.
+    #[must_use]
+    pub const fn with_pacing(mut self, pacing: FramePacing) -> Self {
+        self.pacing = pacing;
+        self
+    }
+
+    /// Play back `frames` of `format` in a loop instead of the test pattern.
+    ///
+    /// The device switches to `format`. The clip is played while the device
+    /// format matches it; after a `set_format` to anything else, streams fall
+    /// back to the test pattern. An empty clip is ignored.
+    #[must_use]
+    pub fn with_clip<I: IntoIterator<Item = Frame>>(mut self, format: Format, frames: I) -> Self {
+        let frames: Vec<Vec<u8>> = frames.into_iter().map(|frame| frame.data).collect();
+        if !frames.is_empty() {
+            self.format = format.clone();
+            self.clip = Some((format, frames));
+        }
+        self
+    }
+
+    /// Recorded frame to deliver as frame `sequence`, if a clip is playing.
+    fn clip_frame(&self, sequence: u32) -> Option<&[u8]> {
+        let (_, frames) = self
+            .clip
+            .as_ref()
+            .filter(|(format, _)| *format == self.format)?;
+        frames
+            .get(sequence as usize % frames.len())
+            .map(Vec::as_slice)
+    }
+
+    /// Stall the stream after `frames` frames have been delivered.
+    ///
+    /// A stalled stream never delivers another frame. Captures with a timeout
+    /// fail with `Timeout` after waiting it out, whatever the pacing;
+    /// blocking captures fail immediately so tests do not hang.
+    #[must_use]
+    pub const fn with_stall_after(mut self, frames: u32) -> Self {
+        self.stall_after = Some(frames);
+        self
+    }
+
+    /// Set the frame interval for this mock device.
+    #[must_use]
+    pub const fn with_frame_interval(mut self, interval: Fraction) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// Set the control table for this mock device. Every control starts at its default value.
+    #[must_use]
+    pub fn with_controls(mut self, controls: Vec<ControlInfo>) -> Self {
+        self.controls = controls
+            .into_iter()
+            .map(|info| {
+                let value = match info.control_type {
+                    ControlType::Boolean => ControlValue::Boolean(info.default != 0),
+                    ControlType::String => ControlValue::String(String::new()),
+                    _ => ControlValue::Integer(info.default),
+                };
+                (info, value)
+            })
+            .collect();
+        self
+    }
+
+    /// Inject the faults scripted in `plan`.
+    #[must_use]
+    pub fn with_faults(mut self, plan: FaultPlan) -> Self {
+        self.faults = plan;
+        self
+    }
+
+    /// Get the remaining fault plan, e.g. to clear faults mid-test.
+    pub fn faults_mut(&mut self) -> &mut FaultPlan {
+        &mut self.faults
+    }
+
+    /// Check whether an injected unplug has removed the device.
+    #[must_use]
+    pub const fn is_unplugged(&self) -> bool {
+        self.unplugged
+    }
+
+    /// Simulate opening the device node again.
+    ///
+    /// Returns a fresh handle with this configuration and a reset frame
+    /// counter, the way reopening a camera restarts its sequence numbers.
+    ///
+    /// # Errors
+    ///
+    /// Returns the injected open error, or `DeviceOpenFailed` once the device
+    /// has been unplugged.
+    pub fn open(&self) -> Result<Self> {
+        if let Some(error) = &self.faults.open {
+            return Err(duplicate_error(error));
+        }
+        if self.unplugged {
+            return Err(CameraError::DeviceOpenFailed(format!(
+                "{}: {}",
+                self.capabilities.bus_info,
+                io::Error::from_raw_os_error(ENODEV)
+            )));
+        }
+        let mut device = self.clone();
+        device.frame_count = 0;
+        Ok(device)
+    }
+
+    /// Number of frames delivered so far, across all streams.
+    #[must_use]
+    pub const fn frame_count(&self) -> u32 {
+        self.frame_count
+    }
+
+    /// Light gains of the simulated sensor for red, green and blue, or `None`
+    /// while every sensor control is at its default.
+    fn sensor_gains(&self) -> Option<[f32; 3]> {
+        let ratio = |id| {
+            let (info, value) = self.controls.iter().find(|(info, _)| info.id == id)?;
+            let value = value.as_integer()?;
+            (value != info.default && info.default > 0).then(|| {
+                #[allow(clippy::cast_precision_loss)]
+                let ratio = value as f32 / info.default as f32;
+                ratio
+            })
+        };
+        let [exposure, gain, red, blue] = [
+            cid::EXPOSURE,
+            cid::ANALOGUE_GAIN,
+            cid::RED_BALANCE,
+            cid::BLUE_BALANCE,
+        ]
+        .map(ratio);
+        if [exposure, gain, red, blue].iter().all(Option::is_none) {
+            return None;
+        }
+        let light = exposure.unwrap_or(1.0) * gain.unwrap_or(1.0);
+        Some([
+            light * red.unwrap_or(1.0),
+            light,
+            light * blue.unwrap_or(1.0),
+        ])
+    }
+
+    /// Validate a control write the way a V4L2 driver would.
+    ///
+    /// Integer values are clamped to the range and rounded to the step; menu
+    /// values must name an existing entry.
+    fn validate_control(&self, id: u32, value: &ControlValue) -> Result<ControlValue> {
+        let (info, _) = self
+            .controls
+            .iter()
+            .find(|(info, _)| info.id == id)
+            .ok_or(CameraError::ControlNotFound(id))?;
+
+        if !info.is_writable() {
+            return Err(CameraError::ControlError(format!(
+                "Control \"{}\" is not writable",
+                info.name
+            )));
+        }
+
+        match (info.control_type, value) {
+            (ControlType::Boolean, ControlValue::Boolean(_))
+            | (ControlType::String, ControlValue::String(_)) => Ok(value.clone()),
+            (ControlType::Boolean, ControlValue::Integer(raw)) => {
+                Ok(ControlValue::Boolean(*raw != 0))
+            }
+            (ControlType::Menu | ControlType::IntegerMenu, ControlValue::Integer(raw)) => {
+                if info
+                    .menu_items
+                    .iter()
+                    .any(|item| i64::from(item.index) == *raw)
+                {
+                    Ok(value.clone())
+                } else {
+                    Err(CameraError::ControlError(format!(
+                        "Invalid menu index {raw} for \"{}\"",
+                        info.name
+                    )))
+                }
+            }
+            (_, ControlValue::Integer(raw)) => {
+                let clamped = (*raw).clamp(info.minimum, info.maximum);
+                let step = i64::try_from(info.step).unwrap_or(1).max(1);
+                let offset = clamped - info.minimum;
+                let rounded = info.minimum + (offset + step / 2) / step * step;
+                Ok(ControlValue::Integer(rounded.min(info.maximum)))
+            }
+            _ => Err(CameraError::ControlError(format!(
+                "Value {value} has wrong type for \"{}\"",
+                info.name
+            ))),
+        }
+    }
+
+    /// Pick the format a driver would grant for `requested`.
+    ///
+    /// Pixel formats missing from the table fall back to the first entry, and
+    /// the size snaps to the closest one the entry lists. An empty table
+    /// accepts the request unchanged.
+    fn grant_format(&self, requested: &Format) -> Format {
+        let Some(entry) = self
+            .find_format(requested.fourcc)
+            .ok()
+            .or_else(|| self.formats.first())
+        else {
+            return Format::new(requested.width, requested.height, requested.fourcc);
+        };
+        let (width, height) = entry
+            .frame_sizes
+            .iter()
+            .filter_map(|size| nearest_in_size(size, requested.width, requested.height))
+            .min_by_key(|&(width, height)| {
+                u64::from(width.abs_diff(requested.width))
+                    + u64::from(height.abs_diff(requested.height))
+            })
+            .unwrap_or((requested.width, requested.height));
+        Format::new(width, height, entry.description.fourcc)
+    }
+
+    /// Pick the interval a driver would grant for `requested` at the current format.
+    ///
+    /// Discrete intervals snap to the nearest entry, stepwise ranges clamp to their
+    /// bounds. Formats or sizes missing from the table accept the request unchanged.
+    fn grant_interval(&self, requested: Fraction) -> Fraction {
+        let Ok(intervals) =
+            self.frame_intervals(self.format.fourcc, self.format.width, self.format.height)
+        else {
+            return requested;
+        };
+
+        let seconds = |fraction: Fraction| {
+            f64::from(fraction.numerator) / f64::from(fraction.denominator.max(1))
+        };
+        let target = seconds(requested);
+
+        let candidates = intervals.iter().map(|interval| match *interval {
+            FrameInterval::Discrete(fraction) => fraction,
+            FrameInterval::Stepwise { min, max, .. } => {
+                if target < seconds(min) {
+                    min
+                } else if target > seconds(max) {
+                    max
+                } else {
+                    requested
+                }
+            }
+        });
+
+        candidates
+            .min_by(|a, b| {
+                (seconds(*a) - target)
+                    .abs()
+                    .total_cmp(&(seconds(*b) - target).abs())
+            })
+            .unwrap_or(requested)
+    }
+
+    /// Fail with `ENODEV` once the device has been unplugged.
+    fn ensure_present(&self) -> Result<()> {
+        if self.unplugged {
+            return Err(CameraError::Io(io::Error::from_raw_os_error(ENODEV)));
+        }
+        Ok(())
+    }
+
+    fn find_format(&self, fourcc: FourCC) -> Result<&MockFormat> {
+        self.formats
+            .iter()
+            .find(|entry| entry.description.fourcc == fourcc)
+            .ok_or_else(|| CameraError::StreamError(format!("Pixel format {fourcc} not supported")))
+    }
 }
 
 impl CameraDevice for MockDevice {
@@ -58,24 +648,118 @@ impl CameraDevice for MockDevice {
     }
 
     fn format(&self) -> Result<Format> {
+        self.ensure_present()?;
         Ok(self.format.clone())
     }
 
     fn set_format(&mut self, format: &Format) -> Result<Format> {
-        self.format = format.clone();
+        self.ensure_present()?;
+        if let Some(error) = &self.faults.set_format {
+            return Err(duplicate_error(error));
+        }
+        // Like a driver, snap to an advertised mode and compute stride and
+        // size rather than trusting the caller
+        self.format = self.grant_format(format);
+        self.interval = self.grant_interval(self.interval);
         Ok(self.format.clone())
     }
 
-    fn create_stream(&mut self, _buffer_count: u32) -> Result<Self::Stream<'_>> {
+    fn frame_interval(&self) -> Result<Fraction> {
+        self.ensure_present()?;
+        Ok(self.interval)
+    }
+
+    fn set_frame_interval(&mut self, interval: Fraction) -> Result<Fraction> {
+        self.ensure_present()?;
+        if interval.numerator == 0 || interval.denominator == 0 {
+            return Err(CameraError::StreamError(format!(
+                "Invalid frame interval {interval}"
+            )));
+        }
+        self.interval = self.grant_interval(interval);
+        Ok(self.interval)
+    }
+
+    fn supported_formats(&self) -> Result<Vec<FormatDescription>> {
+        Ok(self
+            .formats
+            .iter()
+            .map(|entry| entry.description.clone())
+            .collect())
+    }
+
+    fn frame_sizes(&self, fourcc: FourCC) -> Result<Vec<FrameSize>> {
+        Ok(self.find_format(fourcc)?.frame_sizes.clone())
+    }
+
+    fn frame_intervals(
+        &self,
+        fourcc: FourCC,
+        width: u32,
+        height: u32,
+    ) -> Result<Vec<FrameInterval>> {
+        let entry = self.find_format(fourcc)?;
+        if !entry
+            .frame_sizes
+            .iter()
+            .any(|size| size.contains(width, height))
+        {
+            return Err(CameraError::StreamError(format!(
+                "Frame size {width}x{height} not supported for {fourcc}"
+            )));
+        }
+        Ok(entry.frame_intervals.clone())
+    }
+
+    fn controls(&self) -> Result<Vec<ControlInfo>> {
+        self.ensure_present()?;
+        Ok(self.controls.iter().map(|(info, _)| info.clone()).collect())
+    }
+
+    fn control(&self, id: u32) -> Result<ControlValue> {
+        self.ensure_present()?;
+        self.controls
+            .iter()
+            .find(|(info, _)| info.id == id)
+            .map(|(_, value)| value.clone())
+            .ok_or(CameraError::ControlNotFound(id))
+    }
+
+    fn set_controls(&mut self, controls: &[(u32, ControlValue)]) -> Result<()> {
+        self.ensure_present()?;
+        // Validate everything first so a failing write leaves all controls untouched
+        let validated = controls
+            .iter()
+            .map(|(id, value)| Ok((*id, self.validate_control(*id, value)?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        for (id, value) in validated {
+            if let Some((_, current)) = self.controls.iter_mut().find(|(info, _)| info.id == id) {
+                *current = value;
+            }
+        }
+        Ok(())
+    }
+
+    fn create_stream(&mut self, buffer_count: u32) -> Result<Self::Stream<'_>> {
+        self.ensure_present()?;
+        if let Some(error) = &self.faults.create_stream {
+            return Err(duplicate_error(error));
+        }
         Ok(MockStream {
+            pattern: self.pattern,
             device: self,
-            pattern: TestPattern::ColorBars,
+            epoch: None,
+            buffers: vec![Vec::new(); buffer_count.max(1) as usize],
+            queued: QueuedBuffers(Mutex::new((0..buffer_count.max(1) as usize).collect())),
+            #[cfg(feature = "tokio")]
+            pacing: None,
         })
     }
 }
 
 /// Test pattern types for mock frame generation.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum TestPattern {
     /// SMPTE color bars pattern.
     ColorBars,
@@ -86,64 +770,424 @@ pub enum TestPattern {
 }
 
 /// Mock capture stream for testing.
+///
+/// Frames are rendered into reusable buffers that are queued again when their
+/// borrowed frame is dropped, like the mmap buffers of a real stream, so
+/// borrowed frames never allocate once every buffer has been filled.
 pub struct MockStream<'a> {
     device: &'a mut MockDevice,
     pattern: TestPattern,
+    /// Time of the first realtime-paced capture.
+    epoch: Option<Instant>,
+    buffers: Vec<Vec<u8>>,
+    queued: QueuedBuffers,
+    #[cfg(feature = "tokio")]
+    pacing: Option<Pacing>,
+}
+
+/// Indices of the stream buffers waiting to be filled, oldest first.
+#[derive(Debug)]
+struct QueuedBuffers(Mutex<VecDeque<usize>>);
+
+impl QueuedBuffers {
+    fn lock(&self) -> MutexGuard<'_, VecDeque<usize>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl BufferQueue for QueuedBuffers {
+    fn requeue(&self, index: usize) {
+        self.lock().push_back(index);
+    }
+}
+
+/// Async frame pacing state: frame N becomes ready at `epoch + N * interval`.
+#[cfg(feature = "tokio")]
+struct Pacing {
+    epoch: tokio::time::Instant,
+    sleep: Pin<Box<Sleep>>,
 }
 
 impl MockStream<'_> {
     /// Set the test pattern for frame generation.
     #[must_use]
-    pub fn with_pattern(mut self, pattern: TestPattern) -> Self {
+    pub const fn with_pattern(mut self, pattern: TestPattern) -> Self {
         self.pattern = pattern;
         self
     }
+
+    /// Number of buffers queued for upcoming frames.
+    ///
+    /// A borrowed frame holds its buffer out of the queue until it is dropped.
+    #[must_use]
+    pub fn queued_buffers(&self) -> usize {
+        self.queued.lock().len()
+    }
 }
 
 impl CaptureStream for MockStream<'_> {
-    fn next_frame(&mut self) -> Result<Frame> {
-        let format = &self.device.format;
-        let data = generate_test_frame(format, self.pattern);
+    fn next_frame_ref(&mut self) -> Result<FrameRef<'_>> {
+        self.capture(None)
+    }
+
+    fn next_frame_ref_timeout(&mut self, timeout: Duration) -> Result<FrameRef<'_>> {
+        self.capture(Some(timeout))
+    }
+}
+
+impl MockStream<'_> {
+    /// Time at which the next frame is due under realtime pacing.
+    fn frame_due(&mut self) -> Instant {
+        let epoch = *self.epoch.get_or_insert_with(Instant::now);
+        epoch
+            + self
+                .device
+                .interval
+                .duration_of(self.device.frame_count)
+                .unwrap_or_default()
+    }
+
+    /// Check whether the simulated sensor has stopped delivering frames.
+    fn is_stalled(&self) -> bool {
+        self.device
+            .stall_after
+            .is_some_and(|frames| self.device.frame_count >= frames)
+    }
+
+    /// Fail with `Timeout`, after waiting it out.
+    ///
+    /// The wait applies under every pacing, so callers polling a stalled
+    /// stream with a timeout do not spin.
+    fn time_out(timeout: Option<Duration>) -> Result<FrameRef<'static>> {
+        if let Some(timeout) = timeout {
+            thread::sleep(timeout);
+        }
+        Err(CameraError::Timeout)
+    }
+
+    /// Under realtime pacing, sleep until the next frame is due.
+    fn pace(&mut self, timeout: Option<Duration>) -> Result<()> {
+        if self.device.pacing != FramePacing::Realtime {
+            return Ok(());
+        }
+        let wait = self.frame_due().saturating_duration_since(Instant::now());
+        if let Some(timeout) = timeout.filter(|timeout| wait > *timeout) {
+            thread::sleep(timeout);
+            return Err(CameraError::Timeout);
+        }
+        thread::sleep(wait);
+        Ok(())
+    }
+
+    /// Wait for the next frame and apply the faults that replace it.
+    fn wait_for_frame(&mut self, timeout: Option<Duration>) -> Result<()> {
+        loop {
+            if self.device.unplugged {
+                return Err(unplugged_stream_error());
+            }
+            if self.is_stalled() {
+                return Self::time_out(timeout).map(drop);
+            }
+
+            self.pace(timeout)?;
+
+            let frame = self.device.frame_count;
+            let fault = self.device.faults.take_frame_fault(frame, |fault| {
+                !matches!(fault, FrameFault::Truncate(_) | FrameFault::Corrupt(_))
+            });
+            match fault {
+                None => return Ok(()),
+                Some(FrameFault::Timeout) => return Self::time_out(timeout).map(drop),
+                Some(FrameFault::StreamError(message)) => {
+                    return Err(CameraError::StreamError(message))
+                }
+                Some(FrameFault::Unplug) => {
+                    self.device.unplugged = true;
+                    return Err(unplugged_stream_error());
+                }
+                // Dropped: move on to the next frame
+                Some(_) => self.device.frame_count += 1,
+            }
+        }
+    }
+
+    /// Render the next frame into the oldest queued buffer.
+    fn capture(&mut self, timeout: Option<Duration>) -> Result<FrameRef<'_>> {
+        self.wait_for_frame(timeout)?;
+
+        // Like a driver with nothing queued, no frame can be delivered
+        let queued = self
+            .queued
+            .0
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
+        let Some(index) = queued.pop_front() else {
+            return Self::time_out(timeout);
+        };
 
         let seq = self.device.frame_count;
+        let gains = self.device.sensor_gains();
+        let mut bytes_used = self.device.format.size;
+        let format = &self.device.format;
+        let data = self
+            .buffers
+            .get_mut(index)
+            .ok_or_else(|| CameraError::StreamError("no stream buffers".to_owned()))?;
+        if let Some(clip) = self.device.clip_frame(seq) {
+            data.clear();
+            data.extend_from_slice(clip);
+            bytes_used = u32::try_from(clip.len()).unwrap_or(u32::MAX);
+        } else {
+            render_test_frame(data, format, self.pattern, gains);
+        }
+
+        let faults = &mut self.device.faults;
+        while let Some(fault) = faults.take_frame_fault(seq, |_| true) {
+            match fault {
+                FrameFault::Truncate(limit) => bytes_used = bytes_used.min(limit),
+                FrameFault::Corrupt(range) => invert_bytes(data, range),
+                _ => {}
+            }
+        }
         self.device.frame_count += 1;
 
-        Ok(Frame {
-            data,
-            metadata: FrameMetadata {
-                sequence: seq,
-                timestamp: Duration::from_millis(u64::from(seq) * 33), // ~30fps
-                bytes_used: format.size,
-            },
-        })
+        let metadata = FrameMetadata {
+            sequence: seq,
+            timestamp: self.device.interval.duration_of(seq).unwrap_or_default(),
+            bytes_used,
+        };
+        Ok(FrameRef::in_buffer(
+            data.get(..bytes_used as usize).unwrap_or(data),
+            metadata,
+            &self.queued,
+            index,
+        ))
     }
 }
 
-/// Generate test frame data based on pattern.
-fn generate_test_frame(format: &Format, pattern: TestPattern) -> Vec<u8> {
-    let size = (format.width * format.height * 2) as usize; // YUYV = 2 bytes/pixel
-    let mut data = vec![0u8; size];
-
-    match pattern {
-        TestPattern::ColorBars => {
-            generate_color_bars(&mut data, format.width, format.height);
+#[cfg(feature = "tokio")]
+impl AsyncCapture for MockStream<'_> {
+    /// Under realtime pacing, frames become ready at the configured frame
+    /// interval, measured from the first poll. A stalled stream stays pending
+    /// forever.
+    fn poll_frame_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        if self.device.unplugged {
+            // Let the capture report the error
+            return Poll::Ready(Ok(()));
         }
-        TestPattern::Gradient => {
-            generate_gradient(&mut data, format.width, format.height);
+        if self.is_stalled() {
+            return Poll::Pending;
         }
-        TestPattern::Solid(y, u, v) => {
-            generate_solid(&mut data, y, u, v);
+        if self.device.pacing == FramePacing::Immediate {
+            return Poll::Ready(Ok(()));
         }
+
+        let offset = self
+            .device
+            .interval
+            .duration_of(self.device.frame_count)
+            .unwrap_or_default();
+        let pacing = self.pacing.get_or_insert_with(|| {
+            let epoch = tokio::time::Instant::now();
+            Pacing {
+                epoch,
+                sleep: Box::pin(sleep_until(epoch)),
+            }
+        });
+        let due = pacing.epoch + offset;
+        if pacing.sleep.deadline() != due {
+            pacing.sleep.as_mut().reset(due);
+        }
+        pacing.sleep.as_mut().poll(cx).map(Ok)
+    }
+}
+
+/// Capture error of a stream whose device has been unplugged, worded like the
+/// one a V4L2 stream reports.
+fn unplugged_stream_error() -> CameraError {
+    CameraError::StreamError(io::Error::from_raw_os_error(ENODEV).to_string())
+}
+
+/// Invert the bytes of `data` in `range`, clamped to the buffer.
+fn invert_bytes(data: &mut [u8], range: Range<usize>) {
+    let end = range.end.min(data.len());
+    for byte in data.get_mut(range.start.min(end)..end).unwrap_or_default() {
+        *byte = !*byte;
     }
+}
 
+/// Generate test frame data based on pattern.
+///
+/// Packed, semi-planar, planar and raw Bayer formats are filled; compressed
+/// formats hold the pattern as a baseline JPEG, zero-padded to the format's
+/// size.
+#[must_use]
+pub fn generate_test_frame(format: &Format, pattern: TestPattern) -> Vec<u8> {
+    let mut data = Vec::new();
+    render_test_frame(&mut data, format, pattern, None);
     data
 }
 
-/// Generate YUYV color bars pattern.
-fn generate_color_bars(data: &mut [u8], width: u32, height: u32) {
+/// Render a test pattern into `data`, reusing its allocation, seen through
+/// the sensor `gains` if any.
+///
+/// The buffer is sized from the format's stride and size, or to fit the JPEG
+/// of a compressed format.
+fn render_test_frame(
+    data: &mut Vec<u8>,
+    format: &Format,
+    pattern: TestPattern,
+    gains: Option<[f32; 3]>,
+) {
+    data.clear();
+    data.resize(format.size as usize, 0);
+    let Some(info) = format.fourcc.info() else {
+        return;
+    };
+    match info.layout {
+        Layout::Compressed => render_jpeg(data, format, pattern, gains),
+        _ if format.stride == 0 => {}
+        Layout::Packed => render_packed(data, format, info, pattern, gains),
+        Layout::SemiPlanar | Layout::Planar => render_planar(data, format, info, pattern, gains),
+        Layout::Bayer => render_bayer(data, format, pattern, gains),
+    }
+}
+
+/// Write the pattern, rendered as RGB, as a baseline JPEG at the start of
+/// `data`, leaving the zeros after it like the padding of a UVC buffer.
+fn render_jpeg(data: &mut Vec<u8>, format: &Format, pattern: TestPattern, gains: Option<[f32; 3]>) {
+    let mut rgb = Vec::new();
+    let rgb_format = Format::new(format.width, format.height, FourCC::RGB3);
+    render_test_frame(&mut rgb, &rgb_format, pattern, gains);
+    if let Some(jpeg) = crate::mjpeg::encode_rgb(&rgb, format.width, format.height) {
+        data.splice(..jpeg.len().min(data.len()), jpeg);
+    }
+}
+
+/// Fill a packed YUV 4:2:2, RGB or greyscale frame.
+fn render_packed(
+    data: &mut [u8],
+    format: &Format,
+    info: &PixelFormatInfo,
+    pattern: TestPattern,
+    gains: Option<[f32; 3]>,
+) {
+    let width = format.width as usize;
+    let bytes_per_pixel = info.bytes_per_pixel() as usize;
+    let rows = data
+        .chunks_exact_mut(format.stride as usize)
+        .take(format.height as usize);
+
+    for row in rows {
+        if info.chroma_h_subsampling == 2 {
+            // Both pixels of a pair share the color sampled at the even one
+            for (pair, group) in (0u32..).zip(row.chunks_exact_mut(4).take(width / 2)) {
+                let (y_val, u_val, v_val) = sensor_color(pattern, gains, pair * 2, format.width);
+                write_yuv422(format.fourcc, group, y_val, u_val, v_val);
+            }
+        } else {
+            for (x, pixel) in (0u32..).zip(row.chunks_exact_mut(bytes_per_pixel).take(width)) {
+                let (y_val, u_val, v_val) = sensor_color(pattern, gains, x, format.width);
+                write_pixel(format.fourcc, pixel, y_val, u_val, v_val);
+            }
+        }
+    }
+}
+
+/// Fill the luma and chroma planes of a semi-planar or planar frame.
+fn render_planar(
+    data: &mut [u8],
+    format: &Format,
+    info: &PixelFormatInfo,
+    pattern: TestPattern,
+    gains: Option<[f32; 3]>,
+) {
+    let stride = format.stride as usize;
+    let chroma_stride = info.plane_stride(1, format.stride) as usize;
+    let chroma_size = chroma_stride * info.plane_height(1, format.height) as usize;
+
+    let colors: Vec<(u8, u8, u8)> = (0..format.width)
+        .map(|x| sensor_color(pattern, gains, x, format.width))
+        .collect();
+    let luma: Vec<u8> = colors.iter().map(|&(y, _, _)| y).collect();
+    // Each chroma sample takes the color of the first pixel it covers
+    let (cb, cr): (Vec<u8>, Vec<u8>) = colors
+        .iter()
+        .step_by(info.chroma_h_subsampling as usize)
+        .map(|&(_, u, v)| (u, v))
+        .unzip();
+    let (first, second) = if matches!(format.fourcc, FourCC::NV21 | FourCC::NV61 | FourCC::YV12) {
+        (cr, cb)
+    } else {
+        (cb, cr)
+    };
+
+    let (luma_plane, chroma) = data.split_at_mut((stride * format.height as usize).min(data.len()));
+    fill_rows(luma_plane, stride, &luma);
+    if info.layout == Layout::SemiPlanar {
+        let interleaved: Vec<u8> = first
+            .iter()
+            .zip(&second)
+            .flat_map(|(&first, &second)| [first, second])
+            .collect();
+        fill_rows(chroma, chroma_stride, &interleaved);
+    } else {
+        let (first_plane, second_plane) = chroma.split_at_mut(chroma_size.min(chroma.len()));
+        fill_rows(first_plane, chroma_stride, &first);
+        fill_rows(second_plane, chroma_stride, &second);
+    }
+}
+
+/// Fill a raw Bayer frame, scaling the RGB pattern colors to the bit depth.
+fn render_bayer(
+    data: &mut Vec<u8>,
+    format: &Format,
+    pattern: TestPattern,
+    gains: Option<[f32; 3]>,
+) {
+    let Some(bayer) = BayerFormat::from_fourcc(format.fourcc) else {
+        return;
+    };
+    let max = u32::from(bayer.max_value());
+    let colors: Vec<[u16; 3]> = (0..format.width)
+        .map(|x| {
+            let (y_val, u_val, v_val) = sensor_color(pattern, gains, x, format.width);
+            let rgb = PATTERN_COLORIMETRY.yuv_to_rgb(y_val, u_val, v_val);
+            <[u8; 3]>::from(rgb)
+                .map(|value| u16::try_from(u32::from(value) * max / 255).unwrap_or(u16::MAX))
+        })
+        .collect();
+    let samples = (0..format.height)
+        .flat_map(|y| (0u32..).zip(&colors).map(move |(x, color)| (x, y, color)))
+        .map(|(x, y, color)| {
+            let index = bayer.pattern.color_at(x, y).index();
+            color.get(index).copied().unwrap_or_default()
+        })
+        .collect();
+    let raw = RawImage {
+        width: format.width,
+        height: format.height,
+        format: bayer,
+        samples,
+    };
+    if let Ok(packed) = raw.pack(format) {
+        *data = packed;
+    }
+}
+
+/// Copy `line` to the start of every `stride`-byte row of `plane`.
+fn fill_rows(plane: &mut [u8], stride: usize, line: &[u8]) {
+    for row in plane.chunks_exact_mut(stride.max(1)) {
+        if let Some(start) = row.get_mut(..line.len()) {
+            start.copy_from_slice(line);
+        }
+    }
+}
+
+/// YUV color of a pattern at column `x`.
+fn pattern_color(pattern: TestPattern, x: u32, width: u32) -> (u8, u8, u8) {
     // 8 color bars: White, Yellow, Cyan, Green, Magenta, Red, Blue, Black
-    // YUYV values for each bar
-    let bars: [(u8, u8, u8); 8] = [
+    const BARS: [(u8, u8, u8); 8] = [
         (235, 128, 128), // White
         (210, 16, 146),  // Yellow
         (170, 166, 16),  // Cyan
@@ -154,57 +1198,74 @@ fn generate_color_bars(data: &mut [u8], width: u32, height: u32) {
         (16, 128, 128),  // Black
     ];
 
-    let bar_width = width / 8;
-
-    for y in 0..height {
-        for x in (0..width).step_by(2) {
+    match pattern {
+        TestPattern::ColorBars => {
+            let bar_width = (width / 8).max(1);
             let bar_idx = (x / bar_width).min(7) as usize;
-            let (y_val, u_val, v_val) = bars[bar_idx];
-
-            let offset = ((y * width + x) * 2) as usize;
-            if offset + 3 < data.len() {
-                data[offset] = y_val;     // Y0
-                data[offset + 1] = u_val; // U
-                data[offset + 2] = y_val; // Y1
-                data[offset + 3] = v_val; // V
-            }
+            BARS.get(bar_idx).copied().unwrap_or(BARS[7])
+        }
+        TestPattern::Gradient => {
+            #[allow(clippy::cast_possible_truncation)]
+            let y_val = ((u64::from(x) * 255) / u64::from(width.max(1))) as u8;
+            (y_val, 128, 128)
         }
+        TestPattern::Solid(y, u, v) => (y, u, v),
     }
 }
 
-/// Generate YUYV horizontal gradient pattern.
-fn generate_gradient(data: &mut [u8], width: u32, height: u32) {
-    for y in 0..height {
-        for x in (0..width).step_by(2) {
-            #[allow(clippy::cast_possible_truncation)]
-            let y_val = ((x * 255) / width) as u8;
-            let offset = ((y * width + x) * 2) as usize;
-
-            if offset + 3 < data.len() {
-                data[offset] = y_val;     // Y0
-                data[offset + 1] = 128;   // U (neutral)
-                data[offset + 2] = y_val; // Y1
-                data[offset + 3] = 128;   // V (neutral)
-            }
-        }
+/// YUV color of a pattern at column `x`, scaled in RGB by the sensor `gains`.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn sensor_color(pattern: TestPattern, gains: Option<[f32; 3]>, x: u32, width: u32) -> (u8, u8, u8) {
+    let (y_val, u_val, v_val) = pattern_color(pattern, x, width);
+    let Some(gains) = gains else {
+        return (y_val, u_val, v_val);
+    };
+    let rgb = <[u8; 3]>::from(PATTERN_COLORIMETRY.yuv_to_rgb(y_val, u_val, v_val));
+    let mut scaled = [0; 3];
+    for ((value, &sample), gain) in scaled.iter_mut().zip(&rgb).zip(gains) {
+        *value = (f32::from(sample) * gain).round().clamp(0.0, 255.0) as u8;
     }
+    let [red, green, blue] = scaled;
+    PATTERN_COLORIMETRY.rgb_to_yuv(red, green, blue)
+}
+
+/// Write a packed 4:2:2 pixel pair in the component order of `fourcc`.
+fn write_yuv422(fourcc: FourCC, group: &mut [u8], y: u8, u: u8, v: u8) {
+    let bytes = match fourcc {
+        FourCC::YVYU => [y, v, y, u],
+        FourCC::UYVY => [u, y, v, y],
+        FourCC::VYUY => [v, y, u, y],
+        _ => [y, u, y, v],
+    };
+    group.copy_from_slice(&bytes);
 }
 
-/// Generate solid color YUYV frame.
-fn generate_solid(data: &mut [u8], y: u8, u: u8, v: u8) {
-    for i in (0..data.len()).step_by(4) {
-        if i + 3 < data.len() {
-            data[i] = y;     // Y0
-            data[i + 1] = u; // U
-            data[i + 2] = y; // Y1
-            data[i + 3] = v; // V
+/// Write a single packed RGB or greyscale pixel.
+#[allow(clippy::many_single_char_names)]
+fn write_pixel(fourcc: FourCC, pixel: &mut [u8], y: u8, u: u8, v: u8) {
+    let (r, g, b) = PATTERN_COLORIMETRY.yuv_to_rgb(y, u, v);
+    match (fourcc, pixel) {
+        (FourCC::RGB3, [dr, dg, db]) | (FourCC::BGR3, [db, dg, dr]) => {
+            (*dr, *dg, *db) = (r, g, b);
+        }
+        (FourCC::XR24 | FourCC::AR24, [db, dg, dr, da]) => {
+            (*dr, *dg, *db, *da) = (r, g, b, 0xff);
         }
+        (FourCC::RGBP, pixel @ [_, _]) => {
+            let value = (u16::from(r) >> 3) << 11 | (u16::from(g) >> 2) << 5 | u16::from(b) >> 3;
+            pixel.copy_from_slice(&value.to_le_bytes());
+        }
+        (FourCC::GREY, [luma]) => *luma = y,
+        (FourCC::Y16, pixel @ [_, _]) => pixel.copy_from_slice(&[0, y]),
+        _ => {}
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::controls::ControlFlags;
+    use std::time::Duration;
 
     #[test]
     fn test_mock_device_creation() {
@@ -227,6 +1288,65 @@ mod tests {
         assert_eq!(actual.height, 720);
     }
 
+    #[test]
+    fn test_mock_supported_formats() {
+        let device = MockDevice::new();
+        let formats = device
+            .supported_formats()
+            .expect("supported_formats should succeed");
+        assert_eq!(formats.len(), 2);
+        assert_eq!(formats[0].fourcc, FourCC::YUYV);
+        assert!(!formats[0].compressed);
+        assert_eq!(formats[1].fourcc, FourCC::MJPG);
+        assert!(formats[1].compressed);
+    }
+
+    #[test]
+    fn test_mock_frame_sizes_and_intervals() {
+        let device = MockDevice::new();
+        let sizes = device
+            .frame_sizes(FourCC::YUYV)
+            .expect("frame_sizes should succeed");
+        assert!(sizes.contains(&FrameSize::Discrete {
+            width: 1280,
+            height: 720
+        }));
+
+        let intervals = device
+            .frame_intervals(FourCC::YUYV, 1280, 720)
+            .expect("frame_intervals should succeed");
+        assert_eq!(intervals[0], FrameInterval::Discrete(Fraction::new(1, 30)));
+
+        assert!(device.frame_sizes(FourCC::RGB3).is_err());
+        assert!(device.frame_intervals(FourCC::YUYV, 800, 600).is_err());
+    }
+
+    #[test]
+    fn test_mock_custom_formats() {
+        let device = MockDevice::new().with_formats(vec![MockFormat {
+            description: FormatDescription {
+                fourcc: FourCC::RGB3,
+                description: "RGB3".to_owned(),
+                compressed: false,
+            },
+            frame_sizes: vec![FrameSize::Stepwise {
+                min_width: 64,
+                max_width: 4096,
+                step_width: 2,
+                min_height: 64,
+                max_height: 2160,
+                step_height: 2,
+            }],
+            frame_intervals: vec![FrameInterval::Discrete(Fraction::new(1, 60))],
+        }]);
+
+        let intervals = device
+            .frame_intervals(FourCC::RGB3, 1024, 768)
+            .expect("frame_intervals should succeed");
+        assert_eq!(intervals.len(), 1);
+        assert!(device.frame_sizes(FourCC::YUYV).is_err());
+    }
+
     #[test]
     fn test_mock_stream_capture() {
         let mut device = MockDevice::new();
@@ -240,6 +1360,154 @@ mod tests {
         assert_eq!(frame2.metadata.sequence, 1);
     }
 
+    #[test]
+    fn test_mock_frame_interval_snaps_to_supported() {
+        let mut device = MockDevice::new();
+        let current = device
+            .frame_interval()
+            .expect("frame_interval should succeed");
+        assert_eq!(current, Fraction::from_fps(30));
+
+        let granted = device
+            .set_frame_interval(Fraction::from_fps(15))
+            .expect("set_frame_interval should succeed");
+        assert_eq!(granted, Fraction::from_fps(15));
+
+        // 60 fps is not in the default table; the closest supported rate is 30 fps
+        let granted = device
+            .set_frame_interval(Fraction::from_fps(60))
+            .expect("set_frame_interval should succeed");
+        assert_eq!(granted, Fraction::from_fps(30));
+
+        assert!(device.set_frame_interval(Fraction::new(1, 0)).is_err());
+    }
+
+    #[test]
+    fn test_mock_timestamps_follow_frame_interval() {
+        let mut device = MockDevice::new();
+        device
+            .set_frame_interval(Fraction::from_fps(15))
+            .expect("set_frame_interval should succeed");
+        let mut stream = device
+            .create_stream(4)
+            .expect("create_stream should succeed");
+
+        let timestamps: Vec<Duration> = (0..3)
+            .map(|_| {
+                stream
+                    .next_frame()
+                    .expect("next_frame should succeed")
+                    .metadata
+                    .timestamp
+            })
+            .collect();
+
+        assert_eq!(timestamps[0], Duration::ZERO);
+        assert_eq!(timestamps[1], Duration::from_nanos(66_666_666));
+        assert_eq!(timestamps[2], Duration::from_nanos(133_333_333));
+    }
+
+    #[test]
+    fn test_mock_controls_defaults() {
+        let device = MockDevice::new();
+        let controls = device.controls().expect("controls should succeed");
+        assert!(controls.iter().any(|info| info.id == cid::EXPOSURE));
+
+        let brightness = device
+            .control(cid::BRIGHTNESS)
+            .expect("control should succeed");
+        assert_eq!(brightness, ControlValue::Integer(128));
+        let awb = device
+            .control(cid::AUTO_WHITE_BALANCE)
+            .expect("control should succeed");
+        assert_eq!(awb, ControlValue::Boolean(true));
+
+        assert!(matches!(
+            device.control(cid::HUE),
+            Err(CameraError::ControlNotFound(cid::HUE))
+        ));
+    }
+
+    #[test]
+    fn test_mock_set_control_clamps_and_rounds() {
+        let mut device = MockDevice::new().with_controls(vec![ControlInfo::integer(
+            cid::GAIN,
+            "Gain",
+            0,
+            100,
+            10,
+            50,
+        )]);
+
+        device
+            .set_control(cid::GAIN, ControlValue::Integer(34))
+            .expect("set_control should succeed");
+        assert_eq!(
+            device.control(cid::GAIN).expect("control should succeed"),
+            ControlValue::Integer(30)
+        );
+
+        device
+            .set_control(cid::GAIN, ControlValue::Integer(500))
+            .expect("set_control should succeed");
+        assert_eq!(
+            device.control(cid::GAIN).expect("control should succeed"),
+            ControlValue::Integer(100)
+        );
+    }
+
+    #[test]
+    fn test_mock_set_controls_is_atomic() {
+        let mut device = MockDevice::new();
+        let result = device.set_controls(&[
+            (cid::EXPOSURE, ControlValue::Integer(20_000)),
+            (cid::POWER_LINE_FREQUENCY, ControlValue::Integer(7)),
+        ]);
+        assert!(result.is_err(), "invalid menu index should fail");
+        assert_eq!(
+            device
+                .control(cid::EXPOSURE)
+                .expect("control should succeed"),
+            ControlValue::Integer(10_000),
+            "no control should change when one write fails"
+        );
+
+        device
+            .set_controls(&[
+                (cid::EXPOSURE, ControlValue::Integer(20_000)),
+                (cid::ANALOGUE_GAIN, ControlValue::Integer(224)),
+            ])
+            .expect("set_controls should succeed");
+        assert_eq!(
+            device
+                .control(cid::EXPOSURE)
+                .expect("control should succeed"),
+            ControlValue::Integer(20_000)
+        );
+        assert_eq!(
+            device
+                .control(cid::ANALOGUE_GAIN)
+                .expect("control should succeed"),
+            ControlValue::Integer(224)
+        );
+    }
+
+    #[test]
+    fn test_mock_read_only_control() {
+        let mut device = MockDevice::new().with_controls(vec![ControlInfo::integer(
+            cid::EXPOSURE,
+            "Exposure",
+            0,
+            1000,
+            1,
+            100,
+        )
+        .with_flags(ControlFlags::READ_ONLY)]);
+
+        let result = device.set_control(cid::EXPOSURE, ControlValue::Integer(200));
+        assert!(matches!(result, Err(CameraError::ControlError(_))));
+    }
+
     #[test]
     fn test_color_bars_pattern() {
         let format = Format::new(640, 480, FourCC::YUYV);
@@ -279,4 +1547,475 @@ mod tests {
         assert_eq!(data[1], 64);
         assert_eq!(data[3], 192);
     }
+
+    #[test]
+    fn test_frame_sized_per_format() {
+        let rgb = Format::new(64, 48, FourCC::RGB3);
+        assert_eq!(
+            generate_test_frame(&rgb, TestPattern::ColorBars).len(),
+            64 * 48 * 3
+        );
+
+        let grey = Format::new(64, 48, FourCC::GREY);
+        assert_eq!(
+            generate_test_frame(&grey, TestPattern::ColorBars).len(),
+            64 * 48
+        );
+
+        let nv12 = Format::new(64, 48, FourCC::NV12);
+        assert_eq!(
+            generate_test_frame(&nv12, TestPattern::ColorBars).len(),
+            64 * 48 * 3 / 2
+        );
+    }
+
+    #[test]
+    fn test_formats_decode_to_same_colors() {
+        let reference = Format::new(64, 16, FourCC::YUYV);
+        let expected = Frame {
+            data: generate_test_frame(&reference, TestPattern::ColorBars),
+            metadata: FrameMetadata {
+                sequence: 0,
+                timestamp: Duration::ZERO,
+                bytes_used: reference.size,
+            },
+        };
+
+        for fourcc in [
+            FourCC::UYVY,
+            FourCC::YVYU,
+            FourCC::VYUY,
+            FourCC::RGB3,
+            FourCC::BGR3,
+            FourCC::XR24,
+            FourCC::NV12,
+            FourCC::NV21,
+            FourCC::NV16,
+            FourCC::YU12,
+            FourCC::YV12,
+            FourCC::YUV422P,
+            FourCC::SBGGR8,
+            FourCC::SGBRG10,
+            FourCC::SRGGB10P,
+            FourCC::SGRBG12P,
+            FourCC::SRGGB16,
+        ] {
+            let format = Format::new(64, 16, fourcc);
+            let frame = Frame {
+                data: generate_test_frame(&format, TestPattern::ColorBars),
+                ..expected.clone()
+            };
+            for x in (4..64).step_by(8) {
+                assert_eq!(
+                    frame.pixel(x, 8, &format),
+                    expected.pixel(x, 8, &reference),
+                    "{fourcc} at x={x}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_set_format_recomputes_stride() {
+        let mut device = MockDevice::new().with_formats(vec![MockFormat::discrete(
+            FourCC::RGB3,
+            "RGB3",
+            false,
+            &[(640, 480)],
+            &[Fraction::from_fps(30)],
+        )]);
+        let mut requested = Format::new(640, 480, FourCC::RGB3);
+        requested.stride = 1;
+
+        let actual = device
+            .set_format(&requested)
+            .expect("set_format should succeed");
+        assert_eq!(actual.stride, 640 * 3);
+        assert_eq!(actual.size, 640 * 480 * 3);
+    }
+
+    #[test]
+    fn test_set_format_snaps_to_advertised_mode() {
+        let mut device = MockDevice::new();
+
+        // Sizes snap to the closest advertised one
+        let actual = device
+            .set_format(&Format::new(1000, 700, FourCC::MJPG))
+            .expect("set_format should succeed");
+        assert_eq!(
+            (actual.width, actual.height, actual.fourcc),
+            (1280, 720, FourCC::MJPG)
+        );
+
+        // Unadvertised pixel formats fall back to the first entry
+        let actual = device
+            .set_format(&Format::new(640, 480, FourCC::NV12))
+            .expect("set_format should succeed");
+        assert_eq!(actual.fourcc, FourCC::YUYV);
+        assert_eq!(device.format().expect("format should succeed"), actual);
+    }
+
+    #[test]
+    fn test_mock_stalled_stream_times_out() {
+        let mut device = MockDevice::new().with_stall_after(2);
+        let mut stream = device
+            .create_stream(4)
+            .expect("create_stream should succeed");
+
+        let timeout = Duration::from_millis(100);
+        for expected in 0..2 {
+            let frame = stream
+                .next_frame_timeout(timeout)
+                .expect("frames before the stall should arrive");
+            assert_eq!(frame.metadata.sequence, expected);
+        }
+
+        let start = Instant::now();
+        assert!(matches!(
+            stream.next_frame_timeout(timeout),
+            Err(CameraError::Timeout)
+        ));
+        assert!(
+            start.elapsed() >= timeout,
+            "the timeout should be waited out"
+        );
+        assert!(matches!(stream.next_frame(), Err(CameraError::Timeout)));
+    }
+
+    #[test]
+    fn test_mock_try_next_frame() {
+        let mut device = MockDevice::new().with_stall_after(1);
+        let mut stream = device
+            .create_stream(4)
+            .expect("create_stream should succeed");
+
+        let frame = stream
+            .try_next_frame()
+            .expect("try_next_frame should succeed");
+        assert_eq!(frame.map(|frame| frame.metadata.sequence), Some(0));
+        assert!(stream
+            .try_next_frame()
+            .expect("try_next_frame should succeed")
+            .is_none());
+    }
+
+    #[test]
+    fn test_mock_borrowed_frames() {
+        let mut device = MockDevice::new();
+        let format = device.format().expect("format should succeed");
+        let mut stream = device
+            .create_stream(2)
+            .expect("create_stream should succeed");
+
+        let first_buffer = {
+            let frame = stream
+                .next_frame_ref()
+                .expect("next_frame_ref should succeed");
+            assert_eq!(frame.metadata.sequence, 0);
+            assert_eq!(frame.data.len(), format.size as usize);
+            assert_eq!(frame.pixel(0, 0, &format), Some((255, 255, 255)));
+            frame.data.as_ptr()
+        };
+
+        // The dropped view requeued its buffer behind the other one, so the
+        // next frame lands in the other buffer
+        let owned = {
+            let frame = stream
+                .next_frame_ref()
+                .expect("next_frame_ref should succeed");
+            assert_ne!(frame.data.as_ptr(), first_buffer);
+            frame.to_frame()
+        };
+        assert_eq!(owned.metadata.sequence, 1);
+
+        // With two buffers, the third frame reuses the first buffer
+        let third = stream
+            .next_frame_ref()
+            .expect("next_frame_ref should succeed");
+        assert_eq!(third.metadata.sequence, 2);
+        assert_eq!(third.data.as_ptr(), first_buffer);
+        assert_eq!(third.to_frame().data, owned.data);
+    }
+
+    #[test]
+    fn test_mock_mjpeg_frames_are_jpeg() {
+        let mut device = MockDevice::new();
+        let format = device
+            .set_format(&Format::new(640, 480, FourCC::MJPG))
+            .expect("set_format should succeed");
+        let mut stream = device
+            .create_stream(2)
+            .expect("create_stream should succeed");
+        let frame = stream
+            .next_frame_ref()
+            .expect("next_frame_ref should succeed")
+            .to_frame();
+
+        // The JPEG is followed by zero padding up to the buffer size
+        assert_eq!(frame.data.len(), format.size as usize);
+        let jpeg = crate::mjpeg::validate(&frame.data).expect("validate should succeed");
+        assert!(jpeg.len() < frame.data.len());
+        #[cfg(feature = "mjpeg")]
+        crate::validation::validate_color_bars(&frame, &format)
+            .expect("color bars should survive encoding");
+    }
+
+    #[test]
+    fn test_mock_dropped_frame_requeues_buffer() {
+        let mut device = MockDevice::new();
+        let mut stream = device
+            .create_stream(3)
+            .expect("create_stream should succeed");
+        assert_eq!(stream.queued_buffers(), 3);
+
+        let frame = stream
+            .next_frame_ref()
+            .expect("next_frame_ref should succeed");
+        drop(frame);
+        assert_eq!(stream.queued_buffers(), 3);
+
+        // A view kept past its drop point keeps its buffer out of the queue
+        let frame = stream
+            .next_frame_ref()
+            .expect("next_frame_ref should succeed");
+        std::mem::forget(frame);
+        assert_eq!(stream.queued_buffers(), 2);
+    }
+
+    #[test]
+    fn test_mock_realtime_pacing() {
+        let mut device = MockDevice::new()
+            .with_frame_interval(Fraction::from_fps(10))
+            .with_pacing(FramePacing::Realtime);
+        let mut stream = device
+            .create_stream(4)
+            .expect("create_stream should succeed");
+
+        let start = Instant::now();
+        stream.next_frame().expect("first frame is due immediately");
+        // The second frame is not due for another 100ms
+        assert!(matches!(stream.try_next_frame(), Ok(None)));
+        assert!(matches!(
+            stream.next_frame_timeout(Duration::from_millis(10)),
+            Err(CameraError::Timeout)
+        ));
+
+        let frame = stream.next_frame().expect("next_frame should succeed");
+        assert_eq!(frame.metadata.sequence, 1);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        drop(stream);
+        assert_eq!(device.frame_count(), 2);
+    }
+
+    #[test]
+    fn test_mock_device_pattern() {
+        let mut device = MockDevice::new().with_pattern(TestPattern::Solid(81, 90, 240));
+        let format = device.format().expect("format should succeed");
+        let mut stream = device
+            .create_stream(1)
+            .expect("create_stream should succeed");
+
+        let frame = stream.next_frame().expect("next_frame should succeed");
+        assert_eq!(frame.pixel(0, 0, &format), frame.pixel(639, 479, &format));
+        let (red, _, blue) = frame.pixel(0, 0, &format).expect("pixel should exist");
+        assert!(red > 200 && blue < 50);
+    }
+
+    #[test]
+    fn test_mock_sensor_controls_scale_pattern() {
+        let mut device = MockDevice::new().with_pattern(TestPattern::Solid(126, 128, 128));
+        let format = device.format().expect("format should succeed");
+        let capture = |device: &mut MockDevice| {
+            let mut stream = device
+                .create_stream(1)
+                .expect("create_stream should succeed");
+            let frame = stream.next_frame().expect("next_frame should succeed");
+            frame.pixel(0, 0, &format).expect("pixel should exist")
+        };
+        let (red, green, blue) = capture(&mut device);
+        assert!(red == green && green == blue);
+
+        device
+            .set_controls(&[
+                (cid::EXPOSURE, ControlValue::Integer(5_000)),
+                (cid::ANALOGUE_GAIN, ControlValue::Integer(224)),
+            ])
+            .expect("set_controls should succeed");
+        let (same, _, _) = capture(&mut device);
+        assert!(same.abs_diff(red) <= 1);
+
+        device
+            .set_control(cid::EXPOSURE, ControlValue::Integer(7_500))
+            .expect("set_control should succeed");
+        let (brighter, _, _) = capture(&mut device);
+        assert!(brighter.abs_diff(red + red / 2) <= 2);
+
+        device
+            .set_controls(&[
+                (cid::EXPOSURE, ControlValue::Integer(10_000)),
+                (cid::ANALOGUE_GAIN, ControlValue::Integer(112)),
+                (cid::RED_BALANCE, ControlValue::Integer(512)),
+            ])
+            .expect("set_controls should succeed");
+        let (red_half, green_unchanged, _) = capture(&mut device);
+        assert!(red_half.abs_diff(red / 2) <= 2);
+        assert!(green_unchanged.abs_diff(green) <= 2);
+    }
+
+    #[test]
+    fn test_fault_operation_errors() {
+        let plan = FaultPlan::new()
+            .fail_open(CameraError::DeviceOpenFailed("busy".to_owned()))
+            .fail_set_format(CameraError::Io(io::Error::from_raw_os_error(16)))
+            .fail_create_stream(CameraError::StreamError("no buffers".to_owned()));
+        let mut device = MockDevice::new().with_faults(plan);
+
+        assert!(matches!(
+            device.open(),
+            Err(CameraError::DeviceOpenFailed(_))
+        ));
+        let format = Format::new(1280, 720, FourCC::YUYV);
+        // Injected errors fire on every call until cleared
+        for _ in 0..2 {
+            assert!(matches!(
+                device.set_format(&format),
+                Err(CameraError::Io(err)) if err.raw_os_error() == Some(16)
+            ));
+        }
+        assert!(matches!(
+            device.create_stream(4),
+            Err(CameraError::StreamError(_))
+        ));
+
+        device.faults_mut().clear_operation_faults();
+        assert!(device.faults_mut().is_empty());
+        let mut reopened = device.open().expect("open should succeed");
+        assert!(reopened.set_format(&format).is_ok());
+        assert!(reopened.create_stream(4).is_ok());
+    }
+
+    #[test]
+    fn test_fault_transient_frame_errors() {
+        let plan = FaultPlan::new()
+            .on_frame(1, FrameFault::Timeout)
+            .on_frame(2, FrameFault::StreamError("dequeue failed".to_owned()));
+        let mut device = MockDevice::new().with_faults(plan);
+        let mut stream = device
+            .create_stream(4)
+            .expect("create_stream should succeed");
+
+        let mut results = (0..5).map(|_| stream.next_frame().map(|frame| frame.metadata.sequence));
+        assert_eq!(results.next().map(Result::ok), Some(Some(0)));
+        assert!(matches!(results.next(), Some(Err(CameraError::Timeout))));
+        assert_eq!(results.next().map(Result::ok), Some(Some(1)));
+        assert!(matches!(
+            results.next(),
+            Some(Err(CameraError::StreamError(message))) if message == "dequeue failed"
+        ));
+        assert_eq!(results.next().map(Result::ok), Some(Some(2)));
+    }
+
+    #[test]
+    fn test_fault_dropped_frames_leave_sequence_gaps() {
+        let mut device = MockDevice::new().with_faults(FaultPlan::new().drop_frames([1, 2, 4]));
+        let mut stream = device
+            .create_stream(4)
+            .expect("create_stream should succeed");
+
+        let sequences: Vec<u32> = (0..3)
+            .map(|_| {
+                stream
+                    .next_frame()
+                    .expect("next_frame should succeed")
+                    .metadata
+                    .sequence
+            })
+            .collect();
+        assert_eq!(sequences, vec![0, 3, 5]);
+    }
+
+    #[test]
+    fn test_fault_truncate_and_corrupt() {
+        let plan = FaultPlan::new()
+            .on_frame(0, FrameFault::Truncate(1000))
+            .on_frame(0, FrameFault::Corrupt(0..4));
+        let mut device = MockDevice::new().with_faults(plan);
+        let format = device.format().expect("format should succeed");
+        let clean = generate_test_frame(&format, TestPattern::ColorBars);
+        let mut stream = device
+            .create_stream(4)
+            .expect("create_stream should succeed");
+
+        let frame = stream.next_frame().expect("next_frame should succeed");
+        assert_eq!(frame.metadata.bytes_used, 1000);
+        assert_eq!(frame.data.len(), 1000);
+        assert_eq!(frame.data[..4], [!235, !128, !235, !128]);
+        assert_eq!(frame.data[4..], clean[4..1000]);
+
+        let frame = stream.next_frame().expect("next_frame should succeed");
+        assert_eq!(frame.metadata.bytes_used, format.size);
+        assert_eq!(frame.data, clean);
+    }
+
+    #[test]
+    fn test_fault_unplug_mid_stream() {
+        let mut device =
+            MockDevice::new().with_faults(FaultPlan::new().on_frame(2, FrameFault::Unplug));
+        {
+            let mut stream = device
+                .create_stream(4)
+                .expect("create_stream should succeed");
+            assert!(stream.next_frame().is_ok());
+            assert!(stream.next_frame().is_ok());
+            for _ in 0..2 {
+                assert!(matches!(
+                    stream.next_frame_timeout(Duration::from_millis(10)),
+                    Err(CameraError::StreamError(_))
+                ));
+            }
+        }
+
+        assert!(device.is_unplugged());
+        assert!(matches!(device.format(), Err(CameraError::Io(_))));
+        assert!(matches!(device.create_stream(4), Err(CameraError::Io(_))));
+        assert!(matches!(
+            device.open(),
+            Err(CameraError::DeviceOpenFailed(_))
+        ));
+    }
+
+    #[test]
+    fn test_clip_playback_loops() {
+        let format = Format::new(4, 2, FourCC::GREY);
+        let clip = (0..2u8).map(|value| Frame {
+            data: vec![value; 8],
+            metadata: FrameMetadata {
+                sequence: 0,
+                timestamp: Duration::ZERO,
+                bytes_used: 8,
+            },
+        });
+        let mut device = MockDevice::new().with_clip(format.clone(), clip);
+        assert_eq!(device.format().expect("format should succeed"), format);
+
+        {
+            let mut stream = device
+                .create_stream(2)
+                .expect("create_stream should succeed");
+            for expected in [0, 1, 0] {
+                let frame = stream.next_frame().expect("next_frame should succeed");
+                assert_eq!(frame.data, vec![expected; 8]);
+                assert_eq!(frame.metadata.bytes_used, 8);
+            }
+        }
+
+        device
+            .set_format(&Format::new(640, 480, FourCC::YUYV))
+            .expect("set_format should succeed");
+        let mut stream = device
+            .create_stream(2)
+            .expect("create_stream should succeed");
+        let frame = stream.next_frame().expect("next_frame should succeed");
+        assert_eq!(frame.data.len(), 640 * 480 * 2);
+    }
 }
@@ -0,0 +1,530 @@
+//! Format negotiation.
+//!
+//! Picks the best capture mode a device supports for a set of caller
+//! preferences, applies it, and reports every way the result differs from
+//! what was asked for.
+
+use crate::traits::{
+    CameraDevice, CameraError, Format, FourCC, Fraction, FrameInterval, FrameSize, Result,
+};
+
+/// Caller preferences for format negotiation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatRequest {
+    /// Acceptable pixel formats in priority order. Empty accepts any format.
+    pub fourccs: Vec<FourCC>,
+    /// Target frame width in pixels.
+    pub width: u32,
+    /// Target frame height in pixels.
+    pub height: u32,
+    /// Minimum acceptable frame rate. Zero leaves the frame rate unconstrained.
+    pub min_fps: u32,
+}
+
+impl FormatRequest {
+    /// Create a request for the given resolution accepting any pixel format.
+    #[must_use]
+    pub const fn new(width: u32, height: u32) -> Self {
+        Self {
+            fourccs: Vec::new(),
+            width,
+            height,
+            min_fps: 0,
+        }
+    }
+
+    /// Set the acceptable pixel formats in priority order.
+    #[must_use]
+    pub fn with_fourccs(mut self, fourccs: &[FourCC]) -> Self {
+        self.fourccs = fourccs.to_vec();
+        self
+    }
+
+    /// Set the minimum acceptable frame rate.
+    #[must_use]
+    pub const fn with_min_fps(mut self, min_fps: u32) -> Self {
+        self.min_fps = min_fps;
+        self
+    }
+}
+
+/// A way in which the negotiated mode differs from the request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Adjustment {
+    /// A lower-priority pixel format was used.
+    FourCC {
+        /// Most preferred pixel format.
+        requested: FourCC,
+        /// Pixel format in use.
+        actual: FourCC,
+    },
+    /// The resolution differs from the target.
+    Resolution {
+        /// Target resolution.
+        requested: (u32, u32),
+        /// Resolution in use.
+        actual: (u32, u32),
+    },
+    /// The driver granted a different frame interval than the one selected.
+    FrameInterval {
+        /// Interval selected from the enumerated modes.
+        requested: Fraction,
+        /// Interval granted by the driver.
+        actual: Fraction,
+    },
+}
+
+/// Outcome of a successful negotiation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NegotiatedFormat {
+    /// Format applied by the driver.
+    pub format: Format,
+    /// Frame interval granted by the driver, if the frame rate was negotiated.
+    pub interval: Option<Fraction>,
+    /// Differences between the request and the applied mode.
+    pub adjustments: Vec<Adjustment>,
+}
+
+impl NegotiatedFormat {
+    /// Check whether the applied mode matches the request exactly.
+    #[must_use]
+    pub fn is_exact(&self) -> bool {
+        self.adjustments.is_empty()
+    }
+}
+
+/// A mode candidate considered during negotiation.
+#[derive(Debug, Clone, Copy)]
+struct Candidate {
+    fourcc: FourCC,
+    width: u32,
+    height: u32,
+    interval: Option<Fraction>,
+}
+
+/// Negotiate and apply the best supported mode for `request`.
+///
+/// Pixel format priority dominates: the first acceptable format that has any
+/// mode satisfying `min_fps` wins, and within that format the resolution
+/// closest to the target is chosen. The frame interval is set to the slowest
+/// supported rate that still meets `min_fps`.
+///
+/// # Errors
+///
+/// Returns `FormatNotSupported` if no supported mode satisfies the request,
+/// or if the driver falls below `min_fps` after applying the mode; in the
+/// latter case the previous format and frame interval are restored.
+pub fn negotiate<D: CameraDevice>(
+    device: &mut D,
+    request: &FormatRequest,
+) -> Result<NegotiatedFormat> {
+    let candidate = select_mode(device, request)?;
+    let previous_format = device.format()?;
+    // Not every driver reports its frame interval
+    let previous_interval = device.frame_interval().ok();
+
+    let applied = device.set_format(&Format::new(
+        candidate.width,
+        candidate.height,
+        candidate.fourcc,
+    ))?;
+
+    let mut adjustments = Vec::new();
+    if let Some(&preferred) = request.fourccs.first() {
+        if applied.fourcc != preferred {
+            adjustments.push(Adjustment::FourCC {
+                requested: preferred,
+                actual: applied.fourcc,
+            });
+        }
+    }
+    if (applied.width, applied.height) != (request.width, request.height) {
+        adjustments.push(Adjustment::Resolution {
+            requested: (request.width, request.height),
+            actual: (applied.width, applied.height),
+        });
+    }
+
+    let interval = match candidate.interval {
+        Some(selected) => {
+            let granted = device.set_frame_interval(selected)?;
+            if granted != selected {
+                adjustments.push(Adjustment::FrameInterval {
+                    requested: selected,
+                    actual: granted,
+                });
+            }
+            if granted.fps() < f64::from(request.min_fps) {
+                // Best effort: the error below matters more than a failed restore
+                let _ = device.set_format(&previous_format);
+                if let Some(previous) = previous_interval {
+                    let _ = device.set_frame_interval(previous);
+                }
+                return Err(not_supported(
+                    request,
+                    format!(
+                        "driver granted {:.2} fps, below the minimum of {}",
+                        granted.fps(),
+                        request.min_fps
+                    ),
+                ));
+            }
+            Some(granted)
+        }
+        None => None,
+    };
+
+    Ok(NegotiatedFormat {
+        format: applied,
+        interval,
+        adjustments,
+    })
+}
+
+/// Choose the best mode without touching the device configuration.
+fn select_mode<D: CameraDevice>(device: &D, request: &FormatRequest) -> Result<Candidate> {
+    let supported = device.supported_formats()?;
+    let fourccs: Vec<FourCC> = if request.fourccs.is_empty() {
+        supported.iter().map(|desc| desc.fourcc).collect()
+    } else {
+        request
+            .fourccs
+            .iter()
+            .copied()
+            .filter(|fourcc| supported.iter().any(|desc| desc.fourcc == *fourcc))
+            .collect()
+    };
+
+    if fourccs.is_empty() {
+        let offered: Vec<String> = supported
+            .iter()
+            .map(|desc| desc.fourcc.to_string())
+            .collect();
+        return Err(not_supported(
+            request,
+            format!(
+                "none of the requested pixel formats is supported (device offers: {})",
+                offered.join(", ")
+            ),
+        ));
+    }
+
+    let mut best_rates = Vec::new();
+    for fourcc in fourccs {
+        let sizes = device.frame_sizes(fourcc)?;
+        let mut candidates: Vec<Candidate> = Vec::new();
+
+        for size in &sizes {
+            // Skip malformed stepwise ranges rather than trusting the driver
+            let Some((width, height)) = nearest_in_size(size, request.width, request.height) else {
+                continue;
+            };
+            let intervals = device
+                .frame_intervals(fourcc, width, height)
+                .unwrap_or_default();
+            let fastest = intervals.iter().map(fastest_interval).reduce(faster);
+            if let Some(fastest) = fastest {
+                best_rates.push(format!(
+                    "{fourcc} {width}x{height} @ {:.2} fps",
+                    fastest.fps()
+                ));
+            }
+
+            if request.min_fps == 0 {
+                candidates.push(Candidate {
+                    fourcc,
+                    width,
+                    height,
+                    interval: None,
+                });
+                continue;
+            }
+
+            // Drivers that cannot enumerate intervals are given the benefit of
+            // the doubt; the granted rate is checked after applying the mode.
+            let interval = if intervals.is_empty() {
+                Some(Fraction::from_fps(request.min_fps))
+            } else {
+                slowest_meeting(&intervals, request.min_fps)
+            };
+            if interval.is_some() {
+                candidates.push(Candidate {
+                    fourcc,
+                    width,
+                    height,
+                    interval,
+                });
+            }
+        }
+
+        if let Some(best) = candidates
+            .into_iter()
+            .min_by_key(|candidate| distance(candidate, request))
+        {
+            return Ok(best);
+        }
+    }
+
+    Err(not_supported(
+        request,
+        format!(
+            "no mode reaches {} fps (best available: {})",
+            request.min_fps,
+            best_rates.join("; ")
+        ),
+    ))
+}
+
+/// Closest resolution to the target within a frame size entry.
+///
+/// Returns `None` for a stepwise entry whose minimum exceeds its maximum.
+pub(crate) fn nearest_in_size(size: &FrameSize, width: u32, height: u32) -> Option<(u32, u32)> {
+    match *size {
+        FrameSize::Discrete { width, height } => Some((width, height)),
+        FrameSize::Stepwise {
+            min_width,
+            max_width,
+            step_width,
+            min_height,
+            max_height,
+            step_height,
+        } => Some((
+            nearest_step(width, min_width, max_width, step_width)?,
+            nearest_step(height, min_height, max_height, step_height)?,
+        )),
+    }
+}
+
+/// Clamp `value` to `min..=max` and round it to the nearest step from `min`.
+///
+/// Returns `None` if `min` exceeds `max`.
+fn nearest_step(value: u32, min: u32, max: u32, step: u32) -> Option<u32> {
+    if min > max {
+        return None;
+    }
+    let clamped = value.clamp(min, max);
+    if step <= 1 {
+        return Some(clamped);
+    }
+    let remainder = (clamped - min) % step;
+    let below = clamped - remainder;
+    // Round halves up, unless the step above lies past `max`
+    match below.checked_add(step) {
+        Some(above) if above <= max && remainder >= step - step / 2 => Some(above),
+        _ => Some(below),
+    }
+}
+
+/// Resolution distance used to rank candidates (Manhattan distance in pixels).
+const fn distance(candidate: &Candidate, request: &FormatRequest) -> u32 {
+    candidate.width.abs_diff(request.width) + candidate.height.abs_diff(request.height)
+}
+
+/// Shortest interval (highest frame rate) offered by an interval entry.
+const fn fastest_interval(interval: &FrameInterval) -> Fraction {
+    match *interval {
+        FrameInterval::Discrete(fraction) => fraction,
+        FrameInterval::Stepwise { min, .. } => min,
+    }
+}
+
+/// Pick the faster of two intervals.
+fn faster(a: Fraction, b: Fraction) -> Fraction {
+    if b.fps() > a.fps() {
+        b
+    } else {
+        a
+    }
+}
+
+/// Slowest supported interval whose frame rate is at least `min_fps`.
+fn slowest_meeting(intervals: &[FrameInterval], min_fps: u32) -> Option<Fraction> {
+    let target = Fraction::from_fps(min_fps);
+    let min_fps = f64::from(min_fps);
+
+    intervals
+        .iter()
+        .filter_map(|interval| match *interval {
+            FrameInterval::Discrete(fraction) => (fraction.fps() >= min_fps).then_some(fraction),
+            // Stepwise ranges can hit the minimum rate directly when it lies inside the range
+            FrameInterval::Stepwise { min, max, .. } => {
+                if min.fps() < min_fps {
+                    None
+                } else if max.fps() >= min_fps {
+                    Some(max)
+                } else {
+                    Some(target)
+                }
+            }
+        })
+        .reduce(|a, b| if b.fps() < a.fps() { b } else { a })
+}
+
+/// Build a `FormatNotSupported` error for a request.
+fn not_supported(request: &FormatRequest, reason: String) -> CameraError {
+    let fourcc = request.fourccs.first().copied().unwrap_or(FourCC::YUYV);
+    CameraError::FormatNotSupported {
+        format: Format::new(request.width, request.height, fourcc),
+        reason,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{MockDevice, MockFormat};
+
+    #[test]
+    fn test_negotiate_exact_match() {
+        let mut device = MockDevice::new();
+        let request = FormatRequest::new(1280, 720)
+            .with_fourccs(&[FourCC::YUYV])
+            .with_min_fps(30);
+
+        let result = negotiate(&mut device, &request).expect("negotiation should succeed");
+        assert!(
+            result.is_exact(),
+            "unexpected adjustments: {:?}",
+            result.adjustments
+        );
+        assert_eq!(result.format.width, 1280);
+        assert_eq!(result.interval, Some(Fraction::from_fps(30)));
+    }
+
+    #[test]
+    fn test_negotiate_falls_back_to_lower_priority_fourcc() {
+        let mut device = MockDevice::new();
+        let request = FormatRequest::new(640, 480).with_fourccs(&[FourCC::RGB3, FourCC::MJPG]);
+
+        let result = negotiate(&mut device, &request).expect("negotiation should succeed");
+        assert_eq!(result.format.fourcc, FourCC::MJPG);
+        assert_eq!(
+            result.adjustments,
+            vec![Adjustment::FourCC {
+                requested: FourCC::RGB3,
+                actual: FourCC::MJPG
+            }]
+        );
+    }
+
+    #[test]
+    fn test_negotiate_picks_closest_resolution() {
+        let mut device = MockDevice::new();
+        let request = FormatRequest::new(1200, 700).with_fourccs(&[FourCC::YUYV]);
+
+        let result = negotiate(&mut device, &request).expect("negotiation should succeed");
+        assert_eq!((result.format.width, result.format.height), (1280, 720));
+        assert!(result.adjustments.contains(&Adjustment::Resolution {
+            requested: (1200, 700),
+            actual: (1280, 720)
+        }));
+        assert_eq!(result.interval, None, "frame rate was not requested");
+    }
+
+    #[test]
+    fn test_negotiate_selects_slowest_rate_meeting_minimum() {
+        let mut device = MockDevice::new().with_formats(vec![MockFormat::discrete(
+            FourCC::YUYV,
+            "YUYV 4:2:2",
+            false,
+            &[(640, 480)],
+            &[
+                Fraction::from_fps(60),
+                Fraction::from_fps(30),
+                Fraction::from_fps(15),
+            ],
+        )]);
+        let request = FormatRequest::new(640, 480).with_min_fps(20);
+
+        let result = negotiate(&mut device, &request).expect("negotiation should succeed");
+        assert_eq!(result.interval, Some(Fraction::from_fps(30)));
+    }
+
+    #[test]
+    fn test_negotiate_stepwise_size() {
+        let mut device = MockDevice::new().with_formats(vec![MockFormat {
+            frame_sizes: vec![FrameSize::Stepwise {
+                min_width: 64,
+                max_width: 1920,
+                step_width: 16,
+                min_height: 64,
+                max_height: 1080,
+                step_height: 16,
+            }],
+            ..MockFormat::discrete(FourCC::YUYV, "YUYV", false, &[], &[Fraction::from_fps(30)])
+        }]);
+        let request = FormatRequest::new(1000, 3000).with_min_fps(30);
+
+        let result = negotiate(&mut device, &request).expect("negotiation should succeed");
+        assert_eq!((result.format.width, result.format.height), (1008, 1072));
+    }
+
+    #[test]
+    fn test_negotiate_skips_malformed_stepwise_size() {
+        let mut device = MockDevice::new().with_formats(vec![MockFormat {
+            frame_sizes: vec![
+                FrameSize::Stepwise {
+                    min_width: 1920,
+                    max_width: 64,
+                    step_width: 16,
+                    min_height: 64,
+                    max_height: 1080,
+                    step_height: u32::MAX,
+                },
+                FrameSize::Discrete {
+                    width: 320,
+                    height: 240,
+                },
+            ],
+            ..MockFormat::discrete(FourCC::YUYV, "YUYV", false, &[], &[Fraction::from_fps(30)])
+        }]);
+        let request = FormatRequest::new(640, 480);
+
+        let result = negotiate(&mut device, &request).expect("negotiation should succeed");
+        assert_eq!((result.format.width, result.format.height), (320, 240));
+    }
+
+    #[test]
+    fn test_negotiate_no_matching_fourcc() {
+        let mut device = MockDevice::new();
+        let request = FormatRequest::new(640, 480).with_fourccs(&[FourCC::RGB3]);
+
+        let err = negotiate(&mut device, &request).expect_err("negotiation should fail");
+        assert!(matches!(
+            &err,
+            CameraError::FormatNotSupported { format, .. } if format.fourcc == FourCC::RGB3
+        ));
+        assert!(
+            err.to_string().contains("YUYV"),
+            "reason should list offered formats: {err}"
+        );
+    }
+
+    #[test]
+    fn test_negotiate_min_fps_unreachable() {
+        let mut device = MockDevice::new();
+        let request = FormatRequest::new(640, 480)
+            .with_fourccs(&[FourCC::YUYV])
+            .with_min_fps(120);
+
+        let err = negotiate(&mut device, &request).expect_err("negotiation should fail");
+        assert!(matches!(err, CameraError::FormatNotSupported { .. }));
+        assert!(err.to_string().contains("120 fps"), "{err}");
+    }
+
+    #[test]
+    fn test_nearest_step() {
+        assert_eq!(nearest_step(1000, 64, 1920, 16), Some(1008));
+        assert_eq!(nearest_step(1007, 64, 1920, 16), Some(1008));
+        assert_eq!(nearest_step(999, 64, 1920, 16), Some(992));
+        assert_eq!(nearest_step(10, 64, 1920, 16), Some(64));
+        assert_eq!(nearest_step(5000, 64, 1920, 16), Some(1920));
+        assert_eq!(nearest_step(1919, 0, 1919, 2), Some(1918));
+        assert_eq!(nearest_step(640, 1920, 64, 16), None);
+        assert_eq!(
+            nearest_step(u32::MAX - 1, 0, u32::MAX, u32::MAX),
+            Some(u32::MAX)
+        );
+        assert_eq!(nearest_step(u32::MAX, 1, u32::MAX, u32::MAX), Some(1));
+    }
+}
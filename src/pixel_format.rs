@@ -0,0 +1,341 @@
+//! Pixel format descriptors.
+//!
+//! Memory layout properties of the pixel formats known to this crate, keyed by
+//! `FourCC`. The table drives line stride and buffer size computation for
+//! `Format` and tells frame accessors where the samples of a pixel live.
+
+use crate::bayer::BayerFormat;
+use crate::traits::FourCC;
+
+/// Worst-case bytes per pixel assumed when sizing buffers for compressed formats.
+const COMPRESSED_BYTES_PER_PIXEL: u32 = 2;
+
+/// How the samples of a pixel format are arranged in memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Layout {
+    /// All components interleaved in a single plane (e.g., YUYV, RGB3).
+    Packed,
+    /// A luma plane followed by one interleaved chroma plane (e.g., NV12).
+    SemiPlanar,
+    /// A luma plane followed by separate Cb and Cr planes (e.g., YU12).
+    Planar,
+    /// Variable-length compressed bitstream (e.g., MJPG).
+    Compressed,
+    /// Raw Bayer samples, one color per pixel (e.g., SRGGB10P).
+    Bayer,
+}
+
+/// Memory layout description of a pixel format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PixelFormatInfo {
+    /// Pixel format.
+    pub fourcc: FourCC,
+    /// Arrangement of the samples in memory.
+    pub layout: Layout,
+    /// Average bits per pixel over all planes (zero for compressed formats).
+    pub bits_per_pixel: u32,
+    /// Number of planes stored in the buffer.
+    pub planes: u32,
+    /// Horizontal chroma subsampling factor (1 for 4:4:4 or RGB, 2 for 4:2:x).
+    pub chroma_h_subsampling: u32,
+    /// Vertical chroma subsampling factor (2 for 4:2:0, 1 otherwise).
+    pub chroma_v_subsampling: u32,
+}
+
+impl PixelFormatInfo {
+    /// Describe a packed format.
+    const fn packed(fourcc: FourCC, bits_per_pixel: u32, chroma_h_subsampling: u32) -> Self {
+        Self {
+            fourcc,
+            layout: Layout::Packed,
+            bits_per_pixel,
+            planes: 1,
+            chroma_h_subsampling,
+            chroma_v_subsampling: 1,
+        }
+    }
+
+    /// Describe an 8-bit YUV format with separate chroma plane(s).
+    const fn yuv_planar(fourcc: FourCC, layout: Layout, h_sub: u32, v_sub: u32) -> Self {
+        let planes = if matches!(layout, Layout::SemiPlanar) {
+            2
+        } else {
+            3
+        };
+        Self {
+            fourcc,
+            layout,
+            bits_per_pixel: 8 + 16 / (h_sub * v_sub),
+            planes,
+            chroma_h_subsampling: h_sub,
+            chroma_v_subsampling: v_sub,
+        }
+    }
+
+    /// Describe a raw Bayer format.
+    const fn bayer(fourcc: FourCC) -> Self {
+        let bits_per_pixel = match BayerFormat::from_fourcc(fourcc) {
+            Some(format) => format.bits_per_pixel(),
+            None => 0,
+        };
+        Self {
+            fourcc,
+            layout: Layout::Bayer,
+            bits_per_pixel,
+            planes: 1,
+            chroma_h_subsampling: 1,
+            chroma_v_subsampling: 1,
+        }
+    }
+
+    /// Describe a compressed format.
+    const fn compressed(fourcc: FourCC) -> Self {
+        Self {
+            fourcc,
+            layout: Layout::Compressed,
+            bits_per_pixel: 0,
+            planes: 1,
+            chroma_h_subsampling: 1,
+            chroma_v_subsampling: 1,
+        }
+    }
+
+    /// Check whether the format is a compressed bitstream.
+    #[must_use]
+    pub const fn is_compressed(&self) -> bool {
+        matches!(self.layout, Layout::Compressed)
+    }
+
+    /// Check whether the format stores its components in more than one plane.
+    #[must_use]
+    pub const fn is_planar(&self) -> bool {
+        self.planes > 1
+    }
+
+    /// Bytes per pixel of a packed format, rounded up (zero otherwise,
+    /// including Bayer formats, whose CSI-2 packing splits bytes).
+    #[must_use]
+    pub const fn bytes_per_pixel(&self) -> u32 {
+        match self.layout {
+            Layout::Packed => self.bits_per_pixel.div_ceil(8),
+            Layout::SemiPlanar | Layout::Planar | Layout::Compressed | Layout::Bayer => 0,
+        }
+    }
+
+    /// Minimum line stride in bytes of the first plane for `width` pixels.
+    ///
+    /// Planar formats report the luma stride; compressed formats have no
+    /// line structure and report zero.
+    #[must_use]
+    pub const fn min_stride(&self, width: u32) -> u32 {
+        match self.layout {
+            Layout::Packed => (width * self.bits_per_pixel).div_ceil(8),
+            // CSI-2 packing stores whole groups of pixels
+            Layout::Bayer => {
+                let group = match self.bits_per_pixel % 8 {
+                    0 => 1,
+                    4 => 2,
+                    _ => 4,
+                };
+                width.next_multiple_of(group) * self.bits_per_pixel / 8
+            }
+            Layout::SemiPlanar | Layout::Planar => width,
+            Layout::Compressed => 0,
+        }
+    }
+
+    /// Line stride in bytes of `plane`, given the stride of the first plane.
+    #[must_use]
+    pub const fn plane_stride(&self, plane: u32, stride: u32) -> u32 {
+        match self.layout {
+            Layout::Planar if plane > 0 => stride.div_ceil(self.chroma_h_subsampling),
+            // Interleaved CbCr lines are as long as luma lines
+            _ => stride,
+        }
+    }
+
+    /// Number of lines in `plane` for a frame `height` pixels tall.
+    #[must_use]
+    pub const fn plane_height(&self, plane: u32, height: u32) -> u32 {
+        if plane > 0 {
+            height.div_ceil(self.chroma_v_subsampling)
+        } else {
+            height
+        }
+    }
+
+    /// Buffer size in bytes of a frame with the given first-plane stride.
+    ///
+    /// Compressed formats report a worst-case estimate based on the frame
+    /// dimensions, since the actual size varies from frame to frame.
+    #[must_use]
+    pub const fn frame_size(&self, width: u32, height: u32, stride: u32) -> u32 {
+        if self.is_compressed() {
+            return width * height * COMPRESSED_BYTES_PER_PIXEL;
+        }
+
+        let mut size = 0;
+        let mut plane = 0;
+        while plane < self.planes {
+            size += self.plane_stride(plane, stride) * self.plane_height(plane, height);
+            plane += 1;
+        }
+        size
+    }
+}
+
+/// Descriptor table of known pixel formats.
+const PIXEL_FORMATS: &[PixelFormatInfo] = &[
+    PixelFormatInfo::packed(FourCC::YUYV, 16, 2),
+    PixelFormatInfo::packed(FourCC::YVYU, 16, 2),
+    PixelFormatInfo::packed(FourCC::UYVY, 16, 2),
+    PixelFormatInfo::packed(FourCC::VYUY, 16, 2),
+    PixelFormatInfo::packed(FourCC::RGB3, 24, 1),
+    PixelFormatInfo::packed(FourCC::BGR3, 24, 1),
+    PixelFormatInfo::packed(FourCC::XR24, 32, 1),
+    PixelFormatInfo::packed(FourCC::AR24, 32, 1),
+    PixelFormatInfo::packed(FourCC::RGBP, 16, 1),
+    PixelFormatInfo::packed(FourCC::GREY, 8, 1),
+    PixelFormatInfo::packed(FourCC::Y16, 16, 1),
+    PixelFormatInfo::yuv_planar(FourCC::NV12, Layout::SemiPlanar, 2, 2),
+    PixelFormatInfo::yuv_planar(FourCC::NV21, Layout::SemiPlanar, 2, 2),
+    PixelFormatInfo::yuv_planar(FourCC::NV16, Layout::SemiPlanar, 2, 1),
+    PixelFormatInfo::yuv_planar(FourCC::NV61, Layout::SemiPlanar, 2, 1),
+    PixelFormatInfo::yuv_planar(FourCC::YU12, Layout::Planar, 2, 2),
+    PixelFormatInfo::yuv_planar(FourCC::YV12, Layout::Planar, 2, 2),
+    PixelFormatInfo::yuv_planar(FourCC::YUV422P, Layout::Planar, 2, 1),
+    PixelFormatInfo::bayer(FourCC::SBGGR8),
+    PixelFormatInfo::bayer(FourCC::SGBRG8),
+    PixelFormatInfo::bayer(FourCC::SGRBG8),
+    PixelFormatInfo::bayer(FourCC::SRGGB8),
+    PixelFormatInfo::bayer(FourCC::SBGGR10),
+    PixelFormatInfo::bayer(FourCC::SGBRG10),
+    PixelFormatInfo::bayer(FourCC::SGRBG10),
+    PixelFormatInfo::bayer(FourCC::SRGGB10),
+    PixelFormatInfo::bayer(FourCC::SBGGR10P),
+    PixelFormatInfo::bayer(FourCC::SGBRG10P),
+    PixelFormatInfo::bayer(FourCC::SGRBG10P),
+    PixelFormatInfo::bayer(FourCC::SRGGB10P),
+    PixelFormatInfo::bayer(FourCC::SBGGR12),
+    PixelFormatInfo::bayer(FourCC::SGBRG12),
+    PixelFormatInfo::bayer(FourCC::SGRBG12),
+    PixelFormatInfo::bayer(FourCC::SRGGB12),
+    PixelFormatInfo::bayer(FourCC::SBGGR12P),
+    PixelFormatInfo::bayer(FourCC::SGBRG12P),
+    PixelFormatInfo::bayer(FourCC::SGRBG12P),
+    PixelFormatInfo::bayer(FourCC::SRGGB12P),
+    PixelFormatInfo::bayer(FourCC::SBGGR16),
+    PixelFormatInfo::bayer(FourCC::SGBRG16),
+    PixelFormatInfo::bayer(FourCC::SGRBG16),
+    PixelFormatInfo::bayer(FourCC::SRGGB16),
+    PixelFormatInfo::compressed(FourCC::MJPG),
+    PixelFormatInfo::compressed(FourCC::JPEG),
+];
+
+/// Look up the descriptor of a pixel format.
+///
+/// Returns `None` for formats not in the table.
+#[must_use]
+pub const fn lookup(fourcc: FourCC) -> Option<&'static PixelFormatInfo> {
+    let code = u32::from_le_bytes(fourcc.0);
+    let mut rest = PIXEL_FORMATS;
+    while let [info, tail @ ..] = rest {
+        if u32::from_le_bytes(info.fourcc.0) == code {
+            return Some(info);
+        }
+        rest = tail;
+    }
+    None
+}
+
+/// All known pixel format descriptors.
+#[must_use]
+pub const fn all() -> &'static [PixelFormatInfo] {
+    PIXEL_FORMATS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_known_and_unknown() {
+        let info = lookup(FourCC::RGB3).expect("RGB3 should be known");
+        assert_eq!(info.bits_per_pixel, 24);
+        assert_eq!(info.layout, Layout::Packed);
+        assert!(lookup(FourCC::new(b"ZZZZ")).is_none());
+    }
+
+    #[test]
+    fn test_table_has_unique_entries() {
+        for (i, info) in all().iter().enumerate() {
+            assert!(
+                all()
+                    .iter()
+                    .skip(i + 1)
+                    .all(|other| other.fourcc != info.fourcc),
+                "{} listed twice",
+                info.fourcc
+            );
+        }
+    }
+
+    #[test]
+    fn test_packed_stride_and_size() {
+        let yuyv = lookup(FourCC::YUYV).expect("YUYV should be known");
+        assert_eq!(yuyv.min_stride(640), 1280);
+        assert_eq!(yuyv.frame_size(640, 480, 1280), 614_400);
+
+        let grey = lookup(FourCC::GREY).expect("GREY should be known");
+        assert_eq!(grey.min_stride(641), 641);
+        assert_eq!(grey.bytes_per_pixel(), 1);
+    }
+
+    #[test]
+    fn test_planar_size() {
+        let nv12 = lookup(FourCC::NV12).expect("NV12 should be known");
+        assert_eq!(nv12.bits_per_pixel, 12);
+        assert_eq!(nv12.planes, 2);
+        assert_eq!(nv12.frame_size(640, 480, 640), 460_800);
+        // Odd heights round the chroma plane up
+        assert_eq!(nv12.frame_size(4, 3, 4), 12 + 8);
+
+        let yu12 = lookup(FourCC::YU12).expect("YU12 should be known");
+        assert_eq!(yu12.planes, 3);
+        assert_eq!(yu12.plane_stride(1, 640), 320);
+        assert_eq!(yu12.frame_size(640, 480, 640), 460_800);
+        // Odd widths round the chroma lines up
+        assert_eq!(yu12.plane_stride(1, 5), 3);
+        assert_eq!(yu12.frame_size(5, 2, 5), 10 + 3 + 3);
+
+        let yuv422p = lookup(FourCC::YUV422P).expect("422P should be known");
+        assert_eq!(yuv422p.bits_per_pixel, 16);
+        assert_eq!(yuv422p.frame_size(640, 480, 640), 614_400);
+    }
+
+    #[test]
+    fn test_bayer_stride() {
+        let packed = lookup(FourCC::SRGGB10P).expect("SRGGB10P should be known");
+        assert_eq!(packed.layout, Layout::Bayer);
+        assert_eq!(packed.min_stride(4608), 5760);
+        assert_eq!(packed.frame_size(4608, 2592, 5760), 14_929_920);
+
+        let unpacked = lookup(FourCC::SBGGR12).expect("SBGGR12 should be known");
+        assert_eq!(unpacked.min_stride(640), 1280);
+        let packed = lookup(FourCC::SGBRG12P).expect("SGBRG12P should be known");
+        assert_eq!(packed.min_stride(640), 960);
+        // Partial groups take the space of whole ones
+        assert_eq!(packed.min_stride(3), 6);
+        let packed = lookup(FourCC::SBGGR10P).expect("SBGGR10P should be known");
+        assert_eq!(packed.min_stride(10), 15);
+    }
+
+    #[test]
+    fn test_compressed_size_estimate() {
+        let mjpg = lookup(FourCC::MJPG).expect("MJPG should be known");
+        assert!(mjpg.is_compressed());
+        assert_eq!(mjpg.min_stride(640), 0);
+        assert_eq!(mjpg.frame_size(640, 480, 0), 614_400);
+    }
+}
@@ -0,0 +1,666 @@
+//! Raw capture recordings.
+//!
+//! A recording stores captured frames exactly as the device delivered them,
+//! together with the format and the capabilities of the device, so captures
+//! can be archived as regression datasets and replayed later with
+//! [`ReplayDevice`](crate::replay::ReplayDevice).
+//!
+//! The container is a little-endian binary file:
+//!
+//! - Header: the `PICAMREC` magic, a version, the [`Format`], the frame
+//!   interval, the [`DeviceCapabilities`] and, since version 2, the
+//!   colorimetry fields of the format as one byte each. Version 3 stores
+//!   their V4L2 values; version 2 stored the colorspace and YUV encoding by
+//!   their position in an earlier list and is still read.
+//! - One record per frame: sequence number, timestamp in nanoseconds, bytes
+//!   used, then the `bytes_used` payload bytes.
+//! - Index: the offset of every frame record, followed by a fixed-size footer
+//!   with the frame count, the index offset and the `PICAMIDX` magic.
+//!
+//! The index is written by [`Recorder::finish`]. A recording cut short before
+//! that is still readable: [`RecordingReader`] rebuilds the index by scanning
+//! the frame records and ignores a trailing partial frame.
+
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::colorspace::{Colorspace, FieldOrder, Quantization, TransferFunction, YcbcrEncoding};
+use crate::traits::{
+    CameraError, DeviceCapabilities, Format, FourCC, Fraction, Frame, FrameMetadata, FrameRef,
+    Result,
+};
+
+/// Magic bytes starting a recording.
+const MAGIC: [u8; 8] = *b"PICAMREC";
+
+/// Magic bytes ending the index footer.
+const INDEX_MAGIC: [u8; 8] = *b"PICAMIDX";
+
+/// Container version written by this crate.
+const VERSION: u16 = 3;
+
+/// Colorspaces of version 2 recordings, indexed by their stored code.
+const V2_COLORSPACES: [Colorspace; 12] = [
+    Colorspace::Default,
+    Colorspace::Smpte170m,
+    Colorspace::Smpte240m,
+    Colorspace::Rec709,
+    Colorspace::Ntsc,
+    Colorspace::Ebu,
+    Colorspace::Jpeg,
+    Colorspace::Srgb,
+    Colorspace::Oprgb,
+    Colorspace::Rec2020,
+    Colorspace::Raw,
+    Colorspace::DciP3,
+];
+
+/// YUV encodings of version 2 recordings, indexed by their stored code.
+const V2_YCBCR_ENCODINGS: [YcbcrEncoding; 8] = [
+    YcbcrEncoding::Default,
+    YcbcrEncoding::Bt601,
+    YcbcrEncoding::Bt709,
+    YcbcrEncoding::Xv601,
+    YcbcrEncoding::Xv709,
+    YcbcrEncoding::Bt2020,
+    YcbcrEncoding::Bt2020ConstLum,
+    YcbcrEncoding::Smpte240m,
+];
+
+/// Size of a frame record header: sequence, timestamp and bytes used.
+const FRAME_HEADER_LEN: u64 = 16;
+
+/// Size of the index footer: frame count, index offset and magic.
+const FOOTER_LEN: u64 = 20;
+
+/// Stream description stored at the start of a recording.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordingHeader {
+    /// Format of the recorded frames.
+    pub format: Format,
+    /// Frame interval the device was running at.
+    pub interval: Fraction,
+    /// Capabilities of the recording device.
+    pub capabilities: DeviceCapabilities,
+}
+
+impl RecordingHeader {
+    /// Encode the header.
+    fn encode(&self) -> Result<Vec<u8>> {
+        let format = &self.format;
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.extend_from_slice(&format.fourcc.0);
+        for value in [
+            format.width,
+            format.height,
+            format.stride,
+            format.size,
+            self.interval.numerator,
+            self.interval.denominator,
+        ] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let capabilities = &self.capabilities;
+        for text in [
+            &capabilities.driver,
+            &capabilities.card,
+            &capabilities.bus_info,
+        ] {
+            let len = u16::try_from(text.len()).map_err(|_| {
+                CameraError::invalid_data(format!("Capability string too long: {text}"))
+            })?;
+            bytes.extend_from_slice(&len.to_le_bytes());
+            bytes.extend_from_slice(text.as_bytes());
+        }
+        bytes.push(u8::from(capabilities.can_capture));
+        bytes.push(u8::from(capabilities.can_stream));
+        bytes.extend_from_slice(&[
+            format.colorspace.code(),
+            format.xfer.code(),
+            format.ycbcr_enc.code(),
+            format.quantization.code(),
+            format.field.code(),
+        ]);
+        Ok(bytes)
+    }
+
+    /// Decode a header from the start of `reader`.
+    fn decode<R: Read>(reader: &mut R) -> Result<Self> {
+        if read_array(reader)? != MAGIC {
+            return Err(CameraError::invalid_data(
+                "Not a pi-cam-capture recording".to_owned(),
+            ));
+        }
+        let version = u16::from_le_bytes(read_array(reader)?);
+        if !(1..=VERSION).contains(&version) {
+            return Err(CameraError::invalid_data(format!(
+                "Unsupported recording version {version}"
+            )));
+        }
+
+        let fourcc = FourCC(read_array(reader)?);
+        let [width, height, stride, size, numerator, denominator] =
+            [(); 6].map(|()| read_array(reader).map(u32::from_le_bytes));
+        let mut format = Format {
+            stride: stride?,
+            size: size?,
+            ..Format::new(width?, height?, fourcc)
+        };
+        let interval = Fraction::new(numerator?, denominator?);
+
+        let [driver, card, bus_info] = [(); 3].map(|()| read_string(reader));
+        let [can_capture, can_stream] = read_array(reader)?;
+        // Version 1 recordings predate colorimetry and keep the defaults
+        if version >= 2 {
+            let [colorspace, xfer, ycbcr_enc, quantization, field] = read_array(reader)?;
+            let (colorspace, ycbcr_enc) = if version == 2 {
+                (
+                    V2_COLORSPACES.get(usize::from(colorspace)).copied(),
+                    V2_YCBCR_ENCODINGS.get(usize::from(ycbcr_enc)).copied(),
+                )
+            } else {
+                (
+                    Colorspace::from_code(colorspace),
+                    YcbcrEncoding::from_code(ycbcr_enc),
+                )
+            };
+            format.colorspace = decode_code(colorspace, "colorspace")?;
+            format.xfer = decode_code(TransferFunction::from_code(xfer), "transfer function")?;
+            format.ycbcr_enc = decode_code(ycbcr_enc, "YUV encoding")?;
+            format.quantization =
+                decode_code(Quantization::from_code(quantization), "quantization")?;
+            format.field = decode_code(FieldOrder::from_code(field), "field order")?;
+        }
+        Ok(Self {
+            format,
+            interval,
+            capabilities: DeviceCapabilities {
+                driver: driver?,
+                card: card?,
+                bus_info: bus_info?,
+                can_capture: can_capture != 0,
+                can_stream: can_stream != 0,
+            },
+        })
+    }
+}
+
+/// Read exactly `N` bytes.
+fn read_array<const N: usize, R: Read>(reader: &mut R) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Turn a failed colorimetry lookup into an error naming the field.
+fn decode_code<T>(value: Option<T>, what: &str) -> Result<T> {
+    value.ok_or_else(|| CameraError::invalid_data(format!("Unknown {what} code")))
+}
+
+/// Read a length-prefixed UTF-8 string.
+fn read_string<R: Read>(reader: &mut R) -> Result<String> {
+    let len = u16::from_le_bytes(read_array(reader)?);
+    let mut bytes = vec![0; usize::from(len)];
+    reader.read_exact(&mut bytes)?;
+    String::from_utf8(bytes)
+        .map_err(|_| CameraError::invalid_data("Capability string is not UTF-8".to_owned()))
+}
+
+/// Writer of a recording.
+///
+/// Payloads are stored up to `bytes_used`, so compressed frames take only the
+/// space they need.
+#[derive(Debug)]
+pub struct Recorder<W: Write> {
+    writer: W,
+    /// Bytes written so far.
+    position: u64,
+    /// Offset of every frame record.
+    index: Vec<u64>,
+}
+
+impl Recorder<BufWriter<File>> {
+    /// Create a recording file at `path`, replacing any existing file.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Io` if the file cannot be created or written.
+    pub fn create<P: AsRef<Path>>(path: P, header: &RecordingHeader) -> Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?), header)
+    }
+}
+
+impl<W: Write> Recorder<W> {
+    /// Start a recording on `writer` by writing the header.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Io` if writing fails or a capability string exceeds 65535
+    /// bytes.
+    pub fn new(mut writer: W, header: &RecordingHeader) -> Result<Self> {
+        let bytes = header.encode()?;
+        writer.write_all(&bytes)?;
+        Ok(Self {
+            writer,
+            position: bytes.len() as u64,
+            index: Vec::new(),
+        })
+    }
+
+    /// Append a frame.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Io` if writing fails or the timestamp does not fit in 64-bit
+    /// nanoseconds.
+    pub fn write_frame(&mut self, frame: &FrameRef<'_>) -> Result<()> {
+        let metadata = &frame.metadata;
+        let payload = frame
+            .data
+            .get(..metadata.bytes_used as usize)
+            .unwrap_or(frame.data);
+        let timestamp = u64::try_from(metadata.timestamp.as_nanos()).map_err(|_| {
+            CameraError::invalid_data(format!("Timestamp {:?} too large", metadata.timestamp))
+        })?;
+        let bytes_used = u32::try_from(payload.len())
+            .map_err(|_| CameraError::invalid_data("Frame payload too large".to_owned()))?;
+
+        let mut record = Vec::new();
+        record.extend_from_slice(&metadata.sequence.to_le_bytes());
+        record.extend_from_slice(&timestamp.to_le_bytes());
+        record.extend_from_slice(&bytes_used.to_le_bytes());
+        self.writer.write_all(&record)?;
+        self.writer.write_all(payload)?;
+
+        self.index.push(self.position);
+        self.position += FRAME_HEADER_LEN + u64::from(bytes_used);
+        Ok(())
+    }
+
+    /// Number of frames written so far.
+    #[must_use]
+    pub fn frame_count(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Write the index and flush, returning the underlying writer.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Io` if writing or flushing fails.
+    pub fn finish(mut self) -> Result<W> {
+        let count = u32::try_from(self.index.len())
+            .map_err(|_| CameraError::invalid_data("Too many frames to index".to_owned()))?;
+        let mut footer = Vec::new();
+        for offset in &self.index {
+            footer.extend_from_slice(&offset.to_le_bytes());
+        }
+        footer.extend_from_slice(&count.to_le_bytes());
+        footer.extend_from_slice(&self.position.to_le_bytes());
+        footer.extend_from_slice(&INDEX_MAGIC);
+        self.writer.write_all(&footer)?;
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Random-access reader of a recording.
+#[derive(Debug)]
+pub struct RecordingReader<R> {
+    reader: R,
+    header: RecordingHeader,
+    /// Offset of every frame record.
+    index: Vec<u64>,
+}
+
+impl RecordingReader<BufReader<File>> {
+    /// Open the recording at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Io` if the file cannot be read or is not a recording.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read + Seek> RecordingReader<R> {
+    /// Read the header and index of a recording.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Io` if reading fails or the data is not a recording.
+    pub fn new(mut reader: R) -> Result<Self> {
+        reader.seek(SeekFrom::Start(0))?;
+        let header = RecordingHeader::decode(&mut reader)?;
+        let frames_start = reader.stream_position()?;
+        let end = reader.seek(SeekFrom::End(0))?;
+
+        let index = match read_index(&mut reader, frames_start, end)? {
+            Some(index) => index,
+            None => scan_index(&mut reader, frames_start, end)?,
+        };
+        Ok(Self {
+            reader,
+            header,
+            index,
+        })
+    }
+
+    /// Stream description of the recording.
+    pub const fn header(&self) -> &RecordingHeader {
+        &self.header
+    }
+
+    /// Number of frames in the recording.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Check whether the recording holds no frames.
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+
+    /// Read the metadata of frame `index` without its payload.
+    ///
+    /// # Errors
+    ///
+    /// Returns `StreamError` if `index` is out of range, or `Io` if reading
+    /// fails.
+    pub fn metadata(&mut self, index: usize) -> Result<FrameMetadata> {
+        let offset = self.record_offset(index)?;
+        self.reader.seek(SeekFrom::Start(offset))?;
+        read_frame_header(&mut self.reader)
+    }
+
+    /// Read frame `index`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `StreamError` if `index` is out of range, or `Io` if reading
+    /// fails.
+    pub fn read_frame(&mut self, index: usize) -> Result<Frame> {
+        let metadata = self.metadata(index)?;
+        let mut data = vec![0; metadata.bytes_used as usize];
+        self.reader.read_exact(&mut data)?;
+        Ok(Frame { data, metadata })
+    }
+
+    /// Iterate over all frames in recording order.
+    pub fn frames(&mut self) -> impl Iterator<Item = Result<Frame>> + '_ {
+        (0..self.len()).map(|index| self.read_frame(index))
+    }
+
+    /// Offset of the payload of frame `index`, with its metadata.
+    pub(crate) fn payload(&mut self, index: usize) -> Result<(u64, FrameMetadata)> {
+        let metadata = self.metadata(index)?;
+        Ok((self.reader.stream_position()?, metadata))
+    }
+
+    /// Unwrap the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn record_offset(&self, index: usize) -> Result<u64> {
+        self.index.get(index).copied().ok_or_else(|| {
+            CameraError::StreamError(format!(
+                "Frame {index} out of range for recording of {} frames",
+                self.index.len()
+            ))
+        })
+    }
+}
+
+/// Read a frame record header at the current position.
+fn read_frame_header<R: Read>(reader: &mut R) -> Result<FrameMetadata> {
+    let sequence = u32::from_le_bytes(read_array(reader)?);
+    let timestamp = u64::from_le_bytes(read_array(reader)?);
+    let bytes_used = u32::from_le_bytes(read_array(reader)?);
+    Ok(FrameMetadata {
+        sequence,
+        timestamp: Duration::from_nanos(timestamp),
+        bytes_used,
+    })
+}
+
+/// Read the index written by [`Recorder::finish`], if the footer is intact.
+fn read_index<R: Read + Seek>(
+    reader: &mut R,
+    frames_start: u64,
+    end: u64,
+) -> Result<Option<Vec<u64>>> {
+    let Some(footer_start) = end
+        .checked_sub(FOOTER_LEN)
+        .filter(|start| *start >= frames_start)
+    else {
+        return Ok(None);
+    };
+    reader.seek(SeekFrom::Start(footer_start))?;
+    let count = u32::from_le_bytes(read_array(reader)?);
+    let index_offset = u64::from_le_bytes(read_array(reader)?);
+    if read_array(reader)? != INDEX_MAGIC
+        || index_offset < frames_start
+        || index_offset + u64::from(count) * 8 != footer_start
+    {
+        return Ok(None);
+    }
+
+    reader.seek(SeekFrom::Start(index_offset))?;
+    let index = (0..count)
+        .map(|_| read_array(reader).map(u64::from_le_bytes))
+        .collect::<io::Result<Vec<_>>>()?;
+    if index
+        .iter()
+        .any(|offset| *offset + FRAME_HEADER_LEN > index_offset)
+    {
+        return Err(CameraError::invalid_data(
+            "Recording index points past the frames".to_owned(),
+        ));
+    }
+    Ok(Some(index))
+}
+
+/// Rebuild the index of an unfinished recording by walking its frame records.
+fn scan_index<R: Read + Seek>(reader: &mut R, frames_start: u64, end: u64) -> Result<Vec<u64>> {
+    let mut index = Vec::new();
+    let mut offset = frames_start;
+    while offset + FRAME_HEADER_LEN <= end {
+        reader.seek(SeekFrom::Start(offset))?;
+        let metadata = read_frame_header(reader)?;
+        let next = offset + FRAME_HEADER_LEN + u64::from(metadata.bytes_used);
+        if next > end {
+            break;
+        }
+        index.push(offset);
+        offset = next;
+    }
+    Ok(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockDevice;
+    use crate::traits::{CameraDevice, CaptureStream};
+    use std::io::Cursor;
+
+    /// Record `count` mock frames, returning the container and the frames.
+    fn record_mock(count: usize, finish: bool) -> (Vec<u8>, Vec<Frame>) {
+        let mut device = MockDevice::new();
+        let header = RecordingHeader {
+            format: device.format().expect("format should succeed"),
+            interval: device
+                .frame_interval()
+                .expect("frame_interval should succeed"),
+            capabilities: device.capabilities().clone(),
+        };
+        let mut recorder = Recorder::new(Vec::new(), &header).expect("recorder should start");
+        let mut stream = device
+            .create_stream(4)
+            .expect("create_stream should succeed");
+
+        let mut frames = Vec::new();
+        for _ in 0..count {
+            let frame = stream.next_frame_ref().expect("next_frame should succeed");
+            recorder
+                .write_frame(&frame)
+                .expect("write_frame should succeed");
+            frames.push(frame.to_frame());
+        }
+        assert_eq!(recorder.frame_count(), count);
+
+        let bytes = if finish {
+            recorder.finish().expect("finish should succeed")
+        } else {
+            recorder.writer
+        };
+        (bytes, frames)
+    }
+
+    #[test]
+    fn test_recording_round_trip() {
+        let (bytes, frames) = record_mock(3, true);
+        let mut reader = RecordingReader::new(Cursor::new(bytes)).expect("recording should open");
+
+        let header = reader.header();
+        assert_eq!(header.format, Format::new(640, 480, FourCC::YUYV));
+        assert_eq!(header.interval, Fraction::new(1, 30));
+        assert_eq!(header.capabilities.driver, "mock");
+        assert!(header.capabilities.can_stream);
+
+        assert_eq!(reader.len(), 3);
+        let read: Vec<Frame> = reader
+            .frames()
+            .collect::<Result<_>>()
+            .expect("frames should read");
+        for (read, expected) in read.iter().zip(&frames) {
+            assert_eq!(read.metadata, expected.metadata);
+            assert_eq!(read.data, expected.data);
+        }
+    }
+
+    #[test]
+    fn test_recording_seek() {
+        let (bytes, frames) = record_mock(4, true);
+        let mut reader = RecordingReader::new(Cursor::new(bytes)).expect("recording should open");
+
+        let frame = reader.read_frame(2).expect("frame 2 should read");
+        assert_eq!(frame.metadata, frames[2].metadata);
+        assert_eq!(
+            reader.metadata(0).expect("frame 0 should read"),
+            frames[0].metadata
+        );
+        assert!(matches!(
+            reader.read_frame(4),
+            Err(CameraError::StreamError(_))
+        ));
+    }
+
+    #[test]
+    fn test_recording_trims_to_bytes_used() {
+        let header = RecordingHeader {
+            format: Format::new(64, 48, FourCC::MJPG),
+            interval: Fraction::from_fps(30),
+            capabilities: DeviceCapabilities::default(),
+        };
+        let data = vec![0xab; 6144];
+        let frame = FrameRef::new(
+            &data,
+            FrameMetadata {
+                sequence: 9,
+                timestamp: Duration::from_millis(300),
+                bytes_used: 100,
+            },
+        );
+        let mut recorder = Recorder::new(Vec::new(), &header).expect("recorder should start");
+        recorder
+            .write_frame(&frame)
+            .expect("write_frame should succeed");
+        let bytes = recorder.finish().expect("finish should succeed");
+
+        let mut reader = RecordingReader::new(Cursor::new(bytes)).expect("recording should open");
+        let read = reader.read_frame(0).expect("frame should read");
+        assert_eq!(read.data, vec![0xab; 100]);
+        assert_eq!(read.metadata, frame.metadata);
+    }
+
+    #[test]
+    fn test_recording_keeps_colorimetry() {
+        let header = RecordingHeader {
+            format: Format {
+                colorspace: Colorspace::Rec709,
+                xfer: TransferFunction::Rec709,
+                ycbcr_enc: YcbcrEncoding::Bt709,
+                quantization: Quantization::FullRange,
+                field: FieldOrder::None,
+                ..Format::new(64, 48, FourCC::YUYV)
+            },
+            interval: Fraction::from_fps(30),
+            capabilities: DeviceCapabilities::default(),
+        };
+        let bytes = Recorder::new(Vec::new(), &header)
+            .expect("recorder should start")
+            .finish()
+            .expect("finish should succeed");
+        let reader = RecordingReader::new(Cursor::new(bytes)).expect("recording should open");
+        assert_eq!(reader.header(), &header);
+
+        // A version 1 header has no colorimetry bytes
+        let mut v1 = header.encode().expect("header should encode");
+        v1.truncate(v1.len() - 5);
+        v1[8..10].copy_from_slice(&1u16.to_le_bytes());
+        let reader = RecordingReader::new(Cursor::new(v1)).expect("version 1 should open");
+        assert_eq!(reader.header().format, Format::new(64, 48, FourCC::YUYV));
+    }
+
+    #[test]
+    fn test_colorimetry_codes_are_v4l2_values() {
+        let header = RecordingHeader {
+            format: Format {
+                colorspace: Colorspace::Srgb,
+                ycbcr_enc: YcbcrEncoding::Bt2020,
+                field: FieldOrder::Alternate,
+                ..Format::new(64, 48, FourCC::YUYV)
+            },
+            interval: Fraction::from_fps(30),
+            capabilities: DeviceCapabilities::default(),
+        };
+        let mut bytes = header.encode().expect("header should encode");
+        let codes = bytes.len() - 5;
+        // V4L2_COLORSPACE_SRGB, V4L2_YCBCR_ENC_BT2020 and V4L2_FIELD_ALTERNATE
+        assert_eq!(bytes[codes..], [8, 0, 6, 0, 7]);
+
+        // Version 2 stored the colorspace and encoding by list position
+        bytes[8..10].copy_from_slice(&2u16.to_le_bytes());
+        bytes[codes..].copy_from_slice(&[7, 0, 5, 0, 7]);
+        let reader = RecordingReader::new(Cursor::new(bytes)).expect("version 2 should open");
+        assert_eq!(reader.header(), &header);
+    }
+
+    #[test]
+    fn test_unfinished_recording_is_scanned() {
+        let (mut bytes, frames) = record_mock(3, false);
+        // Cut the last frame short, as a crash mid-write would
+        bytes.truncate(bytes.len() - 10);
+
+        let mut reader = RecordingReader::new(Cursor::new(bytes)).expect("recording should open");
+        assert_eq!(reader.len(), 2);
+        let frame = reader.read_frame(1).expect("frame 1 should read");
+        assert_eq!(frame.data, frames[1].data);
+    }
+
+    #[test]
+    fn test_rejects_foreign_data() {
+        assert!(RecordingReader::new(Cursor::new(b"YUV4MPEG2 W4 H2 F30:1\n".to_vec())).is_err());
+
+        let (mut bytes, _) = record_mock(1, true);
+        bytes[8] = 4;
+        assert!(RecordingReader::new(Cursor::new(bytes)).is_err());
+    }
+}
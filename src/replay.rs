@@ -0,0 +1,907 @@
+//! Replay of recorded captures.
+//!
+//! [`ReplayDevice`] plays a recording back through the regular
+//! [`CameraDevice`] and [`CaptureStream`] traits, so pipelines and the
+//! `validation` checks can run against real camera data without hardware.
+//! Three recording layouts are supported:
+//!
+//! - Recordings made with [`Recorder`](crate::recording::Recorder), which
+//!   also restore the capabilities of the recording device.
+//! - Raw frames with a metadata sidecar: the frame payloads written back to
+//!   back, each `bytes_used` bytes long, plus a text [`Sidecar`] describing the
+//!   format and every frame.
+//! - YUV4MPEG2 (Y4M) files, replayed in their planar pixel format with
+//!   timestamps derived from the frame rate.
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::controls::{ControlInfo, ControlValue};
+use crate::pixel_format::PixelFormatInfo;
+use crate::recording::RecordingReader;
+use crate::traits::{
+    CameraDevice, CameraError, CaptureStream, DeviceCapabilities, Format, FormatDescription,
+    FourCC, Fraction, FrameInterval, FrameMetadata, FrameRef, FrameSize, Result,
+};
+use crate::y4m;
+
+/// First line of a sidecar file.
+const SIDECAR_SIGNATURE: &str = "pi-cam-capture sidecar 1";
+
+/// Frame metadata describing a raw frame dump.
+///
+/// The text layout is line based: a signature line, the format, the frame
+/// interval, then one line per frame. Blank lines and lines starting with `#`
+/// are ignored.
+///
+/// ```text
+/// pi-cam-capture sidecar 1
+/// format YUYV 640 480 1280 614400
+/// interval 1/30
+/// frame 0 0 614400
+/// frame 1 33333333 614400
+/// ```
+///
+/// The format line holds the `FourCC`, width, height, stride and size; frame
+/// lines hold the sequence number, timestamp in nanoseconds and bytes used.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sidecar {
+    /// Format of the recorded frames.
+    pub format: Format,
+    /// Frame interval the device was running at.
+    pub interval: Fraction,
+    /// Metadata of every recorded frame, in capture order.
+    pub frames: Vec<FrameMetadata>,
+}
+
+impl Sidecar {
+    /// Create a sidecar with no frames.
+    #[must_use]
+    pub const fn new(format: Format, interval: Fraction) -> Self {
+        Self {
+            format,
+            interval,
+            frames: Vec::new(),
+        }
+    }
+
+    /// Parse the text form of a sidecar.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Io` with `InvalidData` naming the first malformed line.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+        if lines.next().map(|(_, line)| line) != Some(SIDECAR_SIGNATURE) {
+            return Err(CameraError::invalid_data(
+                "Missing sidecar signature".to_owned(),
+            ));
+        }
+
+        let (mut format, mut interval) = (None, None);
+        let mut frames = Vec::new();
+        for (number, line) in lines {
+            let malformed =
+                || CameraError::invalid_data(format!("Malformed sidecar line {number}: {line}"));
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                ["format", fourcc, numbers @ ..] => {
+                    let [width, height, stride, size] =
+                        parse_numbers(numbers).ok_or_else(malformed)?;
+                    let fourcc = parse_fourcc(fourcc).ok_or_else(malformed)?;
+                    format = Some(Format {
+                        stride,
+                        size,
+                        ..Format::new(width, height, fourcc)
+                    });
+                }
+                ["interval", fraction] => {
+                    let parts: Vec<&str> = fraction.split('/').collect();
+                    let [numerator, denominator] = parse_numbers(&parts).ok_or_else(malformed)?;
+                    interval = Some(Fraction::new(numerator, denominator));
+                }
+                ["frame", sequence, timestamp, bytes_used] => frames.push(FrameMetadata {
+                    sequence: sequence.parse().map_err(|_| malformed())?,
+                    timestamp: Duration::from_nanos(timestamp.parse().map_err(|_| malformed())?),
+                    bytes_used: bytes_used.parse().map_err(|_| malformed())?,
+                }),
+                _ => return Err(malformed()),
+            }
+        }
+
+        Ok(Self {
+            format: format
+                .ok_or_else(|| CameraError::invalid_data("Sidecar has no format".to_owned()))?,
+            interval: interval
+                .ok_or_else(|| CameraError::invalid_data("Sidecar has no interval".to_owned()))?,
+            frames,
+        })
+    }
+}
+
+impl fmt::Display for Sidecar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let format = &self.format;
+        writeln!(f, "{SIDECAR_SIGNATURE}")?;
+        writeln!(
+            f,
+            "format {} {} {} {} {}",
+            format.fourcc.to_string().trim_end(),
+            format.width,
+            format.height,
+            format.stride,
+            format.size
+        )?;
+        writeln!(f, "interval {}", self.interval)?;
+        for frame in &self.frames {
+            writeln!(
+                f,
+                "frame {} {} {}",
+                frame.sequence,
+                frame.timestamp.as_nanos(),
+                frame.bytes_used
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse a fixed number of decimal fields.
+fn parse_numbers<const N: usize>(fields: &[&str]) -> Option<[u32; N]> {
+    let mut numbers = [0; N];
+    if fields.len() != N {
+        return None;
+    }
+    for (number, field) in numbers.iter_mut().zip(fields) {
+        *number = field.parse().ok()?;
+    }
+    Some(numbers)
+}
+
+/// Parse a `FourCC` written without its trailing padding spaces.
+fn parse_fourcc(text: &str) -> Option<FourCC> {
+    let mut code = [b' '; 4];
+    if text.is_empty() || text.len() > code.len() {
+        return None;
+    }
+    code.iter_mut()
+        .zip(text.bytes())
+        .for_each(|(dst, src)| *dst = src);
+    Some(FourCC(code))
+}
+
+/// Frame timing of a replay.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplayTiming {
+    /// Deliver every frame as soon as it is requested.
+    Unpaced,
+    /// Deliver frames at their recorded timestamps.
+    Original,
+    /// Deliver frames at their recorded timestamps, sped up by this finite,
+    /// positive factor.
+    Accelerated(f64),
+}
+
+/// Seekable byte source holding the recorded frames.
+trait Source: Read + Seek + Send {}
+
+impl<T: Read + Seek + Send> Source for T {}
+
+/// Location and metadata of a recorded frame.
+#[derive(Debug, Clone)]
+struct RecordedFrame {
+    offset: u64,
+    metadata: FrameMetadata,
+}
+
+/// Camera device replaying a recorded capture.
+///
+/// The recording fixes the format and frame interval: setting either returns
+/// the recorded value, the way a driver reports what it actually granted.
+/// The device exposes no controls.
+pub struct ReplayDevice {
+    capabilities: DeviceCapabilities,
+    format: Format,
+    interval: Fraction,
+    source: Box<dyn Source>,
+    frames: Vec<RecordedFrame>,
+    timing: ReplayTiming,
+    looping: bool,
+}
+
+impl fmt::Debug for ReplayDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ReplayDevice")
+            .field("capabilities", &self.capabilities)
+            .field("format", &self.format)
+            .field("interval", &self.interval)
+            .field("frames", &self.frames.len())
+            .field("timing", &self.timing)
+            .field("looping", &self.looping)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ReplayDevice {
+    /// Open a raw frame dump and its sidecar.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Io` if either file cannot be read, the sidecar is malformed or
+    /// the dump is shorter than the sidecar describes.
+    pub fn open_raw<P: AsRef<Path>, Q: AsRef<Path>>(frames: P, sidecar: Q) -> Result<Self> {
+        let sidecar = Sidecar::parse(&fs::read_to_string(sidecar)?)?;
+        let device = Self::from_raw(BufReader::new(File::open(&frames)?), sidecar)?;
+        Ok(device.with_source_name(frames.as_ref()))
+    }
+
+    /// Replay raw frames from `reader`, described by `sidecar`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Io` if the reader fails or holds fewer bytes than the sidecar
+    /// describes.
+    pub fn from_raw<R: Read + Seek + Send + 'static>(
+        mut reader: R,
+        sidecar: Sidecar,
+    ) -> Result<Self> {
+        let mut offset = 0;
+        let frames: Vec<RecordedFrame> = sidecar
+            .frames
+            .into_iter()
+            .map(|metadata| {
+                let frame = RecordedFrame { offset, metadata };
+                offset += u64::from(frame.metadata.bytes_used);
+                frame
+            })
+            .collect();
+
+        let available = reader.seek(SeekFrom::End(0))?;
+        if available < offset {
+            return Err(CameraError::invalid_data(format!(
+                "Raw dump holds {available} bytes, sidecar describes {offset}"
+            )));
+        }
+        Ok(Self::new(
+            sidecar.format,
+            sidecar.interval,
+            Box::new(reader),
+            frames,
+        ))
+    }
+
+    /// Open a recording made with [`Recorder`](crate::recording::Recorder).
+    ///
+    /// The device reports the capabilities stored in the recording.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Io` if the file cannot be read or is not a recording.
+    pub fn open_recording<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::from_recording(RecordingReader::open(path)?)
+    }
+
+    /// Replay the frames of a recording.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Io` if reading the frame index fails.
+    pub fn from_recording<R: Read + Seek + Send + 'static>(
+        mut recording: RecordingReader<R>,
+    ) -> Result<Self> {
+        let frames = (0..recording.len())
+            .map(|index| {
+                let (offset, metadata) = recording.payload(index)?;
+                Ok(RecordedFrame { offset, metadata })
+            })
+            .collect::<Result<_>>()?;
+        let header = recording.header().clone();
+        Ok(Self::new(
+            header.format,
+            header.interval,
+            Box::new(recording.into_inner()),
+            frames,
+        )
+        .with_capabilities(header.capabilities))
+    }
+
+    /// Open a Y4M file.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Io` if the file cannot be read or is not a supported Y4M stream.
+    pub fn open_y4m<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let device = Self::from_y4m(File::open(&path)?)?;
+        Ok(device.with_source_name(path.as_ref()))
+    }
+
+    /// Replay a Y4M stream from `reader`.
+    ///
+    /// Frames are numbered from zero and timestamped at the stream frame rate.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Io` if the reader fails or does not hold a supported Y4M stream.
+    pub fn from_y4m<R: Read + Seek + Send + 'static>(reader: R) -> Result<Self> {
+        let mut reader = BufReader::new(reader);
+        let header = y4m::read_header(&mut reader)?;
+        let format = header.format();
+        let frames = (0u32..)
+            .zip(y4m::frame_offsets(&mut reader, &header)?)
+            .map(|(sequence, offset)| RecordedFrame {
+                offset,
+                metadata: FrameMetadata {
+                    sequence,
+                    timestamp: header.interval.duration_of(sequence).unwrap_or_default(),
+                    bytes_used: format.size,
+                },
+            })
+            .collect();
+        Ok(Self::new(format, header.interval, Box::new(reader), frames))
+    }
+
+    fn new(
+        format: Format,
+        interval: Fraction,
+        source: Box<dyn Source>,
+        frames: Vec<RecordedFrame>,
+    ) -> Self {
+        Self {
+            capabilities: DeviceCapabilities {
+                driver: "replay".to_owned(),
+                card: "Replay Camera".to_owned(),
+                bus_info: "replay".to_owned(),
+                can_capture: true,
+                can_stream: true,
+            },
+            format,
+            interval,
+            source,
+            frames,
+            timing: ReplayTiming::Unpaced,
+            looping: false,
+        }
+    }
+
+    /// Name the bus after the recording file.
+    fn with_source_name(mut self, path: &Path) -> Self {
+        self.capabilities.bus_info = format!("replay:{}", path.display());
+        self
+    }
+
+    /// Set the capabilities reported by the device, e.g. those of the camera
+    /// the recording was made with.
+    #[must_use]
+    pub fn with_capabilities(mut self, capabilities: DeviceCapabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+
+    /// Set the frame timing. Replays are unpaced by default.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Io` with `InvalidInput` if an `Accelerated` factor is not a
+    /// finite positive number.
+    pub fn with_timing(mut self, timing: ReplayTiming) -> Result<Self> {
+        if let ReplayTiming::Accelerated(factor) = timing {
+            if !factor.is_finite() || factor <= 0.0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Invalid replay speed factor {factor}"),
+                )
+                .into());
+            }
+        }
+        self.timing = timing;
+        Ok(self)
+    }
+
+    /// Restart from the first frame after the last one instead of ending.
+    ///
+    /// Sequence numbers and timestamps keep increasing across laps.
+    #[must_use]
+    pub const fn with_looping(mut self, looping: bool) -> Self {
+        self.looping = looping;
+        self
+    }
+
+    /// Number of frames in the recording.
+    #[must_use]
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Sequence numbers and time covered by one pass over the recording.
+    fn lap_length(&self) -> (u32, Duration) {
+        let (Some(first), Some(last)) = (self.frames.first(), self.frames.last()) else {
+            return (0, Duration::ZERO);
+        };
+        let sequences = last
+            .metadata
+            .sequence
+            .wrapping_sub(first.metadata.sequence)
+            .wrapping_add(1);
+        let duration = last
+            .metadata
+            .timestamp
+            .saturating_sub(first.metadata.timestamp)
+            + self.interval.duration_of(1).unwrap_or_default();
+        (sequences, duration)
+    }
+}
+
+impl CameraDevice for ReplayDevice {
+    type Stream<'a> = ReplayStream<'a>;
+
+    fn capabilities(&self) -> &DeviceCapabilities {
+        &self.capabilities
+    }
+
+    fn format(&self) -> Result<Format> {
+        Ok(self.format.clone())
+    }
+
+    fn set_format(&mut self, _format: &Format) -> Result<Format> {
+        Ok(self.format.clone())
+    }
+
+    fn frame_interval(&self) -> Result<Fraction> {
+        Ok(self.interval)
+    }
+
+    fn set_frame_interval(&mut self, _interval: Fraction) -> Result<Fraction> {
+        Ok(self.interval)
+    }
+
+    fn supported_formats(&self) -> Result<Vec<FormatDescription>> {
+        let info = self.format.fourcc.info();
+        Ok(vec![FormatDescription {
+            fourcc: self.format.fourcc,
+            description: format!("Recorded {}", self.format.fourcc),
+            compressed: info.is_some_and(PixelFormatInfo::is_compressed),
+        }])
+    }
+
+    fn frame_sizes(&self, fourcc: FourCC) -> Result<Vec<FrameSize>> {
+        if fourcc != self.format.fourcc {
+            return Err(CameraError::StreamError(format!(
+                "Pixel format {fourcc} not supported"
+            )));
+        }
+        Ok(vec![FrameSize::Discrete {
+            width: self.format.width,
+            height: self.format.height,
+        }])
+    }
+
+    fn frame_intervals(
+        &self,
+        fourcc: FourCC,
+        width: u32,
+        height: u32,
+    ) -> Result<Vec<FrameInterval>> {
+        if !self
+            .frame_sizes(fourcc)?
+            .iter()
+            .any(|size| size.contains(width, height))
+        {
+            return Err(CameraError::StreamError(format!(
+                "Frame size {width}x{height} not supported for {fourcc}"
+            )));
+        }
+        Ok(vec![FrameInterval::Discrete(self.interval)])
+    }
+
+    fn controls(&self) -> Result<Vec<ControlInfo>> {
+        Ok(Vec::new())
+    }
+
+    fn control(&self, id: u32) -> Result<ControlValue> {
+        Err(CameraError::ControlNotFound(id))
+    }
+
+    fn set_controls(&mut self, controls: &[(u32, ControlValue)]) -> Result<()> {
+        match controls.first() {
+            Some((id, _)) => Err(CameraError::ControlNotFound(*id)),
+            None => Ok(()),
+        }
+    }
+
+    fn create_stream(&mut self, _buffer_count: u32) -> Result<Self::Stream<'_>> {
+        Ok(ReplayStream {
+            device: self,
+            buffer: Vec::new(),
+            position: 0,
+            lap: 0,
+            epoch: None,
+        })
+    }
+}
+
+/// Capture stream replaying frames from the start of a recording.
+///
+/// Once the last frame has been delivered, captures fail with `StreamError`
+/// unless the device loops.
+#[derive(Debug)]
+pub struct ReplayStream<'a> {
+    device: &'a mut ReplayDevice,
+    buffer: Vec<u8>,
+    /// Index of the next frame to deliver.
+    position: usize,
+    /// Number of completed passes over the recording.
+    lap: u32,
+    /// Time of the first paced capture.
+    epoch: Option<Instant>,
+}
+
+impl CaptureStream for ReplayStream<'_> {
+    fn next_frame_ref(&mut self) -> Result<FrameRef<'_>> {
+        self.capture(None)
+    }
+
+    fn next_frame_ref_timeout(&mut self, timeout: Duration) -> Result<FrameRef<'_>> {
+        self.capture(Some(timeout))
+    }
+}
+
+impl ReplayStream<'_> {
+    /// Metadata of the next frame, adjusted for the current lap.
+    fn next_metadata(&mut self) -> Result<(u64, FrameMetadata)> {
+        if self.position >= self.device.frames.len() && self.device.looping {
+            self.position = 0;
+            self.lap += 1;
+        }
+        let frame = self
+            .device
+            .frames
+            .get(self.position)
+            .ok_or_else(|| CameraError::StreamError("End of recording".to_owned()))?;
+
+        let (sequences, duration) = self.device.lap_length();
+        let mut metadata = frame.metadata.clone();
+        metadata.sequence = metadata
+            .sequence
+            .wrapping_add(sequences.wrapping_mul(self.lap));
+        metadata.timestamp = metadata
+            .timestamp
+            .saturating_add(duration.saturating_mul(self.lap));
+        Ok((frame.offset, metadata))
+    }
+
+    /// Sleep until the frame recorded at `timestamp` is due.
+    fn pace(&mut self, timestamp: Duration, timeout: Option<Duration>) -> Result<()> {
+        let speed = match self.device.timing {
+            ReplayTiming::Unpaced => return Ok(()),
+            ReplayTiming::Original => 1.0,
+            ReplayTiming::Accelerated(factor) => factor,
+        };
+        let start = self
+            .device
+            .frames
+            .first()
+            .map_or(Duration::ZERO, |frame| frame.metadata.timestamp);
+        let epoch = *self.epoch.get_or_insert_with(Instant::now);
+        // A tiny speed factor can push the due time past what `Instant`
+        // represents; such a frame is never due
+        let delay = timestamp.saturating_sub(start).as_secs_f64() / speed;
+        let due = Duration::try_from_secs_f64(delay)
+            .ok()
+            .and_then(|delay| epoch.checked_add(delay));
+
+        let wait = due.map_or(Duration::MAX, |due| {
+            due.saturating_duration_since(Instant::now())
+        });
+        if let Some(timeout) = timeout.filter(|timeout| wait > *timeout) {
+            thread::sleep(timeout);
+            return Err(CameraError::Timeout);
+        }
+        thread::sleep(wait);
+        Ok(())
+    }
+
+    /// Read the next recorded frame into the stream buffer.
+    fn capture(&mut self, timeout: Option<Duration>) -> Result<FrameRef<'_>> {
+        let (offset, metadata) = self.next_metadata()?;
+        self.pace(metadata.timestamp, timeout)?;
+
+        // Size the buffer like a driver buffer, so short frames keep their
+        // recorded bytes_used but still cover the full format
+        let used = metadata.bytes_used as usize;
+        self.buffer.clear();
+        self.buffer
+            .resize(used.max(self.device.format.size as usize), 0);
+        let payload = self
+            .buffer
+            .get_mut(..used)
+            .ok_or_else(|| CameraError::StreamError("Frame buffer too small".to_owned()))?;
+        self.device.source.seek(SeekFrom::Start(offset))?;
+        self.device.source.read_exact(payload)?;
+        self.position += 1;
+
+        Ok(FrameRef::new(&self.buffer, metadata))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockDevice;
+    use crate::recording::{Recorder, RecordingHeader};
+    use crate::traits::Frame;
+    use crate::validation::validate_color_bars;
+    use std::io::Cursor;
+
+    /// Record `count` mock frames as a raw dump and sidecar.
+    fn record_mock(count: usize) -> (Vec<u8>, Sidecar) {
+        let mut device = MockDevice::new();
+        let mut sidecar = Sidecar::new(
+            device.format().expect("format should succeed"),
+            device
+                .frame_interval()
+                .expect("frame_interval should succeed"),
+        );
+        let mut stream = device
+            .create_stream(4)
+            .expect("create_stream should succeed");
+
+        let mut dump = Vec::new();
+        for _ in 0..count {
+            let frame = stream.next_frame_ref().expect("next_frame should succeed");
+            dump.extend_from_slice(&frame.data[..frame.metadata.bytes_used as usize]);
+            sidecar.frames.push(frame.metadata.clone());
+        }
+        (dump, sidecar)
+    }
+
+    fn collect(stream: &mut ReplayStream<'_>, count: usize) -> Vec<Frame> {
+        (0..count)
+            .map(|_| stream.next_frame().expect("next_frame should succeed"))
+            .collect()
+    }
+
+    #[test]
+    fn test_sidecar_round_trip() {
+        let mut sidecar = Sidecar::new(
+            Format::new(640, 480, FourCC::Y16),
+            Fraction::new(1001, 30000),
+        );
+        sidecar.frames.push(FrameMetadata {
+            sequence: 7,
+            timestamp: Duration::new(12, 345),
+            bytes_used: 614_400,
+        });
+
+        let text = sidecar.to_string();
+        assert!(text.contains("format Y16 640 480 1280 614400\n"));
+        assert_eq!(
+            Sidecar::parse(&text).expect("sidecar should parse"),
+            sidecar
+        );
+    }
+
+    #[test]
+    fn test_sidecar_parse_errors() {
+        let valid = "pi-cam-capture sidecar 1\n# comment\nformat YUYV 4 2 8 16\ninterval 1/30\n";
+        assert!(Sidecar::parse(valid).is_ok());
+
+        for text in [
+            "format YUYV 4 2 8 16\ninterval 1/30\n",
+            "pi-cam-capture sidecar 1\ninterval 1/30\n",
+            "pi-cam-capture sidecar 1\nformat YUYV 4 2 8\ninterval 1/30\n",
+            "pi-cam-capture sidecar 1\nformat YUYV 4 2 8 16\ninterval 30\n",
+            "pi-cam-capture sidecar 1\nformat YUYV 4 2 8 16\ninterval 1/30\nframe 0 x 16\n",
+        ] {
+            assert!(Sidecar::parse(text).is_err(), "{text:?} should be rejected");
+        }
+    }
+
+    #[test]
+    fn test_replay_raw_matches_recording() {
+        let (dump, sidecar) = record_mock(3);
+        let expected = sidecar.frames.clone();
+        let mut device =
+            ReplayDevice::from_raw(Cursor::new(dump), sidecar).expect("replay should open");
+        assert_eq!(device.frame_count(), 3);
+        let format = device.format().expect("format should succeed");
+
+        let mut stream = device
+            .create_stream(4)
+            .expect("create_stream should succeed");
+        for metadata in &expected {
+            let frame = stream.next_frame().expect("next_frame should succeed");
+            assert_eq!(&frame.metadata, metadata);
+            validate_color_bars(&frame, &format).expect("replayed frame should hold color bars");
+        }
+        assert!(matches!(
+            stream.next_frame(),
+            Err(CameraError::StreamError(_))
+        ));
+    }
+
+    #[test]
+    fn test_replay_raw_rejects_short_dump() {
+        let (mut dump, sidecar) = record_mock(2);
+        dump.truncate(dump.len() - 1);
+        assert!(ReplayDevice::from_raw(Cursor::new(dump), sidecar).is_err());
+    }
+
+    #[test]
+    fn test_replay_truncated_frame_keeps_buffer_size() {
+        let (mut dump, mut sidecar) = record_mock(1);
+        dump.truncate(100);
+        sidecar.frames[0].bytes_used = 100;
+        let mut device =
+            ReplayDevice::from_raw(Cursor::new(dump), sidecar).expect("replay should open");
+
+        let mut stream = device
+            .create_stream(4)
+            .expect("create_stream should succeed");
+        let frame = stream.next_frame().expect("next_frame should succeed");
+        assert_eq!(frame.metadata.bytes_used, 100);
+        assert_eq!(frame.data.len(), 640 * 480 * 2);
+    }
+
+    #[test]
+    fn test_replay_looping_continues_sequence() {
+        let (dump, sidecar) = record_mock(2);
+        let mut device = ReplayDevice::from_raw(Cursor::new(dump), sidecar)
+            .expect("replay should open")
+            .with_looping(true);
+        let interval = device
+            .frame_interval()
+            .expect("frame_interval should succeed");
+
+        let mut stream = device
+            .create_stream(4)
+            .expect("create_stream should succeed");
+        let frames = collect(&mut stream, 5);
+        for (expected, frame) in (0u32..).zip(&frames) {
+            assert_eq!(frame.metadata.sequence, expected);
+            let nominal = interval.duration_of(expected).unwrap_or_default();
+            // Laps add the rounded recorded span, so allow nanosecond drift
+            assert!(
+                frame
+                    .metadata
+                    .timestamp
+                    .as_nanos()
+                    .abs_diff(nominal.as_nanos())
+                    < 1000
+            );
+        }
+        assert_eq!(frames[4].data, frames[0].data);
+    }
+
+    #[test]
+    fn test_replay_timing() {
+        let (dump, mut sidecar) = record_mock(3);
+        sidecar.interval = Fraction::new(1, 1);
+        for (seconds, frame) in (0..).zip(&mut sidecar.frames) {
+            frame.timestamp = Duration::from_secs(seconds);
+        }
+
+        // 1 s between frames, replayed 100 times faster
+        let mut device = ReplayDevice::from_raw(Cursor::new(dump), sidecar)
+            .expect("replay should open")
+            .with_timing(ReplayTiming::Accelerated(100.0))
+            .expect("speed factor should be accepted");
+        let mut stream = device
+            .create_stream(4)
+            .expect("create_stream should succeed");
+
+        let start = Instant::now();
+        stream.next_frame().expect("first frame is due immediately");
+        assert!(matches!(stream.try_next_frame(), Ok(None)));
+        collect(&mut stream, 2);
+        let elapsed = start.elapsed();
+        assert!(
+            elapsed >= Duration::from_millis(20),
+            "replay took only {elapsed:?}"
+        );
+        assert!(elapsed < Duration::from_secs(1), "replay took {elapsed:?}");
+    }
+
+    #[test]
+    fn test_replay_tiny_speed_never_delivers_next_frame() {
+        let (dump, sidecar) = record_mock(2);
+        let mut device = ReplayDevice::from_raw(Cursor::new(dump), sidecar)
+            .expect("replay should open")
+            .with_timing(ReplayTiming::Accelerated(1e-20))
+            .expect("speed factor should be accepted");
+        let mut stream = device
+            .create_stream(4)
+            .expect("create_stream should succeed");
+
+        stream.next_frame().expect("first frame is due immediately");
+        assert!(matches!(
+            stream.next_frame_timeout(Duration::from_millis(10)),
+            Err(CameraError::Timeout)
+        ));
+    }
+
+    #[test]
+    fn test_replay_rejects_invalid_speed() {
+        for factor in [0.0, -2.0, f64::NAN, f64::INFINITY] {
+            let (dump, sidecar) = record_mock(1);
+            let device =
+                ReplayDevice::from_raw(Cursor::new(dump), sidecar).expect("replay should open");
+            assert!(
+                device
+                    .with_timing(ReplayTiming::Accelerated(factor))
+                    .is_err(),
+                "{factor} should be rejected"
+            );
+        }
+    }
+
+    #[test]
+    fn test_replay_y4m() {
+        let mut data = b"YUV4MPEG2 W4 H2 F25:1 C420jpeg\n".to_vec();
+        for fill in 0..3 {
+            data.extend_from_slice(b"FRAME\n");
+            data.extend(std::iter::repeat(fill).take(12));
+        }
+
+        let mut device = ReplayDevice::from_y4m(Cursor::new(data)).expect("replay should open");
+        let format = device.format().expect("format should succeed");
+        assert_eq!(format.fourcc, FourCC::YU12);
+        assert_eq!(device.frame_interval().ok(), Some(Fraction::new(1, 25)));
+        assert_eq!(
+            device.frame_sizes(FourCC::YU12).ok(),
+            Some(vec![FrameSize::Discrete {
+                width: 4,
+                height: 2
+            }])
+        );
+
+        let mut stream = device
+            .create_stream(4)
+            .expect("create_stream should succeed");
+        for (fill, frame) in (0u8..).zip(collect(&mut stream, 3)) {
+            assert_eq!(frame.data, vec![fill; 12]);
+            assert_eq!(
+                frame.metadata.timestamp,
+                Duration::from_millis(40 * u64::from(fill))
+            );
+        }
+    }
+
+    #[test]
+    fn test_replay_recording_restores_capabilities() {
+        let (dump, sidecar) = record_mock(2);
+        let header = RecordingHeader {
+            format: sidecar.format.clone(),
+            interval: sidecar.interval,
+            capabilities: MockDevice::new().capabilities().clone(),
+        };
+        let mut recorder = Recorder::new(Vec::new(), &header).expect("recorder should start");
+        let mut payloads = dump.chunks(sidecar.format.size as usize);
+        for metadata in &sidecar.frames {
+            let data = payloads.next().expect("dump should hold every frame");
+            recorder
+                .write_frame(&FrameRef::new(data, metadata.clone()))
+                .expect("write_frame should succeed");
+        }
+        let bytes = recorder.finish().expect("finish should succeed");
+
+        let recording = RecordingReader::new(Cursor::new(bytes)).expect("recording should open");
+        let mut device = ReplayDevice::from_recording(recording).expect("replay should open");
+        assert_eq!(device.capabilities().driver, "mock");
+
+        let mut stream = device
+            .create_stream(4)
+            .expect("create_stream should succeed");
+        let frames = collect(&mut stream, 2);
+        assert_eq!(frames[1].metadata, sidecar.frames[1]);
+        assert_eq!(frames[1].data, dump[sidecar.format.size as usize..]);
+    }
+}
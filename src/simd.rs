@@ -0,0 +1,532 @@
+//! SIMD kernels for packed YUV 4:2:2 to RGB conversion.
+//!
+//! This is the only module allowed to use `unsafe`: the intrinsics need it,
+//! and each kernel is only handed out by [`Kernel::detect`] once the CPU
+//! features it was compiled for have been detected at runtime. The kernels
+//! evaluate the same fixed-point arithmetic as the scalar path in
+//! [`convert`](crate::convert), so their output is bit-exact with it.
+#![allow(unsafe_code)]
+
+use crate::convert::Coefficients;
+use crate::traits::FourCC;
+
+/// Kernel entry point: converts the leading pixels of a packed 4:2:2 line
+/// into planar R, G and B buffers, returning how many pixels it converted.
+type KernelFn = fn(&Coefficients, &[u8; 16], &[u8], &mut [u8], &mut [u8], &mut [u8]) -> usize;
+
+/// A SIMD conversion kernel supported by the running CPU.
+#[derive(Debug, Clone, Copy)]
+pub struct Kernel {
+    name: &'static str,
+    convert: KernelFn,
+}
+
+impl Kernel {
+    /// Pick the widest kernel the running CPU supports, if any.
+    #[allow(unreachable_code)]
+    pub fn detect() -> Option<Self> {
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("avx2") {
+                return Some(Self {
+                    name: "avx2",
+                    convert: x86::convert_avx2,
+                });
+            }
+            if is_x86_feature_detected!("sse4.1") && is_x86_feature_detected!("ssse3") {
+                return Some(Self {
+                    name: "sse4.1",
+                    convert: x86::convert_sse41,
+                });
+            }
+        }
+        #[cfg(target_arch = "aarch64")]
+        {
+            if std::arch::is_aarch64_feature_detected!("neon") {
+                return Some(Self {
+                    name: "neon",
+                    convert: arm::convert_neon,
+                });
+            }
+        }
+        None
+    }
+
+    /// Name of the instruction set the kernel uses.
+    pub const fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Convert the leading pixels of `line` into `planes`.
+    ///
+    /// Returns the number of pixels converted, a multiple of the kernel
+    /// width; the caller converts the rest with the scalar path.
+    pub fn convert(
+        &self,
+        coefficients: &Coefficients,
+        fourcc: FourCC,
+        line: &[u8],
+        planes: &mut [Vec<u8>; 3],
+    ) -> usize {
+        let [red, green, blue] = planes;
+        (self.convert)(coefficients, &yuyv_order(fourcc), line, red, green, blue)
+    }
+}
+
+/// Byte shuffle reordering four packed 4:2:2 pairs to YUYV order.
+fn yuyv_order(fourcc: FourCC) -> [u8; 16] {
+    let group = match fourcc {
+        FourCC::YVYU => [0, 3, 2, 1],
+        FourCC::UYVY => [1, 0, 3, 2],
+        FourCC::VYUY => [1, 2, 3, 0],
+        _ => [0, 1, 2, 3],
+    };
+    let mut order = [0; 16];
+    for (base, chunk) in (0u8..).step_by(4).zip(order.chunks_exact_mut(4)) {
+        for (slot, index) in chunk.iter_mut().zip(group) {
+            *slot = base + index;
+        }
+    }
+    order
+}
+
+/// Number of pixels in whole `block`-pixel runs that fit in both the line
+/// and the planes.
+fn block_pixels(line: &[u8], planes: &[&mut [u8]; 3], block: usize) -> usize {
+    let pixels = planes
+        .iter()
+        .map(|plane| plane.len())
+        .fold(line.len() / 2, usize::min);
+    pixels / block * block
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use std::arch::x86_64::{
+        __m128i, __m256i, _mm256_add_epi32, _mm256_and_si256, _mm256_broadcastsi128_si256,
+        _mm256_castsi256_si128, _mm256_cvtepu16_epi32, _mm256_extracti128_si256,
+        _mm256_loadu_si256, _mm256_mullo_epi32, _mm256_set1_epi16, _mm256_set1_epi32,
+        _mm256_shuffle_epi32, _mm256_shuffle_epi8, _mm256_srai_epi32, _mm256_srli_epi16,
+        _mm256_sub_epi32, _mm_add_epi32, _mm_and_si128, _mm_cvtepu16_epi32, _mm_loadu_si128,
+        _mm_mullo_epi32, _mm_packs_epi32, _mm_packus_epi16, _mm_set1_epi16, _mm_set1_epi32,
+        _mm_setzero_si128, _mm_shuffle_epi32, _mm_shuffle_epi8, _mm_srai_epi32, _mm_srli_epi16,
+        _mm_srli_si128, _mm_storel_epi64, _mm_sub_epi32,
+    };
+
+    use super::block_pixels;
+    use crate::convert::{Coefficients, FIXED_SHIFT};
+
+    /// SSE4.1 kernel, eight pixels per step.
+    pub(super) fn convert_sse41(
+        coefficients: &Coefficients,
+        order: &[u8; 16],
+        line: &[u8],
+        red: &mut [u8],
+        green: &mut [u8],
+        blue: &mut [u8],
+    ) -> usize {
+        // SAFETY: `Kernel::detect` only hands this kernel out after detecting
+        // SSE4.1 and SSSE3.
+        unsafe { sse41(coefficients, order, line, [red, green, blue]) }
+    }
+
+    /// AVX2 kernel, sixteen pixels per step.
+    pub(super) fn convert_avx2(
+        coefficients: &Coefficients,
+        order: &[u8; 16],
+        line: &[u8],
+        red: &mut [u8],
+        green: &mut [u8],
+        blue: &mut [u8],
+    ) -> usize {
+        // SAFETY: `Kernel::detect` only hands this kernel out after detecting
+        // AVX2.
+        unsafe { avx2(coefficients, order, line, [red, green, blue]) }
+    }
+
+    /// Coefficients broadcast to 128-bit lanes.
+    struct Sse {
+        y_offset: __m128i,
+        y_gain: __m128i,
+        round: __m128i,
+        bias: __m128i,
+        r_v: __m128i,
+        g_u: __m128i,
+        g_v: __m128i,
+        b_u: __m128i,
+    }
+
+    #[target_feature(enable = "sse4.1,ssse3")]
+    unsafe fn sse41(
+        coefficients: &Coefficients,
+        order: &[u8; 16],
+        line: &[u8],
+        mut planes: [&mut [u8]; 3],
+    ) -> usize {
+        let pixels = block_pixels(line, &planes, 8);
+        let k = Sse {
+            y_offset: _mm_set1_epi32(coefficients.y_offset),
+            y_gain: _mm_set1_epi32(coefficients.y_gain),
+            round: _mm_set1_epi32(1 << (FIXED_SHIFT - 1)),
+            bias: _mm_set1_epi32(128),
+            r_v: _mm_set1_epi32(coefficients.r_v),
+            g_u: _mm_set1_epi32(coefficients.g_u),
+            g_v: _mm_set1_epi32(coefficients.g_v),
+            b_u: _mm_set1_epi32(coefficients.b_u),
+        };
+        let mask = _mm_loadu_si128(order.as_ptr().cast());
+        let low_bytes = _mm_set1_epi16(0xff);
+
+        for start in (0..pixels).step_by(8) {
+            // In bounds: `start + 8 <= pixels <= line.len() / 2`
+            let input = _mm_loadu_si128(line.as_ptr().add(start * 2).cast());
+            let input = _mm_shuffle_epi8(input, mask);
+            let luma = _mm_and_si128(input, low_bytes);
+            let chroma = _mm_srli_epi16::<8>(input);
+
+            let low = sse_rgb4(&k, _mm_cvtepu16_epi32(luma), _mm_cvtepu16_epi32(chroma));
+            let high = sse_rgb4(
+                &k,
+                _mm_cvtepu16_epi32(_mm_srli_si128::<8>(luma)),
+                _mm_cvtepu16_epi32(_mm_srli_si128::<8>(chroma)),
+            );
+            for ((plane, low), high) in planes.iter_mut().zip(low).zip(high) {
+                // In bounds: `start + 8 <= pixels <= plane.len()`
+                store8(plane.as_mut_ptr().add(start), low, high);
+            }
+        }
+        pixels
+    }
+
+    /// Convert four pixels, given as 32-bit luma `[y0, y1, y2, y3]` and
+    /// chroma `[u0, v0, u1, v1]`, to unscaled 32-bit R, G and B.
+    #[target_feature(enable = "sse4.1")]
+    #[inline]
+    unsafe fn sse_rgb4(k: &Sse, luma: __m128i, chroma: __m128i) -> [__m128i; 3] {
+        let u = _mm_sub_epi32(_mm_shuffle_epi32::<0b1010_0000>(chroma), k.bias);
+        let v = _mm_sub_epi32(_mm_shuffle_epi32::<0b1111_0101>(chroma), k.bias);
+        let luma = _mm_add_epi32(
+            _mm_mullo_epi32(_mm_sub_epi32(luma, k.y_offset), k.y_gain),
+            k.round,
+        );
+        let red = _mm_add_epi32(luma, _mm_mullo_epi32(v, k.r_v));
+        let green = _mm_sub_epi32(
+            _mm_sub_epi32(luma, _mm_mullo_epi32(u, k.g_u)),
+            _mm_mullo_epi32(v, k.g_v),
+        );
+        let blue = _mm_add_epi32(luma, _mm_mullo_epi32(u, k.b_u));
+        [
+            _mm_srai_epi32::<FIXED_SHIFT>(red),
+            _mm_srai_epi32::<FIXED_SHIFT>(green),
+            _mm_srai_epi32::<FIXED_SHIFT>(blue),
+        ]
+    }
+
+    /// Saturate two runs of four 32-bit values to bytes and store all eight.
+    #[target_feature(enable = "sse4.1")]
+    #[inline]
+    unsafe fn store8(dst: *mut u8, low: __m128i, high: __m128i) {
+        let bytes = _mm_packus_epi16(_mm_packs_epi32(low, high), _mm_setzero_si128());
+        _mm_storel_epi64(dst.cast(), bytes);
+    }
+
+    /// Coefficients broadcast to 256-bit lanes.
+    struct Avx {
+        y_offset: __m256i,
+        y_gain: __m256i,
+        round: __m256i,
+        bias: __m256i,
+        r_v: __m256i,
+        g_u: __m256i,
+        g_v: __m256i,
+        b_u: __m256i,
+    }
+
+    #[target_feature(enable = "avx2")]
+    unsafe fn avx2(
+        coefficients: &Coefficients,
+        order: &[u8; 16],
+        line: &[u8],
+        mut planes: [&mut [u8]; 3],
+    ) -> usize {
+        let pixels = block_pixels(line, &planes, 16);
+        let k = Avx {
+            y_offset: _mm256_set1_epi32(coefficients.y_offset),
+            y_gain: _mm256_set1_epi32(coefficients.y_gain),
+            round: _mm256_set1_epi32(1 << (FIXED_SHIFT - 1)),
+            bias: _mm256_set1_epi32(128),
+            r_v: _mm256_set1_epi32(coefficients.r_v),
+            g_u: _mm256_set1_epi32(coefficients.g_u),
+            g_v: _mm256_set1_epi32(coefficients.g_v),
+            b_u: _mm256_set1_epi32(coefficients.b_u),
+        };
+        // The byte shuffle works within each 128-bit lane
+        let mask = _mm256_broadcastsi128_si256(_mm_loadu_si128(order.as_ptr().cast()));
+        let low_bytes = _mm256_set1_epi16(0xff);
+
+        for start in (0..pixels).step_by(16) {
+            // In bounds: `start + 16 <= pixels <= line.len() / 2`
+            let input = _mm256_loadu_si256(line.as_ptr().add(start * 2).cast());
+            let input = _mm256_shuffle_epi8(input, mask);
+            let luma = _mm256_and_si256(input, low_bytes);
+            let chroma = _mm256_srli_epi16::<8>(input);
+
+            let halves = [
+                (_mm256_castsi256_si128(luma), _mm256_castsi256_si128(chroma)),
+                (
+                    _mm256_extracti128_si256::<1>(luma),
+                    _mm256_extracti128_si256::<1>(chroma),
+                ),
+            ];
+            for (offset, (luma, chroma)) in [start, start + 8].into_iter().zip(halves) {
+                let channels = avx_rgb8(
+                    &k,
+                    _mm256_cvtepu16_epi32(luma),
+                    _mm256_cvtepu16_epi32(chroma),
+                );
+                // In bounds: `offset + 8 <= pixels <= plane.len()`
+                store_channels(&mut planes, offset, channels);
+            }
+        }
+        pixels
+    }
+
+    /// Saturate eight pixels of 32-bit R, G and B to bytes and store them at
+    /// `offset` in the planes.
+    #[target_feature(enable = "avx2")]
+    #[inline]
+    unsafe fn store_channels(planes: &mut [&mut [u8]; 3], offset: usize, channels: [__m256i; 3]) {
+        for (plane, channel) in planes.iter_mut().zip(channels) {
+            store8(
+                plane.as_mut_ptr().add(offset),
+                _mm256_castsi256_si128(channel),
+                _mm256_extracti128_si256::<1>(channel),
+            );
+        }
+    }
+
+    /// Convert eight pixels, given as 32-bit luma and chroma
+    /// `[u0, v0, u1, v1, u2, v2, u3, v3]`, to unscaled 32-bit R, G and B.
+    #[target_feature(enable = "avx2")]
+    #[inline]
+    unsafe fn avx_rgb8(k: &Avx, luma: __m256i, chroma: __m256i) -> [__m256i; 3] {
+        // The dword shuffle works within each 128-bit lane, which holds the
+        // chroma of four pixels
+        let u = _mm256_sub_epi32(_mm256_shuffle_epi32::<0b1010_0000>(chroma), k.bias);
+        let v = _mm256_sub_epi32(_mm256_shuffle_epi32::<0b1111_0101>(chroma), k.bias);
+        let luma = _mm256_add_epi32(
+            _mm256_mullo_epi32(_mm256_sub_epi32(luma, k.y_offset), k.y_gain),
+            k.round,
+        );
+        let red = _mm256_add_epi32(luma, _mm256_mullo_epi32(v, k.r_v));
+        let green = _mm256_sub_epi32(
+            _mm256_sub_epi32(luma, _mm256_mullo_epi32(u, k.g_u)),
+            _mm256_mullo_epi32(v, k.g_v),
+        );
+        let blue = _mm256_add_epi32(luma, _mm256_mullo_epi32(u, k.b_u));
+        [
+            _mm256_srai_epi32::<FIXED_SHIFT>(red),
+            _mm256_srai_epi32::<FIXED_SHIFT>(green),
+            _mm256_srai_epi32::<FIXED_SHIFT>(blue),
+        ]
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod arm {
+    use std::arch::aarch64::{
+        int32x4_t, uint8x8_t, vcombine_s16, vdupq_n_s32, vget_high_u16, vget_low_u16, vget_low_u8,
+        vld1q_u8, vmlaq_s32, vmlsq_s32, vmovl_u16, vmovl_u8, vqmovn_s32, vqmovun_s16, vqtbl1q_u8,
+        vreinterpretq_s32_u32, vshrq_n_s32, vst1_u8, vsubq_s32, vtrn1q_s32, vtrn2q_s32, vuzp1q_u8,
+        vuzp2q_u8,
+    };
+
+    use super::block_pixels;
+    use crate::convert::{Coefficients, FIXED_SHIFT};
+
+    /// NEON kernel, eight pixels per step.
+    pub(super) fn convert_neon(
+        coefficients: &Coefficients,
+        order: &[u8; 16],
+        line: &[u8],
+        red: &mut [u8],
+        green: &mut [u8],
+        blue: &mut [u8],
+    ) -> usize {
+        // SAFETY: `Kernel::detect` only hands this kernel out after detecting
+        // NEON.
+        unsafe { neon(coefficients, order, line, [red, green, blue]) }
+    }
+
+    /// Coefficients broadcast to 128-bit lanes.
+    struct Neon {
+        y_offset: int32x4_t,
+        y_gain: int32x4_t,
+        round: int32x4_t,
+        bias: int32x4_t,
+        r_v: int32x4_t,
+        g_u: int32x4_t,
+        g_v: int32x4_t,
+        b_u: int32x4_t,
+    }
+
+    #[target_feature(enable = "neon")]
+    unsafe fn neon(
+        coefficients: &Coefficients,
+        order: &[u8; 16],
+        line: &[u8],
+        mut planes: [&mut [u8]; 3],
+    ) -> usize {
+        let pixels = block_pixels(line, &planes, 8);
+        let k = Neon {
+            y_offset: vdupq_n_s32(coefficients.y_offset),
+            y_gain: vdupq_n_s32(coefficients.y_gain),
+            round: vdupq_n_s32(1 << (FIXED_SHIFT - 1)),
+            bias: vdupq_n_s32(128),
+            r_v: vdupq_n_s32(coefficients.r_v),
+            g_u: vdupq_n_s32(coefficients.g_u),
+            g_v: vdupq_n_s32(coefficients.g_v),
+            b_u: vdupq_n_s32(coefficients.b_u),
+        };
+        let mask = vld1q_u8(order.as_ptr());
+
+        for start in (0..pixels).step_by(8) {
+            // In bounds: `start + 8 <= pixels <= line.len() / 2`
+            let input = vqtbl1q_u8(vld1q_u8(line.as_ptr().add(start * 2)), mask);
+            // Even bytes are luma, odd bytes alternate Cb and Cr
+            let luma = vmovl_u8(vget_low_u8(vuzp1q_u8(input, input)));
+            let chroma = vmovl_u8(vget_low_u8(vuzp2q_u8(input, input)));
+
+            let low = neon_rgb4(
+                &k,
+                vreinterpretq_s32_u32(vmovl_u16(vget_low_u16(luma))),
+                vreinterpretq_s32_u32(vmovl_u16(vget_low_u16(chroma))),
+            );
+            let high = neon_rgb4(
+                &k,
+                vreinterpretq_s32_u32(vmovl_u16(vget_high_u16(luma))),
+                vreinterpretq_s32_u32(vmovl_u16(vget_high_u16(chroma))),
+            );
+            for ((plane, low), high) in planes.iter_mut().zip(low).zip(high) {
+                // In bounds: `start + 8 <= pixels <= plane.len()`
+                vst1_u8(plane.as_mut_ptr().add(start), narrow(low, high));
+            }
+        }
+        pixels
+    }
+
+    /// Convert four pixels, given as 32-bit luma `[y0, y1, y2, y3]` and
+    /// chroma `[u0, v0, u1, v1]`, to unscaled 32-bit R, G and B.
+    #[target_feature(enable = "neon")]
+    #[inline]
+    unsafe fn neon_rgb4(k: &Neon, luma: int32x4_t, chroma: int32x4_t) -> [int32x4_t; 3] {
+        let u = vsubq_s32(vtrn1q_s32(chroma, chroma), k.bias);
+        let v = vsubq_s32(vtrn2q_s32(chroma, chroma), k.bias);
+        let luma = vmlaq_s32(k.round, vsubq_s32(luma, k.y_offset), k.y_gain);
+        let red = vmlaq_s32(luma, v, k.r_v);
+        let green = vmlsq_s32(vmlsq_s32(luma, u, k.g_u), v, k.g_v);
+        let blue = vmlaq_s32(luma, u, k.b_u);
+        [
+            vshrq_n_s32::<FIXED_SHIFT>(red),
+            vshrq_n_s32::<FIXED_SHIFT>(green),
+            vshrq_n_s32::<FIXED_SHIFT>(blue),
+        ]
+    }
+
+    /// Saturate two runs of four 32-bit values to eight bytes.
+    #[target_feature(enable = "neon")]
+    #[inline]
+    unsafe fn narrow(low: int32x4_t, high: int32x4_t) -> uint8x8_t {
+        vqmovun_s16(vcombine_s16(vqmovn_s32(low), vqmovn_s32(high)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::convert::{Colorimetry, YuvMatrix, YuvRange};
+    use crate::traits::unpack_yuv422;
+
+    /// Every kernel the running CPU supports, not just the widest.
+    fn supported_kernels() -> Vec<Kernel> {
+        let mut kernels = Vec::new();
+        #[cfg(target_arch = "x86_64")]
+        {
+            if is_x86_feature_detected!("avx2") {
+                kernels.push(Kernel {
+                    name: "avx2",
+                    convert: x86::convert_avx2,
+                });
+            }
+            if is_x86_feature_detected!("sse4.1") && is_x86_feature_detected!("ssse3") {
+                kernels.push(Kernel {
+                    name: "sse4.1",
+                    convert: x86::convert_sse41,
+                });
+            }
+        }
+        kernels.extend(Kernel::detect());
+        kernels
+    }
+
+    /// Check a kernel against the scalar conversion of `line`.
+    fn check_kernel(kernel: Kernel, fourcc: FourCC, colorimetry: Colorimetry, line: &[u8]) {
+        let coefficients = Coefficients::new(colorimetry);
+        let mut planes = [(); 3].map(|()| vec![0; line.len() / 2]);
+        let converted = kernel.convert(&coefficients, fourcc, line, &mut planes);
+        assert_eq!(converted, line.len() / 32 * 16, "{}", kernel.name());
+
+        for (pixel, group) in (0..converted).step_by(2).zip(line.chunks_exact(4)) {
+            let (y0, u, y1, v) = unpack_yuv422(fourcc, group).expect("group should unpack");
+            for (pixel, luma) in [(pixel, y0), (pixel + 1, y1)] {
+                let [red, green, blue] = &planes;
+                assert_eq!(
+                    [red[pixel], green[pixel], blue[pixel]],
+                    coefficients.rgb(luma, u, v),
+                    "{} {fourcc} {colorimetry:?} pixel {pixel}",
+                    kernel.name(),
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_kernels_match_scalar() {
+        let line: Vec<u8> = (0..1000u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 13).to_le_bytes()[0])
+            .collect();
+        let colorimetries = [
+            Colorimetry::default(),
+            Colorimetry::new(YuvMatrix::Bt709, YuvRange::Limited),
+            Colorimetry::new(YuvMatrix::Bt2020, YuvRange::Full),
+        ];
+        let fourccs = [FourCC::YUYV, FourCC::YVYU, FourCC::UYVY, FourCC::VYUY];
+
+        for kernel in supported_kernels() {
+            for (fourcc, colorimetry) in fourccs
+                .iter()
+                .flat_map(|&fourcc| colorimetries.map(|colorimetry| (fourcc, colorimetry)))
+            {
+                check_kernel(kernel, fourcc, colorimetry, &line);
+            }
+        }
+    }
+
+    #[test]
+    fn test_yuyv_order() {
+        assert_eq!(yuyv_order(FourCC::YUYV)[..8], [0, 1, 2, 3, 4, 5, 6, 7]);
+        assert_eq!(yuyv_order(FourCC::UYVY)[12..], [13, 12, 15, 14]);
+        assert_eq!(yuyv_order(FourCC::VYUY)[..4], [1, 2, 3, 0]);
+    }
+
+    #[test]
+    fn test_block_pixels() {
+        let line = [0; 40];
+        let [mut red, mut green, mut blue] = [[0; 20]; 3];
+        let planes = [&mut red[..], &mut green[..], &mut blue[..7]];
+        assert_eq!(block_pixels(&line, &planes, 8), 0);
+        let planes = [&mut red[..], &mut green[..], &mut blue[..]];
+        assert_eq!(block_pixels(&line, &planes, 8), 16);
+        assert_eq!(block_pixels(&line[..34], &planes, 16), 16);
+    }
+}
@@ -0,0 +1,580 @@
+//! Whole-frame statistics.
+//!
+//! [`FrameStats::compute`] looks at every pixel of a frame, where the
+//! [`validation`](crate::validation) helpers sample only a few. It gathers
+//! the figures exposure control, focus checks and health monitoring need:
+//!
+//! - Luma and red, green and blue [`Histogram`]s, which also give the mean,
+//!   minimum, maximum and percentiles of each channel.
+//! - The share of clipped and black pixels, and the mean color of the rest.
+//! - Averages over a grid of regions, for metering and spotting vignetting
+//!   or a covered lens.
+//! - A sharpness metric, the variance of the Laplacian of the luma, for the
+//!   whole frame and each region. It is larger for a sharper image of the
+//!   same scene; compare it across frames rather than against a fixed value.
+//!
+//! Any format [`convert::to_rgb24`](crate::convert::to_rgb24) reads is
+//! supported. Luma is taken as [`convert::to_luma`](crate::convert::to_luma)
+//! extracts it: the Y samples of YUV formats, usually limited range, and
+//! full range BT.601 luma for RGB and raw formats.
+
+use crate::convert::{to_luma_with_rgb, to_rgb24};
+use crate::traits::{Format, Result};
+
+/// BT.601 weights of red, green and blue in luma.
+const LUMA_WEIGHTS: [f32; 3] = [0.299, 0.587, 0.114];
+
+/// Settings of [`FrameStats::compute`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatsConfig {
+    /// Number of region columns, at most the frame width.
+    pub columns: u32,
+    /// Number of region rows, at most the frame height.
+    pub rows: u32,
+    /// RGB value from which a channel counts as clipped.
+    pub clip_level: u8,
+    /// RGB value up to which a channel counts as black.
+    pub black_level: u8,
+}
+
+impl Default for StatsConfig {
+    fn default() -> Self {
+        Self::new(4, 4)
+    }
+}
+
+impl StatsConfig {
+    /// Create settings with a grid of `columns` by `rows` regions.
+    #[must_use]
+    pub const fn new(columns: u32, rows: u32) -> Self {
+        Self {
+            columns,
+            rows,
+            clip_level: u8::MAX,
+            black_level: 0,
+        }
+    }
+
+    /// Set the levels at which channels count as clipped and black.
+    #[must_use]
+    pub const fn with_levels(mut self, black_level: u8, clip_level: u8) -> Self {
+        self.black_level = black_level;
+        self.clip_level = clip_level;
+        self
+    }
+}
+
+/// Counts of the 256 values of an 8-bit channel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Histogram {
+    bins: [u32; 256],
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self { bins: [0; 256] }
+    }
+}
+
+impl Histogram {
+    /// Count of each value.
+    #[must_use]
+    pub const fn bins(&self) -> &[u32; 256] {
+        &self.bins
+    }
+
+    /// Number of samples counted.
+    #[must_use]
+    pub fn count(&self) -> u64 {
+        self.bins.iter().map(|&bin| u64::from(bin)).sum()
+    }
+
+    /// Mean value, or 0 if the histogram is empty.
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn mean(&self) -> f32 {
+        let total: u64 = (0u64..)
+            .zip(&self.bins)
+            .map(|(value, &bin)| value * u64::from(bin))
+            .sum();
+        total as f32 / self.count().max(1) as f32
+    }
+
+    /// Smallest value counted.
+    #[must_use]
+    pub fn min(&self) -> Option<u8> {
+        (0..=u8::MAX)
+            .zip(&self.bins)
+            .find(|(_, &bin)| bin > 0)
+            .map(|(value, _)| value)
+    }
+
+    /// Largest value counted.
+    #[must_use]
+    pub fn max(&self) -> Option<u8> {
+        (0..=u8::MAX)
+            .zip(&self.bins)
+            .rev()
+            .find(|(_, &bin)| bin > 0)
+            .map(|(value, _)| value)
+    }
+
+    /// Smallest value at or below which at least `fraction` of the samples
+    /// lie, e.g. `0.5` for the median.
+    #[must_use]
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    pub fn percentile(&self, fraction: f32) -> Option<u8> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+        let wanted = ((fraction.clamp(0.0, 1.0) * count as f32).ceil() as u64).max(1);
+        let mut seen = 0;
+        (0..=u8::MAX).zip(&self.bins).find_map(|(value, &bin)| {
+            seen += u64::from(bin);
+            (seen >= wanted).then_some(value)
+        })
+    }
+
+    /// Count one sample.
+    fn add(&mut self, value: u8) {
+        if let Some(bin) = self.bins.get_mut(usize::from(value)) {
+            *bin = bin.saturating_add(1);
+        }
+    }
+}
+
+/// Averages over one region of the grid.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegionStats {
+    /// Left edge of the region in pixels.
+    pub x: u32,
+    /// Top edge of the region in pixels.
+    pub y: u32,
+    /// Width of the region in pixels.
+    pub width: u32,
+    /// Height of the region in pixels.
+    pub height: u32,
+    /// Mean luma.
+    pub luma: f32,
+    /// Mean red, green and blue.
+    pub rgb: [f32; 3],
+    /// Variance of the Laplacian of the luma inside the region.
+    pub sharpness: f32,
+}
+
+/// Statistics of a whole frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameStats {
+    /// Histogram of the luma.
+    pub luma: Histogram,
+    /// Histograms of red, green and blue.
+    pub rgb: [Histogram; 3],
+    /// Percentage of pixels with a channel at or above the clip level.
+    pub clipped: f32,
+    /// Percentage of pixels with every channel at or below the black level.
+    pub black: f32,
+    /// Mean red, green and blue of the pixels neither clipped nor black, or
+    /// `None` if there are none.
+    pub neutral_rgb: Option<[f32; 3]>,
+    /// Regions of the grid in row-major order.
+    pub regions: Vec<RegionStats>,
+    /// Variance of the Laplacian of the luma over the whole frame.
+    pub sharpness: f32,
+}
+
+impl FrameStats {
+    /// Compute the statistics of a frame.
+    ///
+    /// # Errors
+    ///
+    /// Returns the errors of [`to_rgb24`] for frames it cannot convert.
+    #[allow(clippy::cast_precision_loss)]
+    pub fn compute(data: &[u8], format: &Format, config: &StatsConfig) -> Result<Self> {
+        let rgb = to_rgb24(data, format, format.colorimetry())?;
+        let luma = to_luma_with_rgb(data, format, &rgb)?;
+        let grid = Grid::new(format, config);
+        let mut stats = Self {
+            luma: Histogram::default(),
+            rgb: Default::default(),
+            clipped: 0.0,
+            black: 0.0,
+            neutral_rgb: None,
+            regions: Vec::new(),
+            sharpness: 0.0,
+        };
+        let mut sums = vec![RegionSums::default(); grid.len()];
+        let (mut clipped, mut black) = (0u64, 0u64);
+        let mut neutral = RegionSums::default();
+
+        let pixels = rgb.chunks_exact(3).zip(&luma);
+        for (index, (pixel, &value)) in pixels.enumerate() {
+            stats.luma.add(value);
+            for (histogram, &sample) in stats.rgb.iter_mut().zip(pixel) {
+                histogram.add(sample);
+            }
+            let is_clipped = pixel.iter().any(|&sample| sample >= config.clip_level);
+            let is_black = pixel.iter().all(|&sample| sample <= config.black_level);
+            clipped += u64::from(is_clipped);
+            black += u64::from(is_black);
+            if !is_clipped && !is_black {
+                neutral.add(value, pixel);
+            }
+            if let Some(region) = sums.get_mut(grid.region_of(index)) {
+                region.add(value, pixel);
+            }
+        }
+
+        let mut laplacian = Moments::default();
+        for (index, value) in laplacians(&luma, grid.width) {
+            laplacian.add(value);
+            if let Some(region) = sums.get_mut(grid.region_of(index)) {
+                region.laplacian.add(value);
+            }
+        }
+
+        let count = stats.luma.count().max(1) as f32;
+        stats.clipped = clipped as f32 * 100.0 / count;
+        stats.black = black as f32 * 100.0 / count;
+        stats.neutral_rgb = (neutral.count > 0).then(|| neutral.finish([0; 4]).rgb);
+        stats.sharpness = laplacian.variance();
+        stats.regions = sums
+            .iter()
+            .enumerate()
+            .map(|(index, sums)| sums.finish(grid.bounds(index)))
+            .collect();
+        Ok(stats)
+    }
+
+    /// Mean luma.
+    #[must_use]
+    pub fn mean_luma(&self) -> f32 {
+        self.luma.mean()
+    }
+
+    /// Mean red, green and blue.
+    #[must_use]
+    pub fn mean_rgb(&self) -> [f32; 3] {
+        let [red, green, blue] = &self.rgb;
+        [red.mean(), green.mean(), blue.mean()]
+    }
+
+    /// Mean luma computed from [`mean_rgb`](Self::mean_rgb) with the BT.601
+    /// weights, full range whatever the format.
+    #[must_use]
+    pub fn mean_rgb_luma(&self) -> f32 {
+        LUMA_WEIGHTS
+            .iter()
+            .zip(self.mean_rgb())
+            .map(|(weight, mean)| weight * mean)
+            .sum()
+    }
+}
+
+/// Layout of the region grid over a frame.
+#[derive(Debug, Clone, Copy)]
+struct Grid {
+    width: usize,
+    height: usize,
+    columns: usize,
+    rows: usize,
+}
+
+impl Grid {
+    fn new(format: &Format, config: &StatsConfig) -> Self {
+        let width = format.width as usize;
+        let height = format.height as usize;
+        Self {
+            width,
+            height,
+            columns: (config.columns as usize).clamp(1, width.max(1)),
+            rows: (config.rows as usize).clamp(1, height.max(1)),
+        }
+    }
+
+    /// Number of regions.
+    const fn len(&self) -> usize {
+        self.columns * self.rows
+    }
+
+    /// Index of the region holding the pixel at `index` in row-major order.
+    const fn region_of(&self, index: usize) -> usize {
+        let width = if self.width == 0 { 1 } else { self.width };
+        let height = if self.height == 0 { 1 } else { self.height };
+        let column = index % width * self.columns / width;
+        let row = index / width * self.rows / height;
+        row * self.columns + column
+    }
+
+    /// Position and size of the region at `index`, in pixels.
+    fn bounds(&self, index: usize) -> [u32; 4] {
+        let column = index % self.columns;
+        let row = index / self.columns;
+        let edge = |cell: usize, cells: usize, size: usize| {
+            u32::try_from(cell * size / cells).unwrap_or(u32::MAX)
+        };
+        let left = edge(column, self.columns, self.width);
+        let top = edge(row, self.rows, self.height);
+        let right = edge(column + 1, self.columns, self.width);
+        let bottom = edge(row + 1, self.rows, self.height);
+        [left, top, right - left, bottom - top]
+    }
+}
+
+/// Running sums of the pixels of one region.
+#[derive(Debug, Clone, Copy, Default)]
+struct RegionSums {
+    count: u64,
+    luma: u64,
+    rgb: [u64; 3],
+    laplacian: Moments,
+}
+
+impl RegionSums {
+    fn add(&mut self, luma: u8, pixel: &[u8]) {
+        self.count += 1;
+        self.luma += u64::from(luma);
+        for (sum, &sample) in self.rgb.iter_mut().zip(pixel) {
+            *sum += u64::from(sample);
+        }
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn finish(&self, [x, y, width, height]: [u32; 4]) -> RegionStats {
+        let count = self.count.max(1) as f32;
+        RegionStats {
+            x,
+            y,
+            width,
+            height,
+            luma: self.luma as f32 / count,
+            rgb: self.rgb.map(|sum| sum as f32 / count),
+            sharpness: self.laplacian.variance(),
+        }
+    }
+}
+
+/// Running count, sum and sum of squares, for a variance.
+#[derive(Debug, Clone, Copy, Default)]
+struct Moments {
+    count: u64,
+    sum: f64,
+    sum_squares: f64,
+}
+
+impl Moments {
+    fn add(&mut self, value: i32) {
+        let value = f64::from(value);
+        self.count += 1;
+        self.sum += value;
+        self.sum_squares = value.mul_add(value, self.sum_squares);
+    }
+
+    #[allow(clippy::cast_precision_loss, clippy::cast_possible_truncation)]
+    fn variance(&self) -> f32 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        let count = self.count as f64;
+        let mean = self.sum / count;
+        mean.mul_add(-mean, self.sum_squares / count).max(0.0) as f32
+    }
+}
+
+/// The 4-neighbour Laplacian at every pixel of `luma` away from the border,
+/// with the row-major index of the pixel.
+fn laplacians(luma: &[u8], width: usize) -> impl Iterator<Item = (usize, i32)> + '_ {
+    let rows: Vec<&[u8]> = luma.chunks_exact(width.max(1)).collect();
+    (1..rows.len().saturating_sub(1)).flat_map(move |y| {
+        let (up, middle, down) = match rows.get(y - 1..=y + 1) {
+            Some(&[up, middle, down]) => (up, middle, down),
+            _ => (&[][..], &[][..], &[][..]),
+        };
+        let neighbours = up.iter().skip(1).zip(down.iter().skip(1));
+        middle
+            .windows(3)
+            .zip(neighbours)
+            .enumerate()
+            .map(move |(x, (row, (&above, &below)))| {
+                let [left, center, right] = <[u8; 3]>::try_from(row).unwrap_or_default();
+                let value = 4 * i32::from(center)
+                    - i32::from(left)
+                    - i32::from(right)
+                    - i32::from(above)
+                    - i32::from(below);
+                (y * width + x + 1, value)
+            })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::convert::to_luma;
+    use crate::mock::{generate_test_frame, TestPattern};
+    use crate::traits::FourCC;
+
+    fn stats(format: &Format, data: &[u8]) -> FrameStats {
+        FrameStats::compute(data, format, &StatsConfig::default()).expect("stats should compute")
+    }
+
+    #[test]
+    fn test_histogram() {
+        let mut histogram = Histogram::default();
+        assert_eq!((histogram.min(), histogram.max()), (None, None));
+        assert_eq!(histogram.percentile(0.5), None);
+        for value in [10, 20, 20, 30, 200] {
+            histogram.add(value);
+        }
+        assert_eq!(histogram.count(), 5);
+        assert!((histogram.mean() - 56.0).abs() < 1e-3);
+        assert_eq!((histogram.min(), histogram.max()), (Some(10), Some(200)));
+        assert_eq!(histogram.percentile(0.5), Some(20));
+        assert_eq!(histogram.percentile(0.0), Some(10));
+        assert_eq!(histogram.percentile(1.0), Some(200));
+    }
+
+    #[test]
+    fn test_solid_frame() {
+        let format = Format::new(64, 48, FourCC::YUYV);
+        let data = generate_test_frame(&format, TestPattern::Solid(126, 128, 128));
+        let stats = stats(&format, &data);
+
+        assert_eq!(stats.luma.bins()[126], 64 * 48);
+        assert_eq!((stats.luma.min(), stats.luma.max()), (Some(126), Some(126)));
+        assert!(stats
+            .mean_rgb()
+            .iter()
+            .all(|&mean| (mean - 128.0).abs() < 1.0));
+        assert!(stats.clipped.abs() < f32::EPSILON && stats.black.abs() < f32::EPSILON);
+        assert!(stats.sharpness.abs() < f32::EPSILON);
+
+        assert_eq!(stats.regions.len(), 16);
+        let last = stats.regions.last().expect("grid should have regions");
+        assert_eq!([last.x, last.y, last.width, last.height], [48, 36, 16, 12]);
+        assert!(stats
+            .regions
+            .iter()
+            .all(|region| (region.luma - 126.0).abs() < 1e-3));
+    }
+
+    #[test]
+    fn test_clipped_and_black() {
+        let format = Format::new(64, 48, FourCC::YUYV);
+        let data = generate_test_frame(&format, TestPattern::ColorBars);
+        let config = StatsConfig::default().with_levels(5, 250);
+        let stats = FrameStats::compute(&data, &format, &config).expect("stats should compute");
+        // Every bar but black saturates a channel
+        assert!((stats.clipped - 87.5).abs() < 1e-3, "{}", stats.clipped);
+        assert!((stats.black - 12.5).abs() < 1e-3, "{}", stats.black);
+        assert_eq!(stats.neutral_rgb, None);
+        assert_eq!((stats.luma.min(), stats.luma.max()), (Some(16), Some(235)));
+
+        let config = StatsConfig::default();
+        let format = Format::new(64, 48, FourCC::GREY);
+        let data: Vec<u8> = (0..64 * 48).map(|index| [0, 100, 255][index % 3]).collect();
+        let stats = FrameStats::compute(&data, &format, &config).expect("stats should compute");
+        assert!((stats.clipped - 100.0 / 3.0).abs() < 1e-3);
+        assert!((stats.black - 100.0 / 3.0).abs() < 1e-3);
+        assert_eq!(stats.neutral_rgb, Some([100.0; 3]));
+    }
+
+    #[test]
+    fn test_regions_follow_gradient() {
+        let format = Format::new(64, 48, FourCC::YUYV);
+        let data = generate_test_frame(&format, TestPattern::Gradient);
+        let stats = FrameStats::compute(&data, &format, &StatsConfig::new(4, 1))
+            .expect("stats should compute");
+
+        assert_eq!(stats.regions.len(), 4);
+        assert!(stats
+            .regions
+            .windows(2)
+            .all(|pair| pair[0].luma < pair[1].luma && pair[0].x < pair[1].x));
+        assert!(stats.regions.iter().all(|region| region.height == 48));
+    }
+
+    #[test]
+    fn test_sharpness_drops_with_blur() {
+        let format = Format::new(32, 32, FourCC::GREY);
+        let sharp: Vec<u8> = (0..32 * 32)
+            .map(|index| {
+                if (index % 32 / 4 + index / 32 / 4) % 2 == 0 {
+                    40
+                } else {
+                    220
+                }
+            })
+            .collect();
+        let blurred: Vec<u8> = (0..32 * 32)
+            .map(|index| {
+                let (x, y) = (index % 32, index / 32);
+                let sum: u32 = (0usize..3)
+                    .flat_map(|dy| (0usize..3).map(move |dx| (dx, dy)))
+                    .map(|(dx, dy)| {
+                        let x = (x + dx).saturating_sub(1).min(31);
+                        let y = (y + dy).saturating_sub(1).min(31);
+                        u32::from(sharp[y * 32 + x])
+                    })
+                    .sum();
+                u8::try_from(sum / 9).expect("mean should fit u8")
+            })
+            .collect();
+
+        let sharp = stats(&format, &sharp);
+        let blurred = stats(&format, &blurred);
+        assert!(sharp.sharpness > 2.0 * blurred.sharpness);
+        assert!(blurred.sharpness > 0.0);
+        assert!(sharp.regions.iter().all(|region| region.sharpness > 0.0));
+    }
+
+    #[test]
+    fn test_formats_agree() {
+        let reference = Format::new(64, 48, FourCC::YUYV);
+        let expected = stats(
+            &reference,
+            &generate_test_frame(&reference, TestPattern::ColorBars),
+        );
+        for fourcc in [FourCC::UYVY, FourCC::NV12, FourCC::YU12, FourCC::NV61] {
+            let format = Format::new(64, 48, fourcc);
+            let data = generate_test_frame(&format, TestPattern::ColorBars);
+            let stats = stats(&format, &data);
+            assert_eq!(stats.luma, expected.luma, "{fourcc}");
+            for (mean, expected) in stats.mean_rgb().iter().zip(expected.mean_rgb()) {
+                assert!((mean - expected).abs() < 1.0, "{fourcc}");
+            }
+        }
+
+        // RGB luma is full range, so only the color means compare
+        let format = Format::new(64, 48, FourCC::RGB3);
+        let data = generate_test_frame(&format, TestPattern::ColorBars);
+        let stats = stats(&format, &data);
+        for (mean, expected) in stats.mean_rgb().iter().zip(expected.mean_rgb()) {
+            assert!((mean - expected).abs() < 1.0);
+        }
+        assert!((stats.clipped - expected.clipped).abs() < 1e-3);
+
+        // Luma taken from the RGB conversion matches converting the frame again
+        for fourcc in [FourCC::RGB3, FourCC::SRGGB10P] {
+            let format = Format::new(64, 48, fourcc);
+            let data = generate_test_frame(&format, TestPattern::ColorBars);
+            let mut luma = Histogram::default();
+            for &value in &to_luma(&data, &format).expect("frame should convert") {
+                luma.add(value);
+            }
+            let stats = FrameStats::compute(&data, &format, &StatsConfig::default())
+                .expect("stats should compute");
+            assert_eq!(stats.luma, luma, "{fourcc}");
+        }
+    }
+
+    #[test]
+    fn test_short_frame() {
+        let format = Format::new(64, 48, FourCC::YUYV);
+        assert!(FrameStats::compute(&[0; 100], &format, &StatsConfig::default()).is_err());
+    }
+}
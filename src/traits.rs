@@ -1,7 +1,13 @@
 //! Core traits and types for V4L2 camera abstraction.
 
+use std::fmt;
 use std::time::Duration;
 
+use crate::colorspace::{Colorspace, FieldOrder, Quantization, TransferFunction, YcbcrEncoding};
+use crate::controls::{ControlInfo, ControlValue};
+use crate::convert::{planar_pixel, Colorimetry};
+use crate::pixel_format::{self, Layout, PixelFormatInfo};
+
 /// Pixel format representation (e.g., YUYV, MJPG, RGB3).
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct FourCC(pub [u8; 4]);
@@ -19,6 +25,100 @@ impl FourCC {
     pub const MJPG: Self = Self::new(b"MJPG");
     /// RGB3 pixel format (24-bit RGB).
     pub const RGB3: Self = Self::new(b"RGB3");
+    /// YVYU pixel format (4:2:2 packed, Cr before Cb).
+    pub const YVYU: Self = Self::new(b"YVYU");
+    /// UYVY pixel format (4:2:2 packed, chroma first).
+    pub const UYVY: Self = Self::new(b"UYVY");
+    /// VYUY pixel format (4:2:2 packed, Cr first).
+    pub const VYUY: Self = Self::new(b"VYUY");
+    /// BGR3 pixel format (24-bit BGR).
+    pub const BGR3: Self = Self::new(b"BGR3");
+    /// XR24 pixel format (32-bit BGRX).
+    pub const XR24: Self = Self::new(b"XR24");
+    /// AR24 pixel format (32-bit BGRA).
+    pub const AR24: Self = Self::new(b"AR24");
+    /// RGBP pixel format (16-bit RGB 5-6-5, little endian).
+    pub const RGBP: Self = Self::new(b"RGBP");
+    /// GREY pixel format (8-bit luma).
+    pub const GREY: Self = Self::new(b"GREY");
+    /// Y16 pixel format (16-bit luma, little endian).
+    pub const Y16: Self = Self::new(b"Y16 ");
+    /// NV12 pixel format (4:2:0 semi-planar, Cb before Cr).
+    pub const NV12: Self = Self::new(b"NV12");
+    /// NV21 pixel format (4:2:0 semi-planar, Cr before Cb).
+    pub const NV21: Self = Self::new(b"NV21");
+    /// NV16 pixel format (4:2:2 semi-planar, Cb before Cr).
+    pub const NV16: Self = Self::new(b"NV16");
+    /// NV61 pixel format (4:2:2 semi-planar, Cr before Cb).
+    pub const NV61: Self = Self::new(b"NV61");
+    /// YU12 pixel format (4:2:0 planar, Y/Cb/Cr).
+    pub const YU12: Self = Self::new(b"YU12");
+    /// YV12 pixel format (4:2:0 planar, Y/Cr/Cb).
+    pub const YV12: Self = Self::new(b"YV12");
+    /// 422P pixel format (4:2:2 planar, Y/Cb/Cr).
+    pub const YUV422P: Self = Self::new(b"422P");
+    /// JPEG pixel format (still JPEG).
+    pub const JPEG: Self = Self::new(b"JPEG");
+    /// SBGGR8 pixel format (8-bit Bayer BGGR).
+    pub const SBGGR8: Self = Self::new(b"BA81");
+    /// SGBRG8 pixel format (8-bit Bayer GBRG).
+    pub const SGBRG8: Self = Self::new(b"GBRG");
+    /// SGRBG8 pixel format (8-bit Bayer GRBG).
+    pub const SGRBG8: Self = Self::new(b"GRBG");
+    /// SRGGB8 pixel format (8-bit Bayer RGGB).
+    pub const SRGGB8: Self = Self::new(b"RGGB");
+    /// SBGGR10 pixel format (10-bit Bayer BGGR, little endian 16-bit words).
+    pub const SBGGR10: Self = Self::new(b"BG10");
+    /// SGBRG10 pixel format (10-bit Bayer GBRG, little endian 16-bit words).
+    pub const SGBRG10: Self = Self::new(b"GB10");
+    /// SGRBG10 pixel format (10-bit Bayer GRBG, little endian 16-bit words).
+    pub const SGRBG10: Self = Self::new(b"BA10");
+    /// SRGGB10 pixel format (10-bit Bayer RGGB, little endian 16-bit words).
+    pub const SRGGB10: Self = Self::new(b"RG10");
+    /// SBGGR10P pixel format (10-bit Bayer BGGR, CSI-2 packed).
+    pub const SBGGR10P: Self = Self::new(b"pBAA");
+    /// SGBRG10P pixel format (10-bit Bayer GBRG, CSI-2 packed).
+    pub const SGBRG10P: Self = Self::new(b"pGAA");
+    /// SGRBG10P pixel format (10-bit Bayer GRBG, CSI-2 packed).
+    pub const SGRBG10P: Self = Self::new(b"pgAA");
+    /// SRGGB10P pixel format (10-bit Bayer RGGB, CSI-2 packed).
+    pub const SRGGB10P: Self = Self::new(b"pRAA");
+    /// SBGGR12 pixel format (12-bit Bayer BGGR, little endian 16-bit words).
+    pub const SBGGR12: Self = Self::new(b"BG12");
+    /// SGBRG12 pixel format (12-bit Bayer GBRG, little endian 16-bit words).
+    pub const SGBRG12: Self = Self::new(b"GB12");
+    /// SGRBG12 pixel format (12-bit Bayer GRBG, little endian 16-bit words).
+    pub const SGRBG12: Self = Self::new(b"BA12");
+    /// SRGGB12 pixel format (12-bit Bayer RGGB, little endian 16-bit words).
+    pub const SRGGB12: Self = Self::new(b"RG12");
+    /// SBGGR12P pixel format (12-bit Bayer BGGR, CSI-2 packed).
+    pub const SBGGR12P: Self = Self::new(b"pBCC");
+    /// SGBRG12P pixel format (12-bit Bayer GBRG, CSI-2 packed).
+    pub const SGBRG12P: Self = Self::new(b"pGCC");
+    /// SGRBG12P pixel format (12-bit Bayer GRBG, CSI-2 packed).
+    pub const SGRBG12P: Self = Self::new(b"pgCC");
+    /// SRGGB12P pixel format (12-bit Bayer RGGB, CSI-2 packed).
+    pub const SRGGB12P: Self = Self::new(b"pRCC");
+    /// SBGGR16 pixel format (16-bit Bayer BGGR, little endian 16-bit words).
+    pub const SBGGR16: Self = Self::new(b"BYR2");
+    /// SGBRG16 pixel format (16-bit Bayer GBRG, little endian 16-bit words).
+    pub const SGBRG16: Self = Self::new(b"GB16");
+    /// SGRBG16 pixel format (16-bit Bayer GRBG, little endian 16-bit words).
+    pub const SGRBG16: Self = Self::new(b"GR16");
+    /// SRGGB16 pixel format (16-bit Bayer RGGB, little endian 16-bit words).
+    pub const SRGGB16: Self = Self::new(b"RG16");
+
+    /// Memory layout descriptor of this pixel format, if known.
+    #[must_use]
+    pub const fn info(self) -> Option<&'static PixelFormatInfo> {
+        pixel_format::lookup(self)
+    }
+}
+
+impl fmt::Display for FourCC {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", String::from_utf8_lossy(&self.0))
+    }
 }
 
 impl From<v4l::FourCC> for FourCC {
@@ -46,26 +146,227 @@ pub struct Format {
     pub stride: u32,
     /// Total frame size in bytes.
     pub size: u32,
+    /// Color primaries.
+    pub colorspace: Colorspace,
+    /// Transfer function.
+    pub xfer: TransferFunction,
+    /// YUV encoding.
+    pub ycbcr_enc: YcbcrEncoding,
+    /// Quantization range.
+    pub quantization: Quantization,
+    /// Field order.
+    pub field: FieldOrder,
 }
 
+/// Bytes per pixel assumed for pixel formats missing from the descriptor table.
+const FALLBACK_BYTES_PER_PIXEL: u32 = 2;
+
 impl Format {
     /// Create a new format specification.
+    ///
+    /// Stride and size are the minimum values for the pixel format, as computed
+    /// from its descriptor. Formats missing from the descriptor table are
+    /// assumed to be packed with 2 bytes per pixel. The colorimetry fields
+    /// are left at their defaults.
     #[must_use]
     pub const fn new(width: u32, height: u32, fourcc: FourCC) -> Self {
-        let stride = width * 2; // YUYV is 2 bytes per pixel
-        let size = stride * height;
+        let (stride, size) = match fourcc.info() {
+            Some(info) => {
+                let stride = info.min_stride(width);
+                (stride, info.frame_size(width, height, stride))
+            }
+            None => (
+                width * FALLBACK_BYTES_PER_PIXEL,
+                width * FALLBACK_BYTES_PER_PIXEL * height,
+            ),
+        };
         Self {
             width,
             height,
             fourcc,
             stride,
             size,
+            colorspace: Colorspace::Default,
+            xfer: TransferFunction::Default,
+            ycbcr_enc: YcbcrEncoding::Default,
+            quantization: Quantization::Default,
+            field: FieldOrder::Any,
+        }
+    }
+
+    /// YUV to RGB conversion described by the colorimetry fields.
+    ///
+    /// Defaults are resolved as V4L2 does: the encoding follows the
+    /// colorspace, and the range is full for RGB formats and the JPEG
+    /// colorspace and limited otherwise. A format built with [`Format::new`]
+    /// therefore converts as limited range BT.601.
+    #[must_use]
+    pub const fn colorimetry(&self) -> Colorimetry {
+        let is_rgb = matches!(
+            self.fourcc,
+            FourCC::RGB3 | FourCC::BGR3 | FourCC::XR24 | FourCC::AR24 | FourCC::RGBP
+        );
+        Colorimetry::new(
+            self.ycbcr_enc.resolve(self.colorspace).matrix(),
+            self.quantization.resolve(self.colorspace, is_rgb).range(),
+        )
+    }
+}
+
+/// Description of a pixel format supported by a device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FormatDescription {
+    /// Pixel format.
+    pub fourcc: FourCC,
+    /// Human-readable description reported by the driver.
+    pub description: String,
+    /// Whether the format is compressed (e.g., MJPG).
+    pub compressed: bool,
+}
+
+/// Frame size supported for a pixel format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameSize {
+    /// A single fixed resolution.
+    Discrete {
+        /// Frame width in pixels.
+        width: u32,
+        /// Frame height in pixels.
+        height: u32,
+    },
+    /// A range of resolutions. Continuous ranges are reported with a step of 1.
+    Stepwise {
+        /// Minimum frame width in pixels.
+        min_width: u32,
+        /// Maximum frame width in pixels.
+        max_width: u32,
+        /// Frame width step in pixels.
+        step_width: u32,
+        /// Minimum frame height in pixels.
+        min_height: u32,
+        /// Maximum frame height in pixels.
+        max_height: u32,
+        /// Frame height step in pixels.
+        step_height: u32,
+    },
+}
+
+impl FrameSize {
+    /// Check whether the given resolution is covered by this frame size.
+    #[must_use]
+    pub const fn contains(&self, width: u32, height: u32) -> bool {
+        match *self {
+            Self::Discrete {
+                width: w,
+                height: h,
+            } => w == width && h == height,
+            Self::Stepwise {
+                min_width,
+                max_width,
+                step_width,
+                min_height,
+                max_height,
+                step_height,
+            } => {
+                in_steps(width, min_width, max_width, step_width)
+                    && in_steps(height, min_height, max_height, step_height)
+            }
+        }
+    }
+}
+
+/// Check whether `value` lies in `min..=max` on a multiple of `step` from `min`.
+const fn in_steps(value: u32, min: u32, max: u32, step: u32) -> bool {
+    if value < min || value > max {
+        return false;
+    }
+    step == 0 || (value - min) % step == 0
+}
+
+/// A time span expressed as a fraction of seconds (e.g., 1/30 for a frame interval).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fraction {
+    /// Numerator.
+    pub numerator: u32,
+    /// Denominator.
+    pub denominator: u32,
+}
+
+impl Fraction {
+    /// Create a new fraction.
+    #[must_use]
+    pub const fn new(numerator: u32, denominator: u32) -> Self {
+        Self {
+            numerator,
+            denominator,
+        }
+    }
+
+    /// Create a frame interval for the given frame rate (e.g., 30 -> 1/30).
+    #[must_use]
+    pub const fn from_fps(fps: u32) -> Self {
+        Self::new(1, fps)
+    }
+
+    /// Frame rate corresponding to this frame interval, or 0.0 if the numerator is zero.
+    #[must_use]
+    pub fn fps(&self) -> f64 {
+        if self.numerator == 0 {
+            return 0.0;
+        }
+        f64::from(self.denominator) / f64::from(self.numerator)
+    }
+
+    /// Duration of `count` intervals, or `None` if the denominator is zero.
+    #[must_use]
+    pub fn duration_of(&self, count: u32) -> Option<Duration> {
+        if self.denominator == 0 {
+            return None;
         }
+        let nanos = u128::from(count) * u128::from(self.numerator) * 1_000_000_000
+            / u128::from(self.denominator);
+        Some(Duration::from_nanos(
+            u64::try_from(nanos).unwrap_or(u64::MAX),
+        ))
     }
 }
 
+impl fmt::Display for Fraction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.numerator, self.denominator)
+    }
+}
+
+impl From<v4l::Fraction> for Fraction {
+    fn from(fraction: v4l::Fraction) -> Self {
+        Self::new(fraction.numerator, fraction.denominator)
+    }
+}
+
+impl From<Fraction> for v4l::Fraction {
+    fn from(fraction: Fraction) -> Self {
+        Self::new(fraction.numerator, fraction.denominator)
+    }
+}
+
+/// Frame interval supported for a pixel format and frame size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameInterval {
+    /// A single fixed interval.
+    Discrete(Fraction),
+    /// A range of intervals. Continuous ranges are reported with a step of 1/1.
+    Stepwise {
+        /// Shortest frame interval (highest frame rate).
+        min: Fraction,
+        /// Longest frame interval (lowest frame rate).
+        max: Fraction,
+        /// Interval step.
+        step: Fraction,
+    },
+}
+
 /// Device capability flags.
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct DeviceCapabilities {
     /// Driver name.
     pub driver: String,
@@ -80,7 +381,7 @@ pub struct DeviceCapabilities {
 }
 
 /// Metadata for a captured frame.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct FrameMetadata {
     /// Frame sequence number.
     pub sequence: u32,
@@ -116,8 +417,30 @@ impl Frame {
     ///
     /// This method assumes YUYV format (2 bytes per pixel). For odd x coordinates,
     /// it uses the Y value from the next pixel pair with the shared U/V values.
+    /// Use [`Frame::pixel`] for other pixel formats or padded lines.
+    ///
+    /// Without a [`Format`] there is no colorimetry to go by, so YUV is
+    /// converted as full-range BT.601 ([`Colorimetry::default`]). This differs
+    /// from [`Frame::pixel`], which uses [`Format::colorimetry`]: limited range
+    /// for a format built with [`Format::new`]. Use [`Frame::pixel_at_with`]
+    /// to choose the conversion.
     #[must_use]
     pub fn pixel_at(&self, x: u32, y: u32, width: u32) -> Option<(u8, u8, u8)> {
+        self.pixel_at_with(x, y, width, Colorimetry::default())
+    }
+
+    /// Get RGB values for a pixel of a YUYV frame, converted with
+    /// `colorimetry`.
+    ///
+    /// See [`Frame::pixel_at`].
+    #[must_use]
+    pub fn pixel_at_with(
+        &self,
+        x: u32,
+        y: u32,
+        width: u32,
+        colorimetry: Colorimetry,
+    ) -> Option<(u8, u8, u8)> {
         // YUYV format: [Y0 U Y1 V] repeats
         // Each pair of pixels shares U and V values
 
@@ -140,50 +463,243 @@ impl Frame {
         let v = *self.data.get(offset + 3)?;
 
         // Convert YUV to RGB
-        Some(yuv_to_rgb(y_val, u, v))
+        Some(colorimetry.yuv_to_rgb(y_val, u, v))
+    }
+
+    /// Get RGB values for a pixel, honouring the layout and stride of `format`.
+    ///
+    /// Packed YUV 4:2:2, semi-planar and planar YUV (NV12, YU12, ...), RGB,
+    /// greyscale and raw Bayer formats are supported. Subsampled chroma is
+    /// shared by the pixels it covers; Bayer pixels are demosaiced
+    /// bilinearly. YUV is converted with [`Format::colorimetry`].
+    ///
+    /// With the `mjpeg` feature, MJPEG frames are decoded on every call;
+    /// convert them once with [`convert::to_rgb24`] to read many pixels.
+    ///
+    /// Returns `None` if the coordinates lie outside the frame, the buffer is
+    /// too short or cannot be decoded, or the pixel format is unknown or, in
+    /// builds without the `mjpeg` feature, compressed.
+    ///
+    /// [`convert::to_rgb24`]: crate::convert::to_rgb24
+    #[must_use]
+    pub fn pixel(&self, x: u32, y: u32, format: &Format) -> Option<(u8, u8, u8)> {
+        pixel_in(&self.data, x, y, format, format.colorimetry())
+    }
+
+    /// Get RGB values for a pixel, converting YUV with `colorimetry`.
+    ///
+    /// See [`Frame::pixel`].
+    #[must_use]
+    pub fn pixel_with(
+        &self,
+        x: u32,
+        y: u32,
+        format: &Format,
+        colorimetry: Colorimetry,
+    ) -> Option<(u8, u8, u8)> {
+        pixel_in(&self.data, x, y, format, colorimetry)
+    }
+
+    /// Borrow this frame as a [`FrameRef`].
+    #[must_use]
+    pub fn as_frame_ref(&self) -> FrameRef<'_> {
+        FrameRef::new(&self.data, self.metadata.clone())
     }
 }
 
-/// Convert YUV values to RGB.
-///
-/// Uses the ITU-R BT.601 conversion formula.
-///
-/// # Arguments
-///
-/// * `y` - Luminance value (16-235 for studio range)
-/// * `u` - Blue-difference chroma value (16-240)
-/// * `v` - Red-difference chroma value (16-240)
-///
-/// # Returns
+/// A stream that lends out its buffers as [`FrameRef`]s.
+pub(crate) trait BufferQueue: Sync {
+    /// Hand buffer `index` back to be filled again.
+    fn requeue(&self, index: usize);
+}
+
+/// The stream buffer a [`FrameRef`] holds until it is dropped.
+struct HeldBuffer<'a> {
+    queue: &'a dyn BufferQueue,
+    index: usize,
+}
+
+impl fmt::Debug for HeldBuffer<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HeldBuffer")
+            .field("index", &self.index)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A captured video frame borrowed from a stream buffer.
 ///
-/// RGB tuple with values clamped to 0-255 range.
-#[must_use]
-#[allow(clippy::many_single_char_names)]
-fn yuv_to_rgb(y: u8, u: u8, v: u8) -> (u8, u8, u8) {
-    // ITU-R BT.601 conversion
-    let y_f = f32::from(y);
-    let u_f = f32::from(u) - 128.0;
-    let v_f = f32::from(v) - 128.0;
-
-    let r = 1.402f32.mul_add(v_f, y_f);
-    let g = 0.714_14f32.mul_add(-v_f, 0.344_14f32.mul_add(-u_f, y_f));
-    let b = 1.772f32.mul_add(u_f, y_f);
-
-    let clamp = |val: f32| -> u8 {
-        if val < 0.0 {
-            0
-        } else if val > 255.0 {
-            255
-        } else {
-            #[allow(clippy::cast_possible_truncation)]
-            #[allow(clippy::cast_sign_loss)]
-            {
-                val as u8
-            }
+/// The view holds the dequeued buffer exclusively through a mutable borrow of
+/// the stream, so no further frame can be captured until it is dropped.
+/// Dropping the view requeues the buffer, after which the driver may refill
+/// it: `data` must not be kept beyond the view.
+#[derive(Debug)]
+pub struct FrameRef<'a> {
+    /// Raw frame data, borrowed from the stream buffer up to the bytes the
+    /// driver reported as used.
+    pub data: &'a [u8],
+    /// Frame metadata.
+    pub metadata: FrameMetadata,
+    /// Buffer requeued on drop, if `data` lies in one.
+    held: Option<HeldBuffer<'a>>,
+}
+
+impl<'a> FrameRef<'a> {
+    /// Create a view of `data` that holds no stream buffer.
+    #[must_use]
+    pub const fn new(data: &'a [u8], metadata: FrameMetadata) -> Self {
+        Self {
+            data,
+            metadata,
+            held: None,
+        }
+    }
+
+    /// Create a view of buffer `index` of `queue`, requeued when dropped.
+    pub(crate) fn in_buffer(
+        data: &'a [u8],
+        metadata: FrameMetadata,
+        queue: &'a dyn BufferQueue,
+        index: usize,
+    ) -> Self {
+        Self {
+            data,
+            metadata,
+            held: Some(HeldBuffer { queue, index }),
+        }
+    }
+}
+
+impl Drop for FrameRef<'_> {
+    fn drop(&mut self) {
+        if let Some(held) = self.held.take() {
+            held.queue.requeue(held.index);
+        }
+    }
+}
+
+impl FrameRef<'_> {
+    /// Get RGB values for a pixel, honouring the layout and stride of `format`.
+    ///
+    /// See [`Frame::pixel`].
+    #[must_use]
+    pub fn pixel(&self, x: u32, y: u32, format: &Format) -> Option<(u8, u8, u8)> {
+        pixel_in(self.data, x, y, format, format.colorimetry())
+    }
+
+    /// Get RGB values for a pixel, converting YUV with `colorimetry`.
+    ///
+    /// See [`Frame::pixel`].
+    #[must_use]
+    pub fn pixel_with(
+        &self,
+        x: u32,
+        y: u32,
+        format: &Format,
+        colorimetry: Colorimetry,
+    ) -> Option<(u8, u8, u8)> {
+        pixel_in(self.data, x, y, format, colorimetry)
+    }
+
+    /// Copy the borrowed data into an owned [`Frame`].
+    #[must_use]
+    pub fn to_frame(&self) -> Frame {
+        Frame {
+            data: self.data.to_vec(),
+            metadata: self.metadata.clone(),
         }
+    }
+}
+
+/// Decode a JPEG frame and return the pixel at (`x`, `y`).
+#[cfg(feature = "mjpeg")]
+fn jpeg_pixel(data: &[u8], x: u32, y: u32, format: &Format) -> Option<(u8, u8, u8)> {
+    let rgb = crate::mjpeg::decode_frame(data, format, FourCC::RGB3).ok()?;
+    let offset = (y as usize * format.width as usize + x as usize) * 3;
+    match *rgb.get(offset..offset + 3)? {
+        [red, green, blue] => Some((red, green, blue)),
+        _ => None,
+    }
+}
+
+/// Compressed pixels are unavailable without the `mjpeg` feature.
+#[cfg(not(feature = "mjpeg"))]
+const fn jpeg_pixel(_data: &[u8], _x: u32, _y: u32, _format: &Format) -> Option<(u8, u8, u8)> {
+    None
+}
+
+/// Locate and decode a pixel in a frame buffer laid out as `format`.
+pub(crate) fn pixel_in(
+    data: &[u8],
+    x: u32,
+    y: u32,
+    format: &Format,
+    colorimetry: Colorimetry,
+) -> Option<(u8, u8, u8)> {
+    if x >= format.width || y >= format.height {
+        return None;
+    }
+    let info = format.fourcc.info()?;
+    match info.layout {
+        Layout::SemiPlanar | Layout::Planar => {
+            return planar_pixel(data, x, y, format, info, colorimetry);
+        }
+        Layout::Compressed => return jpeg_pixel(data, x, y, format),
+        Layout::Bayer => return crate::bayer::pixel(data, x, y, format),
+        Layout::Packed => {}
+    }
+
+    let line = y as usize * format.stride as usize;
+    if info.chroma_h_subsampling == 2 {
+        let start = line + (x & !1) as usize * 2;
+        let group = data.get(start..start + 4)?;
+        let (y0, u, y1, v) = unpack_yuv422(format.fourcc, group)?;
+        let luma = if x % 2 == 0 { y0 } else { y1 };
+        Some(colorimetry.yuv_to_rgb(luma, u, v))
+    } else {
+        let bytes_per_pixel = info.bytes_per_pixel() as usize;
+        let start = line + x as usize * bytes_per_pixel;
+        unpack_rgb(format.fourcc, data.get(start..start + bytes_per_pixel)?)
+    }
+}
+
+/// Split a packed 4:2:2 pixel pair into `(y0, u, y1, v)`.
+pub(crate) fn unpack_yuv422(fourcc: FourCC, group: &[u8]) -> Option<(u8, u8, u8, u8)> {
+    let &[a, b, c, d] = group else {
+        return None;
     };
+    match fourcc {
+        FourCC::YUYV => Some((a, b, c, d)),
+        FourCC::YVYU => Some((a, d, c, b)),
+        FourCC::UYVY => Some((b, a, d, c)),
+        FourCC::VYUY => Some((b, c, d, a)),
+        _ => None,
+    }
+}
 
-    (clamp(r), clamp(g), clamp(b))
+/// Decode a single packed RGB or greyscale pixel.
+fn unpack_rgb(fourcc: FourCC, pixel: &[u8]) -> Option<(u8, u8, u8)> {
+    match (fourcc, pixel) {
+        (FourCC::RGB3, &[r, g, b]) => Some((r, g, b)),
+        (FourCC::BGR3, &[b, g, r]) | (FourCC::XR24 | FourCC::AR24, &[b, g, r, _]) => {
+            Some((r, g, b))
+        }
+        (FourCC::RGBP, &[lo, hi]) => {
+            let value = u16::from_le_bytes([lo, hi]);
+            // Replicate the high bits into the low bits to span 0-255
+            let expand = |bits: u16, width: u32| -> u8 {
+                let wide = bits << (8 - width) | bits >> (2 * width - 8);
+                u8::try_from(wide).unwrap_or(u8::MAX)
+            };
+            Some((
+                expand(value >> 11, 5),
+                expand((value >> 5) & 0x3f, 6),
+                expand(value & 0x1f, 5),
+            ))
+        }
+        (FourCC::GREY, &[luma]) | (FourCC::Y16, &[_, luma]) => Some((luma, luma, luma)),
+        _ => None,
+    }
 }
 
 /// Error type for camera operations.
@@ -191,25 +707,58 @@ fn yuv_to_rgb(y: u8, u: u8, v: u8) -> (u8, u8, u8) {
 pub enum CameraError {
     /// Device with given index was not found.
     DeviceNotFound(u32),
+    /// No device matched the given selection criteria.
+    NoMatchingDevice(String),
     /// Failed to open device.
     DeviceOpenFailed(String),
     /// Requested format is not supported.
-    FormatNotSupported(Format),
+    ///
+    /// This was a tuple variant holding only the format. It carries the
+    /// reason as well so that a failed [`negotiate`](crate::negotiate) can
+    /// say why nothing matched; matches on `FormatNotSupported(format)` must
+    /// become `FormatNotSupported { format, .. }`.
+    FormatNotSupported {
+        /// The requested format.
+        format: Format,
+        /// Why the format cannot be used.
+        reason: String,
+    },
     /// Error during streaming operation.
     StreamError(String),
+    /// Control with given ID does not exist on the device.
+    ControlNotFound(u32),
+    /// Failed to read or write a control.
+    ControlError(String),
     /// Operation timed out.
     Timeout,
     /// I/O error.
     Io(std::io::Error),
 }
 
+impl CameraError {
+    /// I/O error for malformed file contents.
+    pub(crate) fn invalid_data(message: String) -> Self {
+        Self::Io(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            message,
+        ))
+    }
+}
+
 impl std::fmt::Display for CameraError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::DeviceNotFound(idx) => write!(f, "Device {idx} not found"),
+            Self::NoMatchingDevice(criteria) => write!(f, "No device matching {criteria}"),
             Self::DeviceOpenFailed(msg) => write!(f, "Failed to open device: {msg}"),
-            Self::FormatNotSupported(fmt) => write!(f, "Format not supported: {fmt:?}"),
+            Self::FormatNotSupported { format, reason } => write!(
+                f,
+                "Format {}x{} {} not supported: {reason}",
+                format.width, format.height, format.fourcc
+            ),
             Self::StreamError(msg) => write!(f, "Stream error: {msg}"),
+            Self::ControlNotFound(id) => write!(f, "Control {id:#010x} not found"),
+            Self::ControlError(msg) => write!(f, "Control error: {msg}"),
             Self::Timeout => write!(f, "Operation timed out"),
             Self::Io(err) => write!(f, "I/O error: {err}"),
         }
@@ -243,12 +792,191 @@ pub trait CameraDevice {
     /// Set capture format. Returns the actual format set by the driver.
     fn set_format(&mut self, format: &Format) -> Result<Format>;
 
+    /// Get the current capture frame interval (time per frame).
+    fn frame_interval(&self) -> Result<Fraction>;
+
+    /// Set the capture frame interval. Returns the interval granted by the driver,
+    /// which may differ from the requested one.
+    fn set_frame_interval(&mut self, interval: Fraction) -> Result<Fraction>;
+
+    /// Enumerate the pixel formats supported for capture.
+    fn supported_formats(&self) -> Result<Vec<FormatDescription>>;
+
+    /// Enumerate the frame sizes supported for a pixel format.
+    fn frame_sizes(&self, fourcc: FourCC) -> Result<Vec<FrameSize>>;
+
+    /// Enumerate the frame intervals supported for a pixel format and frame size.
+    fn frame_intervals(
+        &self,
+        fourcc: FourCC,
+        width: u32,
+        height: u32,
+    ) -> Result<Vec<FrameInterval>>;
+
+    /// Enumerate the controls exposed by the device.
+    fn controls(&self) -> Result<Vec<ControlInfo>>;
+
+    /// Read the current value of a control.
+    fn control(&self, id: u32) -> Result<ControlValue>;
+
+    /// Write a single control value.
+    fn set_control(&mut self, id: u32, value: ControlValue) -> Result<()> {
+        self.set_controls(&[(id, value)])
+    }
+
+    /// Write several control values at once using extended controls.
+    ///
+    /// Controls of the same class are applied atomically: either all of them
+    /// are set or none is.
+    fn set_controls(&mut self, controls: &[(u32, ControlValue)]) -> Result<()>;
+
     /// Create a capture stream with the specified number of buffers.
     fn create_stream(&mut self, buffer_count: u32) -> Result<Self::Stream<'_>>;
 }
 
 /// Abstraction over capture stream operations.
 pub trait CaptureStream {
-    /// Capture the next frame from the stream.
-    fn next_frame(&mut self) -> Result<Frame>;
+    /// Capture the next frame without copying it out of the stream buffer.
+    ///
+    /// The returned view borrows the stream; drop it before capturing again.
+    /// Dropping it hands the buffer back to the driver.
+    fn next_frame_ref(&mut self) -> Result<FrameRef<'_>>;
+
+    /// Capture the next frame without copying, waiting at most `timeout`.
+    ///
+    /// Returns `Timeout` if no frame arrives in time. A zero timeout only
+    /// returns a frame that is already available.
+    fn next_frame_ref_timeout(&mut self, timeout: Duration) -> Result<FrameRef<'_>>;
+
+    /// Capture the next frame into an owned buffer.
+    fn next_frame(&mut self) -> Result<Frame> {
+        self.next_frame_ref().map(|frame| frame.to_frame())
+    }
+
+    /// Capture the next frame into an owned buffer, waiting at most `timeout`.
+    ///
+    /// Returns `Timeout` if no frame arrives in time.
+    fn next_frame_timeout(&mut self, timeout: Duration) -> Result<Frame> {
+        self.next_frame_ref_timeout(timeout)
+            .map(|frame| frame.to_frame())
+    }
+
+    /// Capture a frame only if one is already available, without blocking.
+    ///
+    /// Returns `Ok(None)` when no frame is ready yet.
+    fn try_next_frame(&mut self) -> Result<Option<Frame>> {
+        match self.next_frame_ref_timeout(Duration::ZERO) {
+            Ok(frame) => Ok(Some(frame.to_frame())),
+            Err(CameraError::Timeout) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fourcc_display() {
+        assert_eq!(FourCC::YUYV.to_string(), "YUYV");
+        assert_eq!(FourCC::MJPG.to_string(), "MJPG");
+    }
+
+    #[test]
+    fn test_fraction_fps() {
+        assert_eq!(Fraction::from_fps(30), Fraction::new(1, 30));
+        assert!((Fraction::new(1001, 30000).fps() - 29.97).abs() < 0.01);
+        assert!(Fraction::new(0, 1).fps().abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_fraction_duration_of() {
+        let interval = Fraction::from_fps(30);
+        assert_eq!(interval.duration_of(0), Some(Duration::ZERO));
+        assert_eq!(interval.duration_of(3), Some(Duration::from_millis(100)));
+        assert_eq!(Fraction::new(1, 0).duration_of(1), None);
+    }
+
+    #[test]
+    fn test_frame_size_discrete_contains() {
+        let size = FrameSize::Discrete {
+            width: 640,
+            height: 480,
+        };
+        assert!(size.contains(640, 480));
+        assert!(!size.contains(640, 360));
+    }
+
+    #[test]
+    fn test_frame_size_stepwise_contains() {
+        let size = FrameSize::Stepwise {
+            min_width: 16,
+            max_width: 1920,
+            step_width: 16,
+            min_height: 16,
+            max_height: 1080,
+            step_height: 8,
+        };
+        assert!(size.contains(640, 480));
+        assert!(size.contains(1920, 1080));
+        assert!(!size.contains(650, 480), "width off-step");
+        assert!(!size.contains(3840, 2160), "out of range");
+        assert!(!size.contains(8, 8), "below minimum");
+    }
+
+    #[test]
+    fn test_format_new_uses_descriptor() {
+        let rgb = Format::new(640, 480, FourCC::RGB3);
+        assert_eq!((rgb.stride, rgb.size), (1920, 921_600));
+
+        let nv12 = Format::new(640, 480, FourCC::NV12);
+        assert_eq!((nv12.stride, nv12.size), (640, 460_800));
+
+        let unknown = Format::new(640, 480, FourCC::new(b"ZZZZ"));
+        assert_eq!((unknown.stride, unknown.size), (1280, 614_400));
+    }
+
+    #[test]
+    fn test_pixel_honours_stride() {
+        let mut format = Format::new(2, 2, FourCC::RGB3);
+        format.stride = 8;
+        let frame = Frame {
+            data: vec![
+                0, 0, 0, 1, 2, 3, 0xee, 0xee, //
+                4, 5, 6, 7, 8, 9, 0xee, 0xee,
+            ],
+            metadata: FrameMetadata {
+                sequence: 0,
+                timestamp: Duration::ZERO,
+                bytes_used: 16,
+            },
+        };
+
+        assert_eq!(frame.pixel(1, 0, &format), Some((1, 2, 3)));
+        assert_eq!(frame.pixel(0, 1, &format), Some((4, 5, 6)));
+        assert_eq!(frame.pixel(2, 0, &format), None, "outside the frame");
+    }
+
+    #[test]
+    fn test_pixel_rgb565_and_grey() {
+        let metadata = FrameMetadata {
+            sequence: 0,
+            timestamp: Duration::ZERO,
+            bytes_used: 2,
+        };
+        let white = Frame {
+            data: vec![0xff, 0xff],
+            metadata,
+        };
+        assert_eq!(
+            white.pixel(0, 0, &Format::new(1, 1, FourCC::RGBP)),
+            Some((255, 255, 255))
+        );
+        assert_eq!(
+            white.pixel(1, 0, &Format::new(2, 1, FourCC::GREY)),
+            Some((255, 255, 255))
+        );
+        assert_eq!(white.pixel(0, 0, &Format::new(1, 1, FourCC::NV12)), None);
+    }
 }
@@ -5,36 +5,39 @@
 
 use crate::traits::{CameraError, Format, Frame, Result};
 
-/// Expected RGB values for SMPTE color bars (8 bars).
+/// Expected RGB values for 100% SMPTE color bars (8 bars).
 ///
-/// These are the RGB values resulting from converting the YUV values
-/// used by the mock device's color bar pattern.
+/// Frames are converted with the colorimetry of their format, so the limited
+/// range YUV bars of the mock device and vivid decode to full strength.
 ///
 /// Colors in order: White, Yellow, Cyan, Green, Magenta, Red, Blue, Black
 const SMPTE_COLOR_BARS: [(u8, u8, u8); 8] = [
-    (235, 235, 235), // White
-    (235, 235, 11),  // Yellow
-    (12, 236, 237),  // Cyan
-    (13, 237, 13),   // Green
-    (237, 13, 237),  // Magenta
-    (238, 14, 13),   // Red
-    (15, 15, 239),   // Blue
-    (16, 16, 16),    // Black
+    (255, 255, 255), // White
+    (255, 255, 0),   // Yellow
+    (0, 255, 255),   // Cyan
+    (0, 255, 0),     // Green
+    (255, 0, 255),   // Magenta
+    (255, 0, 0),     // Red
+    (0, 0, 255),     // Blue
+    (0, 0, 0),       // Black
 ];
 
-/// Tolerance for RGB color matching (accounts for YUV->RGB conversion errors).
-const COLOR_TOLERANCE: i32 = 15;
+/// Tolerance for RGB color matching (accounts for rounding of the 8-bit YUV
+/// samples).
+const COLOR_TOLERANCE: i32 = 4;
 
 /// Validates that a frame contains the SMPTE color bar pattern.
 ///
 /// This function checks 8 vertical stripes at their center positions,
 /// verifying that each stripe contains the expected color with a tolerance
-/// for YUV-to-RGB conversion inaccuracies.
+/// for YUV-to-RGB conversion inaccuracies. Pixels are read with
+/// [`Frame::pixel`], so packed, semi-planar and planar formats can all be
+/// validated, as can MJPEG with the `mjpeg` feature.
 ///
 /// # Arguments
 ///
 /// * `frame` - The frame to validate
-/// * `format` - The frame format (contains width and height)
+/// * `format` - The frame format (geometry and colorimetry)
 ///
 /// # Returns
 ///
@@ -47,6 +50,10 @@ const COLOR_TOLERANCE: i32 = 15;
 /// - The frame dimensions don't match the format
 /// - Any color bar doesn't match the expected color within tolerance
 pub fn validate_color_bars(frame: &Frame, format: &Format) -> Result<()> {
+    #[cfg(feature = "mjpeg")]
+    if let Some((frame, format)) = decode_jpeg(frame, format)? {
+        return validate_color_bars(&frame, &format);
+    }
     let width = format.width;
     let height = format.height;
     let bar_width = width / 8;
@@ -57,7 +64,7 @@ pub fn validate_color_bars(frame: &Frame, format: &Format) -> Result<()> {
         #[allow(clippy::cast_possible_truncation)]
         let sample_x = (bar_idx as u32 * bar_width) + (bar_width / 2);
 
-        let actual_rgb = frame.pixel_at(sample_x, center_y, width).ok_or_else(|| {
+        let actual_rgb = frame.pixel(sample_x, center_y, format).ok_or_else(|| {
             CameraError::StreamError(format!(
                 "Failed to get pixel at ({sample_x}, {center_y})"
             ))
@@ -79,7 +86,9 @@ pub fn validate_color_bars(frame: &Frame, format: &Format) -> Result<()> {
 /// This function samples a horizontal line at the center of the frame and
 /// verifies that the luminance increases monotonically from left to right.
 /// It also checks that there is a significant overall luminance change
-/// across the frame (not a solid color).
+/// across the frame (not a solid color). Like [`validate_color_bars`], it
+/// accepts any packed, semi-planar or planar format, and MJPEG with the
+/// `mjpeg` feature.
 ///
 /// # Arguments
 ///
@@ -98,6 +107,10 @@ pub fn validate_color_bars(frame: &Frame, format: &Format) -> Result<()> {
 /// - The luminance doesn't increase monotonically
 /// - The total luminance change is too small (solid color)
 pub fn validate_gradient(frame: &Frame, format: &Format) -> Result<()> {
+    #[cfg(feature = "mjpeg")]
+    if let Some((frame, format)) = decode_jpeg(frame, format)? {
+        return validate_gradient(&frame, &format);
+    }
     let width = format.width;
     let height = format.height;
     let center_y = height / 2;
@@ -109,7 +122,7 @@ pub fn validate_gradient(frame: &Frame, format: &Format) -> Result<()> {
     let mut last_luminance: Option<f32> = None;
 
     for x in (0..width).step_by(sample_step as usize) {
-        let (r, g, b) = frame.pixel_at(x, center_y, width).ok_or_else(|| {
+        let (r, g, b) = frame.pixel(x, center_y, format).ok_or_else(|| {
             CameraError::StreamError(format!("Failed to get pixel at ({x}, {center_y})"))
         })?;
 
@@ -223,6 +236,21 @@ fn colors_match(actual: (u8, u8, u8), expected: (u8, u8, u8), tolerance: i32) ->
     r_diff <= tol && g_diff <= tol && b_diff <= tol
 }
 
+/// Decode a JPEG frame to RGB once, rather than for every sampled pixel.
+#[cfg(feature = "mjpeg")]
+fn decode_jpeg(frame: &Frame, format: &Format) -> Result<Option<(Frame, Format)>> {
+    if !crate::convert::is_jpeg(format) {
+        return Ok(None);
+    }
+    let data = crate::mjpeg::decode_frame(&frame.data, format, crate::traits::FourCC::RGB3)?;
+    let frame = Frame {
+        data,
+        metadata: frame.metadata.clone(),
+    };
+    let format = Format::new(format.width, format.height, crate::traits::FourCC::RGB3);
+    Ok(Some((frame, format)))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -280,6 +308,66 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_validate_planar_formats() {
+        for fourcc in [FourCC::NV12, FourCC::NV21, FourCC::YU12, FourCC::YV12] {
+            let mut device = MockDevice::new();
+            let format = device
+                .set_format(&Format::new(640, 480, fourcc))
+                .expect("set_format failed");
+
+            let stream = device.create_stream(1).expect("create_stream failed");
+            let mut stream = stream.with_pattern(TestPattern::ColorBars);
+            let frame = stream.next_frame().expect("next_frame failed");
+            let result = validate_color_bars(&frame, &format);
+            assert!(
+                result.is_ok(),
+                "{fourcc} color bars should validate: {result:?}"
+            );
+
+            let mut stream = stream.with_pattern(TestPattern::Gradient);
+            let frame = stream.next_frame().expect("next_frame failed");
+            let result = validate_gradient(&frame, &format);
+            assert!(
+                result.is_ok(),
+                "{fourcc} gradient should validate: {result:?}"
+            );
+        }
+    }
+
+    #[cfg(feature = "mjpeg")]
+    #[test]
+    fn test_validate_mjpeg() {
+        use crate::mock::generate_test_frame;
+        use crate::traits::FrameMetadata;
+
+        let rgb_format = Format::new(640, 480, FourCC::RGB3);
+        let format = Format::new(640, 480, FourCC::MJPG);
+        for pattern in [TestPattern::ColorBars, TestPattern::Gradient] {
+            let rgb = generate_test_frame(&rgb_format, pattern);
+            let mut jpeg = Vec::new();
+            jpeg_encoder::Encoder::new(&mut jpeg, 95)
+                .encode(&rgb, 640, 480, jpeg_encoder::ColorType::Rgb)
+                .expect("encoding failed");
+            // Padding after the end of the image is ignored
+            jpeg.resize(jpeg.len() + 64, 0);
+            let frame = Frame {
+                metadata: FrameMetadata {
+                    sequence: 0,
+                    timestamp: std::time::Duration::ZERO,
+                    bytes_used: u32::try_from(jpeg.len()).expect("frame fits u32"),
+                },
+                data: jpeg,
+            };
+
+            let result = match pattern {
+                TestPattern::ColorBars => validate_color_bars(&frame, &format),
+                _ => validate_gradient(&frame, &format),
+            };
+            assert!(result.is_ok(), "{pattern:?} should validate: {result:?}");
+        }
+    }
+
     #[test]
     fn test_validate_gradient_wrong_pattern() {
         let mut device = MockDevice::new();
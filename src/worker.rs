@@ -0,0 +1,470 @@
+//! Background capture worker.
+//!
+//! [`CaptureWorker`] owns a camera device, runs its capture stream on a
+//! dedicated thread and hands frames to consumers through a bounded queue.
+//! When consumers fall behind, the [`OverflowPolicy`] decides which frames are
+//! kept.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::traits::{CameraDevice, CameraError, CaptureStream, Frame, Result};
+
+/// How often the capture thread checks for a stop request while no frames arrive.
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// What to do with a new frame when the queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Pause capture until a consumer makes room. The driver drops frames
+    /// instead once its buffers run out.
+    Block,
+    /// Discard the oldest queued frame to make room for the new one.
+    DropOldest,
+    /// Discard the new frame and keep the queued ones.
+    DropNewest,
+    /// Keep only the most recent frame, regardless of queue capacity.
+    LatestOnly,
+}
+
+/// Capture worker configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WorkerConfig {
+    /// Maximum number of frames waiting for a consumer.
+    pub capacity: usize,
+    /// Overflow handling when the queue is full.
+    pub policy: OverflowPolicy,
+    /// Number of driver buffers requested for the capture stream.
+    pub buffer_count: u32,
+}
+
+impl Default for WorkerConfig {
+    fn default() -> Self {
+        Self::new(4, OverflowPolicy::DropOldest)
+    }
+}
+
+impl WorkerConfig {
+    /// Create a configuration with the given queue capacity and overflow policy.
+    #[must_use]
+    pub const fn new(capacity: usize, policy: OverflowPolicy) -> Self {
+        Self {
+            capacity,
+            policy,
+            buffer_count: 4,
+        }
+    }
+
+    /// Set the number of driver buffers requested for the capture stream.
+    #[must_use]
+    pub const fn with_buffer_count(mut self, buffer_count: u32) -> Self {
+        self.buffer_count = buffer_count;
+        self
+    }
+}
+
+/// Frame counters of a capture worker.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WorkerStats {
+    /// Frames captured from the device.
+    pub captured: u64,
+    /// Frames discarded by the overflow policy.
+    pub dropped: u64,
+}
+
+/// Queue contents shared between the capture thread and consumers.
+#[derive(Debug, Default)]
+struct Queue {
+    frames: VecDeque<Frame>,
+    /// Set once the capture thread has exited.
+    finished: bool,
+    /// Error that ended capture, handed to the first consumer to see it.
+    error: Option<CameraError>,
+}
+
+/// State shared between the capture thread and the worker handle.
+#[derive(Debug, Default)]
+struct Shared {
+    queue: Mutex<Queue>,
+    /// Signalled when a frame is queued or the capture thread exits.
+    frame_ready: Condvar,
+    /// Signalled when a frame is taken or a stop is requested.
+    space_ready: Condvar,
+    stop: AtomicBool,
+    captured: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, Queue> {
+        // Queue updates never leave it inconsistent, so a poisoned lock is usable
+        self.queue.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn stopping(&self) -> bool {
+        self.stop.load(Ordering::Acquire)
+    }
+
+    /// Queue a captured frame according to `config`.
+    fn push(&self, frame: Frame, config: &WorkerConfig) {
+        let capacity = match config.policy {
+            OverflowPolicy::LatestOnly => 1,
+            _ => config.capacity.max(1),
+        };
+
+        let mut queue = self.lock();
+        match config.policy {
+            OverflowPolicy::Block => {
+                queue = self
+                    .space_ready
+                    .wait_while(queue, |queue| {
+                        queue.frames.len() >= capacity && !self.stopping()
+                    })
+                    .unwrap_or_else(PoisonError::into_inner);
+                if self.stopping() {
+                    return;
+                }
+            }
+            OverflowPolicy::DropOldest | OverflowPolicy::LatestOnly => {
+                let excess = (queue.frames.len() + 1).saturating_sub(capacity);
+                queue.frames.drain(..excess);
+                self.dropped.fetch_add(excess as u64, Ordering::Relaxed);
+            }
+            OverflowPolicy::DropNewest => {
+                if queue.frames.len() >= capacity {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                    return;
+                }
+            }
+        }
+
+        queue.frames.push_back(frame);
+        drop(queue);
+        self.frame_ready.notify_one();
+    }
+
+    /// Take the next frame, or the end-of-capture error once drained.
+    fn take(&self, queue: &mut Queue) -> Option<Result<Frame>> {
+        if let Some(frame) = queue.frames.pop_front() {
+            self.space_ready.notify_one();
+            return Some(Ok(frame));
+        }
+        queue.finished.then(|| {
+            Err(queue
+                .error
+                .take()
+                .unwrap_or_else(|| CameraError::StreamError("capture worker stopped".to_owned())))
+        })
+    }
+
+    /// Wait for a frame to be queued, giving up at `deadline` if one is given.
+    fn wait_for_frame<'a>(
+        &self,
+        queue: MutexGuard<'a, Queue>,
+        deadline: Option<Instant>,
+    ) -> Result<MutexGuard<'a, Queue>> {
+        let Some(deadline) = deadline else {
+            return Ok(self
+                .frame_ready
+                .wait(queue)
+                .unwrap_or_else(PoisonError::into_inner));
+        };
+
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(CameraError::Timeout);
+        }
+        Ok(self
+            .frame_ready
+            .wait_timeout(queue, remaining)
+            .unwrap_or_else(PoisonError::into_inner)
+            .0)
+    }
+
+    /// Record that the capture thread has exited.
+    fn finish(&self, error: Option<CameraError>) {
+        let mut queue = self.lock();
+        queue.finished = true;
+        queue.error = error;
+        drop(queue);
+        self.frame_ready.notify_all();
+    }
+}
+
+/// A camera device capturing on a background thread.
+///
+/// Dropping the worker stops capture and waits for the thread to exit.
+#[derive(Debug)]
+pub struct CaptureWorker<D> {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<D>>,
+}
+
+impl<D> CaptureWorker<D>
+where
+    D: CameraDevice + Send + 'static,
+{
+    /// Start capturing from `device` on a new thread.
+    ///
+    /// The device should already be configured; the worker captures in its
+    /// current format.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Io` if the thread cannot be spawned.
+    pub fn spawn(device: D, config: WorkerConfig) -> Result<Self> {
+        let shared = Arc::new(Shared::default());
+        let thread_shared = Arc::clone(&shared);
+
+        let thread = thread::Builder::new()
+            .name("pi-cam-capture".to_owned())
+            .spawn(move || {
+                let mut device = device;
+                let result = capture_loop(&mut device, &config, &thread_shared);
+                thread_shared.finish(result.err());
+                device
+            })?;
+
+        Ok(Self {
+            shared,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl<D> CaptureWorker<D> {
+    /// Wait for the next frame.
+    ///
+    /// # Errors
+    ///
+    /// Once the queue is drained after capture ended, returns the error that
+    /// ended it, then `StreamError` on later calls.
+    pub fn recv(&self) -> Result<Frame> {
+        self.recv_until(None)
+    }
+
+    /// Wait at most `timeout` for the next frame.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Timeout` if no frame is queued in time, or the capture error
+    /// as for [`CaptureWorker::recv`].
+    pub fn recv_timeout(&self, timeout: Duration) -> Result<Frame> {
+        self.recv_until(Some(Instant::now() + timeout))
+    }
+
+    /// Take the next frame if one is queued, without blocking.
+    ///
+    /// # Errors
+    ///
+    /// Returns the capture error as for [`CaptureWorker::recv`].
+    pub fn try_recv(&self) -> Result<Option<Frame>> {
+        match self.recv_until(Some(Instant::now())) {
+            Ok(frame) => Ok(Some(frame)),
+            Err(CameraError::Timeout) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Current frame counters.
+    pub fn stats(&self) -> WorkerStats {
+        WorkerStats {
+            captured: self.shared.captured.load(Ordering::Relaxed),
+            dropped: self.shared.dropped.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Stop capture and get the device back.
+    ///
+    /// # Errors
+    ///
+    /// Returns `StreamError` if the capture thread panicked.
+    pub fn stop(mut self) -> Result<D> {
+        self.request_stop();
+        self.thread
+            .take()
+            .ok_or_else(|| CameraError::StreamError("capture worker already stopped".to_owned()))?
+            .join()
+            .map_err(|_| CameraError::StreamError("capture worker panicked".to_owned()))
+    }
+
+    fn request_stop(&self) {
+        self.shared.stop.store(true, Ordering::Release);
+        // Take the lock so a capture thread about to wait cannot miss the wakeup
+        drop(self.shared.lock());
+        self.shared.space_ready.notify_all();
+    }
+
+    fn recv_until(&self, deadline: Option<Instant>) -> Result<Frame> {
+        let mut queue = self.shared.lock();
+        loop {
+            if let Some(result) = self.shared.take(&mut queue) {
+                return result;
+            }
+            queue = self.shared.wait_for_frame(queue, deadline)?;
+        }
+    }
+}
+
+impl<D> Drop for CaptureWorker<D> {
+    fn drop(&mut self) {
+        self.request_stop();
+        if let Some(thread) = self.thread.take() {
+            // A panicked capture thread has nothing left to clean up
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Capture frames into the shared queue until stopped or an error occurs.
+fn capture_loop<D: CameraDevice>(
+    device: &mut D,
+    config: &WorkerConfig,
+    shared: &Shared,
+) -> Result<()> {
+    let mut stream = device.create_stream(config.buffer_count)?;
+
+    while !shared.stopping() {
+        match stream.next_frame_timeout(STOP_POLL_INTERVAL) {
+            Ok(frame) => {
+                shared.captured.fetch_add(1, Ordering::Relaxed);
+                shared.push(frame, config);
+            }
+            Err(CameraError::Timeout) => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{FaultPlan, FrameFault, MockDevice};
+
+    /// Spawn a worker over a mock that delivers `frames` frames, and wait
+    /// until all of them have been queued or dropped.
+    fn spawn_and_drain(frames: u32, config: WorkerConfig) -> CaptureWorker<MockDevice> {
+        let device = MockDevice::new().with_stall_after(frames);
+        let worker = CaptureWorker::spawn(device, config).expect("spawn should succeed");
+
+        // Frames are counted as captured before they are pushed, so settle on
+        // the queue itself; drops are recorded under the same lock
+        let settled = || {
+            let queue = worker.shared.lock();
+            queue.frames.len() as u64 + worker.stats().dropped >= u64::from(frames)
+        };
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !settled() {
+            assert!(Instant::now() < deadline, "worker did not capture in time");
+            thread::sleep(Duration::from_millis(1));
+        }
+        worker
+    }
+
+    /// Sequence numbers of every queued frame.
+    fn queued_sequences(worker: &CaptureWorker<MockDevice>) -> Vec<u32> {
+        std::iter::from_fn(|| worker.try_recv().expect("try_recv should succeed"))
+            .map(|frame| frame.metadata.sequence)
+            .collect()
+    }
+
+    #[test]
+    fn test_worker_drop_newest() {
+        let worker = spawn_and_drain(10, WorkerConfig::new(4, OverflowPolicy::DropNewest));
+        assert_eq!(queued_sequences(&worker), vec![0, 1, 2, 3]);
+        assert_eq!(worker.stats().dropped, 6);
+    }
+
+    #[test]
+    fn test_worker_drop_oldest() {
+        let worker = spawn_and_drain(10, WorkerConfig::new(4, OverflowPolicy::DropOldest));
+        assert_eq!(queued_sequences(&worker), vec![6, 7, 8, 9]);
+        assert_eq!(worker.stats().dropped, 6);
+    }
+
+    #[test]
+    fn test_worker_latest_only() {
+        let worker = spawn_and_drain(10, WorkerConfig::new(4, OverflowPolicy::LatestOnly));
+        assert_eq!(queued_sequences(&worker), vec![9]);
+        assert_eq!(
+            worker.stats(),
+            WorkerStats {
+                captured: 10,
+                dropped: 9
+            }
+        );
+    }
+
+    #[test]
+    fn test_worker_block_delivers_every_frame() {
+        let device = MockDevice::new().with_stall_after(10);
+        let worker = CaptureWorker::spawn(device, WorkerConfig::new(2, OverflowPolicy::Block))
+            .expect("spawn should succeed");
+
+        let sequences: Vec<u32> = (0..10)
+            .map(|_| {
+                worker
+                    .recv_timeout(Duration::from_secs(5))
+                    .expect("frame should arrive")
+                    .metadata
+                    .sequence
+            })
+            .collect();
+        assert_eq!(sequences, (0..10).collect::<Vec<_>>());
+        assert_eq!(worker.stats().dropped, 0);
+    }
+
+    #[test]
+    fn test_worker_stop_returns_device() {
+        let worker = CaptureWorker::spawn(
+            MockDevice::new(),
+            WorkerConfig::new(1, OverflowPolicy::Block),
+        )
+        .expect("spawn should succeed");
+        worker
+            .recv_timeout(Duration::from_secs(5))
+            .expect("frame should arrive");
+
+        // The capture thread is blocked on a full queue and must still stop
+        let device = worker.stop().expect("stop should succeed");
+        assert_eq!(device.capabilities().driver, "mock");
+    }
+
+    #[test]
+    fn test_worker_recv_timeout_when_stalled() {
+        let worker = CaptureWorker::spawn(
+            MockDevice::new().with_stall_after(0),
+            WorkerConfig::default(),
+        )
+        .expect("spawn should succeed");
+        assert!(matches!(
+            worker.recv_timeout(Duration::from_millis(20)),
+            Err(CameraError::Timeout)
+        ));
+    }
+
+    #[test]
+    fn test_worker_reports_unplug() {
+        let device =
+            MockDevice::new().with_faults(FaultPlan::new().on_frame(3, FrameFault::Unplug));
+        let worker = CaptureWorker::spawn(device, WorkerConfig::new(8, OverflowPolicy::Block))
+            .expect("spawn should succeed");
+
+        for expected in 0..3 {
+            let frame = worker
+                .recv_timeout(Duration::from_secs(5))
+                .expect("frames before the unplug should arrive");
+            assert_eq!(frame.metadata.sequence, expected);
+        }
+        assert!(matches!(
+            worker.recv_timeout(Duration::from_secs(5)),
+            Err(CameraError::StreamError(_))
+        ));
+        let device = worker.stop().expect("stop should succeed");
+        assert!(device.is_unplugged());
+    }
+}
@@ -0,0 +1,1003 @@
+//! YUV4MPEG2 (Y4M) stream support.
+//!
+//! Y4M is a minimal container for uncompressed planar YUV video: a one-line
+//! text header describing the frame geometry, rate and chroma layout, then
+//! each frame as a `FRAME` line followed by its Y, Cb and Cr planes. Most
+//! video tools read and write it, which makes it a convenient exchange format
+//! for captured test footage.
+//!
+//! [`Y4mWriter`] converts packed 4:2:2 frames such as YUYV to the planar
+//! layout Y4M stores. The quantization range of the format travels in the
+//! `XCOLORRANGE` extension tag understood by ffmpeg. [`Y4mReader`] yields
+//! the frames of a stream, either as stored or repacked to YUYV for the
+//! validators and the mock device.
+
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::colorspace::{Colorspace, Quantization};
+use crate::convert::average;
+use crate::pixel_format::{Layout, PixelFormatInfo};
+use crate::traits::{
+    unpack_yuv422, CameraError, Format, FourCC, Fraction, Frame, FrameMetadata, FrameRef, Result,
+};
+
+/// Magic string starting every Y4M stream.
+const SIGNATURE: &[u8] = b"YUV4MPEG2";
+
+/// Marker starting every frame.
+const FRAME_MARKER: &[u8] = b"FRAME";
+
+/// Longest header or frame line accepted, to bound reads of non-Y4M input.
+const MAX_LINE_LEN: u64 = 1024;
+
+/// Chroma layout of a Y4M stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Y4mChroma {
+    /// 4:2:0, chroma halved in both directions (`C420`, `C420jpeg`, ...).
+    C420,
+    /// 4:2:2, chroma halved horizontally (`C422`).
+    C422,
+    /// Luma only (`Cmono`).
+    Mono,
+}
+
+impl Y4mChroma {
+    /// Parse the value of a `C` header tag.
+    fn parse(tag: &str) -> Result<Self> {
+        match tag {
+            "420" | "420jpeg" | "420paldv" | "420mpeg2" => Ok(Self::C420),
+            "422" => Ok(Self::C422),
+            "mono" => Ok(Self::Mono),
+            _ => Err(CameraError::invalid_data(format!(
+                "Unsupported Y4M colorspace C{tag}"
+            ))),
+        }
+    }
+
+    /// Value of the `C` header tag written for this layout.
+    ///
+    /// 4:2:0 is tagged `420jpeg`: chroma averaged over a 2x2 block is sited
+    /// between the luma samples.
+    const fn tag(self) -> &'static str {
+        match self {
+            Self::C420 => "420jpeg",
+            Self::C422 => "422",
+            Self::Mono => "mono",
+        }
+    }
+
+    /// Check whether frames of `fourcc` can be written in this layout.
+    ///
+    /// Packed 4:2:2 formats convert to every layout; planar formats are
+    /// written as they are and need a matching layout.
+    #[must_use]
+    pub fn accepts(self, fourcc: FourCC) -> bool {
+        fourcc == self.fourcc() || fourcc.info().is_some_and(is_packed_yuv422)
+    }
+
+    /// Pixel format of frames in this layout.
+    #[must_use]
+    pub const fn fourcc(self) -> FourCC {
+        match self {
+            Self::C420 => FourCC::YU12,
+            Self::C422 => FourCC::YUV422P,
+            Self::Mono => FourCC::GREY,
+        }
+    }
+}
+
+/// Stream header of a Y4M file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Y4mHeader {
+    /// Frame width in pixels.
+    pub width: u32,
+    /// Frame height in pixels.
+    pub height: u32,
+    /// Time per frame (the inverse of the `F` tag).
+    pub interval: Fraction,
+    /// Chroma layout.
+    pub chroma: Y4mChroma,
+    /// Quantization range (the `XCOLORRANGE` tag), `Default` if untagged.
+    pub quantization: Quantization,
+}
+
+impl Y4mHeader {
+    /// Parse a header line, without its trailing newline.
+    ///
+    /// Tags this crate has no use for (interlacing, aspect ratio, comments)
+    /// are skipped. A missing `C` tag means 4:2:0, as the format specifies.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Io` with `InvalidData` if the signature, size or frame rate is
+    /// missing or malformed, or the chroma layout is unsupported.
+    pub fn parse(line: &str) -> Result<Self> {
+        let mut tokens = line.split(' ').filter(|token| !token.is_empty());
+        if tokens.next().map(str::as_bytes) != Some(SIGNATURE) {
+            return Err(CameraError::invalid_data(
+                "Missing YUV4MPEG2 signature".to_owned(),
+            ));
+        }
+
+        let (mut width, mut height, mut interval) = (None, None, None);
+        let mut chroma = Y4mChroma::C420;
+        let mut quantization = Quantization::Default;
+        for token in tokens {
+            let mut chars = token.chars();
+            let tag = chars.next();
+            let value = chars.as_str();
+            match tag {
+                Some('W') => width = Some(parse_number(value, "width")?),
+                Some('H') => height = Some(parse_number(value, "height")?),
+                Some('F') => interval = Some(parse_rate(value)?),
+                Some('C') => chroma = Y4mChroma::parse(value)?,
+                Some('X') => match value {
+                    "COLORRANGE=FULL" => quantization = Quantization::FullRange,
+                    "COLORRANGE=LIMITED" => quantization = Quantization::LimitedRange,
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+
+        let missing = |what: &str| CameraError::invalid_data(format!("Y4M header has no {what}"));
+        let header = Self {
+            width: width.ok_or_else(|| missing("width"))?,
+            height: height.ok_or_else(|| missing("height"))?,
+            interval: interval.ok_or_else(|| missing("frame rate"))?,
+            chroma,
+            quantization,
+        };
+        if header.chroma != Y4mChroma::Mono && header.width % 2 != 0 {
+            return Err(CameraError::invalid_data(format!(
+                "Odd width {} is not supported for subsampled chroma",
+                header.width
+            )));
+        }
+        Ok(header)
+    }
+
+    /// Format of the frames in this stream.
+    #[must_use]
+    pub const fn format(&self) -> Format {
+        Format {
+            quantization: self.quantization,
+            ..Format::new(self.width, self.height, self.chroma.fourcc())
+        }
+    }
+
+    /// Size in bytes of one frame payload.
+    #[must_use]
+    pub const fn frame_size(&self) -> u64 {
+        self.format().size as u64
+    }
+}
+
+impl fmt::Display for Y4mHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C{}",
+            self.width,
+            self.height,
+            self.interval.denominator,
+            self.interval.numerator,
+            self.chroma.tag()
+        )?;
+        match self.quantization {
+            Quantization::Default => Ok(()),
+            Quantization::FullRange => write!(f, " XCOLORRANGE=FULL"),
+            Quantization::LimitedRange => write!(f, " XCOLORRANGE=LIMITED"),
+        }
+    }
+}
+
+/// Parse a positive decimal header value.
+fn parse_number(value: &str, what: &str) -> Result<u32> {
+    value
+        .parse()
+        .ok()
+        .filter(|number| *number > 0)
+        .ok_or_else(|| CameraError::invalid_data(format!("Invalid Y4M {what} \"{value}\"")))
+}
+
+/// Parse an `F` tag (`frames:seconds`) into a frame interval.
+fn parse_rate(value: &str) -> Result<Fraction> {
+    let (frames, seconds) = value
+        .split_once(':')
+        .ok_or_else(|| CameraError::invalid_data(format!("Invalid Y4M frame rate \"{value}\"")))?;
+    Ok(Fraction::new(
+        parse_number(seconds, "frame rate")?,
+        parse_number(frames, "frame rate")?,
+    ))
+}
+
+/// Read one line without its newline, or `None` at the end of the input.
+fn read_line<R: BufRead>(reader: &mut R) -> Result<Option<Vec<u8>>> {
+    let mut line = Vec::new();
+    reader
+        .by_ref()
+        .take(MAX_LINE_LEN)
+        .read_until(b'\n', &mut line)?;
+    if line.is_empty() {
+        return Ok(None);
+    }
+    if line.pop() != Some(b'\n') {
+        return Err(CameraError::invalid_data(
+            "Unterminated or overlong Y4M line".to_owned(),
+        ));
+    }
+    Ok(Some(line))
+}
+
+/// Read and parse the stream header.
+///
+/// # Errors
+///
+/// Returns `Io` if the input cannot be read or is not a supported Y4M stream.
+pub fn read_header<R: BufRead>(reader: &mut R) -> Result<Y4mHeader> {
+    let line = read_line(reader)?
+        .ok_or_else(|| CameraError::invalid_data("Empty Y4M stream".to_owned()))?;
+    let line = String::from_utf8(line)
+        .map_err(|_| CameraError::invalid_data("Y4M header is not text".to_owned()))?;
+    Y4mHeader::parse(&line)
+}
+
+/// Locate the payload of every frame after the header.
+///
+/// Frame parameters are skipped. A final frame cut short by the end of the
+/// input is left out, so a recording interrupted mid-write stays usable.
+pub(crate) fn frame_offsets<R: BufRead + Seek>(
+    reader: &mut R,
+    header: &Y4mHeader,
+) -> Result<Vec<u64>> {
+    let start = reader.stream_position()?;
+    let end = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(start))?;
+
+    let frame_size = header.frame_size();
+    let mut offsets = Vec::new();
+    while let Some(line) = read_line(reader)? {
+        if !matches!(line.strip_prefix(FRAME_MARKER), Some([] | [b' ', ..])) {
+            return Err(CameraError::invalid_data(format!(
+                "Expected Y4M frame {} marker",
+                offsets.len()
+            )));
+        }
+        let offset = reader.stream_position()?;
+        if offset + frame_size > end {
+            break;
+        }
+        offsets.push(offset);
+        reader.seek(SeekFrom::Start(offset + frame_size))?;
+    }
+    Ok(offsets)
+}
+
+/// Check whether a format is packed YUV 4:2:2 (YUYV and its reorderings).
+fn is_packed_yuv422(info: &PixelFormatInfo) -> bool {
+    info.layout == Layout::Packed && info.chroma_h_subsampling == 2
+}
+
+/// Halve the vertical chroma resolution of a plane by averaging row pairs.
+///
+/// A trailing unpaired row is kept as it is.
+fn halve_rows(plane: &[u8], width: usize) -> Vec<u8> {
+    plane
+        .chunks(width * 2)
+        .flat_map(|pair| {
+            let (top, bottom) = pair.split_at(width.min(pair.len()));
+            let bottom = if bottom.is_empty() { top } else { bottom };
+            top.iter().zip(bottom).map(|(&a, &b)| average(a, b))
+        })
+        .collect()
+}
+
+/// Copy the planes of a planar or greyscale frame, dropping line padding.
+fn copy_planes(data: &[u8], format: &Format, info: &PixelFormatInfo) -> Option<Vec<u8>> {
+    let width = format.width as usize;
+    let mut planes = Vec::new();
+    let mut offset = 0;
+    for plane in 0..info.planes {
+        let stride = info.plane_stride(plane, format.stride) as usize;
+        let lines = info.plane_height(plane, format.height) as usize;
+        let row_len = if plane == 0 {
+            width
+        } else {
+            width / info.chroma_h_subsampling as usize
+        };
+        for line in 0..lines {
+            planes.extend_from_slice(data.get(offset + line * stride..)?.get(..row_len)?);
+        }
+        offset += stride * lines;
+    }
+    Some(planes)
+}
+
+/// Convert a frame to the planar layout of `chroma`.
+///
+/// Returns `None` if the data is shorter than the format describes.
+fn to_planar(data: &[u8], format: &Format, chroma: Y4mChroma) -> Option<Vec<u8>> {
+    let info = format.fourcc.info()?;
+    if format.fourcc == chroma.fourcc() {
+        return copy_planes(data, format, info);
+    }
+
+    let width = format.width as usize;
+    let height = format.height as usize;
+    let mut luma = Vec::with_capacity(width * height);
+    let mut cb = Vec::with_capacity(width / 2 * height);
+    let mut cr = Vec::with_capacity(width / 2 * height);
+    for line in data.chunks(format.stride.max(1) as usize).take(height) {
+        for group in line.get(..width * 2)?.chunks_exact(4) {
+            let (y0, u, y1, v) = unpack_yuv422(format.fourcc, group)?;
+            luma.extend_from_slice(&[y0, y1]);
+            cb.push(u);
+            cr.push(v);
+        }
+    }
+    if luma.len() < width * height {
+        return None;
+    }
+
+    match chroma {
+        Y4mChroma::Mono => return Some(luma),
+        Y4mChroma::C420 => {
+            cb = halve_rows(&cb, width / 2);
+            cr = halve_rows(&cr, width / 2);
+        }
+        Y4mChroma::C422 => {}
+    }
+    luma.extend_from_slice(&cb);
+    luma.extend_from_slice(&cr);
+    Some(luma)
+}
+
+/// Repack a planar Y4M frame as YUYV.
+///
+/// 4:2:0 chroma rows are shared by both luma rows they cover; greyscale frames
+/// get neutral chroma.
+fn to_yuyv(planes: &[u8], header: &Y4mHeader) -> Option<Vec<u8>> {
+    let width = header.width as usize;
+    let height = header.height as usize;
+    // Slice the chroma planes the way `format` sized them
+    let info = header.format().fourcc.info()?;
+    let chroma_width = info.plane_stride(1, header.width) as usize;
+    let chroma_rows = info.plane_height(1, header.height) as usize;
+    let chroma_size = chroma_width * chroma_rows;
+    let luma = planes.get(..width * height)?;
+    let (cb, cr) = match header.chroma {
+        // Greyscale frames have no chroma planes to slice
+        Y4mChroma::Mono => (&[][..], &[][..]),
+        Y4mChroma::C420 | Y4mChroma::C422 => {
+            let cb = planes.get(width * height..)?;
+            (cb, cb.get(chroma_size..)?)
+        }
+    };
+    let neutral = vec![128; chroma_width];
+
+    let mut packed = Vec::with_capacity(width * height * 2);
+    for (row, luma_row) in luma.chunks_exact(width).enumerate() {
+        let chroma_row = match header.chroma {
+            Y4mChroma::C420 => row / 2,
+            Y4mChroma::C422 | Y4mChroma::Mono => row,
+        };
+        let start = chroma_row * chroma_width;
+        let (blue, red) = match header.chroma {
+            Y4mChroma::Mono => (neutral.as_slice(), neutral.as_slice()),
+            Y4mChroma::C420 | Y4mChroma::C422 => (
+                cb.get(start..start + chroma_width)?,
+                cr.get(start..start + chroma_width)?,
+            ),
+        };
+        for ((pair, &u), &v) in luma_row.chunks(2).zip(blue).zip(red) {
+            match *pair {
+                [y0, y1] => packed.extend_from_slice(&[y0, u, y1, v]),
+                // The last column of an odd-width frame fills half a pair
+                [y0] => packed.extend_from_slice(&[y0, u]),
+                _ => {}
+            }
+        }
+    }
+    Some(packed)
+}
+
+/// Writer of a Y4M stream.
+#[derive(Debug)]
+pub struct Y4mWriter<W: Write> {
+    writer: W,
+    format: Format,
+    chroma: Y4mChroma,
+    frames: u32,
+}
+
+impl Y4mWriter<BufWriter<File>> {
+    /// Create a Y4M file at `path`, replacing any existing file.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Io` if the file cannot be created, or the errors of
+    /// [`Y4mWriter::new`].
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        format: &Format,
+        interval: Fraction,
+        chroma: Y4mChroma,
+    ) -> Result<Self> {
+        Self::new(
+            BufWriter::new(File::create(path)?),
+            format,
+            interval,
+            chroma,
+        )
+    }
+}
+
+impl<W: Write> Y4mWriter<W> {
+    /// Start a stream of `format` frames stored in the `chroma` layout.
+    ///
+    /// The header carries the frame size, the frame rate derived from
+    /// `interval` and the chroma layout.
+    ///
+    /// # Errors
+    ///
+    /// Returns `FormatNotSupported` if `format` cannot be converted to
+    /// `chroma` or has an odd width with subsampled chroma, or `Io` if writing
+    /// the header fails.
+    pub fn new(
+        mut writer: W,
+        format: &Format,
+        interval: Fraction,
+        chroma: Y4mChroma,
+    ) -> Result<Self> {
+        let unsupported = |reason: String| CameraError::FormatNotSupported {
+            format: format.clone(),
+            reason,
+        };
+        if !chroma.accepts(format.fourcc) {
+            return Err(unsupported(format!(
+                "cannot be written as Y4M C{}",
+                chroma.tag()
+            )));
+        }
+        if chroma != Y4mChroma::Mono && format.width % 2 != 0 {
+            return Err(unsupported(
+                "odd widths cannot hold subsampled chroma".to_owned(),
+            ));
+        }
+
+        // Tag the range only when the format says something about it
+        let quantization = if format.colorspace == Colorspace::Default {
+            format.quantization
+        } else {
+            format.quantization.resolve(format.colorspace, false)
+        };
+        let header = Y4mHeader {
+            width: format.width,
+            height: format.height,
+            interval,
+            chroma,
+            quantization,
+        };
+        writeln!(writer, "{header}")?;
+        Ok(Self {
+            writer,
+            format: format.clone(),
+            chroma,
+            frames: 0,
+        })
+    }
+
+    /// Convert and append a frame.
+    ///
+    /// # Errors
+    ///
+    /// Returns `StreamError` if the frame holds less data than its format
+    /// describes, or `Io` if writing fails.
+    pub fn write_frame(&mut self, frame: &FrameRef<'_>) -> Result<()> {
+        let planes = to_planar(frame.data, &self.format, self.chroma).ok_or_else(|| {
+            CameraError::StreamError(format!(
+                "Frame {} holds {} bytes, too few for {}x{} {}",
+                frame.metadata.sequence,
+                frame.data.len(),
+                self.format.width,
+                self.format.height,
+                self.format.fourcc
+            ))
+        })?;
+        self.writer.write_all(FRAME_MARKER)?;
+        self.writer.write_all(b"\n")?;
+        self.writer.write_all(&planes)?;
+        self.frames += 1;
+        Ok(())
+    }
+
+    /// Number of frames written so far.
+    #[must_use]
+    pub const fn frame_count(&self) -> u32 {
+        self.frames
+    }
+
+    /// Flush the stream and return the underlying writer.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Io` if flushing fails.
+    pub fn finish(mut self) -> Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Sequential reader of a Y4M stream.
+///
+/// Frames are numbered from zero and timestamped at the stream frame rate.
+/// A final frame cut short by the end of the input ends the stream.
+#[derive(Debug)]
+pub struct Y4mReader<R> {
+    reader: R,
+    header: Y4mHeader,
+    sequence: u32,
+    yuyv: bool,
+}
+
+impl Y4mReader<BufReader<File>> {
+    /// Open the Y4M file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Io` if the file cannot be read or is not a supported Y4M
+    /// stream.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: BufRead> Y4mReader<R> {
+    /// Read the stream header.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Io` if reading fails or the input is not a supported Y4M
+    /// stream.
+    pub fn new(mut reader: R) -> Result<Self> {
+        let header = read_header(&mut reader)?;
+        Ok(Self {
+            reader,
+            header,
+            sequence: 0,
+            yuyv: false,
+        })
+    }
+
+    /// Repack frames as YUYV instead of yielding the stored planar layout.
+    #[must_use]
+    pub const fn with_yuyv_output(mut self, yuyv: bool) -> Self {
+        self.yuyv = yuyv;
+        self
+    }
+
+    /// Stream header.
+    pub const fn header(&self) -> &Y4mHeader {
+        &self.header
+    }
+
+    /// Format of the frames yielded by this reader.
+    pub const fn format(&self) -> Format {
+        if self.yuyv {
+            Format {
+                quantization: self.header.quantization,
+                ..Format::new(self.header.width, self.header.height, FourCC::YUYV)
+            }
+        } else {
+            self.header.format()
+        }
+    }
+
+    /// Read the next frame, or `None` at the end of the stream.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Io` if reading fails or a frame marker is malformed.
+    pub fn read_frame(&mut self) -> Result<Option<Frame>> {
+        let Some(line) = read_line(&mut self.reader)? else {
+            return Ok(None);
+        };
+        if !matches!(line.strip_prefix(FRAME_MARKER), Some([] | [b' ', ..])) {
+            return Err(CameraError::invalid_data(format!(
+                "Expected Y4M frame {} marker",
+                self.sequence
+            )));
+        }
+
+        let mut planes = vec![0; self.header.format().size as usize];
+        match self.reader.read_exact(&mut planes) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err.into()),
+        }
+
+        let data = if self.yuyv {
+            to_yuyv(&planes, &self.header)
+                .ok_or_else(|| CameraError::invalid_data("Truncated Y4M frame".to_owned()))?
+        } else {
+            planes
+        };
+        let sequence = self.sequence;
+        self.sequence += 1;
+        Ok(Some(Frame {
+            metadata: FrameMetadata {
+                sequence,
+                timestamp: self
+                    .header
+                    .interval
+                    .duration_of(sequence)
+                    .unwrap_or_default(),
+                bytes_used: u32::try_from(data.len()).unwrap_or(u32::MAX),
+            },
+            data,
+        }))
+    }
+}
+
+impl<R: BufRead> Iterator for Y4mReader<R> {
+    type Item = Result<Frame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_frame().transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{generate_test_frame, MockDevice, TestPattern};
+    use crate::traits::{CameraDevice, CaptureStream};
+    use crate::validation::validate_color_bars;
+    use std::io::Cursor;
+
+    /// Write `frames` as a Y4M stream in the `chroma` layout.
+    fn write_stream(format: &Format, chroma: Y4mChroma, frames: &[Frame]) -> Vec<u8> {
+        let mut writer = Y4mWriter::new(Vec::new(), format, Fraction::from_fps(30), chroma)
+            .expect("writer should start");
+        for frame in frames {
+            writer
+                .write_frame(&frame.as_frame_ref())
+                .expect("write_frame should succeed");
+        }
+        assert_eq!(writer.frame_count() as usize, frames.len());
+        writer.finish().expect("finish should succeed")
+    }
+
+    fn color_bars(format: &Format) -> Frame {
+        Frame {
+            data: generate_test_frame(format, TestPattern::ColorBars),
+            metadata: FrameMetadata {
+                sequence: 0,
+                timestamp: std::time::Duration::ZERO,
+                bytes_used: format.size,
+            },
+        }
+    }
+
+    #[test]
+    fn test_parse_header() {
+        let header =
+            Y4mHeader::parse("YUV4MPEG2 W640 H480 F30000:1001 Ip A1:1 C420jpeg XYSCSS=420JPEG")
+                .expect("header should parse");
+        assert_eq!(header.width, 640);
+        assert_eq!(header.height, 480);
+        assert_eq!(header.interval, Fraction::new(1001, 30000));
+        assert_eq!(header.chroma, Y4mChroma::C420);
+        assert_eq!(header.format().fourcc, FourCC::YU12);
+        assert_eq!(header.frame_size(), 460_800);
+
+        let mono = Y4mHeader::parse("YUV4MPEG2 W3 H2 F25:1 Cmono").expect("header should parse");
+        assert_eq!(mono.format().fourcc, FourCC::GREY);
+        assert_eq!(mono.frame_size(), 6);
+    }
+
+    #[test]
+    fn test_parse_header_errors() {
+        for line in [
+            "YUV4MPEG W640 H480 F30:1",
+            "YUV4MPEG2 H480 F30:1",
+            "YUV4MPEG2 W640 H480",
+            "YUV4MPEG2 W640 H480 F30",
+            "YUV4MPEG2 W640 H480 F30:1 C444",
+            "YUV4MPEG2 W641 H480 F30:1 C422",
+        ] {
+            assert!(Y4mHeader::parse(line).is_err(), "{line} should be rejected");
+        }
+    }
+
+    #[test]
+    fn test_frame_offsets_skip_truncated_frame() {
+        let mut data = b"YUV4MPEG2 W4 H2 F30:1 C420\n".to_vec();
+        for fill in 0..2 {
+            data.extend_from_slice(b"FRAME\n");
+            data.extend(std::iter::repeat(fill).take(12));
+        }
+        data.extend_from_slice(b"FRAME Ixyz\n");
+        data.extend_from_slice(&[0; 5]);
+
+        let mut reader = Cursor::new(data);
+        let header = read_header(&mut reader).expect("header should parse");
+        let offsets = frame_offsets(&mut reader, &header).expect("frames should index");
+        assert_eq!(offsets, vec![33, 51]);
+    }
+
+    #[test]
+    fn test_frame_offsets_reject_garbage() {
+        let mut reader = Cursor::new(b"YUV4MPEG2 W2 H2 F30:1 Cmono\nFRAMES\n1234".to_vec());
+        let header = read_header(&mut reader).expect("header should parse");
+        assert!(frame_offsets(&mut reader, &header).is_err());
+    }
+
+    #[test]
+    fn test_header_tags() {
+        let format = Format::new(640, 480, FourCC::YUYV);
+        let bytes = Y4mWriter::new(
+            Vec::new(),
+            &format,
+            Fraction::new(1001, 30000),
+            Y4mChroma::C420,
+        )
+        .expect("writer should start")
+        .finish()
+        .expect("finish should succeed");
+        assert_eq!(bytes, b"YUV4MPEG2 W640 H480 F30000:1001 Ip A1:1 C420jpeg\n");
+
+        let header = read_header(&mut Cursor::new(bytes)).expect("header should parse");
+        assert_eq!(header.interval, Fraction::new(1001, 30000));
+        assert_eq!(header.chroma, Y4mChroma::C420);
+    }
+
+    #[test]
+    fn test_color_range_tag() {
+        let header = Y4mHeader::parse("YUV4MPEG2 W4 H2 F30:1 C422 XCOLORRANGE=FULL")
+            .expect("header should parse");
+        assert_eq!(header.format().quantization, Quantization::FullRange);
+        assert_eq!(
+            header.to_string(),
+            "YUV4MPEG2 W4 H2 F30:1 Ip A1:1 C422 XCOLORRANGE=FULL"
+        );
+
+        // A colorspace resolves the default range of the format
+        let format = Format {
+            colorspace: Colorspace::Srgb,
+            ..Format::new(4, 2, FourCC::YUYV)
+        };
+        let bytes = Y4mWriter::new(Vec::new(), &format, Fraction::from_fps(30), Y4mChroma::C422)
+            .expect("writer should start")
+            .finish()
+            .expect("finish should succeed");
+        let header = read_header(&mut Cursor::new(bytes)).expect("header should parse");
+        assert_eq!(header.quantization, Quantization::LimitedRange);
+    }
+
+    #[test]
+    fn test_yuyv_422_round_trip_is_lossless() {
+        let format = Format::new(64, 8, FourCC::YUYV);
+        let mut frame = color_bars(&format);
+        // Vary the luma so the two pixels of every pair differ
+        for (index, byte) in frame.data.iter_mut().enumerate().step_by(2) {
+            *byte = u8::try_from(index % 251).expect("luma should fit u8");
+        }
+        let bytes = write_stream(&format, Y4mChroma::C422, &[frame.clone(), frame.clone()]);
+
+        let reader = Y4mReader::new(Cursor::new(bytes))
+            .expect("reader should open")
+            .with_yuyv_output(true);
+        assert_eq!(reader.format(), format);
+        let frames: Vec<Frame> = reader.collect::<Result<_>>().expect("frames should read");
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].data, frame.data);
+        assert_eq!(frames[1].metadata.sequence, 1);
+        assert_eq!(
+            frames[1].metadata.timestamp,
+            Fraction::from_fps(30)
+                .duration_of(1)
+                .expect("30 fps should have a frame duration")
+        );
+    }
+
+    #[test]
+    fn test_yuyv_420_keeps_color_bars() {
+        let format = Format::new(640, 480, FourCC::UYVY);
+        let bytes = write_stream(&format, Y4mChroma::C420, &[color_bars(&format)]);
+        assert_eq!(bytes.len(), 43 + 6 + 460_800);
+
+        let mut planar = Y4mReader::new(Cursor::new(bytes.clone())).expect("reader should open");
+        assert_eq!(planar.format().fourcc, FourCC::YU12);
+        let frame = planar
+            .read_frame()
+            .expect("frame should read")
+            .expect("stream should hold a frame");
+        assert_eq!(frame.data.len(), 460_800);
+
+        let mut packed = Y4mReader::new(Cursor::new(bytes))
+            .expect("reader should open")
+            .with_yuyv_output(true);
+        let frame = packed
+            .read_frame()
+            .expect("frame should read")
+            .expect("stream should hold a frame");
+        validate_color_bars(&frame, &packed.format()).expect("color bars should survive 4:2:0");
+        assert!(packed.read_frame().expect("end should read").is_none());
+    }
+
+    #[test]
+    fn test_planar_source_drops_padding() {
+        let mut format = Format::new(4, 2, FourCC::GREY);
+        format.stride = 8;
+        format.size = 16;
+        let frame = Frame {
+            data: vec![1, 2, 3, 4, 0, 0, 0, 0, 5, 6, 7, 8, 0, 0, 0, 0],
+            metadata: FrameMetadata {
+                sequence: 0,
+                timestamp: std::time::Duration::ZERO,
+                bytes_used: 16,
+            },
+        };
+        let bytes = write_stream(&format, Y4mChroma::Mono, &[frame]);
+
+        let mut reader = Y4mReader::new(Cursor::new(bytes)).expect("reader should open");
+        let frame = reader
+            .next()
+            .expect("stream should hold a frame")
+            .expect("frame should read");
+        assert_eq!(frame.data, vec![1, 2, 3, 4, 5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn test_mono_round_trip_with_yuyv_output() {
+        let format = Format::new(4, 2, FourCC::GREY);
+        let frame = Frame {
+            data: vec![10, 20, 30, 40, 50, 60, 70, 80],
+            metadata: FrameMetadata {
+                sequence: 0,
+                timestamp: std::time::Duration::ZERO,
+                bytes_used: 8,
+            },
+        };
+        let bytes = write_stream(&format, Y4mChroma::Mono, &[frame]);
+
+        let mut reader = Y4mReader::new(Cursor::new(bytes))
+            .expect("reader should open")
+            .with_yuyv_output(true);
+        let frame = reader
+            .read_frame()
+            .expect("frame should read")
+            .expect("stream should hold a frame");
+        assert_eq!(
+            frame.data,
+            vec![10, 128, 20, 128, 30, 128, 40, 128, 50, 128, 60, 128, 70, 128, 80, 128]
+        );
+    }
+
+    #[test]
+    fn test_writer_rejects_unsupported_formats() {
+        let interval = Fraction::from_fps(30);
+        for (format, chroma) in [
+            (Format::new(640, 480, FourCC::RGB3), Y4mChroma::C422),
+            (Format::new(640, 480, FourCC::YU12), Y4mChroma::C422),
+            (Format::new(641, 480, FourCC::YUYV), Y4mChroma::C420),
+        ] {
+            assert!(matches!(
+                Y4mWriter::new(Vec::new(), &format, interval, chroma),
+                Err(CameraError::FormatNotSupported { .. })
+            ));
+        }
+
+        let format = Format::new(640, 480, FourCC::YUYV);
+        let mut writer = Y4mWriter::new(Vec::new(), &format, interval, Y4mChroma::C422)
+            .expect("writer should start");
+        let short = Frame {
+            data: vec![0; 100],
+            metadata: FrameMetadata {
+                sequence: 3,
+                timestamp: std::time::Duration::ZERO,
+                bytes_used: 100,
+            },
+        };
+        assert!(matches!(
+            writer.write_frame(&short.as_frame_ref()),
+            Err(CameraError::StreamError(_))
+        ));
+
+        // A zero stride cannot describe the frame
+        let format = Format {
+            stride: 0,
+            ..Format::new(4, 2, FourCC::YUYV)
+        };
+        let mut writer = Y4mWriter::new(Vec::new(), &format, interval, Y4mChroma::C422)
+            .expect("writer should start");
+        let frame = color_bars(&Format::new(4, 2, FourCC::YUYV));
+        assert!(matches!(
+            writer.write_frame(&frame.as_frame_ref()),
+            Err(CameraError::StreamError(_))
+        ));
+    }
+
+    #[test]
+    fn test_odd_width_rejected_both_ways() {
+        let interval = Fraction::from_fps(30);
+        let format = Format::new(5, 2, FourCC::YUYV);
+        for (chroma, tag) in [(Y4mChroma::C420, "C420jpeg"), (Y4mChroma::C422, "C422")] {
+            assert!(Y4mWriter::new(Vec::new(), &format, interval, chroma).is_err());
+
+            let mut data = format!("YUV4MPEG2 W5 H2 F30:1 {tag}\nFRAME\n").into_bytes();
+            data.extend_from_slice(&[16; 20]);
+            assert!(
+                Y4mReader::new(Cursor::new(data)).is_err(),
+                "{tag} should be rejected"
+            );
+        }
+
+        // Greyscale has no chroma to subsample, so odd widths round-trip
+        let format = Format::new(5, 2, FourCC::GREY);
+        let frame = Frame {
+            data: (0..10).collect(),
+            metadata: FrameMetadata {
+                sequence: 0,
+                timestamp: std::time::Duration::ZERO,
+                bytes_used: 10,
+            },
+        };
+        let bytes = write_stream(&format, Y4mChroma::Mono, std::slice::from_ref(&frame));
+        let mut reader = Y4mReader::new(Cursor::new(bytes.clone())).expect("reader should open");
+        let read = reader
+            .read_frame()
+            .expect("read_frame should succeed")
+            .expect("stream should hold a frame");
+        assert_eq!(read.data, frame.data);
+
+        // Repacked as YUYV, the last column still fills half a pair
+        let mut reader = Y4mReader::new(Cursor::new(bytes))
+            .expect("reader should open")
+            .with_yuyv_output(true);
+        let read = reader
+            .read_frame()
+            .expect("read_frame should succeed")
+            .expect("stream should hold a frame");
+        assert_eq!(read.data.len(), reader.format().size as usize);
+        assert_eq!(&read.data[..10], &[0, 128, 1, 128, 2, 128, 3, 128, 4, 128]);
+    }
+
+    #[test]
+    fn test_reader_stops_at_truncated_frame() {
+        let mut data = b"YUV4MPEG2 W4 H2 F30:1 C422\nFRAME\n".to_vec();
+        data.extend_from_slice(&[16; 16]);
+        data.extend_from_slice(b"FRAME\n");
+        data.extend_from_slice(&[16; 5]);
+
+        let reader = Y4mReader::new(Cursor::new(data)).expect("reader should open");
+        let frames: Vec<Frame> = reader.collect::<Result<_>>().expect("frames should read");
+        assert_eq!(frames.len(), 1);
+    }
+
+    #[test]
+    fn test_clip_feeds_mock() {
+        let format = Format::new(640, 480, FourCC::YUYV);
+        let bytes = write_stream(&format, Y4mChroma::C420, &[color_bars(&format)]);
+        let reader = Y4mReader::new(Cursor::new(bytes))
+            .expect("reader should open")
+            .with_yuyv_output(true);
+        let clip: Vec<Frame> = reader.collect::<Result<_>>().expect("frames should read");
+
+        let mut device = MockDevice::new()
+            .with_pattern(TestPattern::Gradient)
+            .with_clip(format.clone(), clip);
+        let mut stream = device
+            .create_stream(2)
+            .expect("create_stream should succeed");
+        for _ in 0..2 {
+            let frame = stream.next_frame().expect("next_frame should succeed");
+            validate_color_bars(&frame, &format).expect("clip should play back");
+        }
+    }
+}
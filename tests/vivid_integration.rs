@@ -8,59 +8,54 @@
 //! Expected vivid configuration (set by `dev-setup.sh`):
 //! - Device 1: Gray Ramp pattern (gradient) - `test_pattern=20`
 //! - Device 2: 100% Colorbar pattern - `test_pattern=1`
-//! - Format: 640x480 YUYV
+//! - Format: 640x480 YUYV, with NV12, NV21 and YU12 for the planar tests
 //!
 //! Tests will fail if vivid is not available or not configured correctly.
 
 #![cfg(feature = "integration")]
 
+use pi_cam_capture::controls::{cid, ControlType, ControlValue};
 use pi_cam_capture::device::V4L2Device;
-use pi_cam_capture::traits::{CameraDevice, CaptureStream, Format, FourCC};
+use pi_cam_capture::discovery::{list_devices, DeviceInfo};
+use pi_cam_capture::negotiation::{negotiate, FormatRequest};
+use pi_cam_capture::recording::{Recorder, RecordingHeader, RecordingReader};
+use pi_cam_capture::replay::ReplayDevice;
+use pi_cam_capture::traits::{
+    CameraDevice, CameraError, CaptureStream, Format, FourCC, FrameInterval,
+};
 use pi_cam_capture::validation::{validate_color_bars, validate_frame_sequence, validate_gradient};
 use serial_test::serial;
-use std::fs;
-use std::path::Path;
+use std::io::Cursor;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Semi-planar and planar formats commonly delivered by CSI pipelines.
+const PLANAR_FORMATS: [FourCC; 3] = [FourCC::NV12, FourCC::NV21, FourCC::YU12];
 
 /// Find all available vivid virtual camera devices.
 ///
-/// Uses sysfs to check device driver name before opening, avoiding
-/// unnecessary device opens on real cameras.
+/// Uses the library discovery API and keeps capture nodes reported by the
+/// vivid driver, in ascending node index order.
 ///
-/// Returns a vector of device indices for all vivid devices found.
-fn find_vivid_devices() -> Vec<u32> {
-    let video4linux = Path::new("/sys/class/video4linux");
-    if !video4linux.exists() {
-        return Vec::new();
-    }
-
-    let mut devices = Vec::new();
-    for index in 0..10 {
-        let name_path = video4linux.join(format!("video{index}")).join("name");
-        let Ok(name) = fs::read_to_string(&name_path) else {
-            continue;
-        };
-
-        if !name.to_lowercase().contains("vivid") {
-            continue;
-        }
-
-        // Verify we can actually open it
-        if V4L2Device::open(index).is_ok() {
-            devices.push(index);
-        }
-    }
-    devices
+/// Returns a vector of device node paths for all vivid devices found.
+fn find_vivid_devices() -> Vec<PathBuf> {
+    list_devices()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|info| info.capabilities.driver == "vivid" && info.capabilities.can_capture)
+        .map(|info| info.path)
+        .collect()
 }
 
 /// Macro to fail test if vivid is not available.
 ///
-/// Returns the first vivid device index.
+/// Returns the first vivid device path.
 /// Integration tests MUST have vivid loaded - they should fail, not silently skip.
 /// This ensures CI catches missing vivid configuration.
 macro_rules! require_vivid {
     () => {
-        match find_vivid_devices().first().copied() {
-            Some(idx) => idx,
+        match find_vivid_devices().into_iter().next() {
+            Some(path) => path,
             None => {
                 panic!(
                     "vivid virtual camera not available.\n\
@@ -74,7 +69,7 @@ macro_rules! require_vivid {
 
 /// Macro to get both vivid devices (for pattern-specific tests).
 ///
-/// Returns a tuple of (gradient device index, colorbar device index).
+/// Returns a tuple of (gradient device path, colorbar device path).
 /// Expects `dev-setup.sh` configuration:
 /// - First device: Gray Ramp (gradient)
 /// - Second device: 75% Colorbar
@@ -89,16 +84,16 @@ macro_rules! require_vivid_pair {
                 devices.len()
             );
         }
-        (devices[0], devices[1])
+        (devices[0].clone(), devices[1].clone())
     }};
 }
 
 #[test]
 #[serial]
 fn test_vivid_device_open() {
-    let device_index = require_vivid!();
+    let device_path = require_vivid!();
 
-    let device = V4L2Device::open(device_index).expect("Failed to open vivid device");
+    let device = V4L2Device::open_path(&device_path).expect("Failed to open vivid device");
     let caps = device.capabilities();
 
     assert!(caps.driver.contains("vivid"), "Expected vivid driver");
@@ -111,12 +106,54 @@ fn test_vivid_device_open() {
     println!("  Bus: {}", caps.bus_info);
 }
 
+#[test]
+#[serial]
+fn test_vivid_discovery() {
+    let device_path = require_vivid!();
+
+    let info = DeviceInfo::probe(&device_path).expect("Failed to probe vivid device");
+    assert_eq!(info.path, device_path);
+    assert_eq!(info.capabilities.driver, "vivid");
+
+    let listed = list_devices().expect("Failed to list devices");
+    assert!(
+        listed.iter().any(|dev| dev.path == device_path),
+        "list_devices should include {}",
+        device_path.display()
+    );
+
+    println!("Discovered vivid device:");
+    println!("  Path: {}", info.path.display());
+    println!("  Card: {}", info.capabilities.card);
+    println!("  Bus: {}", info.capabilities.bus_info);
+}
+
+#[test]
+#[serial]
+fn test_vivid_open_by_card_and_bus_info() {
+    let device_path = require_vivid!();
+    let info = DeviceInfo::probe(&device_path).expect("Failed to probe vivid device");
+
+    let by_card =
+        V4L2Device::open_by_card(&info.capabilities.card).expect("Failed to open by card");
+    assert_eq!(by_card.capabilities().card, info.capabilities.card);
+
+    let by_bus = V4L2Device::open_by_bus_info(&info.capabilities.bus_info)
+        .expect("Failed to open by bus info");
+    assert_eq!(by_bus.capabilities().bus_info, info.capabilities.bus_info);
+
+    assert!(
+        V4L2Device::open_by_card("no such camera").is_err(),
+        "Unknown card name should not match"
+    );
+}
+
 #[test]
 #[serial]
 fn test_vivid_format_query() {
-    let device_index = require_vivid!();
+    let device_path = require_vivid!();
 
-    let device = V4L2Device::open(device_index).expect("Failed to open vivid device");
+    let device = V4L2Device::open_path(&device_path).expect("Failed to open vivid device");
     let format = device.format().expect("Failed to query format");
 
     println!("Current format:");
@@ -129,12 +166,166 @@ fn test_vivid_format_query() {
     assert!(format.height > 0, "Height should be positive");
 }
 
+#[test]
+#[serial]
+fn test_vivid_format_enumeration() {
+    let device_path = require_vivid!();
+
+    let device = V4L2Device::open_path(&device_path).expect("Failed to open vivid device");
+    let formats = device
+        .supported_formats()
+        .expect("Failed to enumerate formats");
+
+    println!("Supported formats:");
+    for desc in &formats {
+        println!(
+            "  {} - {} (compressed: {})",
+            desc.fourcc, desc.description, desc.compressed
+        );
+    }
+    assert!(
+        formats.iter().any(|desc| desc.fourcc == FourCC::YUYV),
+        "vivid should support YUYV"
+    );
+
+    let sizes = device
+        .frame_sizes(FourCC::YUYV)
+        .expect("Failed to enumerate frame sizes");
+    println!("YUYV frame sizes: {sizes:?}");
+    assert!(
+        sizes.iter().any(|size| size.contains(640, 480)),
+        "vivid should support 640x480 YUYV"
+    );
+
+    let intervals = device
+        .frame_intervals(FourCC::YUYV, 640, 480)
+        .expect("Failed to enumerate frame intervals");
+    println!("YUYV 640x480 intervals: {intervals:?}");
+    assert!(!intervals.is_empty(), "vivid should report frame intervals");
+}
+
+#[test]
+#[serial]
+fn test_vivid_frame_interval() {
+    let device_path = require_vivid!();
+
+    let mut device = V4L2Device::open_path(&device_path).expect("Failed to open vivid device");
+    device
+        .set_format(&Format::new(640, 480, FourCC::YUYV))
+        .expect("Failed to set format");
+
+    let intervals = device
+        .frame_intervals(FourCC::YUYV, 640, 480)
+        .expect("Failed to enumerate frame intervals");
+    let requested = intervals
+        .iter()
+        .find_map(|interval| match interval {
+            FrameInterval::Discrete(fraction) => Some(*fraction),
+            FrameInterval::Stepwise { .. } => None,
+        })
+        .expect("vivid should report discrete frame intervals");
+
+    let granted = device
+        .set_frame_interval(requested)
+        .expect("Failed to set frame interval");
+    println!("Requested {requested} s/frame, granted {granted} s/frame");
+
+    assert_eq!(
+        granted, requested,
+        "Discrete interval should be granted as-is"
+    );
+    assert_eq!(
+        device
+            .frame_interval()
+            .expect("Failed to get frame interval"),
+        granted
+    );
+}
+
+#[test]
+#[serial]
+fn test_vivid_controls() {
+    let device_path = require_vivid!();
+
+    let mut device = V4L2Device::open_path(&device_path).expect("Failed to open vivid device");
+    let controls = device.controls().expect("Failed to enumerate controls");
+
+    println!("Controls:");
+    for info in &controls {
+        println!(
+            "  {:#010x} {} {:?} [{}..{}] default={}",
+            info.id, info.name, info.control_type, info.minimum, info.maximum, info.default
+        );
+    }
+
+    let brightness = controls
+        .iter()
+        .find(|info| info.id == cid::BRIGHTNESS)
+        .expect("vivid should expose a brightness control");
+    assert_eq!(brightness.control_type, ControlType::Integer);
+
+    let original = device
+        .control(cid::BRIGHTNESS)
+        .expect("Failed to read brightness");
+
+    let target = (brightness.minimum + brightness.maximum) / 4;
+    device
+        .set_control(cid::BRIGHTNESS, ControlValue::Integer(target))
+        .expect("Failed to set brightness");
+    assert_eq!(
+        device
+            .control(cid::BRIGHTNESS)
+            .expect("Failed to read brightness"),
+        ControlValue::Integer(target)
+    );
+
+    device
+        .set_controls(&[
+            (cid::BRIGHTNESS, original),
+            (cid::CONTRAST, ControlValue::Integer(128)),
+        ])
+        .expect("Failed to set controls");
+}
+
+#[test]
+#[serial]
+fn test_vivid_negotiate_format() {
+    let device_path = require_vivid!();
+
+    let mut device = V4L2Device::open_path(&device_path).expect("Failed to open vivid device");
+    let request = FormatRequest::new(640, 480)
+        .with_fourccs(&[FourCC::YUYV])
+        .with_min_fps(25);
+
+    let negotiated = negotiate(&mut device, &request).expect("Negotiation failed");
+    println!("Negotiated: {negotiated:?}");
+
+    assert_eq!(negotiated.format.fourcc, FourCC::YUYV);
+    assert_eq!(
+        (negotiated.format.width, negotiated.format.height),
+        (640, 480)
+    );
+    let interval = negotiated
+        .interval
+        .expect("Frame rate should be negotiated");
+    assert!(
+        interval.fps() >= 25.0,
+        "Granted rate below minimum: {interval}"
+    );
+
+    let impossible = FormatRequest::new(640, 480).with_fourccs(&[FourCC::new(b"ZZZZ")]);
+    assert!(matches!(
+        negotiate(&mut device, &impossible),
+        Err(CameraError::FormatNotSupported { .. })
+    ));
+}
+
 #[test]
 #[serial]
 fn test_vivid_set_format() {
-    let device_index = require_vivid!();
+    let device_path = require_vivid!();
 
-    let mut device = V4L2Device::open(device_index).expect("Failed to open vivid device");
+    let mut device = V4L2Device::open_path(&device_path).expect("Failed to open vivid device");
 
     // Request a specific format
     let requested = Format::new(640, 480, FourCC::YUYV);
@@ -153,9 +344,9 @@ fn test_vivid_set_format() {
 #[test]
 #[serial]
 fn test_vivid_capture_single_frame() {
-    let device_index = require_vivid!();
+    let device_path = require_vivid!();
 
-    let mut device = V4L2Device::open(device_index).expect("Failed to open vivid device");
+    let mut device = V4L2Device::open_path(&device_path).expect("Failed to open vivid device");
 
     // Set a known format
     let format = Format::new(640, 480, FourCC::YUYV);
@@ -182,12 +373,63 @@ fn test_vivid_capture_single_frame() {
     assert!(frame.metadata.bytes_used > 0, "Bytes used should be positive");
 }
 
+#[test]
+#[serial]
+fn test_vivid_capture_borrowed_frames() {
+    let device_path = require_vivid!();
+
+    let mut device = V4L2Device::open_path(&device_path).expect("Failed to open vivid device");
+
+    let format = Format::new(640, 480, FourCC::YUYV);
+    let format = device.set_format(&format).expect("Failed to set format");
+
+    let mut stream = device.create_stream(4).expect("Failed to create stream");
+
+    // Cycle through every mmap buffer more than once without copying
+    let mut last_sequence = None;
+    for _ in 0..10 {
+        let frame = stream.next_frame_ref().expect("Failed to capture frame");
+        assert!(
+            frame.data.len() >= format.size as usize,
+            "Frame data too small: {} < {}",
+            frame.data.len(),
+            format.size
+        );
+        if let Some(last) = last_sequence {
+            assert!(frame.metadata.sequence > last, "Sequence should increase");
+        }
+        last_sequence = Some(frame.metadata.sequence);
+    }
+}
+
+#[test]
+#[serial]
+fn test_vivid_capture_with_timeout() {
+    let device_path = require_vivid!();
+
+    let mut device = V4L2Device::open_path(&device_path).expect("Failed to open vivid device");
+    device
+        .set_format(&Format::new(640, 480, FourCC::YUYV))
+        .expect("Failed to set format");
+
+    let mut stream = device.create_stream(4).expect("Failed to create stream");
+    let timeout = Duration::from_secs(2);
+
+    // The first capture starts streaming; later ones poll for a ready buffer
+    for _ in 0..3 {
+        let frame = stream
+            .next_frame_timeout(timeout)
+            .expect("vivid should deliver a frame within the timeout");
+        assert!(frame.metadata.bytes_used > 0);
+    }
+}
+
 #[test]
 #[serial]
 fn test_vivid_capture_multiple_frames() {
-    let device_index = require_vivid!();
+    let device_path = require_vivid!();
 
-    let mut device = V4L2Device::open(device_index).expect("Failed to open vivid device");
+    let mut device = V4L2Device::open_path(&device_path).expect("Failed to open vivid device");
 
     let format = Format::new(640, 480, FourCC::YUYV);
     device.set_format(&format).expect("Failed to set format");
@@ -222,7 +464,7 @@ fn test_vivid_gradient_pattern() {
     let (gradient_device, _) = require_vivid_pair!();
 
     let mut device =
-        V4L2Device::open(gradient_device).expect("Failed to open vivid gradient device");
+        V4L2Device::open_path(&gradient_device).expect("Failed to open vivid gradient device");
 
     let format = Format::new(640, 480, FourCC::YUYV);
     let format = device.set_format(&format).expect("Failed to set format");
@@ -250,7 +492,7 @@ fn test_vivid_colorbar_pattern() {
     let (_, colorbar_device) = require_vivid_pair!();
 
     let mut device =
-        V4L2Device::open(colorbar_device).expect("Failed to open vivid colorbar device");
+        V4L2Device::open_path(&colorbar_device).expect("Failed to open vivid colorbar device");
 
     let format = Format::new(640, 480, FourCC::YUYV);
     let format = device.set_format(&format).expect("Failed to set format");
@@ -272,12 +514,65 @@ fn test_vivid_colorbar_pattern() {
     println!("Color bars pattern validation passed");
 }
 
+#[test]
+#[serial]
+fn test_vivid_planar_colorbar_pattern() {
+    let (_, colorbar_device) = require_vivid_pair!();
+
+    let mut device =
+        V4L2Device::open_path(&colorbar_device).expect("Failed to open vivid colorbar device");
+
+    for fourcc in PLANAR_FORMATS {
+        let format = device
+            .set_format(&Format::new(640, 480, fourcc))
+            .expect("Failed to set format");
+        assert_eq!(format.fourcc, fourcc, "vivid should accept {fourcc}");
+
+        let mut stream = device.create_stream(4).expect("Failed to create stream");
+        let frame = stream.next_frame().expect("Failed to capture frame");
+        assert!(frame.data.len() >= format.size as usize);
+
+        let result = validate_color_bars(&frame, &format);
+        assert!(
+            result.is_ok(),
+            "Color bars validation failed for {fourcc}: {:?}",
+            result.err()
+        );
+    }
+}
+
+#[test]
+#[serial]
+fn test_vivid_planar_gradient_pattern() {
+    let (gradient_device, _) = require_vivid_pair!();
+
+    let mut device =
+        V4L2Device::open_path(&gradient_device).expect("Failed to open vivid gradient device");
+
+    for fourcc in PLANAR_FORMATS {
+        let format = device
+            .set_format(&Format::new(640, 480, fourcc))
+            .expect("Failed to set format");
+        assert_eq!(format.fourcc, fourcc, "vivid should accept {fourcc}");
+
+        let mut stream = device.create_stream(4).expect("Failed to create stream");
+        let frame = stream.next_frame().expect("Failed to capture frame");
+
+        let result = validate_gradient(&frame, &format);
+        assert!(
+            result.is_ok(),
+            "Gradient validation failed for {fourcc}: {:?}",
+            result.err()
+        );
+    }
+}
+
 #[test]
 #[serial]
 fn test_vivid_pixel_access() {
-    let device_index = require_vivid!();
+    let device_path = require_vivid!();
 
-    let mut device = V4L2Device::open(device_index).expect("Failed to open vivid device");
+    let mut device = V4L2Device::open_path(&device_path).expect("Failed to open vivid device");
 
     let format = Format::new(640, 480, FourCC::YUYV);
     let format = device.set_format(&format).expect("Failed to set format");
@@ -300,3 +595,50 @@ fn test_vivid_pixel_access() {
     let center = frame.pixel_at(format.width / 2, format.height / 2, format.width);
     assert!(center.is_some(), "Center pixel should be accessible");
 }
+
+#[test]
+#[serial]
+fn test_vivid_record_and_replay() {
+    let (_, colorbar_device) = require_vivid_pair!();
+
+    let mut device =
+        V4L2Device::open_path(&colorbar_device).expect("Failed to open vivid colorbar device");
+    let format = device
+        .set_format(&Format::new(640, 480, FourCC::YUYV))
+        .expect("Failed to set format");
+    let header = RecordingHeader {
+        format: format.clone(),
+        interval: device
+            .frame_interval()
+            .expect("Failed to get frame interval"),
+        capabilities: device.capabilities().clone(),
+    };
+
+    let mut recorder =
+        Recorder::new(Cursor::new(Vec::new()), &header).expect("Failed to start recording");
+    {
+        let mut stream = device.create_stream(4).expect("Failed to create stream");
+        for _ in 0..5 {
+            let frame = stream.next_frame_ref().expect("Failed to capture frame");
+            recorder
+                .write_frame(&frame)
+                .expect("Failed to record frame");
+        }
+    }
+    let mut recording = recorder.finish().expect("Failed to finish recording");
+    recording.set_position(0);
+
+    let reader = RecordingReader::new(recording).expect("Failed to read recording");
+    assert_eq!(reader.len(), 5);
+    let mut replay = ReplayDevice::from_recording(reader).expect("Failed to open replay");
+    assert_eq!(replay.capabilities().driver, "vivid");
+
+    let mut stream = replay
+        .create_stream(4)
+        .expect("Failed to create replay stream");
+    let frames: Vec<_> = (0..5)
+        .map(|_| stream.next_frame().expect("Failed to replay frame"))
+        .collect();
+    validate_frame_sequence(&frames).expect("Replayed sequence should be valid");
+    validate_color_bars(&frames[0], &format).expect("Replayed frame should hold color bars");
+}